@@ -0,0 +1,482 @@
+//! A call graph over the functions of a `Program`.
+//!
+//! Calls are `Operation::Branch` instructions. A branch whose target
+//! evaluates to a constant is a direct call, including tail calls. A branch
+//! with a non-constant target is an indirect call when it has a successor in
+//! the control flow graph, and otherwise a return, which is not part of the
+//! call graph.
+//!
+//! Every function in the program is a vertex, and its vertex index is its
+//! function index. Direct calls to addresses which are not functions in the
+//! program go to an external vertex for each address, and indirect calls go
+//! to one shared vertex for unresolved targets.
+
+use crate::error::*;
+use crate::executor::eval;
+use crate::graph;
+use crate::il;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// The kind of a call.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum CallKind {
+    /// The target of the call is a constant.
+    Direct,
+    /// The target of the call is computed.
+    Indirect,
+}
+
+/// One call, from a call site in the head of a `CallGraphEdge`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct Call {
+    location: il::ProgramLocation,
+    kind: CallKind,
+}
+
+impl Call {
+    /// The location of the `Operation::Branch` which makes this call.
+    pub fn location(&self) -> &il::ProgramLocation {
+        &self.location
+    }
+
+    /// Whether this call is direct or indirect.
+    pub fn kind(&self) -> CallKind {
+        self.kind
+    }
+
+    /// Returns true if this call is indirect.
+    pub fn is_indirect(&self) -> bool {
+        self.kind == CallKind::Indirect
+    }
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            CallKind::Direct => write!(f, "{}", self.location),
+            CallKind::Indirect => write!(f, "{} (indirect)", self.location),
+        }
+    }
+}
+
+/// What a vertex in the `CallGraph` stands for.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum CallTarget {
+    /// A function in the program, with its address and name.
+    Function { address: u64, name: String },
+    /// An address called directly which is not a function in the program.
+    External(u64),
+    /// The targets of indirect calls.
+    Unresolved,
+}
+
+/// A vertex in the `CallGraph`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct CallGraphVertex {
+    index: usize,
+    target: CallTarget,
+}
+
+impl CallGraphVertex {
+    /// What this vertex stands for.
+    pub fn target(&self) -> &CallTarget {
+        &self.target
+    }
+
+    /// The index of the function this vertex stands for, if it is a function
+    /// in the program.
+    pub fn function_index(&self) -> Option<usize> {
+        match self.target {
+            CallTarget::Function { .. } => Some(self.index),
+            CallTarget::External(_) | CallTarget::Unresolved => None,
+        }
+    }
+
+    /// The address this vertex stands for, if known.
+    pub fn address(&self) -> Option<u64> {
+        match self.target {
+            CallTarget::Function { address, .. } | CallTarget::External(address) => Some(address),
+            CallTarget::Unresolved => None,
+        }
+    }
+}
+
+impl graph::Vertex for CallGraphVertex {
+    fn index(&self) -> usize {
+        self.index
+    }
+
+    fn dot_label(&self) -> String {
+        match self.target {
+            CallTarget::Function {
+                address, ref name, ..
+            } => format!("{}\n0x{:x}", name, address),
+            CallTarget::External(address) => format!("external\n0x{:x}", address),
+            CallTarget::Unresolved => "unresolved".to_string(),
+        }
+    }
+
+    fn dot_fill_color(&self) -> String {
+        match self.target {
+            CallTarget::Function { .. } => "#ffddcc".to_string(),
+            CallTarget::External(_) | CallTarget::Unresolved => "#dddddd".to_string(),
+        }
+    }
+}
+
+/// An edge in the `CallGraph`, holding every call from the head to the tail.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct CallGraphEdge {
+    head: usize,
+    tail: usize,
+    calls: Vec<Call>,
+}
+
+impl CallGraphEdge {
+    /// The calls from the head to the tail, in order of their locations.
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+}
+
+impl graph::Edge for CallGraphEdge {
+    fn head(&self) -> usize {
+        self.head
+    }
+
+    fn tail(&self) -> usize {
+        self.tail
+    }
+
+    fn dot_label(&self) -> String {
+        self.calls
+            .iter()
+            .map(|call| call.to_string())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// The call graph of a `Program`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CallGraph {
+    graph: graph::Graph<CallGraphVertex, CallGraphEdge>,
+    externals: BTreeMap<u64, usize>,
+    unresolved: Option<usize>,
+}
+
+impl CallGraph {
+    /// Build the call graph of a `Program`.
+    pub fn new(program: &il::Program) -> Result<CallGraph> {
+        let functions = program.functions_map();
+
+        let mut graph = graph::Graph::new();
+        for (index, function) in &functions {
+            graph.insert_vertex(CallGraphVertex {
+                index: *index,
+                target: CallTarget::Function {
+                    address: function.address(),
+                    name: function.name(),
+                },
+            })?;
+        }
+
+        let mut next_index = functions.keys().next_back().map_or(0, |index| index + 1);
+        let mut externals = BTreeMap::new();
+        let mut unresolved = None;
+        let mut calls: BTreeMap<(usize, usize), Vec<Call>> = BTreeMap::new();
+
+        for (index, function) in functions {
+            for (location, target) in call_sites(function)? {
+                let (tail, kind) = match target {
+                    Some(address) => {
+                        let tail = match program.function_by_address(address) {
+                            Some(callee) => {
+                                callee.index().ok_or("Function in program has no index")?
+                            }
+                            None => *externals.entry(address).or_insert_with(|| {
+                                next_index += 1;
+                                next_index - 1
+                            }),
+                        };
+                        (tail, CallKind::Direct)
+                    }
+                    None => {
+                        let tail = *unresolved.get_or_insert_with(|| {
+                            next_index += 1;
+                            next_index - 1
+                        });
+                        (tail, CallKind::Indirect)
+                    }
+                };
+                calls
+                    .entry((index, tail))
+                    .or_default()
+                    .push(Call { location, kind });
+            }
+        }
+
+        for (address, index) in &externals {
+            graph.insert_vertex(CallGraphVertex {
+                index: *index,
+                target: CallTarget::External(*address),
+            })?;
+        }
+        if let Some(index) = unresolved {
+            graph.insert_vertex(CallGraphVertex {
+                index,
+                target: CallTarget::Unresolved,
+            })?;
+        }
+        for ((head, tail), calls) in calls {
+            graph.insert_edge(CallGraphEdge { head, tail, calls })?;
+        }
+
+        Ok(CallGraph {
+            graph,
+            externals,
+            unresolved,
+        })
+    }
+
+    /// Get the underlying `Graph`.
+    pub fn graph(&self) -> &graph::Graph<CallGraphVertex, CallGraphEdge> {
+        &self.graph
+    }
+
+    /// Get a vertex by its index.
+    pub fn vertex(&self, index: usize) -> Result<&CallGraphVertex> {
+        self.graph.vertex(index)
+    }
+
+    /// Get the index of the vertex for an external address, if it is called.
+    pub fn external(&self, address: u64) -> Option<usize> {
+        self.externals.get(&address).cloned()
+    }
+
+    /// The addresses called directly which are not functions in the program.
+    pub fn external_addresses(&self) -> Vec<u64> {
+        self.externals.keys().cloned().collect()
+    }
+
+    /// Get the index of the vertex for the targets of indirect calls, if
+    /// there are any.
+    pub fn unresolved(&self) -> Option<usize> {
+        self.unresolved
+    }
+
+    /// The indices of the vertices which call the given vertex.
+    pub fn callers(&self, index: usize) -> Result<Vec<usize>> {
+        self.graph.predecessor_indices(index)
+    }
+
+    /// The indices of the vertices called by the given vertex.
+    pub fn callees(&self, index: usize) -> Result<Vec<usize>> {
+        self.graph.successor_indices(index)
+    }
+
+    /// The calls from one vertex to another.
+    pub fn calls(&self, head: usize, tail: usize) -> Result<&[Call]> {
+        Ok(self.graph.edge(head, tail)?.calls())
+    }
+
+    /// Every call made by a vertex, with the index of the vertex it calls.
+    pub fn calls_from(&self, index: usize) -> Result<Vec<(usize, &Call)>> {
+        Ok(self
+            .graph
+            .edges_out(index)?
+            .into_iter()
+            .flat_map(|edge| edge.calls().iter().map(move |call| (edge.tail, call)))
+            .collect())
+    }
+
+    /// The indices of the functions in the program which no function calls
+    /// directly.
+    pub fn roots(&self) -> Vec<usize> {
+        self.graph
+            .vertices_without_predecessors()
+            .into_iter()
+            .filter_map(|vertex| vertex.function_index())
+            .collect()
+    }
+
+    /// The sets of vertices which are mutually recursive, including functions
+    /// which call themselves.
+    pub fn recursive_components(&self) -> Vec<BTreeSet<usize>> {
+        self.graph
+            .compute_strongly_connected_components()
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1
+                    || component
+                        .iter()
+                        .all(|index| self.graph.edge(*index, *index).is_ok())
+            })
+            .collect()
+    }
+
+    /// Returns true if the given vertex may call itself, directly or through
+    /// other functions.
+    pub fn is_recursive(&self, index: usize) -> bool {
+        self.recursive_components()
+            .iter()
+            .any(|component| component.contains(&index))
+    }
+
+    /// The indices of the vertices reachable through calls from the given
+    /// entries, including the entries.
+    pub fn reachable(&self, entries: &[usize]) -> Result<BTreeSet<usize>> {
+        let mut reachable = BTreeSet::new();
+        for entry in entries {
+            if !self.graph.has_vertex(*entry) {
+                bail!(ErrorKind::GraphVertexNotFound(*entry));
+            }
+            if reachable.contains(entry) {
+                continue;
+            }
+            reachable.extend(self.graph.compute_post_order(*entry)?);
+        }
+        Ok(reachable)
+    }
+
+    /// Returns a string in the graphviz format.
+    pub fn dot_graph(&self) -> String {
+        self.graph.dot_graph()
+    }
+}
+
+/// The location of every call in a function, with its target if the call is
+/// direct.
+pub(crate) fn call_sites(
+    function: &il::Function,
+) -> Result<Vec<(il::ProgramLocation, Option<u64>)>> {
+    let mut call_sites = Vec::new();
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let target = match *instruction.operation() {
+                il::Operation::Branch { ref target } => target,
+                _ => continue,
+            };
+            let location = il::RefProgramLocation::new(
+                function,
+                il::RefFunctionLocation::Instruction(block, instruction),
+            );
+            let target = match eval(target) {
+                Ok(constant) => constant.value_u64(),
+                Err(_) => None,
+            };
+            if target.is_none() && location.forward()?.is_empty() {
+                // A return
+                continue;
+            }
+            call_sites.push((location.into(), target));
+        }
+    }
+    Ok(call_sites)
+}
+
+// A function whose entry block branches to each target in turn, followed by a
+// return.
+#[cfg(test)]
+fn calling_function(address: u64, targets: &[il::Expression]) -> il::Function {
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let mut previous: Option<usize> = None;
+    for target in targets {
+        let index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.branch(target.clone());
+            block.index()
+        };
+        match previous {
+            Some(previous) => control_flow_graph
+                .unconditional_edge(previous, index)
+                .unwrap(),
+            None => control_flow_graph.set_entry(index).unwrap(),
+        }
+        previous = Some(index);
+    }
+    let index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.branch(il::expr_scalar("ra", 32));
+        block.index()
+    };
+    match previous {
+        Some(previous) => control_flow_graph
+            .unconditional_edge(previous, index)
+            .unwrap(),
+        None => control_flow_graph.set_entry(index).unwrap(),
+    }
+    il::Function::new(address, control_flow_graph)
+}
+
+#[test]
+fn call_graph_test() {
+    /*
+    main (0x1000): calls f twice, g, 0x9000, and [sp]
+    f (0x2000): calls f
+    g (0x3000): calls h
+    h (0x4000): calls g
+    unused (0x5000)
+    */
+    let mut program = il::Program::new();
+    program.add_function(calling_function(
+        0x1000,
+        &[
+            il::expr_const(0x2000, 32),
+            il::expr_const(0x2000, 32),
+            il::expr_const(0x3000, 32),
+            il::expr_const(0x9000, 32),
+            il::expr_scalar("sp", 32),
+        ],
+    ));
+    program.add_function(calling_function(0x2000, &[il::expr_const(0x2000, 32)]));
+    program.add_function(calling_function(0x3000, &[il::expr_const(0x4000, 32)]));
+    program.add_function(calling_function(0x4000, &[il::expr_const(0x3000, 32)]));
+    program.add_function(calling_function(0x5000, &[]));
+
+    let call_graph = CallGraph::new(&program).unwrap();
+
+    let external = call_graph.external(0x9000).unwrap();
+    let unresolved = call_graph.unresolved().unwrap();
+    assert_eq!(call_graph.external_addresses(), vec![0x9000]);
+    assert_eq!(call_graph.vertex(external).unwrap().address(), Some(0x9000));
+    assert_eq!(call_graph.vertex(external).unwrap().function_index(), None);
+
+    assert_eq!(
+        call_graph.callees(0).unwrap(),
+        vec![1, 2, external, unresolved]
+    );
+    assert_eq!(call_graph.callers(1).unwrap(), vec![0, 1]);
+    assert_eq!(call_graph.callers(4).unwrap(), Vec::<usize>::new());
+
+    let calls = call_graph.calls(0, 1).unwrap();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].kind(), CallKind::Direct);
+    assert_eq!(
+        calls[0].location(),
+        &il::ProgramLocation::new(Some(0), il::FunctionLocation::Instruction(0, 0))
+    );
+    assert!(call_graph.calls(0, unresolved).unwrap()[0].is_indirect());
+    assert_eq!(call_graph.calls_from(0).unwrap().len(), 5);
+
+    assert_eq!(call_graph.roots(), vec![0, 4]);
+    assert_eq!(
+        call_graph.recursive_components(),
+        vec![
+            vec![1].into_iter().collect(),
+            vec![2, 3].into_iter().collect()
+        ]
+    );
+    assert!(call_graph.is_recursive(3));
+    assert!(!call_graph.is_recursive(0));
+
+    assert_eq!(
+        call_graph.reachable(&[2]).unwrap(),
+        vec![2, 3].into_iter().collect()
+    );
+    assert_eq!(call_graph.reachable(&[0]).unwrap().len(), 6);
+    assert!(call_graph.reachable(&[100]).is_err());
+
+    assert!(call_graph.dot_graph().contains("unknown@00002000"));
+}
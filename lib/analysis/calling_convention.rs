@@ -71,6 +71,9 @@ impl ArgumentType {
 /// Represents the calling convention of a particular platform.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct CallingConvention {
+    /// The type of this calling convention.
+    type_: CallingConventionType,
+
     /// arguments passed in registers.
     argument_registers: Vec<il::Scalar>,
 
@@ -140,6 +143,7 @@ impl CallingConvention {
                 let return_type = ReturnAddressType::Stack(0);
 
                 CallingConvention {
+                    type_: typ,
                     argument_registers: argument_registers,
                     preserved_registers: preserved_registers,
                     trashed_registers: trashed_registers,
@@ -165,6 +169,7 @@ impl CallingConvention {
                 let return_type = ReturnAddressType::Stack(0);
 
                 CallingConvention {
+                    type_: typ,
                     argument_registers: Vec::new(),
                     preserved_registers: preserved_registers,
                     trashed_registers: trashed_registers,
@@ -218,6 +223,7 @@ impl CallingConvention {
                 let return_type = ReturnAddressType::Register(il::scalar("$ra", 32));
 
                 CallingConvention {
+                    type_: typ,
                     argument_registers: argument_registers,
                     preserved_registers: preserved_registers,
                     trashed_registers: trashed_registers,
@@ -275,21 +281,27 @@ impl CallingConvention {
                 trashed_registers.insert(il::scalar("r12", 32));
                 trashed_registers.insert(il::scalar("r13", 32));
 
-                let return_type = ReturnAddressType::Register(il::scalar("r3", 32));
+                let return_type = ReturnAddressType::Register(il::scalar("lr", 32));
 
                 CallingConvention {
+                    type_: typ,
                     argument_registers: argument_registers,
                     preserved_registers: preserved_registers,
                     trashed_registers: trashed_registers,
                     stack_argument_offset: 4,
                     stack_argument_length: 4,
                     return_address_type: return_type,
-                    return_register: il::scalar("lr", 32),
+                    return_register: il::scalar("r3", 32),
                 }
             }
         }
     }
 
+    /// Get the type of this calling convention.
+    pub fn type_(&self) -> &CallingConventionType {
+        &self.type_
+    }
+
     /// Get the registers the first n arguments are passed in.
     pub fn argument_registers(&self) -> &[il::Scalar] {
         &self.argument_registers
@@ -364,3 +376,14 @@ impl CallingConvention {
         }
     }
 }
+
+#[test]
+fn ppc_return_test() {
+    let calling_convention = CallingConvention::new(CallingConventionType::PpcSystemV);
+
+    assert_eq!(calling_convention.return_register(), &il::scalar("r3", 32));
+    assert_eq!(
+        calling_convention.return_address_type(),
+        &ReturnAddressType::Register(il::scalar("lr", 32))
+    );
+}
@@ -5,8 +5,13 @@
 //!
 //! Calling Constants::eval() uses the known constant values to replace scalars,
 //! and then attempts to evaluate the expression to an `il::Constant`.
+//!
+//! `constants` treats every call as writing every scalar.
+//! `constants_interprocedural` propagates constants into, and back out of,
+//! the functions of a program which are called.
 
 use crate::analysis::fixed_point;
+use crate::analysis::interprocedural;
 use crate::error::*;
 use crate::executor::eval;
 use crate::il;
@@ -40,6 +45,26 @@ pub fn constants<'r>(
     Ok(result)
 }
 
+/// Compute constants for every function in the given program, following
+/// calls to functions in the program.
+///
+/// Calls are analysed in contexts of the last `context_depth` call sites, and
+/// the constants at each location are joined over its contexts.
+pub fn constants_interprocedural(
+    program: &il::Program,
+    context_depth: usize,
+) -> Result<HashMap<il::ProgramLocation, Constants>> {
+    let analysis = ConstantsAnalysis {};
+    interprocedural::fixed_point_interprocedural(
+        &analysis,
+        program,
+        None,
+        context_depth,
+        &fixed_point::FixedPointOptions::new(),
+    )?
+    .merge_inputs(&analysis)
+}
+
 #[allow(dead_code)] // Bottom is never used
 #[derive(Clone, Debug, PartialEq)]
 enum Constant {
@@ -248,3 +273,26 @@ impl<'r> fixed_point::FixedPointAnalysis<'r, Constants> for ConstantsAnalysis {
         Ok(state0.join(state1))
     }
 }
+
+impl<'r> interprocedural::InterproceduralAnalysis<'r, Constants> for ConstantsAnalysis {
+    fn entry(&self, _function: &'r il::Function) -> Result<Constants> {
+        Ok(Constants::new())
+    }
+
+    fn trans(&self, location: il::RefProgramLocation<'r>, state: Constants) -> Result<Constants> {
+        fixed_point::FixedPointAnalysis::trans(self, location, Some(state))
+    }
+
+    fn join(&self, state0: Constants, state1: &Constants) -> Result<Constants> {
+        Ok(state0.join(state1))
+    }
+
+    fn unknown_call(
+        &self,
+        _location: &il::RefProgramLocation<'r>,
+        mut state: Constants,
+    ) -> Result<Constants> {
+        state.top();
+        Ok(state)
+    }
+}
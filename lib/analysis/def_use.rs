@@ -19,6 +19,7 @@ pub fn def_use(function: &il::Function) -> Result<HashMap<il::ProgramLocation, L
                 .operation()
                 .scalars_read()
                 .into_iter()
+                .flatten()
                 .for_each(|scalar_read| {
                     rd[&location].locations().into_iter().for_each(|rd| {
                         rd.function_location()
@@ -29,6 +30,7 @@ pub fn def_use(function: &il::Function) -> Result<HashMap<il::ProgramLocation, L
                             .operation()
                             .scalars_written()
                             .into_iter()
+                            .flatten()
                             .for_each(|scalar_written| {
                                 if scalar_written == scalar_read {
                                     du.entry(rd.clone())
@@ -211,3 +213,62 @@ fn use_def_test() {
             .into()
         ));
 }
+
+#[test]
+fn def_use_multiple_writes_test() {
+    /*
+    a, b = intrinsic
+    a = 1
+    c = a + b
+    a, b = intrinsic
+    d = a
+    */
+    let writes_a_b = || {
+        il::Intrinsic::new(
+            "ab",
+            "ab",
+            Vec::new(),
+            Some(vec![il::expr_scalar("a", 32), il::expr_scalar("b", 32)]),
+            Some(Vec::new()),
+            Vec::new(),
+        )
+    };
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let block_index = {
+        let block = control_flow_graph.new_block().unwrap();
+
+        block.intrinsic(writes_a_b());
+        block.assign(il::scalar("a", 32), il::expr_const(1, 32));
+        block.assign(
+            il::scalar("c", 32),
+            il::Expression::add(il::expr_scalar("a", 32), il::expr_scalar("b", 32)).unwrap(),
+        );
+        block.intrinsic(writes_a_b());
+        block.assign(il::scalar("d", 32), il::expr_scalar("a", 32));
+
+        block.index()
+    };
+
+    control_flow_graph.set_entry(block_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+
+    let location = |instruction_index| {
+        il::ProgramLocation::new(
+            None,
+            il::FunctionLocation::Instruction(block_index, instruction_index),
+        )
+    };
+
+    let du = def_use(&function).unwrap();
+
+    // Each scalar an instruction writes is a definition of it.
+    assert_eq!(du[&location(0)].len(), 1);
+    assert!(du[&location(0)].contains(&location(2)));
+    assert_eq!(du[&location(1)].len(), 1);
+    assert!(du[&location(1)].contains(&location(2)));
+    assert_eq!(du[&location(3)].len(), 1);
+    assert!(du[&location(3)].contains(&location(4)));
+}
@@ -2,8 +2,9 @@
 
 use crate::error::*;
 use crate::il;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
+use std::hash::Hash;
 
 /// A trait which implements a forward, flow-sensitive analysis to a
 /// fixed point.
@@ -14,6 +15,166 @@ pub trait FixedPointAnalysis<'f, State: 'f + Clone + Debug + PartialOrd> {
 
     /// Given two states, join them into one state.
     fn join(&self, state0: State, state1: &State) -> Result<State>;
+
+    /// Widen the state previously computed at a loop head with a new state,
+    /// so states over lattices of infinite height stop growing.
+    ///
+    /// By default, the new state is used.
+    fn widen(&self, _previous: &State, state: State) -> Result<State> {
+        Ok(state)
+    }
+
+    /// Narrow the state previously computed at a loop head with a new,
+    /// smaller state, recovering precision lost by widening.
+    ///
+    /// By default, the new state is used.
+    fn narrow(&self, _previous: &State, state: State) -> Result<State> {
+        Ok(state)
+    }
+}
+
+/// Options for the fixed-point engines.
+#[derive(Clone, Debug)]
+pub struct FixedPointOptions {
+    force: bool,
+    widening_delay: usize,
+    narrowing_passes: usize,
+    max_iterations: Option<usize>,
+}
+
+impl FixedPointOptions {
+    /// Create options which do not force the partial order, widen at every
+    /// revisit of a loop head, do not narrow, and have no iteration budget.
+    pub fn new() -> FixedPointOptions {
+        FixedPointOptions {
+            force: false,
+            widening_delay: 0,
+            narrowing_passes: 0,
+            max_iterations: None,
+        }
+    }
+
+    /// When force is true, the partial order over inputs is forced by joining
+    /// states which do not inherently enforce the partial order.
+    pub fn force(&self) -> bool {
+        self.force
+    }
+
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    /// The number of times a loop head is revisited before its states are
+    /// widened.
+    pub fn widening_delay(&self) -> usize {
+        self.widening_delay
+    }
+
+    pub fn set_widening_delay(&mut self, widening_delay: usize) {
+        self.widening_delay = widening_delay;
+    }
+
+    /// The number of passes over the function which narrow its states once
+    /// a fixed point is reached.
+    pub fn narrowing_passes(&self) -> usize {
+        self.narrowing_passes
+    }
+
+    pub fn set_narrowing_passes(&mut self, narrowing_passes: usize) {
+        self.narrowing_passes = narrowing_passes;
+    }
+
+    /// The largest number of locations evaluated before the analysis stops,
+    /// if there is a limit.
+    pub fn max_iterations(&self) -> Option<usize> {
+        self.max_iterations
+    }
+
+    pub fn set_max_iterations(&mut self, max_iterations: Option<usize>) {
+        self.max_iterations = max_iterations;
+    }
+}
+
+impl Default for FixedPointOptions {
+    fn default() -> FixedPointOptions {
+        FixedPointOptions::new()
+    }
+}
+
+/// The states found by a fixed-point engine.
+#[derive(Clone, Debug)]
+pub struct FixedPoint<Location: Eq + Hash, State> {
+    states: HashMap<Location, State>,
+    converged: bool,
+    iterations: usize,
+}
+
+impl<Location: Eq + Hash, State> FixedPoint<Location, State> {
+    /// The state after each location.
+    pub fn states(&self) -> &HashMap<Location, State> {
+        &self.states
+    }
+
+    /// Take the state after each location.
+    pub fn into_states(self) -> HashMap<Location, State> {
+        self.states
+    }
+
+    /// Returns true if the states reached a fixed point. Otherwise, the
+    /// iteration budget ran out, and the states are partial results which
+    /// may not hold for every execution.
+    pub fn converged(&self) -> bool {
+        self.converged
+    }
+
+    /// The number of locations evaluated.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+}
+
+/// The indices of the blocks of a function which are the targets of
+/// retreating edges in a depth-first search from its entry. Every cycle in
+/// the control flow graph passes through one of these loop heads.
+pub fn loop_heads(function: &il::Function) -> Result<HashSet<usize>> {
+    let control_flow_graph = function.control_flow_graph();
+    let entry = control_flow_graph
+        .entry()
+        .ok_or("Function's control flow graph must have entry")?;
+    let post_order: HashMap<usize, usize> = control_flow_graph
+        .graph()
+        .compute_post_order(entry)?
+        .into_iter()
+        .enumerate()
+        .map(|(order, index)| (index, order))
+        .collect();
+
+    Ok(control_flow_graph
+        .edges()
+        .into_iter()
+        .filter(
+            |edge| match (post_order.get(&edge.head()), post_order.get(&edge.tail())) {
+                (Some(head), Some(tail)) => tail >= head,
+                _ => false,
+            },
+        )
+        .map(|edge| edge.tail())
+        .collect())
+}
+
+/// Returns true if the location is the first location of a loop head.
+pub(crate) fn is_loop_head(location: &il::RefProgramLocation, loop_heads: &HashSet<usize>) -> bool {
+    match *location.function_location() {
+        il::RefFunctionLocation::Instruction(block, instruction) => {
+            loop_heads.contains(&block.index())
+                && block
+                    .instructions()
+                    .first()
+                    .is_some_and(|first| first.index() == instruction.index())
+        }
+        il::RefFunctionLocation::EmptyBlock(block) => loop_heads.contains(&block.index()),
+        il::RefFunctionLocation::Edge(_) => false,
+    }
 }
 
 /// A forward, work-list data-flow analysis algorithm.
@@ -29,6 +190,27 @@ where
     Analysis: FixedPointAnalysis<'f, State>,
     State: 'f + Clone + Debug + PartialOrd,
 {
+    let mut options = FixedPointOptions::new();
+    options.set_force(force);
+    Ok(fixed_point_forward_with(analysis, function, &options)?.into_states())
+}
+
+/// A forward, work-list data-flow analysis algorithm, which widens and
+/// narrows states at loop heads, and stops when its iteration budget runs
+/// out.
+pub fn fixed_point_forward_with<'f, Analysis, State>(
+    analysis: Analysis,
+    function: &'f il::Function,
+    options: &FixedPointOptions,
+) -> Result<FixedPoint<il::ProgramLocation, State>>
+where
+    Analysis: FixedPointAnalysis<'f, State>,
+    State: 'f + Clone + Debug + PartialOrd,
+{
+    let loop_heads = loop_heads(function)?;
+    let mut visits: HashMap<il::ProgramLocation, usize> = HashMap::new();
+    let mut iterations = 0;
+
     let mut states: HashMap<il::ProgramLocation, State> = HashMap::new();
 
     let mut queue: VecDeque<il::ProgramLocation> = VecDeque::new();
@@ -53,7 +235,19 @@ where
         }
     }
 
+    // Every location evaluated, in the order first evaluated.
+    let mut order: Vec<il::ProgramLocation> = Vec::new();
+
     while !queue.is_empty() {
+        if options.max_iterations.is_some_and(|max| iterations >= max) {
+            return Ok(FixedPoint {
+                states,
+                converged: false,
+                iterations,
+            });
+        }
+        iterations += 1;
+
         let location = queue.pop_front().unwrap();
 
         // TODO this should not be an unwrap
@@ -76,7 +270,16 @@ where
 
         let mut state = analysis.trans(location.clone(), state)?;
 
-        if let Some(in_state) = states.get(&location.clone().into()) {
+        let program_location: il::ProgramLocation = location.clone().into();
+        if let Some(in_state) = states.get(&program_location) {
+            if is_loop_head(&location, &loop_heads) {
+                let visits = visits.entry(program_location.clone()).or_insert(0);
+                *visits += 1;
+                if *visits > options.widening_delay {
+                    state = analysis.widen(in_state, state)?;
+                }
+            }
+
             let ordering = match state.partial_cmp(in_state) {
                 Some(ordering) => match ordering {
                     ::std::cmp::Ordering::Less => Some("less"),
@@ -87,20 +290,20 @@ where
                 },
                 None => Some("no relation"),
             };
-            if force {
+            if options.force {
                 state = analysis.join(state, in_state)?;
-            } else {
-                if let Some(ordering) = ordering {
-                    bail!(
-                        "Found a state which was not >= previous state (it was {}) @ {}",
-                        ordering,
-                        location
-                    );
-                }
+            } else if let Some(ordering) = ordering {
+                bail!(
+                    "Found a state which was not >= previous state (it was {}) @ {}",
+                    ordering,
+                    location
+                );
             }
+        } else {
+            order.push(program_location.clone());
         }
 
-        states.insert(location.clone().into(), state);
+        states.insert(program_location, state);
 
         for successor in location.forward()? {
             if !queue.contains(&successor.clone().into()) {
@@ -109,7 +312,53 @@ where
         }
     }
 
-    Ok(states)
+    // Narrow the fixed point by reevaluating every location from the states
+    // of its predecessors, which can only shrink the states.
+    for _ in 0..options.narrowing_passes {
+        let mut changed = false;
+        for program_location in &order {
+            if options.max_iterations.is_some_and(|max| iterations >= max) {
+                break;
+            }
+            iterations += 1;
+
+            let location = program_location.function_location().apply(function)?;
+            let location = il::RefProgramLocation::new(function, location);
+
+            let state =
+                location
+                    .backward()?
+                    .into_iter()
+                    .try_fold(None, |s: Option<State>, p| {
+                        Ok::<_, Error>(match states.get(&p.into()) {
+                            Some(in_state) => Some(match s {
+                                Some(s) => analysis.join(s, in_state)?,
+                                None => in_state.clone(),
+                            }),
+                            None => s,
+                        })
+                    })?;
+
+            let mut state = analysis.trans(location.clone(), state)?;
+            let previous = &states[program_location];
+            if is_loop_head(&location, &loop_heads) {
+                state = analysis.narrow(previous, state)?;
+            }
+            if state.partial_cmp(previous) != Some(::std::cmp::Ordering::Equal) {
+                changed = true;
+                states.insert(program_location.clone(), state);
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    Ok(FixedPoint {
+        states,
+        converged: true,
+        iterations,
+    })
 }
 
 /// A guaranteed sound analysis, which enforces the partial order over states.
@@ -137,31 +386,62 @@ where
     Analysis: FixedPointAnalysis<'f, State>,
     State: 'f + Clone + Debug + PartialOrd,
 {
+    let mut options = FixedPointOptions::new();
+    options.set_force(force);
+    Ok(fixed_point_backward_with(analysis, function, &options)?.into_states())
+}
+
+/// A backward, work-list data-flow analysis algorithm, which widens and
+/// narrows states at loop heads, and stops when its iteration budget runs
+/// out.
+pub fn fixed_point_backward_with<'f, Analysis, State>(
+    analysis: Analysis,
+    function: &'f il::Function,
+    options: &FixedPointOptions,
+) -> Result<FixedPoint<il::RefProgramLocation<'f>, State>>
+where
+    Analysis: FixedPointAnalysis<'f, State>,
+    State: 'f + Clone + Debug + PartialOrd,
+{
+    let loop_heads = loop_heads(function)?;
+    let mut visits: HashMap<il::RefProgramLocation<'f>, usize> = HashMap::new();
+    let mut iterations = 0;
+
     let mut states: HashMap<il::RefProgramLocation<'f>, State> = HashMap::new();
 
     let mut queue: VecDeque<il::RefProgramLocation<'f>> = VecDeque::new();
 
-    // Find the entry block to the function.
-    let exit_index = function
-        .control_flow_graph()
-        .entry()
-        .ok_or("Function's control flow graph must have entry")?;
-    let exit_block = function.control_flow_graph().block(exit_index)?;
-
-    match exit_block.instructions().last() {
-        Some(ref instruction) => {
-            let location = il::RefFunctionLocation::Instruction(exit_block, instruction);
-            let location = il::RefProgramLocation::new(function, location);
-            queue.push_back(location.clone());
-        }
-        None => {
-            let location = il::RefFunctionLocation::EmptyBlock(exit_block);
-            let location = il::RefProgramLocation::new(function, location);
-            queue.push_back(location.clone());
-        }
+    // Start from the last location of every block, beginning with the blocks
+    // which exit the function, so blocks which never reach an exit, such as
+    // infinite loops, are evaluated too.
+    let control_flow_graph = function.control_flow_graph();
+    let mut blocks = control_flow_graph.blocks();
+    blocks.sort_by_key(|block| {
+        control_flow_graph
+            .edges_out(block.index())
+            .is_ok_and(|edges| !edges.is_empty())
+    });
+    for block in blocks {
+        let location = match block.instructions().last() {
+            Some(instruction) => il::RefFunctionLocation::Instruction(block, instruction),
+            None => il::RefFunctionLocation::EmptyBlock(block),
+        };
+        queue.push_back(il::RefProgramLocation::new(function, location));
     }
 
+    // Every location evaluated, in the order first evaluated.
+    let mut order: Vec<il::RefProgramLocation<'f>> = Vec::new();
+
     while !queue.is_empty() {
+        if options.max_iterations.is_some_and(|max| iterations >= max) {
+            return Ok(FixedPoint {
+                states,
+                converged: false,
+                iterations,
+            });
+        }
+        iterations += 1;
+
         let location = queue.pop_front().unwrap();
 
         let location_successors = location.forward()?;
@@ -179,6 +459,14 @@ where
         let mut state = analysis.trans(location.clone(), state)?;
 
         if let Some(in_state) = states.get(&location) {
+            if is_loop_head(&location, &loop_heads) {
+                let visits = visits.entry(location.clone()).or_insert(0);
+                *visits += 1;
+                if *visits > options.widening_delay {
+                    state = analysis.widen(in_state, state)?;
+                }
+            }
+
             let ordering = match state.partial_cmp(in_state) {
                 Some(ordering) => match ordering {
                     ::std::cmp::Ordering::Less => Some("less"),
@@ -189,17 +477,17 @@ where
                 },
                 None => Some("no relation"),
             };
-            if force {
+            if options.force {
                 state = analysis.join(state, in_state)?;
-            } else {
-                if let Some(ordering) = ordering {
-                    bail!(
-                        "Found a state which was not >= previous state (it was {}) @ {}",
-                        ordering,
-                        location
-                    );
-                }
+            } else if let Some(ordering) = ordering {
+                bail!(
+                    "Found a state which was not >= previous state (it was {}) @ {}",
+                    ordering,
+                    location
+                );
             }
+        } else {
+            order.push(location.clone());
         }
 
         states.insert(location.clone(), state);
@@ -211,7 +499,49 @@ where
         }
     }
 
-    Ok(states)
+    // Narrow the fixed point by reevaluating every location from the states
+    // of its successors, which can only shrink the states.
+    for _ in 0..options.narrowing_passes {
+        let mut changed = false;
+        for location in &order {
+            if options.max_iterations.is_some_and(|max| iterations >= max) {
+                break;
+            }
+            iterations += 1;
+
+            let state = location
+                .forward()?
+                .iter()
+                .try_fold(None, |s: Option<State>, p| {
+                    Ok::<_, Error>(match states.get(p) {
+                        Some(in_state) => Some(match s {
+                            Some(s) => analysis.join(s, in_state)?,
+                            None => in_state.clone(),
+                        }),
+                        None => s,
+                    })
+                })?;
+
+            let mut state = analysis.trans(location.clone(), state)?;
+            let previous = &states[location];
+            if is_loop_head(location, &loop_heads) {
+                state = analysis.narrow(previous, state)?;
+            }
+            if state.partial_cmp(previous) != Some(::std::cmp::Ordering::Equal) {
+                changed = true;
+                states.insert(location.clone(), state);
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    Ok(FixedPoint {
+        states,
+        converged: true,
+        iterations,
+    })
 }
 
 /// A guaranteed sound analysis, which enforces the partial order over states.
@@ -225,3 +555,87 @@ where
 {
     fixed_point_backward_options(analysis, function, false)
 }
+
+// Counts the assignments executed, which never reaches a fixed point in a
+// loop without widening.
+#[cfg(test)]
+struct CountAnalysis {
+    widen: bool,
+}
+
+#[cfg(test)]
+impl<'f> FixedPointAnalysis<'f, u64> for CountAnalysis {
+    fn trans(&self, location: il::RefProgramLocation<'f>, state: Option<u64>) -> Result<u64> {
+        let state = state.unwrap_or(0);
+        Ok(match location.instruction() {
+            Some(_) => state.saturating_add(1),
+            None => state,
+        })
+    }
+
+    fn join(&self, state0: u64, state1: &u64) -> Result<u64> {
+        Ok(state0.max(*state1))
+    }
+
+    fn widen(&self, previous: &u64, state: u64) -> Result<u64> {
+        Ok(if self.widen && state > *previous {
+            u64::MAX
+        } else {
+            state
+        })
+    }
+}
+
+#[test]
+fn fixed_point_widening_test() {
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let entry_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("a", 32), il::expr_const(0, 32));
+        block.index()
+    };
+
+    let loop_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("a", 32), il::expr_const(1, 32));
+        block.assign(il::scalar("b", 32), il::expr_const(2, 32));
+        block.index()
+    };
+
+    control_flow_graph
+        .unconditional_edge(entry_index, loop_index)
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(loop_index, loop_index)
+        .unwrap();
+    control_flow_graph.set_entry(entry_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+
+    assert_eq!(
+        loop_heads(&function).unwrap(),
+        vec![loop_index].into_iter().collect()
+    );
+
+    // Without widening, the budget stops the analysis.
+    let mut options = FixedPointOptions::new();
+    options.set_force(true);
+    options.set_max_iterations(Some(50));
+    let fixed_point =
+        fixed_point_forward_with(CountAnalysis { widen: false }, &function, &options).unwrap();
+    assert!(!fixed_point.converged());
+    assert_eq!(fixed_point.iterations(), 50);
+
+    let fixed_point =
+        fixed_point_forward_with(CountAnalysis { widen: true }, &function, &options).unwrap();
+    assert!(fixed_point.converged());
+
+    let block = function.control_flow_graph().block(loop_index).unwrap();
+    let location: il::ProgramLocation = il::RefProgramLocation::new(
+        &function,
+        il::RefFunctionLocation::Instruction(block, &block.instructions()[1]),
+    )
+    .into();
+    assert_eq!(fixed_point.states()[&location], u64::MAX);
+}
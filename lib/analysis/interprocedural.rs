@@ -0,0 +1,763 @@
+//! An interprocedural fixed-point engine for data-flow analysis.
+//!
+//! Calls are `Operation::Branch` instructions with a successor in the
+//! control flow graph. When the target of a call is a function in the
+//! `Program`, the callee is analysed from the state before the call, and the
+//! state after the call is made from the callee's summary: the join of its
+//! states at exit. Calls to targets outside of the program are left to the
+//! analysis.
+//!
+//! States are kept per context, the call string of the last `k` call sites
+//! leading to a function. With a context depth of 0, every call to a function
+//! shares one context, and the analysis is context-insensitive.
+
+use crate::analysis::call_graph::CallGraph;
+use crate::analysis::fixed_point::{self, FixedPointOptions};
+use crate::error::*;
+use crate::executor::eval;
+use crate::il;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+
+/// The call sites of the calls leading to a function, the most recent last.
+pub type Context = Vec<il::ProgramLocation>;
+
+/// A trait which implements a forward, flow-sensitive and interprocedural
+/// analysis to a fixed point.
+pub trait InterproceduralAnalysis<'p, State: 'p + Clone + Debug + PartialOrd> {
+    /// The state at entry to a function which is analysed without a caller.
+    fn entry(&self, function: &'p il::Function) -> Result<State>;
+
+    /// Given the state before a location which is not a call, create the
+    /// state after it.
+    fn trans(&self, location: il::RefProgramLocation<'p>, state: State) -> Result<State>;
+
+    /// Given two states, join them into one state.
+    fn join(&self, state0: State, state1: &State) -> Result<State>;
+
+    /// Widen the state previously computed at a loop head, or at entry to a
+    /// function, with a new state.
+    ///
+    /// By default, the new state is used.
+    fn widen(&self, _previous: &State, state: State) -> Result<State> {
+        Ok(state)
+    }
+
+    /// The addresses a call may branch to, given the state before the call.
+    ///
+    /// By default, only constant targets are resolved.
+    fn targets(&self, location: &il::RefProgramLocation<'p>, _state: &State) -> Vec<u64> {
+        match location
+            .instruction()
+            .map(|instruction| instruction.operation())
+        {
+            Some(il::Operation::Branch {
+                target: il::Expression::Constant(constant),
+            }) => constant.value_u64().into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Given the state before a call, create the state at entry to the
+    /// callee.
+    ///
+    /// By default, the state before the call is used.
+    fn call(
+        &self,
+        _location: &il::RefProgramLocation<'p>,
+        _callee: &'p il::Function,
+        state: &State,
+    ) -> Result<State> {
+        Ok(state.clone())
+    }
+
+    /// Given the join of a function's states at exit, create the summary of
+    /// the function.
+    ///
+    /// By default, the state at exit is the summary.
+    fn summary(&self, _function: &'p il::Function, exit: State) -> Result<State> {
+        Ok(exit)
+    }
+
+    /// Given the state before a call, and the summary of the callee, create
+    /// the state after the call.
+    ///
+    /// By default, the summary is used.
+    fn return_(
+        &self,
+        _location: &il::RefProgramLocation<'p>,
+        _state: &State,
+        summary: &State,
+    ) -> Result<State> {
+        Ok(summary.clone())
+    }
+
+    /// Given the state before a call to a target which is not a function in
+    /// the program, create the state after the call.
+    fn unknown_call(&self, location: &il::RefProgramLocation<'p>, state: State) -> Result<State>;
+}
+
+/// The states found by the interprocedural fixed-point engine.
+#[derive(Clone, Debug)]
+pub struct InterproceduralFixedPoint<State> {
+    inputs: HashMap<(Context, il::ProgramLocation), State>,
+    states: HashMap<(Context, il::ProgramLocation), State>,
+    summaries: HashMap<(usize, Context), State>,
+    converged: bool,
+    iterations: usize,
+}
+
+impl<State: Clone> InterproceduralFixedPoint<State> {
+    /// The state before each location, in each context it was reached in.
+    pub fn inputs(&self) -> &HashMap<(Context, il::ProgramLocation), State> {
+        &self.inputs
+    }
+
+    /// The state after each location, in each context it was reached in.
+    pub fn states(&self) -> &HashMap<(Context, il::ProgramLocation), State> {
+        &self.states
+    }
+
+    /// The summary of each function, in each context it was called in.
+    pub fn summaries(&self) -> &HashMap<(usize, Context), State> {
+        &self.summaries
+    }
+
+    /// Get the state before a location in a context.
+    pub fn input(
+        &self,
+        location: &il::ProgramLocation,
+        context: &[il::ProgramLocation],
+    ) -> Option<&State> {
+        self.inputs.get(&(context.to_vec(), location.clone()))
+    }
+
+    /// Get the state after a location in a context.
+    pub fn state(
+        &self,
+        location: &il::ProgramLocation,
+        context: &[il::ProgramLocation],
+    ) -> Option<&State> {
+        self.states.get(&(context.to_vec(), location.clone()))
+    }
+
+    /// Get the summary of a function in a context.
+    pub fn summary(
+        &self,
+        function_index: usize,
+        context: &[il::ProgramLocation],
+    ) -> Option<&State> {
+        self.summaries.get(&(function_index, context.to_vec()))
+    }
+
+    /// Returns true if the states reached a fixed point. Otherwise, the
+    /// iteration budget ran out, and the states are partial results which
+    /// may not hold for every execution.
+    pub fn converged(&self) -> bool {
+        self.converged
+    }
+
+    /// The number of locations evaluated.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Join the states before each location over every context.
+    pub fn merge_inputs<'p, Analysis>(
+        &self,
+        analysis: &Analysis,
+    ) -> Result<HashMap<il::ProgramLocation, State>>
+    where
+        Analysis: InterproceduralAnalysis<'p, State>,
+        State: 'p + Debug + PartialOrd,
+    {
+        merge(analysis, &self.inputs)
+    }
+
+    /// Join the states after each location over every context.
+    pub fn merge_states<'p, Analysis>(
+        &self,
+        analysis: &Analysis,
+    ) -> Result<HashMap<il::ProgramLocation, State>>
+    where
+        Analysis: InterproceduralAnalysis<'p, State>,
+        State: 'p + Debug + PartialOrd,
+    {
+        merge(analysis, &self.states)
+    }
+}
+
+fn merge<'p, Analysis, State>(
+    analysis: &Analysis,
+    states: &HashMap<(Context, il::ProgramLocation), State>,
+) -> Result<HashMap<il::ProgramLocation, State>>
+where
+    Analysis: InterproceduralAnalysis<'p, State>,
+    State: 'p + Clone + Debug + PartialOrd,
+{
+    let mut merged: HashMap<il::ProgramLocation, State> = HashMap::new();
+    for ((_, location), state) in states {
+        let state = match merged.remove(location) {
+            Some(merged) => analysis.join(merged, state)?,
+            None => state.clone(),
+        };
+        merged.insert(location.clone(), state);
+    }
+    Ok(merged)
+}
+
+/// Returns true if the location is a branch which returns to its caller,
+/// rather than a call.
+fn is_return(location: &il::RefProgramLocation) -> Result<bool> {
+    Ok(location.forward()?.is_empty())
+}
+
+/// Returns true if a branch to `target` at a location is a call: a branch to
+/// a constant target, or a branch with successors.
+pub(crate) fn is_call(location: &il::RefProgramLocation, target: &il::Expression) -> Result<bool> {
+    Ok(eval(target).is_ok() || !location.forward()?.is_empty())
+}
+
+/// An interprocedural, work-list data-flow analysis algorithm.
+///
+/// Each function in `entries` is analysed from `InterproceduralAnalysis::entry`
+/// in an empty context. When `entries` is `None`, every function without a
+/// direct caller is an entry, and functions which are never reached, such as
+/// those only called indirectly, are analysed as entries once the analysis of
+/// the others is complete.
+///
+/// A branch without successors in the control flow graph returns from its
+/// function, unless its target is a function in the program, when it is a
+/// tail call. Calls from a context are analysed in the context of the call
+/// site appended to the caller's context, keeping the last `context_depth`
+/// call sites.
+///
+/// The widening delay and iteration budget of the options are respected.
+/// States are always joined with the states they replace.
+pub fn fixed_point_interprocedural<'p, Analysis, State>(
+    analysis: &Analysis,
+    program: &'p il::Program,
+    entries: Option<&[usize]>,
+    context_depth: usize,
+    options: &FixedPointOptions,
+) -> Result<InterproceduralFixedPoint<State>>
+where
+    Analysis: InterproceduralAnalysis<'p, State>,
+    State: 'p + Clone + Debug + PartialOrd,
+{
+    let mut engine = Engine {
+        analysis,
+        program,
+        context_depth,
+        options,
+        queue: VecDeque::new(),
+        queued: HashSet::new(),
+        loop_heads: HashMap::new(),
+        visits: HashMap::new(),
+        entry_states: HashMap::new(),
+        exits: HashMap::new(),
+        callers: HashMap::new(),
+        result: InterproceduralFixedPoint {
+            inputs: HashMap::new(),
+            states: HashMap::new(),
+            summaries: HashMap::new(),
+            converged: true,
+            iterations: 0,
+        },
+    };
+
+    let roots: Vec<usize> = match entries {
+        Some(entries) => entries.to_vec(),
+        None => CallGraph::new(program)?.roots(),
+    };
+    // Functions without an entry, which are never reached, are only rooted
+    // once.
+    let mut rooted: HashSet<usize> = HashSet::new();
+    for root in roots {
+        rooted.insert(root);
+        engine.root(root)?;
+    }
+
+    loop {
+        engine.run()?;
+        if !engine.result.converged || entries.is_some() {
+            break;
+        }
+        let unreached: Vec<usize> = program
+            .functions_map()
+            .into_keys()
+            .filter(|index| {
+                !rooted.contains(index)
+                    && !engine
+                        .entry_states
+                        .keys()
+                        .any(|(function, _)| function == index)
+            })
+            .collect();
+        if unreached.is_empty() {
+            break;
+        }
+        for function in unreached {
+            rooted.insert(function);
+            engine.root(function)?;
+        }
+    }
+
+    Ok(engine.result)
+}
+
+struct Engine<'a, 'p, Analysis, State> {
+    analysis: &'a Analysis,
+    program: &'p il::Program,
+    context_depth: usize,
+    options: &'a FixedPointOptions,
+    queue: VecDeque<(Context, il::ProgramLocation)>,
+    queued: HashSet<(Context, il::ProgramLocation)>,
+    loop_heads: HashMap<usize, HashSet<usize>>,
+    /// The number of times each loop head, and each function entry, has been
+    /// revisited.
+    visits: HashMap<(Context, il::ProgramLocation), usize>,
+    entry_states: HashMap<(usize, Context), State>,
+    /// The states after the exits of each function.
+    exits: HashMap<(usize, Context), HashMap<il::ProgramLocation, State>>,
+    /// The call sites, and their contexts, of each function.
+    callers: HashMap<(usize, Context), HashSet<(Context, il::ProgramLocation)>>,
+    result: InterproceduralFixedPoint<State>,
+}
+
+impl<'a, 'p, Analysis, State> Engine<'a, 'p, Analysis, State>
+where
+    Analysis: InterproceduralAnalysis<'p, State>,
+    State: 'p + Clone + Debug + PartialOrd,
+{
+    fn function(&self, index: usize) -> Result<&'p il::Function> {
+        self.program
+            .function(index)
+            .ok_or_else(|| format!("Program has no function with index {}", index).into())
+    }
+
+    fn push(&mut self, context: Context, location: il::ProgramLocation) {
+        let item = (context, location);
+        if self.queued.insert(item.clone()) {
+            self.queue.push_back(item);
+        }
+    }
+
+    /// Analyse a function from its entry state, in an empty context.
+    fn root(&mut self, index: usize) -> Result<()> {
+        let function = self.function(index)?;
+        let state = self.analysis.entry(function)?;
+        self.enter(index, Vec::new(), state)
+    }
+
+    /// Join a state into the entry state of a function in a context, and
+    /// analyse the function if its entry state changed.
+    fn enter(&mut self, index: usize, context: Context, state: State) -> Result<()> {
+        let function = self.function(index)?;
+        let entry: il::ProgramLocation = match il::RefProgramLocation::from_function(function) {
+            Some(entry) => entry?.into(),
+            None => return Ok(()),
+        };
+        let key = (index, context.clone());
+        let state = match self.entry_states.get(&key) {
+            Some(previous) => {
+                let visits = self
+                    .visits
+                    .entry((context.clone(), entry.clone()))
+                    .or_insert(0);
+                *visits += 1;
+                let state = if *visits > self.options.widening_delay() {
+                    self.analysis.widen(previous, state)?
+                } else {
+                    state
+                };
+                if state.partial_cmp(previous) == Some(::std::cmp::Ordering::Equal) {
+                    return Ok(());
+                }
+                self.analysis.join(state, previous)?
+            }
+            None => state,
+        };
+        self.entry_states.insert(key, state);
+        self.push(context, entry);
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<()> {
+        while let Some((context, program_location)) = self.queue.pop_front() {
+            self.queued
+                .remove(&(context.clone(), program_location.clone()));
+            if self
+                .options
+                .max_iterations()
+                .is_some_and(|max| self.result.iterations >= max)
+            {
+                self.result.converged = false;
+                return Ok(());
+            }
+            self.result.iterations += 1;
+            self.evaluate(context, program_location)?;
+        }
+        Ok(())
+    }
+
+    fn evaluate(&mut self, context: Context, program_location: il::ProgramLocation) -> Result<()> {
+        let location = program_location.apply(self.program)?;
+        let function = location.function();
+        let function_index = function.index().ok_or("Function in program has no index")?;
+
+        // Join the states of the predecessors, and the entry state.
+        let mut state: Option<State> = None;
+        let is_entry = il::RefProgramLocation::from_function(function)
+            .transpose()?
+            .is_some_and(|entry| entry == location);
+        if is_entry {
+            state = self
+                .entry_states
+                .get(&(function_index, context.clone()))
+                .cloned();
+        }
+        for predecessor in location.backward()? {
+            if let Some(in_state) = self
+                .result
+                .states
+                .get(&(context.clone(), predecessor.into()))
+            {
+                state = Some(match state {
+                    Some(state) => self.analysis.join(state, in_state)?,
+                    None => in_state.clone(),
+                });
+            }
+        }
+        let state = match state {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        self.result
+            .inputs
+            .insert((context.clone(), program_location.clone()), state.clone());
+
+        let is_return = is_return(&location)?;
+        let state = match location
+            .instruction()
+            .map(|instruction| instruction.operation())
+        {
+            Some(il::Operation::Branch { .. }) => {
+                let targets = self.analysis.targets(&location, &state);
+                let callees: Vec<&'p il::Function> = targets
+                    .iter()
+                    .filter_map(|target| self.program.function_by_address(*target))
+                    .collect();
+                if callees.is_empty() && is_return {
+                    Some(state)
+                } else {
+                    self.call(&context, &location, &targets, &callees, state)?
+                }
+            }
+            _ => Some(self.analysis.trans(location.clone(), state)?),
+        };
+        let mut state = match state {
+            Some(state) => state,
+            // No callee has returned yet.
+            None => return Ok(()),
+        };
+
+        let key = (context.clone(), program_location.clone());
+        if let Some(previous) = self.result.states.get(&key) {
+            let loop_heads = match self.loop_heads.get(&function_index) {
+                Some(loop_heads) => loop_heads,
+                None => {
+                    let loop_heads = fixed_point::loop_heads(function)?;
+                    self.loop_heads.entry(function_index).or_insert(loop_heads)
+                }
+            };
+            if fixed_point::is_loop_head(&location, loop_heads) {
+                let visits = self.visits.entry(key.clone()).or_insert(0);
+                *visits += 1;
+                if *visits > self.options.widening_delay() {
+                    state = self.analysis.widen(previous, state)?;
+                }
+            }
+            if state.partial_cmp(previous) == Some(::std::cmp::Ordering::Equal) {
+                return Ok(());
+            }
+            state = self.analysis.join(state, previous)?;
+        }
+        self.result.states.insert(key, state.clone());
+
+        if is_return {
+            self.exit(function_index, context, program_location, state)?;
+        } else {
+            for successor in location.forward()? {
+                self.push(context.clone(), successor.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Analyse a call, returning the state after the call, or `None` if no
+    /// callee has a summary yet.
+    fn call(
+        &mut self,
+        context: &Context,
+        location: &il::RefProgramLocation<'p>,
+        targets: &[u64],
+        callees: &[&'p il::Function],
+        state: State,
+    ) -> Result<Option<State>> {
+        let program_location: il::ProgramLocation = location.clone().into();
+        let mut callee_context = context.clone();
+        if self.context_depth > 0 {
+            callee_context.push(program_location.clone());
+            if callee_context.len() > self.context_depth {
+                callee_context.remove(0);
+            }
+        } else {
+            callee_context.clear();
+        }
+
+        let mut result: Option<State> = None;
+        // Some targets are not functions in the program.
+        if callees.len() < targets.len() || callees.is_empty() {
+            result = Some(self.analysis.unknown_call(location, state.clone())?);
+        }
+
+        for callee in callees {
+            let index = callee.index().ok_or("Function in program has no index")?;
+            let entry = self.analysis.call(location, callee, &state)?;
+            self.callers
+                .entry((index, callee_context.clone()))
+                .or_default()
+                .insert((context.clone(), program_location.clone()));
+            self.enter(index, callee_context.clone(), entry)?;
+
+            if let Some(summary) = self.result.summaries.get(&(index, callee_context.clone())) {
+                let after = self.analysis.return_(location, &state, summary)?;
+                result = Some(match result {
+                    Some(result) => self.analysis.join(result, &after)?,
+                    None => after,
+                });
+            }
+        }
+        Ok(result)
+    }
+
+    /// Update the summary of a function with the state after one of its
+    /// exits, and reanalyse its callers if the summary changed.
+    fn exit(
+        &mut self,
+        index: usize,
+        context: Context,
+        location: il::ProgramLocation,
+        state: State,
+    ) -> Result<()> {
+        let key = (index, context);
+        let exits = self.exits.entry(key.clone()).or_default();
+        exits.insert(location, state);
+        let mut exit: Option<State> = None;
+        for state in exits.values() {
+            exit = Some(match exit {
+                Some(exit) => self.analysis.join(exit, state)?,
+                None => state.clone(),
+            });
+        }
+        let exit = match exit {
+            Some(exit) => exit,
+            None => return Ok(()),
+        };
+        let summary = self.analysis.summary(self.function(index)?, exit)?;
+        let unchanged = self.result.summaries.get(&key).is_some_and(|previous| {
+            summary.partial_cmp(previous) == Some(::std::cmp::Ordering::Equal)
+        });
+        if unchanged {
+            return Ok(());
+        }
+        self.result.summaries.insert(key.clone(), summary);
+        let callers: Vec<(Context, il::ProgramLocation)> = self
+            .callers
+            .get(&key)
+            .map(|callers| callers.iter().cloned().collect())
+            .unwrap_or_default();
+        for (context, location) in callers {
+            self.push(context, location);
+        }
+        Ok(())
+    }
+}
+
+// A function at 0x2000 which computes y = x + 1, and returns.
+#[cfg(test)]
+fn increment_function() -> il::Function {
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let block_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(
+            il::scalar("y", 32),
+            il::Expression::add(il::expr_scalar("x", 32), il::expr_const(1, 32)).unwrap(),
+        );
+        block.branch(il::expr_scalar("ra", 32));
+        block.index()
+    };
+    control_flow_graph.set_entry(block_index).unwrap();
+    il::Function::new(0x2000, control_flow_graph)
+}
+
+#[test]
+fn interprocedural_context_test() {
+    /*
+    x = 1
+    call 0x2000
+    x = 2
+    call 0x2000
+    nop
+    */
+    use crate::analysis::constants;
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let first_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("x", 32), il::expr_const(1, 32));
+        block.branch(il::expr_const(0x2000, 32));
+        block.index()
+    };
+
+    let second_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("x", 32), il::expr_const(2, 32));
+        block.branch(il::expr_const(0x2000, 32));
+        block.index()
+    };
+
+    let exit_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.nop();
+        block.index()
+    };
+
+    control_flow_graph
+        .unconditional_edge(first_index, second_index)
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(second_index, exit_index)
+        .unwrap();
+    control_flow_graph.set_entry(first_index).unwrap();
+
+    let mut program = il::Program::new();
+    program.add_function(il::Function::new(0x1000, control_flow_graph));
+    program.add_function(increment_function());
+
+    let after_first =
+        il::ProgramLocation::new(Some(0), il::FunctionLocation::Instruction(second_index, 0));
+    let after_second =
+        il::ProgramLocation::new(Some(0), il::FunctionLocation::Instruction(exit_index, 0));
+    let y = il::scalar("y", 32);
+
+    // Each call site is its own context.
+    let constants = constants::constants_interprocedural(&program, 1).unwrap();
+    assert_eq!(constants[&after_first].scalar(&y), Some(&il::const_(2, 32)));
+    assert_eq!(
+        constants[&after_second].scalar(&y),
+        Some(&il::const_(3, 32))
+    );
+
+    // Both calls share one context, and the summary joins them.
+    let constants = constants::constants_interprocedural(&program, 0).unwrap();
+    assert_eq!(constants[&after_first].scalar(&y), None);
+    assert_eq!(constants[&after_second].scalar(&y), None);
+    assert_eq!(constants[&after_second].scalar(&il::scalar("x", 32)), None);
+}
+
+#[test]
+fn interprocedural_recursion_test() {
+    /*
+    0x1000:
+        call 0x2000
+        nop
+
+    0x2000:
+        r = 7
+        if c {
+            call 0x2000
+        }
+        return
+    */
+    use crate::analysis::constants;
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.branch(il::expr_const(0x2000, 32));
+        block.index()
+    };
+    let exit_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.nop();
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(call_index, exit_index)
+        .unwrap();
+    control_flow_graph.set_entry(call_index).unwrap();
+    let main = il::Function::new(0x1000, control_flow_graph);
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let head_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("r", 32), il::expr_const(7, 32));
+        block.index()
+    };
+    let recurse_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.branch(il::expr_const(0x2000, 32));
+        block.index()
+    };
+    let return_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.branch(il::expr_scalar("ra", 32));
+        block.index()
+    };
+    let c = il::expr_scalar("c", 1);
+    control_flow_graph
+        .conditional_edge(head_index, recurse_index, c.clone())
+        .unwrap();
+    control_flow_graph
+        .conditional_edge(
+            head_index,
+            return_index,
+            il::Expression::cmpeq(c, il::expr_const(0, 1)).unwrap(),
+        )
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(recurse_index, return_index)
+        .unwrap();
+    control_flow_graph.set_entry(head_index).unwrap();
+    let recursive = il::Function::new(0x2000, control_flow_graph);
+
+    let mut program = il::Program::new();
+    program.add_function(main);
+    program.add_function(recursive);
+
+    for context_depth in 0..3 {
+        let constants = constants::constants_interprocedural(&program, context_depth).unwrap();
+        let after_call =
+            il::ProgramLocation::new(Some(0), il::FunctionLocation::Instruction(exit_index, 0));
+        assert_eq!(
+            constants[&after_call].scalar(&il::scalar("r", 32)),
+            Some(&il::const_(7, 32))
+        );
+    }
+}
+
+#[test]
+fn interprocedural_no_entry_test() {
+    use crate::analysis::constants;
+
+    // A function with no entry is never reached, and is not analysed.
+    let mut program = il::Program::new();
+    program.add_function(il::Function::new(0x1000, il::ControlFlowGraph::new()));
+    program.add_function(increment_function());
+
+    let constants = constants::constants_interprocedural(&program, 0).unwrap();
+    assert_eq!(constants.len(), 2);
+}
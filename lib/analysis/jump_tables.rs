@@ -0,0 +1,578 @@
+//! Resolution of jump tables, and other bounded indirect branches.
+//!
+//! An indirect branch which only returns from its function lifts to an
+//! `Operation::Branch` with a non-constant target and no successors. When the
+//! target of such a branch is computed from an entry loaded out of a table in
+//! read-only memory, and the index into the table is bounded, the targets of
+//! the branch are the entries of the table.
+//!
+//! The definitions of the target are followed backwards to the load of the
+//! table entry, and value-set analysis bounds the address of the entry. Each
+//! entry is read from memory which is readable and not writable, and every
+//! target must be executable. Entries loaded through the stack pointer, such
+//! as the return address of a `ret`, are not tables, and value-set analysis
+//! is not run for them.
+//!
+//! `translate_function` feeds the targets back to the translator as manual
+//! edges, guarded by the target of the branch, and replaces each resolved
+//! branch with a `Nop`, so a switch lifts to a multi-way `ControlFlowGraph`.
+
+use crate::analysis::interprocedural::is_call;
+use crate::analysis::value_set;
+use crate::architecture::{Architecture, Endian};
+use crate::error::*;
+use crate::il;
+use crate::memory::MemoryPermissions;
+use crate::translator::TranslationMemory;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The most entries a jump table may have.
+const MAX_ENTRIES: u64 = 1024;
+
+/// The most locations followed backwards from a branch to the load of its
+/// table entry.
+const MAX_SLICE_LENGTH: usize = 64;
+
+/// The most times a function is lifted again with the targets of its jump
+/// tables, as resolving one table may reveal another.
+const MAX_ROUNDS: usize = 8;
+
+/// The manual edges of each resolved branch, by the address of the branch.
+type Edges = BTreeMap<u64, Vec<(u64, u64, Option<il::Expression>)>>;
+
+/// A resolved indirect branch.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JumpTable {
+    location: il::ProgramLocation,
+    address: u64,
+    target: il::Expression,
+    table: u64,
+    targets: Vec<u64>,
+}
+
+impl JumpTable {
+    /// The location of the `Operation::Branch`.
+    pub fn location(&self) -> &il::ProgramLocation {
+        &self.location
+    }
+
+    /// The address of the branch instruction.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// The target expression of the branch.
+    pub fn target(&self) -> &il::Expression {
+        &self.target
+    }
+
+    /// The lowest address of an entry in the table.
+    pub fn table(&self) -> u64 {
+        self.table
+    }
+
+    /// The distinct targets of the branch, in order.
+    pub fn targets(&self) -> &[u64] {
+        &self.targets
+    }
+
+    /// The manual edges from the branch to each target, guarded by the
+    /// target of the branch, as taken by
+    /// `Translator::translate_function_extended`.
+    pub fn edges(&self) -> Result<Vec<(u64, u64, Option<il::Expression>)>> {
+        self.targets
+            .iter()
+            .map(|target| {
+                let condition = il::Expression::cmpeq(
+                    self.target.clone(),
+                    il::expr_const(*target, self.target.bits()),
+                )?;
+                Ok((self.address, *target, Some(condition)))
+            })
+            .collect()
+    }
+}
+
+/// The load of a table entry, found by following the definitions of a branch
+/// target backwards.
+struct TableLoad {
+    location: il::ProgramLocation,
+    entry: il::Scalar,
+    index: il::Expression,
+    /// The target of the branch, in terms of the entry and the scalars before
+    /// the load.
+    target: il::Expression,
+}
+
+/// Resolve the indirect branches in a function which return from the
+/// function, and branch through a bounded table in read-only memory.
+pub fn jump_tables(
+    function: &il::Function,
+    architecture: &dyn Architecture,
+    memory: &dyn TranslationMemory,
+) -> Result<Vec<JumpTable>> {
+    let stack_pointer = architecture.stack_pointer();
+    let mut loads = Vec::new();
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let target = match *instruction.operation() {
+                il::Operation::Branch { ref target } => target,
+                _ => continue,
+            };
+            let address = match instruction.address() {
+                Some(address) => address,
+                None => continue,
+            };
+            let location = il::RefProgramLocation::new(
+                function,
+                il::RefFunctionLocation::Instruction(block, instruction),
+            );
+            if is_call(&location, target)? {
+                continue;
+            }
+            if let Some(load) = slice(location.clone(), target.clone())? {
+                if load.index.scalars().contains(&&stack_pointer) {
+                    continue;
+                }
+                loads.push((location.into(), address, target.clone(), load));
+            }
+        }
+    }
+
+    if loads.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let value_sets = value_set::value_sets(function, architecture, &[])?;
+
+    let mut jump_tables = Vec::new();
+    for (location, address, target, load) in loads {
+        let value_sets = match value_sets.get(&load.location) {
+            Some(value_sets) => value_sets,
+            None => continue,
+        };
+        if let Some((table, targets)) = table_targets(&load, value_sets, architecture, memory) {
+            jump_tables.push(JumpTable {
+                location,
+                address,
+                target,
+                table,
+                targets,
+            });
+        }
+    }
+
+    Ok(jump_tables)
+}
+
+/// Translate a function, resolving its jump tables into edges.
+pub fn translate_function(
+    architecture: &dyn Architecture,
+    memory: &dyn TranslationMemory,
+    address: u64,
+) -> Result<il::Function> {
+    let mut edges = Edges::new();
+    let mut function = architecture
+        .translator()
+        .translate_function(memory, address)?;
+
+    for _ in 0..MAX_ROUNDS {
+        // A function whose jump tables cannot be resolved is still lifted,
+        // with its indirect branches unresolved.
+        match resolve(&function, architecture, memory, address, &mut edges) {
+            Ok(Some(resolved)) => function = resolved,
+            Ok(None) => break,
+            Err(e) => {
+                warn!(
+                    "Failed to resolve jump tables of function at 0x{:x}: {}",
+                    address, e
+                );
+                break;
+            }
+        }
+    }
+
+    Ok(function)
+}
+
+/// Resolve the jump tables of a function not yet in `edges`, and lift the
+/// function again with the edges of every table resolved so far. Returns
+/// `None` when no new table is resolved.
+fn resolve(
+    function: &il::Function,
+    architecture: &dyn Architecture,
+    memory: &dyn TranslationMemory,
+    address: u64,
+    edges: &mut Edges,
+) -> Result<Option<il::Function>> {
+    let mut resolved = false;
+    for jump_table in jump_tables(function, architecture, memory)? {
+        if let Entry::Vacant(entry) = edges.entry(jump_table.address()) {
+            entry.insert(jump_table.edges()?);
+            resolved = true;
+        }
+    }
+    if !resolved {
+        return Ok(None);
+    }
+
+    let mut function = architecture.translator().translate_function_extended(
+        memory,
+        address,
+        edges.values().flatten().cloned().collect(),
+    )?;
+    remove_branches(&mut function, &edges.keys().cloned().collect());
+    Ok(Some(function))
+}
+
+/// Replace the branches at the given addresses, which have been resolved into
+/// edges, with `Nop`.
+fn remove_branches(function: &mut il::Function, addresses: &BTreeSet<u64>) {
+    for block in function.blocks_mut() {
+        for instruction in block.instructions_mut() {
+            let resolved = instruction.is_branch()
+                && instruction
+                    .address()
+                    .is_some_and(|address| addresses.contains(&address));
+            if resolved {
+                *instruction.operation_mut() = il::Operation::Nop;
+            }
+        }
+    }
+}
+
+/// Follow the definitions of the scalars in a branch target backwards from
+/// the branch, until the load of the table entry.
+///
+/// Only locations with one predecessor are followed.
+fn slice(
+    mut location: il::RefProgramLocation,
+    mut target: il::Expression,
+) -> Result<Option<TableLoad>> {
+    for _ in 0..MAX_SLICE_LENGTH {
+        let mut predecessors = location.backward()?;
+        if predecessors.len() != 1 {
+            return Ok(None);
+        }
+        location = predecessors.remove(0);
+
+        let instruction = match location.instruction() {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        match *instruction.operation() {
+            il::Operation::Assign { ref dst, ref src } => {
+                if target.scalars().contains(&dst) {
+                    target = target.replace_scalar(dst, src)?;
+                }
+            }
+            il::Operation::Load { ref dst, ref index } => {
+                if target.scalars().contains(&dst) {
+                    return Ok(Some(TableLoad {
+                        location: location.clone().into(),
+                        entry: dst.clone(),
+                        index: index.clone(),
+                        target,
+                    }));
+                }
+            }
+            il::Operation::Store { .. } | il::Operation::Nop => {}
+            il::Operation::Branch { .. } => return Ok(None),
+            il::Operation::Intrinsic { ref intrinsic } => match intrinsic.scalars_written() {
+                Some(scalars_written) => {
+                    if scalars_written
+                        .iter()
+                        .any(|scalar| target.scalars().contains(scalar))
+                    {
+                        return Ok(None);
+                    }
+                }
+                None => return Ok(None),
+            },
+        }
+    }
+    Ok(None)
+}
+
+/// Read the table of a table load, returning the lowest address of an entry
+/// and the distinct targets, if the table has more than one entry, and every
+/// entry is in read-only memory and gives an executable target.
+fn table_targets(
+    load: &TableLoad,
+    value_sets: &value_set::ValueSets,
+    architecture: &dyn Architecture,
+    memory: &dyn TranslationMemory,
+) -> Option<(u64, Vec<u64>)> {
+    let addresses = value_sets.interval(&load.index)?.values(MAX_ENTRIES)?;
+    // One entry is a pointer to a function, rather than a table.
+    if addresses.len() < 2 {
+        return None;
+    }
+    let table = addresses[0];
+
+    let mut targets = BTreeSet::new();
+    for address in addresses {
+        let entry = read(memory, architecture.endian(), address, load.entry.bits())?;
+        let target = load
+            .target
+            .replace_scalar(&load.entry, &il::expr_const(entry, load.entry.bits()))
+            .ok()?;
+        let target = value_sets.eval(&target).value()?;
+        let executable = memory
+            .permissions(target)
+            .is_some_and(|permissions| permissions.contains(MemoryPermissions::EXECUTE));
+        if !executable {
+            return None;
+        }
+        targets.insert(target);
+    }
+
+    Some((table, targets.into_iter().collect()))
+}
+
+/// Read a value from memory which is readable, and not writable.
+fn read(memory: &dyn TranslationMemory, endian: Endian, address: u64, bits: usize) -> Option<u64> {
+    if bits == 0 || bits > 64 || !bits.is_multiple_of(8) {
+        return None;
+    }
+    let mut value = 0;
+    for i in 0..(bits / 8) as u64 {
+        let address = address.checked_add(i)?;
+        let read_only = memory.permissions(address).is_some_and(|permissions| {
+            permissions.contains(MemoryPermissions::READ)
+                && !permissions.contains(MemoryPermissions::WRITE)
+        });
+        if !read_only {
+            return None;
+        }
+        let byte = memory.get_u8(address)? as u64;
+        value |= match endian {
+            Endian::Big => byte << (bits as u64 - 8 - i * 8),
+            Endian::Little => byte << (i * 8),
+        };
+    }
+    Some(value)
+}
+
+// A translator for a toy instruction set, where each byte is an instruction:
+//   0x00: return
+//   0x01: if i >= 3, branch to 0x1100
+//   0x02: t = [0x3000 + i * 4]
+//   0x03: branch to t
+//   0x04: rsp = 0x3000 + i * 4
+//   0x05: t = [rsp]
+//   0xff: invalid
+//   any other byte b: r = b
+#[cfg(test)]
+#[derive(Clone, Debug)]
+struct Switch;
+
+#[cfg(test)]
+impl crate::translator::Translator for Switch {
+    fn translate_block(
+        &self,
+        bytes: &[u8],
+        address: u64,
+    ) -> Result<crate::translator::BlockTranslationResult> {
+        let i = || il::expr_scalar("i", 64);
+        let mut instructions = Vec::new();
+        let mut successors = Vec::new();
+        for (offset, byte) in bytes.iter().enumerate() {
+            let instruction_address = address + offset as u64;
+            let mut control_flow_graph = il::ControlFlowGraph::new();
+            let block_index = {
+                let block = control_flow_graph.new_block()?;
+                match *byte {
+                    0x00 => block.branch(il::expr_scalar("ra", 64)),
+                    0x01 => block.nop(),
+                    0x02 => block.load(
+                        il::scalar("t", 32),
+                        il::Expression::add(
+                            il::expr_const(0x3000, 64),
+                            il::Expression::mul(i(), il::expr_const(4, 64))?,
+                        )?,
+                    ),
+                    0x03 => block.branch(il::Expression::zext(64, il::expr_scalar("t", 32))?),
+                    0x04 => block.assign(
+                        il::scalar("rsp", 64),
+                        il::Expression::add(
+                            il::expr_const(0x3000, 64),
+                            il::Expression::mul(i(), il::expr_const(4, 64))?,
+                        )?,
+                    ),
+                    0x05 => block.load(il::scalar("t", 32), il::expr_scalar("rsp", 64)),
+                    0xff => bail!("Invalid instruction at 0x{:x}", instruction_address),
+                    byte => block.assign(il::scalar("r", 64), il::expr_const(byte as u64, 64)),
+                }
+                block.index()
+            };
+            control_flow_graph.set_entry(block_index)?;
+            control_flow_graph.set_exit(block_index)?;
+            control_flow_graph.set_address(Some(instruction_address));
+            instructions.push((instruction_address, control_flow_graph));
+            match *byte {
+                0x00 | 0x03 => break,
+                0x01 => {
+                    let in_bounds = il::Expression::cmpltu(i(), il::expr_const(3, 64))?;
+                    successors.push((instruction_address + 1, Some(in_bounds.clone())));
+                    successors.push((
+                        0x1100,
+                        Some(il::Expression::cmpeq(in_bounds, il::expr_const(0, 1))?),
+                    ));
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let length = instructions.len();
+        Ok(crate::translator::BlockTranslationResult::new(
+            instructions,
+            address,
+            length,
+            successors,
+        ))
+    }
+}
+
+#[cfg(test)]
+impl Architecture for Switch {
+    fn name(&self) -> &str {
+        "switch"
+    }
+    fn endian(&self) -> Endian {
+        Endian::Little
+    }
+    fn translator(&self) -> Box<dyn crate::translator::Translator> {
+        Box::new(Switch)
+    }
+    fn calling_convention(&self) -> crate::analysis::calling_convention::CallingConvention {
+        crate::architecture::Amd64::new().calling_convention()
+    }
+    fn stack_pointer(&self) -> il::Scalar {
+        crate::architecture::Amd64::new().stack_pointer()
+    }
+    fn word_size(&self) -> usize {
+        64
+    }
+    fn box_clone(&self) -> Box<dyn Architecture> {
+        Box::new(Switch)
+    }
+}
+
+#[cfg(test)]
+fn switch_memory(table_permissions: MemoryPermissions) -> crate::memory::backing::Memory {
+    let code = MemoryPermissions::READ | MemoryPermissions::EXECUTE;
+    let mut memory = crate::memory::backing::Memory::new(Endian::Little);
+    memory.set_memory(0x1000, vec![0x01, 0x02, 0x03], code);
+    memory.set_memory(0x1100, vec![0x00], code);
+    memory.set_memory(0x1200, vec![0x41, 0x00], code);
+    memory.set_memory(0x1300, vec![0x42, 0x00], code);
+    memory.set_memory(0x1400, vec![0x43, 0x00], code);
+    memory.set_memory(
+        0x3000,
+        vec![
+            0x00, 0x12, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x13, 0x00, 0x00,
+        ],
+        table_permissions,
+    );
+    memory
+}
+
+#[test]
+fn jump_tables_test() {
+    use crate::translator::Translator;
+
+    let memory = switch_memory(MemoryPermissions::READ);
+    let function = Switch.translate_function(&memory, 0x1000).unwrap();
+
+    let tables = jump_tables(&function, &Switch, &memory).unwrap();
+    assert_eq!(tables.len(), 1);
+    assert_eq!(tables[0].address(), 0x1002);
+    assert_eq!(tables[0].table(), 0x3000);
+    assert_eq!(tables[0].targets(), &[0x1200, 0x1300, 0x1400]);
+
+    // A table which may be written holds no known targets.
+    let writable = switch_memory(MemoryPermissions::READ | MemoryPermissions::WRITE);
+    assert!(jump_tables(&function, &Switch, &writable)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn jump_tables_stack_test() {
+    use crate::translator::Translator;
+
+    // The same table, with its entry loaded through the stack pointer, is
+    // taken to be a return address.
+    let mut memory = switch_memory(MemoryPermissions::READ);
+    memory.set_memory(
+        0x1000,
+        vec![0x01, 0x04, 0x05, 0x03],
+        MemoryPermissions::READ | MemoryPermissions::EXECUTE,
+    );
+    let function = Switch.translate_function(&memory, 0x1000).unwrap();
+
+    assert!(jump_tables(&function, &Switch, &memory).unwrap().is_empty());
+}
+
+#[test]
+fn translate_function_jump_tables_test() {
+    let memory = switch_memory(MemoryPermissions::READ);
+    let function = translate_function(&Switch, &memory, 0x1000).unwrap();
+
+    let instructions: Vec<&il::Instruction> = function
+        .blocks()
+        .into_iter()
+        .flat_map(|block| block.instructions())
+        .collect();
+    for address in [0x1200, 0x1300, 0x1400] {
+        assert!(instructions
+            .iter()
+            .any(|instruction| instruction.address() == Some(address)));
+    }
+
+    // The branch is now a nop, followed by an edge to each target.
+    let branch = instructions
+        .iter()
+        .find(|instruction| instruction.address() == Some(0x1002))
+        .unwrap();
+    assert!(!branch.is_branch());
+
+    let block = function
+        .blocks()
+        .into_iter()
+        .find(|block| {
+            block
+                .instructions()
+                .iter()
+                .any(|instruction| instruction.address() == Some(0x1002))
+        })
+        .unwrap();
+    let edges = function
+        .control_flow_graph()
+        .edges_out(block.index())
+        .unwrap();
+    assert_eq!(edges.len(), 3);
+    assert!(edges.iter().all(|edge| edge.condition().is_some()));
+}
+
+#[test]
+fn translate_function_unresolved_test() {
+    // A target which cannot be lifted leaves the branch unresolved.
+    let mut memory = switch_memory(MemoryPermissions::READ);
+    memory.set_memory(
+        0x1300,
+        vec![0xff],
+        MemoryPermissions::READ | MemoryPermissions::EXECUTE,
+    );
+    let function = translate_function(&Switch, &memory, 0x1000).unwrap();
+
+    let branch = function
+        .blocks()
+        .into_iter()
+        .flat_map(|block| block.instructions())
+        .find(|instruction| instruction.address() == Some(0x1002))
+        .unwrap();
+    assert!(branch.is_branch());
+}
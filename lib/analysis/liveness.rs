@@ -0,0 +1,602 @@
+//! Liveness analysis.
+//!
+//! A scalar is live at a location if its value may be read before it is next
+//! written. Liveness is computed backwards over a function, with the
+//! `CallingConvention` of an `Architecture` giving the scalars read and
+//! written at `Operation::Branch`:
+//!
+//! * A call, which is a branch with a successor, or to a constant target,
+//!   reads the argument registers and the stack pointer, and writes the
+//!   registers the calling convention trashes.
+//! * A return, which is a branch to a non-constant target with no successor,
+//!   reads the scalars of its target.
+//! * Leaving the function, the return register, the preserved registers and
+//!   the stack pointer are live.
+//!
+//! `liveness_program` follows calls to the functions of a program: a call to
+//! a function of the program reads the stack pointer, and the scalars live at
+//! entry to the callee which the calling convention does not preserve.
+//!
+//! Intrinsics which do not give the scalars they read are assumed to read
+//! every scalar of the function, and intrinsics which do not give the scalars
+//! they write are assumed to write none.
+
+use crate::analysis::fixed_point;
+use crate::analysis::interprocedural::is_call;
+use crate::architecture::Architecture;
+use crate::error::*;
+use crate::executor::eval;
+use crate::il;
+use std::cmp::{Ordering, PartialOrd};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Compute liveness for the given function.
+pub fn liveness(function: &il::Function, architecture: &dyn Architecture) -> Result<Liveness> {
+    function_liveness(function, &LivenessAnalysis::new(architecture))
+}
+
+/// Compute liveness for every function in a program, following calls to the
+/// functions of the program.
+pub fn liveness_program(
+    program: &il::Program,
+    architecture: &dyn Architecture,
+) -> Result<BTreeMap<usize, Liveness>> {
+    let mut analysis = LivenessAnalysis::new(architecture);
+    let preserved = architecture
+        .calling_convention()
+        .preserved_registers()
+        .clone();
+
+    // The scalars read by calls grow from none until no summary changes.
+    loop {
+        let mut livenesses = BTreeMap::new();
+        let mut callees = HashMap::new();
+        for (index, function) in program.functions_map() {
+            let liveness = function_liveness(function, &analysis)?;
+            let entry = function
+                .control_flow_graph()
+                .entry()
+                .and_then(|entry| liveness.block_live_in(entry))
+                .map(|live_in| live_in.difference(&preserved).cloned().collect())
+                .unwrap_or_default();
+            callees.insert(function.address(), entry);
+            livenesses.insert(index, liveness);
+        }
+        if callees == analysis.callees {
+            return Ok(livenesses);
+        }
+        analysis.callees = callees;
+    }
+}
+
+fn function_liveness(function: &il::Function, analysis: &LivenessAnalysis) -> Result<Liveness> {
+    let mut analysis = analysis.clone();
+    analysis.scalars = function_scalars(function, &analysis);
+
+    let live_in = fixed_point::fixed_point_backward(analysis.clone(), function)?;
+
+    let mut liveness = Liveness {
+        live_in: HashMap::new(),
+        live_out: HashMap::new(),
+        block_live_in: HashMap::new(),
+        block_live_out: HashMap::new(),
+    };
+
+    for (location, state) in &live_in {
+        let successors = location.forward()?;
+        let live_out = if successors.is_empty() {
+            analysis.exit.clone()
+        } else {
+            successors
+                .iter()
+                .filter_map(|successor| live_in.get(successor))
+                .fold(HashSet::new(), |mut live_out, state| {
+                    live_out.extend(state.scalars.iter().cloned());
+                    live_out
+                })
+        };
+        liveness
+            .live_in
+            .insert(location.clone().into(), state.scalars.clone());
+        liveness.live_out.insert(location.clone().into(), live_out);
+    }
+
+    for block in function.blocks() {
+        let (first, last) = match (block.instructions().first(), block.instructions().last()) {
+            (Some(first), Some(last)) => (
+                il::RefFunctionLocation::Instruction(block, first),
+                il::RefFunctionLocation::Instruction(block, last),
+            ),
+            _ => (
+                il::RefFunctionLocation::EmptyBlock(block),
+                il::RefFunctionLocation::EmptyBlock(block),
+            ),
+        };
+        let first: il::ProgramLocation = il::RefProgramLocation::new(function, first).into();
+        let last: il::ProgramLocation = il::RefProgramLocation::new(function, last).into();
+        if let Some(live_in) = liveness.live_in.get(&first) {
+            liveness
+                .block_live_in
+                .insert(block.index(), live_in.clone());
+        }
+        if let Some(live_out) = liveness.live_out.get(&last) {
+            liveness
+                .block_live_out
+                .insert(block.index(), live_out.clone());
+        }
+    }
+
+    Ok(liveness)
+}
+
+/// Every scalar named in a function, or read or written by its calls, or
+/// live when leaving it.
+fn function_scalars(function: &il::Function, analysis: &LivenessAnalysis) -> HashSet<il::Scalar> {
+    let mut scalars = HashSet::new();
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let operation = instruction.operation();
+            if let il::Operation::Intrinsic { ref intrinsic } = *operation {
+                for argument in intrinsic.arguments() {
+                    scalars.extend(argument.scalars().into_iter().cloned());
+                }
+            }
+            if let Some(scalars_read) = operation.scalars_read() {
+                scalars.extend(scalars_read.into_iter().cloned());
+            }
+            if let Some(scalars_written) = operation.scalars_written() {
+                scalars.extend(scalars_written.into_iter().cloned());
+            }
+        }
+    }
+    for edge in function.edges() {
+        if let Some(condition) = edge.condition() {
+            scalars.extend(condition.scalars().into_iter().cloned());
+        }
+    }
+    scalars.extend(analysis.trashed.iter().cloned());
+    scalars.extend(analysis.arguments.iter().cloned());
+    scalars.extend(analysis.exit.iter().cloned());
+    for callee in analysis.callees.values() {
+        scalars.extend(callee.iter().cloned());
+    }
+    scalars
+}
+
+/// The scalars live before, and after, each location and block of a
+/// function.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Liveness {
+    live_in: HashMap<il::ProgramLocation, HashSet<il::Scalar>>,
+    live_out: HashMap<il::ProgramLocation, HashSet<il::Scalar>>,
+    block_live_in: HashMap<usize, HashSet<il::Scalar>>,
+    block_live_out: HashMap<usize, HashSet<il::Scalar>>,
+}
+
+impl Liveness {
+    /// Get the scalars live before a location.
+    pub fn live_in(&self, location: &il::ProgramLocation) -> Option<&HashSet<il::Scalar>> {
+        self.live_in.get(location)
+    }
+
+    /// Get the scalars live after a location.
+    pub fn live_out(&self, location: &il::ProgramLocation) -> Option<&HashSet<il::Scalar>> {
+        self.live_out.get(location)
+    }
+
+    /// Get the scalars live at entry to a block.
+    pub fn block_live_in(&self, block_index: usize) -> Option<&HashSet<il::Scalar>> {
+        self.block_live_in.get(&block_index)
+    }
+
+    /// Get the scalars live at exit from a block.
+    pub fn block_live_out(&self, block_index: usize) -> Option<&HashSet<il::Scalar>> {
+        self.block_live_out.get(&block_index)
+    }
+
+    /// Returns true if a scalar is live after a location.
+    pub fn is_live_out(&self, location: &il::ProgramLocation, scalar: &il::Scalar) -> bool {
+        self.live_out
+            .get(location)
+            .is_some_and(|live_out| live_out.contains(scalar))
+    }
+
+    /// Get the scalars live before each location.
+    pub fn live_in_map(&self) -> &HashMap<il::ProgramLocation, HashSet<il::Scalar>> {
+        &self.live_in
+    }
+
+    /// Get the scalars live after each location.
+    pub fn live_out_map(&self) -> &HashMap<il::ProgramLocation, HashSet<il::Scalar>> {
+        &self.live_out
+    }
+}
+
+/// A set of live scalars, ordered by inclusion.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct LiveScalars {
+    scalars: HashSet<il::Scalar>,
+}
+
+impl LiveScalars {
+    fn kill<'s, I: IntoIterator<Item = &'s il::Scalar>>(&mut self, scalars: I) {
+        for scalar in scalars {
+            self.scalars.remove(scalar);
+        }
+    }
+
+    fn gen<'s, I: IntoIterator<Item = &'s il::Scalar>>(&mut self, scalars: I) {
+        self.scalars.extend(scalars.into_iter().cloned());
+    }
+}
+
+impl PartialOrd for LiveScalars {
+    fn partial_cmp(&self, other: &LiveScalars) -> Option<Ordering> {
+        if self.scalars == other.scalars {
+            Some(Ordering::Equal)
+        } else if self.scalars.is_subset(&other.scalars) {
+            Some(Ordering::Less)
+        } else if self.scalars.is_superset(&other.scalars) {
+            Some(Ordering::Greater)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone)]
+struct LivenessAnalysis {
+    /// The registers which may be written by a call.
+    trashed: HashSet<il::Scalar>,
+    /// The scalars read by a call.
+    arguments: HashSet<il::Scalar>,
+    /// The scalars live when leaving the function.
+    exit: HashSet<il::Scalar>,
+    stack_pointer: il::Scalar,
+    /// Every scalar of the function, which are all read by an intrinsic
+    /// which does not give the scalars it reads.
+    scalars: HashSet<il::Scalar>,
+    /// The scalars read by calls to the functions of a program, by address.
+    callees: HashMap<u64, HashSet<il::Scalar>>,
+}
+
+impl LivenessAnalysis {
+    fn new(architecture: &dyn Architecture) -> LivenessAnalysis {
+        let calling_convention = architecture.calling_convention();
+
+        let mut arguments: HashSet<il::Scalar> = calling_convention
+            .argument_registers()
+            .iter()
+            .cloned()
+            .collect();
+        arguments.insert(architecture.stack_pointer());
+
+        let mut exit = calling_convention.preserved_registers().clone();
+        exit.insert(calling_convention.return_register().clone());
+        exit.insert(architecture.stack_pointer());
+
+        LivenessAnalysis {
+            trashed: calling_convention.trashed_registers().clone(),
+            arguments,
+            exit,
+            stack_pointer: architecture.stack_pointer(),
+            scalars: HashSet::new(),
+            callees: HashMap::new(),
+        }
+    }
+}
+
+impl<'f> fixed_point::FixedPointAnalysis<'f, LiveScalars> for LivenessAnalysis {
+    fn trans(
+        &self,
+        location: il::RefProgramLocation<'f>,
+        state: Option<LiveScalars>,
+    ) -> Result<LiveScalars> {
+        let is_exit = location.forward()?.is_empty();
+        let mut state = match state {
+            Some(state) => state,
+            None => {
+                if is_exit {
+                    LiveScalars {
+                        scalars: self.exit.clone(),
+                    }
+                } else {
+                    LiveScalars::default()
+                }
+            }
+        };
+
+        match *location.function_location() {
+            il::RefFunctionLocation::Instruction(_, instruction) => {
+                match *instruction.operation() {
+                    il::Operation::Branch { ref target } => {
+                        let callee = eval(target)
+                            .ok()
+                            .and_then(|target| target.value_u64())
+                            .and_then(|target| self.callees.get(&target));
+                        if let Some(callee) = callee {
+                            state.kill(&self.trashed);
+                            state.gen(callee);
+                            state.gen(Some(&self.stack_pointer));
+                        } else if is_call(&location, target)? {
+                            state.kill(&self.trashed);
+                            state.gen(&self.arguments);
+                        }
+                        state.gen(target.scalars());
+                    }
+                    ref operation => {
+                        if let Some(scalars_written) = operation.scalars_written() {
+                            state.kill(scalars_written);
+                        }
+                        match operation.scalars_read() {
+                            Some(scalars_read) => state.gen(scalars_read),
+                            None => state.gen(&self.scalars),
+                        }
+                    }
+                }
+            }
+            il::RefFunctionLocation::Edge(edge) => {
+                if let Some(condition) = edge.condition() {
+                    state.gen(condition.scalars());
+                }
+            }
+            il::RefFunctionLocation::EmptyBlock(_) => {}
+        }
+
+        Ok(state)
+    }
+
+    fn join(&self, mut state0: LiveScalars, state1: &LiveScalars) -> Result<LiveScalars> {
+        state0.gen(&state1.scalars);
+        Ok(state0)
+    }
+}
+
+#[test]
+fn liveness_test() {
+    /*
+    a = in
+    b = 4
+    dead = 7
+    if a < 10 {
+        c = a
+    }
+    else {
+        c = b
+    }
+    rdi = c
+    call 0x4000
+    return
+    */
+    use crate::architecture::Amd64;
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let head_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("a", 64), il::expr_scalar("in", 64));
+        block.assign(il::scalar("b", 64), il::expr_const(4, 64));
+        block.assign(il::scalar("dead", 64), il::expr_const(7, 64));
+        block.index()
+    };
+
+    let lt_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("c", 64), il::expr_scalar("a", 64));
+        block.index()
+    };
+
+    let gt_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("c", 64), il::expr_scalar("b", 64));
+        block.index()
+    };
+
+    let call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rdi", 64), il::expr_scalar("c", 64));
+        block.branch(il::expr_const(0x4000, 64));
+        block.index()
+    };
+
+    let return_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.branch(il::expr_scalar("ret", 64));
+        block.index()
+    };
+
+    let condition =
+        il::Expression::cmpltu(il::expr_scalar("a", 64), il::expr_const(10, 64)).unwrap();
+    control_flow_graph
+        .conditional_edge(head_index, lt_index, condition.clone())
+        .unwrap();
+    control_flow_graph
+        .conditional_edge(
+            head_index,
+            gt_index,
+            il::Expression::cmpeq(condition, il::expr_const(0, 1)).unwrap(),
+        )
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(lt_index, call_index)
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(gt_index, call_index)
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(call_index, return_index)
+        .unwrap();
+    control_flow_graph.set_entry(head_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+    let liveness = liveness(&function, &Amd64::new()).unwrap();
+
+    let location = |block_index, instruction_index| {
+        il::ProgramLocation::new(
+            None,
+            il::FunctionLocation::Instruction(block_index, instruction_index),
+        )
+    };
+    let scalar = |name: &str| il::scalar(name, 64);
+
+    let entry = liveness.block_live_in(head_index).unwrap();
+    assert!(entry.contains(&scalar("in")));
+    assert!(entry.contains(&scalar("rbx")));
+    assert!(entry.contains(&scalar("ret")));
+    assert!(!entry.contains(&scalar("a")));
+    assert!(!entry.contains(&scalar("rax")));
+
+    assert!(!liveness.is_live_out(&location(head_index, 2), &scalar("dead")));
+    assert!(liveness.is_live_out(&location(head_index, 1), &scalar("b")));
+    assert!(liveness
+        .block_live_out(head_index)
+        .unwrap()
+        .contains(&scalar("a")));
+
+    let lt = liveness.block_live_in(lt_index).unwrap();
+    assert!(lt.contains(&scalar("a")));
+    assert!(!lt.contains(&scalar("b")));
+    let gt = liveness.block_live_in(gt_index).unwrap();
+    assert!(gt.contains(&scalar("b")));
+    assert!(!gt.contains(&scalar("a")));
+
+    // The call reads its arguments and writes the trashed registers.
+    let call = liveness.live_in(&location(call_index, 1)).unwrap();
+    assert!(call.contains(&scalar("rdi")));
+    assert!(call.contains(&scalar("rsp")));
+    assert!(!call.contains(&scalar("rax")));
+    assert!(liveness.is_live_out(&location(call_index, 1), &scalar("rax")));
+    assert!(!liveness
+        .live_in(&location(call_index, 0))
+        .unwrap()
+        .contains(&scalar("rdi")));
+
+    // Leaving the function reads the return register and preserved registers.
+    let exit = liveness.live_in(&location(return_index, 0)).unwrap();
+    assert!(exit.contains(&scalar("ret")));
+    assert!(exit.contains(&scalar("rax")));
+    assert!(exit.contains(&scalar("r12")));
+    assert!(!exit.contains(&scalar("rdi")));
+}
+
+#[test]
+fn liveness_program_test() {
+    /*
+    0x1000:
+        rdi = 1
+        rsi = 2
+        call 0x2000
+        return
+
+    0x2000:
+        rax = rsi
+        return
+    */
+    use crate::architecture::Amd64;
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rdi", 64), il::expr_const(1, 64));
+        block.assign(il::scalar("rsi", 64), il::expr_const(2, 64));
+        block.branch(il::expr_const(0x2000, 64));
+        block.index()
+    };
+    let return_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.branch(il::expr_scalar("ret", 64));
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(call_index, return_index)
+        .unwrap();
+    control_flow_graph.set_entry(call_index).unwrap();
+    let main = il::Function::new(0x1000, control_flow_graph);
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let block_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rax", 64), il::expr_scalar("rsi", 64));
+        block.branch(il::expr_scalar("ret", 64));
+        block.index()
+    };
+    control_flow_graph.set_entry(block_index).unwrap();
+    let callee = il::Function::new(0x2000, control_flow_graph);
+
+    let mut program = il::Program::new();
+    program.add_function(main);
+    program.add_function(callee);
+
+    let location = |instruction_index| {
+        il::ProgramLocation::new(
+            Some(0),
+            il::FunctionLocation::Instruction(call_index, instruction_index),
+        )
+    };
+    let scalar = |name: &str| il::scalar(name, 64);
+
+    // Every argument register is read by a call to an unknown function.
+    let main_liveness = liveness(program.function(0).unwrap(), &Amd64::new()).unwrap();
+    assert!(main_liveness.is_live_out(&location(0), &scalar("rdi")));
+
+    // The callee reads only rsi.
+    let livenesses = liveness_program(&program, &Amd64::new()).unwrap();
+    let main_liveness = &livenesses[&0];
+    assert!(!main_liveness.is_live_out(&location(0), &scalar("rdi")));
+    assert!(main_liveness.is_live_out(&location(1), &scalar("rsi")));
+    let call = main_liveness.live_in(&location(2)).unwrap();
+    assert!(call.contains(&scalar("rsp")));
+    assert!(call.contains(&scalar("ret")));
+    assert!(!call.contains(&scalar("rdi")));
+}
+
+#[test]
+fn liveness_intrinsic_test() {
+    /*
+    a = 1
+    b = 2
+    cpuid
+    b = 3
+    return
+    */
+    use crate::architecture::Amd64;
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let block_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("a", 64), il::expr_const(1, 64));
+        block.assign(il::scalar("b", 64), il::expr_const(2, 64));
+        block.intrinsic(il::Intrinsic::new(
+            "cpuid",
+            "cpuid",
+            Vec::new(),
+            None,
+            None,
+            Vec::new(),
+        ));
+        block.assign(il::scalar("b", 64), il::expr_const(3, 64));
+        block.branch(il::expr_scalar("ret", 64));
+        block.index()
+    };
+    control_flow_graph.set_entry(block_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+    let liveness = liveness(&function, &Amd64::new()).unwrap();
+
+    let location = |instruction_index| {
+        il::ProgramLocation::new(
+            None,
+            il::FunctionLocation::Instruction(block_index, instruction_index),
+        )
+    };
+    let scalar = |name: &str| il::scalar(name, 64);
+
+    // The intrinsic may read any scalar of the function.
+    let intrinsic = liveness.live_in(&location(2)).unwrap();
+    assert!(intrinsic.contains(&scalar("a")));
+    assert!(intrinsic.contains(&scalar("b")));
+    assert!(intrinsic.contains(&scalar("rdi")));
+    assert!(liveness.is_live_out(&location(0), &scalar("a")));
+    assert!(liveness.is_live_out(&location(1), &scalar("b")));
+    assert!(!liveness.is_live_out(&location(3), &scalar("b")));
+}
@@ -1,14 +1,24 @@
 //! Implementations and traits for static analysis over Falcon IL.
 
+pub mod call_graph;
 pub mod calling_convention;
 pub mod constants;
 mod dead_code_elimination;
 mod def_use;
 pub mod fixed_point;
+pub mod interprocedural;
+pub mod jump_tables;
+pub mod liveness;
 mod location_set;
 mod reaching_definitions;
+pub mod signature;
+pub mod slicing;
+pub mod stack_frame;
 pub mod stack_pointer_offsets;
+pub mod taint;
+pub mod types;
 mod use_def;
+pub mod value_set;
 
 pub use self::dead_code_elimination::dead_code_elimination;
 pub use self::def_use::def_use;
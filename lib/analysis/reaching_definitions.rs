@@ -1,71 +1,158 @@
+use crate::analysis::calling_convention::CallingConvention;
+use crate::analysis::interprocedural::is_call;
 use crate::analysis::{fixed_point, LocationSet};
 use crate::error::*;
 use crate::il;
-use std::collections::HashMap;
+use std::cmp::{Ordering, PartialOrd};
+use std::collections::{BTreeSet, HashMap};
 
 /// Compute reaching definitions for the given function.
 pub fn reaching_definitions<'r>(
     function: &'r il::Function,
 ) -> Result<HashMap<il::ProgramLocation, LocationSet>> {
-    let rda = ReachingDefinitionsAnalysis { function: function };
+    Ok(scalar_reaching_definitions(function, None)?
+        .into_iter()
+        .map(|(location, definitions)| (location, definitions.locations()))
+        .collect())
+}
+
+/// Compute the definitions of each scalar which reach the locations of the
+/// given function.
+///
+/// With a calling convention, calls define the registers it trashes, and the
+/// return register.
+pub(crate) fn scalar_reaching_definitions(
+    function: &il::Function,
+    calling_convention: Option<&CallingConvention>,
+) -> Result<HashMap<il::ProgramLocation, Definitions>> {
+    let rda = ReachingDefinitionsAnalysis { calling_convention };
     fixed_point::fixed_point_forward(rda, function)
 }
 
+/// The definitions of each scalar which may reach a location, where `None`
+/// is the value of the scalar at function entry. Scalars without definitions
+/// hold their value at function entry.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Definitions {
+    definitions: HashMap<il::Scalar, BTreeSet<Option<il::ProgramLocation>>>,
+}
+
+impl Definitions {
+    /// Get the definitions of a scalar.
+    pub(crate) fn get(&self, scalar: &il::Scalar) -> BTreeSet<Option<il::ProgramLocation>> {
+        match self.definitions.get(scalar) {
+            Some(definitions) => definitions.clone(),
+            None => vec![None].into_iter().collect(),
+        }
+    }
+
+    fn define(&mut self, scalar: il::Scalar, location: il::ProgramLocation) {
+        self.definitions
+            .insert(scalar, vec![Some(location)].into_iter().collect());
+    }
+
+    /// Join the definitions of another path into these definitions.
+    pub(crate) fn join(&mut self, other: &Definitions) {
+        for scalar in other.definitions.keys() {
+            let mut definitions = self.get(scalar);
+            definitions.extend(other.get(scalar));
+            self.definitions.insert(scalar.clone(), definitions);
+        }
+        for (scalar, definitions) in self.definitions.iter_mut() {
+            if !other.definitions.contains_key(scalar) {
+                definitions.insert(None);
+            }
+        }
+    }
+
+    /// The locations of the definitions of every scalar.
+    fn locations(&self) -> LocationSet {
+        let mut locations = LocationSet::new();
+        self.definitions
+            .values()
+            .flatten()
+            .flatten()
+            .for_each(|location| locations.insert(location.clone()));
+        locations
+    }
+
+    fn is_subset(&self, other: &Definitions) -> bool {
+        self.definitions
+            .keys()
+            .chain(other.definitions.keys())
+            .all(|scalar| self.get(scalar).is_subset(&other.get(scalar)))
+    }
+}
+
+impl PartialEq for Definitions {
+    fn eq(&self, other: &Definitions) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Definitions {
+    fn partial_cmp(&self, other: &Definitions) -> Option<Ordering> {
+        match (self.is_subset(other), other.is_subset(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (false, false) => None,
+        }
+    }
+}
+
 // We require a struct to implement methods for our analysis over.
-struct ReachingDefinitionsAnalysis<'r> {
-    function: &'r il::Function,
+struct ReachingDefinitionsAnalysis<'c> {
+    calling_convention: Option<&'c CallingConvention>,
 }
 
-impl<'r> fixed_point::FixedPointAnalysis<'r, LocationSet> for ReachingDefinitionsAnalysis<'r> {
-    fn trans(
-        &self,
-        location: il::RefProgramLocation<'r>,
-        state: Option<LocationSet>,
-    ) -> Result<LocationSet> {
-        let mut state = match state {
-            Some(state) => state,
-            None => LocationSet::new(),
+impl<'c> ReachingDefinitionsAnalysis<'c> {
+    /// The scalars defined at a location.
+    fn written(&self, location: &il::RefProgramLocation) -> Result<Vec<il::Scalar>> {
+        let instruction = match *location.function_location() {
+            il::RefFunctionLocation::Instruction(_, instruction) => instruction,
+            il::RefFunctionLocation::EmptyBlock(_) | il::RefFunctionLocation::Edge(_) => {
+                return Ok(Vec::new())
+            }
         };
-
-        match *location.function_location() {
-            il::RefFunctionLocation::Instruction(_, ref instruction) => {
-                instruction
-                    .operation()
-                    .scalars_written()
-                    .into_iter()
-                    .for_each(|scalar_written| {
-                        let kill: Vec<il::ProgramLocation> = state
-                            .locations()
-                            .into_iter()
-                            .filter(|location| {
-                                location
-                                    .function_location()
-                                    .apply(self.function)
-                                    .unwrap()
-                                    .instruction()
-                                    .unwrap()
-                                    .operation()
-                                    .scalars_written()
-                                    .into_iter()
-                                    .any(|scalar| scalar == scalar_written)
-                            })
-                            .cloned()
-                            .collect();
-                        kill.iter().for_each(|location| state.remove(&location));
-                        state.insert(location.clone().into());
-                    });
+        if let (il::Operation::Branch { ref target }, Some(calling_convention)) =
+            (instruction.operation(), self.calling_convention)
+        {
+            if is_call(location, target)? {
+                let mut written: Vec<il::Scalar> = calling_convention
+                    .trashed_registers()
+                    .iter()
+                    .cloned()
+                    .collect();
+                written.push(calling_convention.return_register().clone());
+                return Ok(written);
             }
-            il::RefFunctionLocation::EmptyBlock(_) | il::RefFunctionLocation::Edge(_) => {}
         }
+        Ok(instruction
+            .operation()
+            .scalars_written()
+            .unwrap_or_default()
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+}
 
+impl<'r, 'c> fixed_point::FixedPointAnalysis<'r, Definitions> for ReachingDefinitionsAnalysis<'c> {
+    fn trans(
+        &self,
+        location: il::RefProgramLocation<'r>,
+        state: Option<Definitions>,
+    ) -> Result<Definitions> {
+        let mut state = state.unwrap_or_default();
+        for scalar in self.written(&location)? {
+            state.define(scalar, location.clone().into());
+        }
         Ok(state)
     }
 
-    fn join(&self, mut state0: LocationSet, state1: &LocationSet) -> Result<LocationSet> {
-        state1
-            .locations()
-            .into_iter()
-            .for_each(|location| state0.insert(location.clone()));
+    fn join(&self, mut state0: Definitions, state1: &Definitions) -> Result<Definitions> {
+        state0.join(state1);
         Ok(state0)
     }
 }
@@ -209,3 +296,56 @@ fn reaching_definitions_test() {
         .into()
     ));
 }
+
+#[test]
+fn reaching_definitions_call_test() {
+    /*
+    rax = 1
+    call 0x2000
+    rbx = rax
+    */
+    use crate::architecture::{Amd64, Architecture};
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rax", 64), il::expr_const(1, 64));
+        block.branch(il::expr_const(0x2000, 64));
+        block.index()
+    };
+    let tail_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rbx", 64), il::expr_scalar("rax", 64));
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(call_index, tail_index)
+        .unwrap();
+    control_flow_graph.set_entry(call_index).unwrap();
+    let function = il::Function::new(0x1000, control_flow_graph);
+
+    let block = function.control_flow_graph().block(call_index).unwrap();
+    let assign: il::ProgramLocation = il::RefProgramLocation::new(
+        &function,
+        il::RefFunctionLocation::Instruction(block, block.instruction(0).unwrap()),
+    )
+    .into();
+    let call: il::ProgramLocation = il::RefProgramLocation::new(
+        &function,
+        il::RefFunctionLocation::Instruction(block, block.instruction(1).unwrap()),
+    )
+    .into();
+
+    // Without a calling convention, the call defines nothing.
+    let rd = reaching_definitions(&function).unwrap();
+    assert!(rd[&call].contains(&assign));
+    assert!(!rd[&call].contains(&call));
+
+    // With a calling convention, the call defines the return register.
+    let calling_convention = Amd64::new().calling_convention();
+    let rd = scalar_reaching_definitions(&function, Some(&calling_convention)).unwrap();
+    let rax = rd[&call].get(&il::scalar("rax", 64));
+    assert_eq!(rax, vec![Some(call.clone())].into_iter().collect());
+    let rbx = rd[&call].get(&il::scalar("rbx", 64));
+    assert_eq!(rbx, vec![None].into_iter().collect());
+}
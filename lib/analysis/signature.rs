@@ -0,0 +1,686 @@
+//! Function argument and return value recovery.
+//!
+//! The `Signature` of a function is recovered with the `CallingConvention` of
+//! an `Architecture`:
+//!
+//! * The arguments are the argument registers, and the stack slots above the
+//!   return address, which the function reads before it writes them. Stack
+//!   slots are found with `value_sets`. The arity counts every argument
+//!   before the last argument read, as the calling convention passes
+//!   arguments in order.
+//! * The function returns a value if, on some path to every return, the
+//!   return register is written by an instruction, or is last written by a
+//!   call to a function which returns a value. Only `signatures` knows which
+//!   functions return a value, so `signature` counts only instructions.
+//! * The stack cleanup is the number of bytes the function pops from the
+//!   stack, beyond the return address, found with `stack_pointer_offsets`.
+//!
+//! A call is a branch with a successor, or to a constant target. Calls write
+//! the registers the calling convention trashes, and read no arguments.
+//!
+//! `signatures` recovers the signature of every function in a program, by
+//! function index. `il::Function` does not hold its signature, and this
+//! table is the way to find the signature of each function in a program.
+
+use crate::analysis::calling_convention::{ArgumentType, CallingConvention, ReturnAddressType};
+use crate::analysis::fixed_point;
+use crate::analysis::interprocedural::is_call;
+use crate::analysis::stack_pointer_offsets::stack_pointer_offsets;
+use crate::analysis::value_set::{value_sets, Region, ValueSets};
+use crate::architecture::Architecture;
+use crate::error::*;
+use crate::executor::eval;
+use crate::il;
+use std::cmp::{Ordering, PartialOrd};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The largest number of addresses a load or store may access before it is
+/// ignored.
+const MAX_ADDRESSES: u64 = 64;
+
+/// Recover the signature of a function.
+///
+/// Calls are not known to return a value, so the function returns a value
+/// only if an instruction may write the return register before every return.
+pub fn signature(function: &il::Function, architecture: &dyn Architecture) -> Result<Signature> {
+    signature_with(
+        function,
+        architecture,
+        &value_sets(function, architecture, &[])?,
+    )
+}
+
+/// Recover the signature of a function, with the `value_sets` already
+/// computed for it.
+pub fn signature_with(
+    function: &il::Function,
+    architecture: &dyn Architecture,
+    value_sets: &HashMap<il::ProgramLocation, ValueSets>,
+) -> Result<Signature> {
+    Ok(recover(function, architecture, value_sets)?.0)
+}
+
+/// Recover the signature of a function, and how its return register is
+/// written before each return.
+fn recover(
+    function: &il::Function,
+    architecture: &dyn Architecture,
+    value_sets: &HashMap<il::ProgramLocation, ValueSets>,
+) -> Result<(Signature, Vec<ReturnWrites>)> {
+    let calling_convention = architecture.calling_convention();
+    let stack_pointer = architecture.stack_pointer();
+
+    let analysis = InputAnalysis {
+        value_sets,
+        trashed: calling_convention.trashed_registers().clone(),
+        stack_argument_offset: calling_convention.stack_argument_offset() as i64,
+        bits: stack_pointer.bits(),
+    };
+    let inputs = fixed_point::fixed_point_backward(analysis, function)?;
+
+    let entry = match il::RefProgramLocation::from_function(function) {
+        Some(entry) => entry?,
+        None => bail!("Function {} has no entry", function.name()),
+    };
+    let entry_inputs = inputs
+        .get(&entry)
+        .map(|inputs| inputs.inputs.clone())
+        .unwrap_or_default();
+
+    let register_arguments: Vec<il::Scalar> = calling_convention
+        .argument_registers()
+        .iter()
+        .filter(|scalar| entry_inputs.contains(&Input::Scalar((*scalar).clone())))
+        .cloned()
+        .collect();
+    let mut stack_arguments: Vec<u64> = entry_inputs
+        .iter()
+        .filter_map(|input| match *input {
+            Input::Stack(offset) => Some(offset as u64),
+            Input::Scalar(_) => None,
+        })
+        .collect();
+    stack_arguments.sort_unstable();
+
+    let register_arity = calling_convention
+        .argument_registers()
+        .iter()
+        .rposition(|scalar| register_arguments.contains(scalar))
+        .map(|position| position + 1)
+        .unwrap_or(0);
+    let arity = match stack_arguments.last() {
+        Some(offset) => {
+            let length = calling_convention.stack_argument_length().max(1) as u64;
+            calling_convention.argument_registers().len()
+                + ((offset - calling_convention.stack_argument_offset() as u64) / length) as usize
+                + 1
+        }
+        None => register_arity,
+    };
+
+    let exits = exits(function)?;
+
+    let return_register = calling_convention.return_register();
+    let return_writes = exits
+        .iter()
+        .map(|exit| writes_before(function, exit, return_register))
+        .collect::<Result<Vec<ReturnWrites>>>()?;
+    let returns_value = returns_value(&return_writes, &HashSet::new());
+
+    let return_address_length = match *calling_convention.return_address_type() {
+        ReturnAddressType::Stack(offset) => (offset + stack_pointer.bits() / 8) as isize,
+        ReturnAddressType::Register(_) => 0,
+    };
+    let offsets = stack_pointer_offsets(function, architecture)?;
+    let mut stack_cleanup = None;
+    for (index, exit) in exits.iter().enumerate() {
+        let cleanup = offsets
+            .get(exit)
+            .and_then(|offset| offset.value())
+            .map(|offset| offset - return_address_length)
+            .filter(|cleanup| *cleanup >= 0)
+            .map(|cleanup| cleanup as usize);
+        if index == 0 {
+            stack_cleanup = cleanup;
+        } else if stack_cleanup != cleanup {
+            stack_cleanup = None;
+            break;
+        }
+    }
+
+    let signature = Signature {
+        arity,
+        register_arguments,
+        stack_arguments,
+        returns_value,
+        stack_cleanup,
+    };
+    Ok((signature, return_writes))
+}
+
+/// Recover the signature of every function in a program.
+///
+/// Functions whose signature cannot be recovered are omitted.
+pub fn signatures(
+    program: &il::Program,
+    architecture: &dyn Architecture,
+) -> Result<BTreeMap<usize, Signature>> {
+    let mut function_value_sets = BTreeMap::new();
+    for (index, function) in program.functions_map() {
+        match value_sets(function, architecture, &[]) {
+            Ok(value_sets) => {
+                function_value_sets.insert(index, value_sets);
+            }
+            Err(e) => warn!(
+                "Failed to recover the signature of function {}: {}",
+                function.name(),
+                e
+            ),
+        }
+    }
+    let function_value_sets = function_value_sets
+        .iter()
+        .map(|(index, value_sets)| (*index, value_sets))
+        .collect();
+    Ok(signatures_with(program, architecture, &function_value_sets))
+}
+
+/// Recover the signature of every function in a program with `value_sets`
+/// already computed for it, by function index.
+///
+/// Functions without value sets, or whose signature cannot be recovered, are
+/// omitted.
+pub(crate) fn signatures_with(
+    program: &il::Program,
+    architecture: &dyn Architecture,
+    value_sets: &BTreeMap<usize, &HashMap<il::ProgramLocation, ValueSets>>,
+) -> BTreeMap<usize, Signature> {
+    let mut recovered = BTreeMap::new();
+    for (index, function) in program.functions_map() {
+        let value_sets = match value_sets.get(&index) {
+            Some(value_sets) => *value_sets,
+            None => continue,
+        };
+        match recover(function, architecture, value_sets) {
+            Ok(recovered_signature) => {
+                recovered.insert(index, (function.address(), recovered_signature));
+            }
+            Err(e) => warn!(
+                "Failed to recover the signature of function {}: {}",
+                function.name(),
+                e
+            ),
+        }
+    }
+
+    // A call to a function which returns a value returns a value, and this
+    // may reveal more functions which return a value.
+    loop {
+        let returning: HashSet<u64> = recovered
+            .values()
+            .filter(|(_, (signature, _))| signature.returns_value)
+            .map(|(address, _)| *address)
+            .collect();
+        let mut changed = false;
+        for (_, (signature, return_writes)) in recovered.values_mut() {
+            if !signature.returns_value && returns_value(return_writes, &returning) {
+                signature.returns_value = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    recovered
+        .into_iter()
+        .map(|(index, (_, (signature, _)))| (index, signature))
+        .collect()
+}
+
+/// The arguments a function takes, and the values it returns.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Signature {
+    arity: usize,
+    register_arguments: Vec<il::Scalar>,
+    stack_arguments: Vec<u64>,
+    returns_value: bool,
+    stack_cleanup: Option<usize>,
+}
+
+impl Signature {
+    /// Get the number of arguments the function takes.
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Get the argument registers the function reads, in the order of the
+    /// calling convention.
+    pub fn register_arguments(&self) -> &[il::Scalar] {
+        &self.register_arguments
+    }
+
+    /// Get the offsets, at function entry, of the arguments on the stack the
+    /// function reads.
+    pub fn stack_arguments(&self) -> &[u64] {
+        &self.stack_arguments
+    }
+
+    /// Returns true if the function returns a value in the return register.
+    pub fn returns_value(&self) -> bool {
+        self.returns_value
+    }
+
+    /// Get the number of bytes of arguments the function pops from the
+    /// stack, if it is the same at every return.
+    pub fn stack_cleanup(&self) -> Option<usize> {
+        self.stack_cleanup
+    }
+
+    /// Get the type of each argument, by the given calling convention.
+    pub fn argument_types(&self, calling_convention: &CallingConvention) -> Vec<ArgumentType> {
+        (0..self.arity)
+            .map(|argument| calling_convention.argument_type(argument))
+            .collect()
+    }
+}
+
+/// The locations which return from a function.
+fn exits(function: &il::Function) -> Result<Vec<il::ProgramLocation>> {
+    let control_flow_graph = function.control_flow_graph();
+    let mut exits = Vec::new();
+    for block in control_flow_graph.blocks() {
+        if !control_flow_graph.edges_out(block.index())?.is_empty() {
+            continue;
+        }
+        if let Some(instruction) = block.instructions().last() {
+            if let il::Operation::Branch { ref target } = *instruction.operation() {
+                if eval(target).is_err() {
+                    exits.push(
+                        il::RefProgramLocation::new(
+                            function,
+                            il::RefFunctionLocation::Instruction(block, instruction),
+                        )
+                        .into(),
+                    );
+                }
+            }
+        }
+    }
+    Ok(exits)
+}
+
+/// How a scalar may be written before a location.
+#[derive(Clone, Debug, Default)]
+struct ReturnWrites {
+    /// An instruction may write the scalar on some path to the location.
+    written: bool,
+    /// The constant targets of the calls which last write the scalar on
+    /// other paths to the location.
+    calls: Vec<u64>,
+}
+
+/// Returns true if, before every return, the return register may be written
+/// by an instruction, or by a call to one of the given addresses of functions
+/// which return a value.
+fn returns_value(return_writes: &[ReturnWrites], returning: &HashSet<u64>) -> bool {
+    !return_writes.is_empty()
+        && return_writes.iter().all(|writes| {
+            writes.written || writes.calls.iter().any(|call| returning.contains(call))
+        })
+}
+
+/// Find how a scalar may be written on the paths from the entry of a function
+/// to a location, by instructions, and by the calls which write it last.
+fn writes_before(
+    function: &il::Function,
+    location: &il::ProgramLocation,
+    scalar: &il::Scalar,
+) -> Result<ReturnWrites> {
+    let mut writes = ReturnWrites::default();
+    let mut visited = HashSet::new();
+    let mut queue =
+        il::RefProgramLocation::new(function, location.function_location().apply(function)?)
+            .backward()?;
+    while let Some(location) = queue.pop() {
+        if !visited.insert(il::ProgramLocation::from(location.clone())) {
+            continue;
+        }
+        if let il::RefFunctionLocation::Instruction(_, instruction) = *location.function_location()
+        {
+            match *instruction.operation() {
+                il::Operation::Branch { ref target } => {
+                    if is_call(&location, target)? {
+                        if let Some(target) =
+                            eval(target).ok().and_then(|target| target.value_u64())
+                        {
+                            writes.calls.push(target);
+                        }
+                        continue;
+                    }
+                }
+                ref operation => {
+                    if operation
+                        .scalars_written()
+                        .is_some_and(|scalars_written| scalars_written.contains(&scalar))
+                    {
+                        writes.written = true;
+                        return Ok(writes);
+                    }
+                }
+            }
+        }
+        queue.append(&mut location.backward()?);
+    }
+    Ok(writes)
+}
+
+/// A value a function may read before writing it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Input {
+    Scalar(il::Scalar),
+    /// A stack slot, by its offset from the stack pointer at function entry.
+    Stack(i64),
+}
+
+/// The values read before they are written, ordered by inclusion.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Inputs {
+    inputs: HashSet<Input>,
+}
+
+impl PartialOrd for Inputs {
+    fn partial_cmp(&self, other: &Inputs) -> Option<Ordering> {
+        if self.inputs == other.inputs {
+            Some(Ordering::Equal)
+        } else if self.inputs.is_subset(&other.inputs) {
+            Some(Ordering::Less)
+        } else if self.inputs.is_superset(&other.inputs) {
+            Some(Ordering::Greater)
+        } else {
+            None
+        }
+    }
+}
+
+impl Inputs {
+    fn kill(&mut self, scalar: &il::Scalar) {
+        self.inputs.remove(&Input::Scalar(scalar.clone()));
+    }
+
+    fn gen<'s, I: IntoIterator<Item = &'s il::Scalar>>(&mut self, scalars: I) {
+        self.inputs.extend(
+            scalars
+                .into_iter()
+                .map(|scalar| Input::Scalar(scalar.clone())),
+        );
+    }
+}
+
+struct InputAnalysis<'a> {
+    value_sets: &'a HashMap<il::ProgramLocation, ValueSets>,
+    /// The registers which may be written by a call.
+    trashed: HashSet<il::Scalar>,
+    stack_argument_offset: i64,
+    /// The bits of the stack pointer.
+    bits: usize,
+}
+
+impl<'a> InputAnalysis<'a> {
+    /// The stack slots, at or above the first stack argument, an address may
+    /// point to.
+    fn stack_slots(&self, location: &il::ProgramLocation, address: &il::Expression) -> Vec<i64> {
+        let value_set = match self.value_sets.get(location) {
+            Some(value_sets) => value_sets.eval(address),
+            None => return Vec::new(),
+        };
+        let offsets = match value_set
+            .region(&Region::Stack)
+            .and_then(|interval| interval.values(MAX_ADDRESSES))
+        {
+            Some(offsets) => offsets,
+            None => return Vec::new(),
+        };
+        offsets
+            .into_iter()
+            .map(|offset| {
+                il::const_(offset, self.bits)
+                    .value_i64()
+                    .unwrap_or(offset as i64)
+            })
+            .filter(|offset| *offset >= self.stack_argument_offset)
+            .collect()
+    }
+}
+
+impl<'a, 'f> fixed_point::FixedPointAnalysis<'f, Inputs> for InputAnalysis<'a> {
+    fn trans(&self, location: il::RefProgramLocation<'f>, state: Option<Inputs>) -> Result<Inputs> {
+        let mut state = state.unwrap_or_default();
+
+        match *location.function_location() {
+            il::RefFunctionLocation::Instruction(_, instruction) => {
+                let program_location: il::ProgramLocation = location.clone().into();
+                match *instruction.operation() {
+                    il::Operation::Assign { ref dst, ref src } => {
+                        state.kill(dst);
+                        state.gen(src.scalars());
+                    }
+                    il::Operation::Load { ref dst, ref index } => {
+                        state.kill(dst);
+                        state.gen(index.scalars());
+                        for slot in self.stack_slots(&program_location, index) {
+                            state.inputs.insert(Input::Stack(slot));
+                        }
+                    }
+                    il::Operation::Store { ref index, ref src } => {
+                        let slots = self.stack_slots(&program_location, index);
+                        if let [slot] = slots[..] {
+                            state.inputs.remove(&Input::Stack(slot));
+                        }
+                        state.gen(index.scalars());
+                        state.gen(src.scalars());
+                    }
+                    il::Operation::Branch { ref target } => {
+                        if is_call(&location, target)? {
+                            for scalar in &self.trashed {
+                                state.kill(scalar);
+                            }
+                        }
+                        state.gen(target.scalars());
+                    }
+                    il::Operation::Intrinsic { ref intrinsic } => {
+                        for scalar in intrinsic.scalars_written().unwrap_or_default() {
+                            state.kill(scalar);
+                        }
+                        state.gen(intrinsic.scalars_read().unwrap_or_default());
+                    }
+                    il::Operation::Nop => {}
+                }
+            }
+            il::RefFunctionLocation::Edge(edge) => {
+                if let Some(condition) = edge.condition() {
+                    state.gen(condition.scalars());
+                }
+            }
+            il::RefFunctionLocation::EmptyBlock(_) => {}
+        }
+
+        Ok(state)
+    }
+
+    fn join(&self, mut state0: Inputs, state1: &Inputs) -> Result<Inputs> {
+        state0.inputs.extend(state1.inputs.iter().cloned());
+        Ok(state0)
+    }
+}
+
+#[cfg(test)]
+fn returning_block(block: &mut il::Block, stack_pointer: &str, bits: usize, cleanup: u64) {
+    let sp = || il::expr_scalar(stack_pointer, bits);
+    block.load(il::scalar("ret", bits), sp());
+    block.assign(
+        il::scalar(stack_pointer, bits),
+        il::Expression::add(sp(), il::expr_const(bits as u64 / 8 + cleanup, bits)).unwrap(),
+    );
+    block.branch(il::expr_scalar("ret", bits));
+}
+
+#[test]
+fn signature_test() {
+    /*
+    rsi = 1
+    rax = rdi + rsi
+    rax = rax + rdx
+    ret
+    */
+    use crate::architecture::{Amd64, X86};
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rsi", 64), il::expr_const(1, 64));
+        block.assign(
+            il::scalar("rax", 64),
+            il::Expression::add(il::expr_scalar("rdi", 64), il::expr_scalar("rsi", 64)).unwrap(),
+        );
+        block.assign(
+            il::scalar("rax", 64),
+            il::Expression::add(il::expr_scalar("rax", 64), il::expr_scalar("rdx", 64)).unwrap(),
+        );
+        returning_block(block, "rsp", 64, 0);
+    }
+    control_flow_graph.set_entry(0).unwrap();
+    let function = il::Function::new(0x1000, control_flow_graph);
+
+    let amd64 = signature(&function, &Amd64::new()).unwrap();
+    assert_eq!(amd64.arity(), 3);
+    assert_eq!(
+        amd64.register_arguments(),
+        &[il::scalar("rdi", 64), il::scalar("rdx", 64)]
+    );
+    assert!(amd64.stack_arguments().is_empty());
+    assert!(amd64.returns_value());
+    assert_eq!(amd64.stack_cleanup(), Some(0));
+
+    /*
+    ecx = [esp + 4]
+    edx = [esp + 8]
+    [ecx] = edx
+    ret 8
+    */
+    let esp = || il::expr_scalar("esp", 32);
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    {
+        let block = control_flow_graph.new_block().unwrap();
+        block.load(
+            il::scalar("ecx", 32),
+            il::Expression::add(esp(), il::expr_const(4, 32)).unwrap(),
+        );
+        block.load(
+            il::scalar("edx", 32),
+            il::Expression::add(esp(), il::expr_const(8, 32)).unwrap(),
+        );
+        block.store(il::expr_scalar("ecx", 32), il::expr_scalar("edx", 32));
+        returning_block(block, "esp", 32, 8);
+    }
+    control_flow_graph.set_entry(0).unwrap();
+    let stdcall = il::Function::new(0x2000, control_flow_graph);
+
+    let x86 = signature(&stdcall, &X86::new()).unwrap();
+    assert_eq!(x86.arity(), 2);
+    assert!(x86.register_arguments().is_empty());
+    assert_eq!(x86.stack_arguments(), &[4, 8]);
+    assert!(!x86.returns_value());
+    assert_eq!(x86.stack_cleanup(), Some(8));
+    assert_eq!(
+        x86.argument_types(&X86::new().calling_convention()),
+        vec![ArgumentType::Stack(4), ArgumentType::Stack(8)]
+    );
+
+    let mut program = il::Program::new();
+    program.add_function(function);
+    program.add_function(stdcall);
+    let signatures = signatures(&program, &X86::new()).unwrap();
+    assert_eq!(signatures[&1], x86);
+}
+
+#[test]
+fn signature_call_test() {
+    /*
+    0x1000:
+      rdi = 1
+      call 0x2000
+      ret
+
+    0x2000:
+      rax = 1    (only when the callee returns a value)
+      ret
+    */
+    use crate::architecture::Amd64;
+
+    let caller = || {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+        let call_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.assign(il::scalar("rdi", 64), il::expr_const(1, 64));
+            block.branch(il::expr_const(0x2000, 64));
+            block.index()
+        };
+        let return_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            returning_block(block, "rsp", 64, 0);
+            block.index()
+        };
+        control_flow_graph
+            .unconditional_edge(call_index, return_index)
+            .unwrap();
+        control_flow_graph.set_entry(call_index).unwrap();
+        il::Function::new(0x1000, control_flow_graph)
+    };
+    let callee = |returns_value: bool| {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+        let index = {
+            let block = control_flow_graph.new_block().unwrap();
+            if returns_value {
+                block.assign(il::scalar("rax", 64), il::expr_const(1, 64));
+            }
+            returning_block(block, "rsp", 64, 0);
+            block.index()
+        };
+        control_flow_graph.set_entry(index).unwrap();
+        il::Function::new(0x2000, control_flow_graph)
+    };
+
+    // Alone, the call is not known to return a value.
+    let signature = signature(&caller(), &Amd64::new()).unwrap();
+    assert!(!signature.returns_value());
+    assert!(signature.register_arguments().is_empty());
+
+    // The caller returns the value its callee returns.
+    let mut program = il::Program::new();
+    program.add_function(caller());
+    program.add_function(callee(true));
+    let recovered = signatures(&program, &Amd64::new()).unwrap();
+    assert_eq!(recovered.len(), 2);
+    assert!(recovered
+        .values()
+        .all(|signature| signature.returns_value()));
+
+    // A caller of a function which returns no value returns no value.
+    let mut program = il::Program::new();
+    program.add_function(caller());
+    program.add_function(callee(false));
+    let recovered = signatures(&program, &Amd64::new()).unwrap();
+    assert_eq!(recovered.len(), 2);
+    assert!(recovered
+        .values()
+        .all(|signature| !signature.returns_value()));
+
+    // A function whose signature cannot be recovered is omitted.
+    let mut program = il::Program::new();
+    program.add_function(caller());
+    program.add_function(il::Function::new(0x3000, il::ControlFlowGraph::new()));
+    let recovered = signatures(&program, &Amd64::new()).unwrap();
+    assert_eq!(recovered.len(), 1);
+}
@@ -0,0 +1,792 @@
+//! Backward and forward program slicing.
+//!
+//! A backward slice from a scalar at a location holds every location which
+//! may influence the value of the scalar read there. A forward slice from a
+//! scalar at a location holds every location whose execution, or values, may
+//! be influenced by the value of the scalar after that location.
+//!
+//! Slices follow data dependence, through `use_def` and `def_use` chains, and
+//! control dependence, where a location depends on the conditional edges
+//! which decide whether its block executes.
+//!
+//! The `_program` variants slice across the functions of a `Program`,
+//! following values into and out of calls to functions in the program. Calls
+//! are not assumed to write any scalar, so a value which reaches a call may
+//! come from the callee, or from before the call.
+//!
+//! A slice is a `LocationSet`, which `reduce_function` and `reduce_program`
+//! turn into functions where every instruction outside the slice is a `Nop`.
+
+use crate::analysis::call_graph::CallGraph;
+use crate::analysis::{def_use, use_def, LocationSet};
+use crate::error::*;
+use crate::executor::eval;
+use crate::graph;
+use crate::il;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Compute the backward slice from the scalar read at a location, within one
+/// function.
+pub fn backward_slice(
+    function: &il::Function,
+    location: &il::ProgramLocation,
+    scalar: &il::Scalar,
+) -> Result<LocationSet> {
+    let mut slicer = Slicer::new(None, Some(function));
+    slicer.backward(function, location, scalar)?;
+    slicer.run(Direction::Backward)
+}
+
+/// Compute the forward slice from the scalar after a location, within one
+/// function.
+pub fn forward_slice(
+    function: &il::Function,
+    location: &il::ProgramLocation,
+    scalar: &il::Scalar,
+) -> Result<LocationSet> {
+    let mut slicer = Slicer::new(None, Some(function));
+    slicer.forward(function, location, scalar)?;
+    slicer.run(Direction::Forward)
+}
+
+/// Compute the backward slice from the scalar read at a location, following
+/// values through the calls between the functions of a program.
+pub fn backward_slice_program(
+    program: &il::Program,
+    location: &il::ProgramLocation,
+    scalar: &il::Scalar,
+) -> Result<LocationSet> {
+    let mut slicer = Slicer::new(Some(program), None);
+    slicer.backward(function(program, location)?, location, scalar)?;
+    slicer.run(Direction::Backward)
+}
+
+/// Compute the forward slice from the scalar after a location, following
+/// values through the calls between the functions of a program.
+pub fn forward_slice_program(
+    program: &il::Program,
+    location: &il::ProgramLocation,
+    scalar: &il::Scalar,
+) -> Result<LocationSet> {
+    let mut slicer = Slicer::new(Some(program), None);
+    slicer.forward(function(program, location)?, location, scalar)?;
+    slicer.run(Direction::Forward)
+}
+
+/// Reduce a function to a slice, replacing every instruction which is not in
+/// the slice with a `Nop`.
+///
+/// The control flow graph is kept, as are branches which return from the
+/// function, so the reduced function may still be executed.
+pub fn reduce_function(function: &il::Function, slice: &LocationSet) -> Result<il::Function> {
+    let mut kill = Vec::new();
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let location = il::RefProgramLocation::new(
+                function,
+                il::RefFunctionLocation::Instruction(block, instruction),
+            );
+            let returns = instruction.is_branch() && location.forward()?.is_empty();
+            if !returns && !slice.contains(&location.into()) {
+                kill.push((block.index(), instruction.index()));
+            }
+        }
+    }
+
+    let mut reduced = function.clone();
+    for (block_index, instruction_index) in kill {
+        *reduced
+            .block_mut(block_index)?
+            .instruction_mut(instruction_index)
+            .ok_or("Failed to find instruction")?
+            .operation_mut() = il::Operation::Nop;
+    }
+    Ok(reduced)
+}
+
+/// Reduce every function in a program to a slice.
+pub fn reduce_program(program: &il::Program, slice: &LocationSet) -> Result<il::Program> {
+    let mut reduced = program.clone();
+    for (index, function) in program.functions_map() {
+        reduced.replace_function(index, reduce_function(function, slice)?)?;
+    }
+    Ok(reduced)
+}
+
+fn function<'p>(
+    program: &'p il::Program,
+    location: &il::ProgramLocation,
+) -> Result<&'p il::Function> {
+    location
+        .function_index()
+        .and_then(|index| program.function(index))
+        .ok_or_else(|| format!("No function in program for location {}", location).into())
+}
+
+/// Apply a location to the function it is in.
+fn apply<'f>(
+    function: &'f il::Function,
+    location: &il::ProgramLocation,
+) -> Result<il::RefProgramLocation<'f>> {
+    Ok(il::RefProgramLocation::new(
+        function,
+        location.function_location().apply(function)?,
+    ))
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Direction {
+    Backward,
+    Forward,
+}
+
+/// The control dependence of the blocks of a function.
+struct ControlDependence {
+    /// The blocks whose conditional edges decide whether each block executes.
+    controllers: HashMap<usize, HashSet<usize>>,
+    /// The blocks whose execution each block's conditional edges decide.
+    dependents: HashMap<usize, HashSet<usize>>,
+}
+
+impl ControlDependence {
+    /// Control dependence is the dominance frontier over the reversed
+    /// control flow graph, with a virtual exit.
+    fn new(function: &il::Function) -> Result<ControlDependence> {
+        let control_flow_graph = function.control_flow_graph();
+        let blocks = control_flow_graph.blocks();
+        let exit = blocks
+            .iter()
+            .map(|block| block.index() + 1)
+            .max()
+            .unwrap_or(0);
+
+        let mut reverse: graph::Graph<graph::NullVertex, graph::NullEdge> = graph::Graph::new();
+        reverse.insert_vertex(graph::NullVertex::new(exit))?;
+        for block in &blocks {
+            reverse.insert_vertex(graph::NullVertex::new(block.index()))?;
+        }
+        for edge in control_flow_graph.edges() {
+            if reverse.edge(edge.tail(), edge.head()).is_err() {
+                reverse.insert_edge(graph::NullEdge::new(edge.tail(), edge.head()))?;
+            }
+        }
+        for block in &blocks {
+            if control_flow_graph.edges_out(block.index())?.is_empty() {
+                reverse.insert_edge(graph::NullEdge::new(exit, block.index()))?;
+            }
+        }
+        // Blocks which never reach an exit, such as infinite loops, are
+        // treated as exits.
+        loop {
+            let reachable: HashSet<usize> = reverse.compute_post_order(exit)?.into_iter().collect();
+            match blocks
+                .iter()
+                .find(|block| !reachable.contains(&block.index()))
+            {
+                Some(block) => reverse.insert_edge(graph::NullEdge::new(exit, block.index()))?,
+                None => break,
+            }
+        }
+
+        let mut controllers = reverse.compute_dominance_frontiers(exit)?;
+        controllers.remove(&exit);
+        let mut dependents: HashMap<usize, HashSet<usize>> = HashMap::new();
+        for (block, block_controllers) in &mut controllers {
+            block_controllers.remove(&exit);
+            for controller in block_controllers.iter() {
+                dependents.entry(*controller).or_default().insert(*block);
+            }
+        }
+
+        Ok(ControlDependence {
+            controllers,
+            dependents,
+        })
+    }
+}
+
+/// The block a location executes in, where edges execute in their head.
+fn block_index(location: &il::RefProgramLocation) -> usize {
+    match *location.function_location() {
+        il::RefFunctionLocation::Instruction(block, _)
+        | il::RefFunctionLocation::EmptyBlock(block) => block.index(),
+        il::RefFunctionLocation::Edge(edge) => edge.head(),
+    }
+}
+
+fn is_conditional_edge(location: &il::RefProgramLocation) -> bool {
+    location
+        .edge()
+        .is_some_and(|edge| edge.condition().is_some())
+}
+
+/// Every location in a block, including the edges out of it.
+fn block_locations(
+    function: &il::Function,
+    block_index: usize,
+) -> Result<Vec<il::RefProgramLocation<'_>>> {
+    let block = function.block(block_index)?;
+    let mut locations: Vec<il::RefProgramLocation> = if block.instructions().is_empty() {
+        vec![il::RefProgramLocation::new(
+            function,
+            il::RefFunctionLocation::EmptyBlock(block),
+        )]
+    } else {
+        block
+            .instructions()
+            .iter()
+            .map(|instruction| {
+                il::RefProgramLocation::new(
+                    function,
+                    il::RefFunctionLocation::Instruction(block, instruction),
+                )
+            })
+            .collect()
+    };
+    for edge in function.control_flow_graph().edges_out(block_index)? {
+        locations.push(il::RefProgramLocation::new(
+            function,
+            il::RefFunctionLocation::Edge(edge),
+        ));
+    }
+    Ok(locations)
+}
+
+/// The locations which leave a function.
+fn exits(function: &il::Function) -> Result<Vec<il::RefProgramLocation<'_>>> {
+    let control_flow_graph = function.control_flow_graph();
+    let mut exits = Vec::new();
+    for block in control_flow_graph.blocks() {
+        if control_flow_graph.edges_out(block.index())?.is_empty() {
+            let function_location = match block.instructions().last() {
+                Some(instruction) => il::RefFunctionLocation::Instruction(block, instruction),
+                None => il::RefFunctionLocation::EmptyBlock(block),
+            };
+            exits.push(il::RefProgramLocation::new(function, function_location));
+        }
+    }
+    Ok(exits)
+}
+
+/// The scalars read at a location.
+fn scalars_read(location: &il::RefProgramLocation) -> Vec<il::Scalar> {
+    let scalars = match *location.function_location() {
+        il::RefFunctionLocation::Instruction(_, instruction) => {
+            instruction.operation().scalars_read().unwrap_or_default()
+        }
+        il::RefFunctionLocation::Edge(edge) => edge
+            .condition()
+            .map(|condition| condition.scalars())
+            .unwrap_or_default(),
+        il::RefFunctionLocation::EmptyBlock(_) => Vec::new(),
+    };
+    scalars.into_iter().cloned().collect()
+}
+
+/// The scalars written at a location.
+fn scalars_written(location: &il::RefProgramLocation) -> Vec<il::Scalar> {
+    location
+        .instruction()
+        .and_then(|instruction| instruction.operation().scalars_written())
+        .unwrap_or_default()
+        .into_iter()
+        .cloned()
+        .collect()
+}
+
+/// The address called at a location, if it branches to a constant target.
+fn call_target(location: &il::RefProgramLocation) -> Option<u64> {
+    match *location.instruction()?.operation() {
+        il::Operation::Branch { ref target } => eval(target).ok()?.value_u64(),
+        _ => None,
+    }
+}
+
+/// The data and control dependences of one function.
+struct Dependences {
+    use_def: HashMap<il::ProgramLocation, LocationSet>,
+    def_use: HashMap<il::ProgramLocation, LocationSet>,
+    control_dependence: ControlDependence,
+}
+
+struct Slicer<'p> {
+    /// The program, when slicing across functions.
+    program: Option<&'p il::Program>,
+    /// The function, when slicing within one function.
+    function: Option<&'p il::Function>,
+    call_graph: Option<CallGraph>,
+    dependences: HashMap<Option<usize>, Dependences>,
+    slice: LocationSet,
+    /// Locations in the slice whose dependences are to be followed, and
+    /// whether their data dependences are followed, which they are not for
+    /// the location of a criterion.
+    queue: VecDeque<(il::ProgramLocation, bool)>,
+    /// The locations whose data dependences have been queued.
+    followed: HashSet<il::ProgramLocation>,
+    /// The criteria already followed by `backward` and `forward`.
+    criteria: HashSet<(il::ProgramLocation, il::Scalar)>,
+}
+
+impl<'p> Slicer<'p> {
+    fn new(program: Option<&'p il::Program>, function: Option<&'p il::Function>) -> Slicer<'p> {
+        Slicer {
+            program,
+            function,
+            call_graph: None,
+            dependences: HashMap::new(),
+            slice: LocationSet::new(),
+            queue: VecDeque::new(),
+            followed: HashSet::new(),
+            criteria: HashSet::new(),
+        }
+    }
+
+    fn dependences(&mut self, function: &il::Function) -> Result<&Dependences> {
+        let index = function.index();
+        if let Entry::Vacant(entry) = self.dependences.entry(index) {
+            entry.insert(Dependences {
+                use_def: use_def(function)?,
+                def_use: def_use(function)?,
+                control_dependence: ControlDependence::new(function)?,
+            });
+        }
+        Ok(&self.dependences[&index])
+    }
+
+    /// The call sites of a function in the program.
+    fn call_sites(&mut self, function: &il::Function) -> Result<Vec<il::ProgramLocation>> {
+        let (program, index) = match (self.program, function.index()) {
+            (Some(program), Some(index)) => (program, index),
+            _ => return Ok(Vec::new()),
+        };
+        if self.call_graph.is_none() {
+            self.call_graph = Some(CallGraph::new(program)?);
+        }
+        let call_graph = self.call_graph.as_ref().unwrap();
+        let mut call_sites = Vec::new();
+        for caller in call_graph.callers(index)? {
+            for call in call_graph.calls(caller, index)? {
+                call_sites.push(call.location().clone());
+            }
+        }
+        Ok(call_sites)
+    }
+
+    /// The function in the program called at a location.
+    fn callee(&self, location: &il::RefProgramLocation) -> Option<&'p il::Function> {
+        let address = call_target(location)?;
+        self.program?.function_by_address(address)
+    }
+
+    fn insert(&mut self, location: il::ProgramLocation) {
+        if self.followed.insert(location.clone()) {
+            self.slice.insert(location.clone());
+            self.queue.push_back((location, true));
+        }
+    }
+
+    /// Add the location of a criterion, whose data dependences are only
+    /// those on the scalar of the criterion.
+    fn insert_criterion(&mut self, location: il::ProgramLocation) {
+        if !self.slice.contains(&location) {
+            self.slice.insert(location.clone());
+            self.queue.push_back((location, false));
+        }
+    }
+
+    /// Add the definitions of a scalar read at a location.
+    ///
+    /// Paths backwards from the location which reach a call continue at each
+    /// exit of the callee, and paths which reach the entry of the function
+    /// continue at each call site of the function.
+    fn backward(
+        &mut self,
+        function: &'p il::Function,
+        location: &il::ProgramLocation,
+        scalar: &il::Scalar,
+    ) -> Result<()> {
+        if !self.criteria.insert((location.clone(), scalar.clone())) {
+            return Ok(());
+        }
+        let start = apply(function, location)?;
+        self.insert_criterion(location.clone());
+
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<il::RefProgramLocation> = start.backward()?.into_iter().collect();
+        let mut reaches_entry = queue.is_empty();
+        while let Some(location) = queue.pop_front() {
+            if !visited.insert(location.clone()) {
+                continue;
+            }
+            if scalars_written(&location).contains(scalar) {
+                self.insert(location.into());
+                continue;
+            }
+            if let Some(callee) = self.callee(&location) {
+                self.insert(location.clone().into());
+                for exit in exits(callee)? {
+                    self.backward(callee, &exit.into(), scalar)?;
+                }
+            }
+            let predecessors = location.backward()?;
+            reaches_entry |= predecessors.is_empty();
+            queue.extend(predecessors);
+        }
+
+        if reaches_entry {
+            for call_site in self.call_sites(function)? {
+                let caller = function_in_program(self.program, &call_site)?;
+                self.backward(caller, &call_site, scalar)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the uses of a scalar after a location.
+    ///
+    /// Paths forwards from the location which reach a call continue at the
+    /// entry of the callee, and paths which leave the function continue after
+    /// each call site of the function.
+    fn forward(
+        &mut self,
+        function: &'p il::Function,
+        location: &il::ProgramLocation,
+        scalar: &il::Scalar,
+    ) -> Result<()> {
+        if !self.criteria.insert((location.clone(), scalar.clone())) {
+            return Ok(());
+        }
+        let start = apply(function, location)?;
+        self.insert_criterion(location.clone());
+        self.forward_from(function, start.forward()?, scalar)
+    }
+
+    fn forward_from(
+        &mut self,
+        function: &'p il::Function,
+        locations: Vec<il::RefProgramLocation<'p>>,
+        scalar: &il::Scalar,
+    ) -> Result<()> {
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<il::RefProgramLocation> = locations.into_iter().collect();
+        let mut leaves = false;
+        while let Some(location) = queue.pop_front() {
+            if !visited.insert(location.clone()) {
+                continue;
+            }
+            if scalars_read(&location).contains(scalar) {
+                self.insert(location.clone().into());
+            }
+            if scalars_written(&location).contains(scalar) {
+                continue;
+            }
+            if let Some(callee) = self.callee(&location) {
+                if let Some(entry) = il::RefProgramLocation::from_function(callee) {
+                    let entry = entry?;
+                    if self.criteria.insert((entry.clone().into(), scalar.clone())) {
+                        self.forward_from(callee, vec![entry], scalar)?;
+                    }
+                }
+            }
+            let successors = location.forward()?;
+            leaves |= successors.is_empty();
+            queue.extend(successors);
+        }
+
+        if leaves {
+            for call_site in self.call_sites(function)? {
+                let caller = function_in_program(self.program, &call_site)?;
+                if self.criteria.insert((call_site.clone(), scalar.clone())) {
+                    let call_site = apply(caller, &call_site)?;
+                    self.forward_from(caller, call_site.forward()?, scalar)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Close the slice over data and control dependence.
+    fn run(mut self, direction: Direction) -> Result<LocationSet> {
+        while let Some((program_location, data)) = self.queue.pop_front() {
+            let function = match self.function {
+                Some(function) => function,
+                None => function_in_program(self.program, &program_location)?,
+            };
+            let location = apply(function, &program_location)?;
+
+            let dependences = self.dependences(function)?;
+            let mut dependent: Vec<il::ProgramLocation> = if data {
+                match direction {
+                    Direction::Backward => dependences.use_def.get(&program_location),
+                    Direction::Forward => dependences.def_use.get(&program_location),
+                }
+                .map(|locations| locations.locations().iter().cloned().collect())
+                .unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+
+            let control_dependence = &dependences.control_dependence;
+            let blocks = match direction {
+                Direction::Backward => control_dependence.controllers.get(&block_index(&location)),
+                // Only the conditional edges out of a block decide which
+                // blocks execute.
+                Direction::Forward if is_conditional_edge(&location) => {
+                    control_dependence.dependents.get(&block_index(&location))
+                }
+                Direction::Forward => None,
+            };
+            for block in blocks.into_iter().flatten() {
+                for location in block_locations(function, *block)? {
+                    if direction == Direction::Forward || is_conditional_edge(&location) {
+                        dependent.push(location.into());
+                    }
+                }
+            }
+
+            for location in dependent {
+                self.insert(location);
+            }
+
+            // Follow values across the boundaries of the function.
+            if data && self.program.is_some() {
+                match direction {
+                    Direction::Backward => {
+                        for scalar in scalars_read(&location) {
+                            self.backward(function, &program_location, &scalar)?;
+                        }
+                    }
+                    Direction::Forward => {
+                        for scalar in scalars_written(&location) {
+                            self.forward(function, &program_location, &scalar)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(self.slice)
+    }
+}
+
+fn function_in_program<'p>(
+    program: Option<&'p il::Program>,
+    location: &il::ProgramLocation,
+) -> Result<&'p il::Function> {
+    function(
+        program.ok_or("Slicing a location outside of the function")?,
+        location,
+    )
+}
+
+#[cfg(test)]
+fn location(function: Option<usize>, block: usize, instruction: usize) -> il::ProgramLocation {
+    il::ProgramLocation::new(
+        function,
+        il::FunctionLocation::Instruction(block, instruction),
+    )
+}
+
+#[cfg(test)]
+fn edge(head: usize, tail: usize) -> il::ProgramLocation {
+    il::ProgramLocation::new(None, il::FunctionLocation::Edge(head, tail))
+}
+
+#[test]
+fn slice_test() {
+    /*
+    a = in
+    b = 7
+    c = in2
+    d = 3
+    if a == 0
+        b = c
+    r = b
+    */
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let head_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("a", 32), il::expr_scalar("in", 32));
+        block.assign(il::scalar("b", 32), il::expr_const(7, 32));
+        block.assign(il::scalar("c", 32), il::expr_scalar("in2", 32));
+        block.assign(il::scalar("d", 32), il::expr_const(3, 32));
+        block.index()
+    };
+
+    let then_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("b", 32), il::expr_scalar("c", 32));
+        block.index()
+    };
+
+    let tail_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("r", 32), il::expr_scalar("b", 32));
+        block.index()
+    };
+
+    let zero = il::Expression::cmpeq(il::expr_scalar("a", 32), il::expr_const(0, 32)).unwrap();
+    let not_zero = il::Expression::cmpneq(il::expr_scalar("a", 32), il::expr_const(0, 32)).unwrap();
+    control_flow_graph
+        .conditional_edge(head_index, then_index, zero)
+        .unwrap();
+    control_flow_graph
+        .conditional_edge(head_index, tail_index, not_zero)
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(then_index, tail_index)
+        .unwrap();
+    control_flow_graph.set_entry(head_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+
+    let backward = backward_slice(&function, &location(None, 2, 0), &il::scalar("b", 32)).unwrap();
+    assert_eq!(backward.len(), 7);
+    assert!(backward.contains(&location(None, 0, 0)));
+    assert!(backward.contains(&location(None, 0, 1)));
+    assert!(backward.contains(&location(None, 0, 2)));
+    assert!(!backward.contains(&location(None, 0, 3)));
+    assert!(backward.contains(&location(None, 1, 0)));
+    assert!(backward.contains(&edge(0, 1)));
+    assert!(backward.contains(&edge(0, 2)));
+    assert!(!backward.contains(&edge(1, 2)));
+
+    let forward = forward_slice(&function, &location(None, 0, 0), &il::scalar("a", 32)).unwrap();
+    assert_eq!(forward.len(), 6);
+    assert!(forward.contains(&edge(0, 1)));
+    assert!(forward.contains(&edge(0, 2)));
+    assert!(forward.contains(&location(None, 1, 0)));
+    assert!(forward.contains(&edge(1, 2)));
+    assert!(forward.contains(&location(None, 2, 0)));
+    assert!(!forward.contains(&location(None, 0, 1)));
+
+    let reduced = reduce_function(&function, &backward).unwrap();
+    let head = reduced.block(0).unwrap();
+    assert_eq!(head.instructions().len(), 4);
+    assert_eq!(
+        *head.instruction(3).unwrap().operation(),
+        il::Operation::Nop
+    );
+    assert_eq!(
+        head.instruction(1).unwrap().operation(),
+        function
+            .block(0)
+            .unwrap()
+            .instruction(1)
+            .unwrap()
+            .operation()
+    );
+    assert_eq!(reduced.edges().len(), 3);
+}
+
+#[test]
+fn slice_criterion_test() {
+    /*
+    rax = in
+    rcx = in2
+    rdx = rax + rcx
+    */
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let block_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rax", 64), il::expr_scalar("in", 64));
+        block.assign(il::scalar("rcx", 64), il::expr_scalar("in2", 64));
+        block.assign(
+            il::scalar("rdx", 64),
+            il::Expression::add(il::expr_scalar("rax", 64), il::expr_scalar("rcx", 64)).unwrap(),
+        );
+        block.index()
+    };
+    control_flow_graph.set_entry(block_index).unwrap();
+    let function = il::Function::new(0, control_flow_graph);
+
+    // Only the definitions of the scalar of the criterion are followed from
+    // its location.
+    let backward =
+        backward_slice(&function, &location(None, 0, 2), &il::scalar("rax", 64)).unwrap();
+    assert_eq!(backward.len(), 2);
+    assert!(backward.contains(&location(None, 0, 0)));
+    assert!(!backward.contains(&location(None, 0, 1)));
+    assert!(backward.contains(&location(None, 0, 2)));
+}
+
+#[test]
+fn slice_program_test() {
+    /*
+    0x1000:
+        a = 5
+        x = 9
+        call 0x2000
+        y = r
+        z = x
+        return
+
+    0x2000:
+        r = a + 1
+        return
+    */
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("a", 32), il::expr_const(5, 32));
+        block.assign(il::scalar("x", 32), il::expr_const(9, 32));
+        block.branch(il::expr_const(0x2000, 32));
+        block.index()
+    };
+    let return_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("y", 32), il::expr_scalar("r", 32));
+        block.assign(il::scalar("z", 32), il::expr_scalar("x", 32));
+        block.branch(il::expr_scalar("ra", 32));
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(call_index, return_index)
+        .unwrap();
+    control_flow_graph.set_entry(call_index).unwrap();
+    let caller = il::Function::new(0x1000, control_flow_graph);
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(
+            il::scalar("r", 32),
+            il::Expression::add(il::expr_scalar("a", 32), il::expr_const(1, 32)).unwrap(),
+        );
+        block.branch(il::expr_scalar("ra", 32));
+        block.index()
+    };
+    control_flow_graph.set_entry(index).unwrap();
+    let callee = il::Function::new(0x2000, control_flow_graph);
+
+    let mut program = il::Program::new();
+    program.add_function(caller);
+    program.add_function(callee);
+
+    let backward =
+        backward_slice_program(&program, &location(Some(0), 1, 0), &il::scalar("r", 32)).unwrap();
+    assert!(backward.contains(&location(Some(0), 0, 0)));
+    assert!(!backward.contains(&location(Some(0), 0, 1)));
+    assert!(backward.contains(&location(Some(0), 0, 2)));
+    assert!(backward.contains(&location(Some(1), 0, 0)));
+    assert!(!backward.contains(&location(Some(0), 1, 1)));
+
+    let forward =
+        forward_slice_program(&program, &location(Some(0), 0, 0), &il::scalar("a", 32)).unwrap();
+    assert!(forward.contains(&location(Some(1), 0, 0)));
+    assert!(forward.contains(&location(Some(0), 1, 0)));
+    assert!(!forward.contains(&location(Some(0), 1, 1)));
+
+    let reduced = reduce_program(&program, &backward).unwrap();
+    let caller = reduced.function(0).unwrap();
+    assert_eq!(
+        *caller.block(0).unwrap().instruction(1).unwrap().operation(),
+        il::Operation::Nop
+    );
+    assert_eq!(
+        *caller.block(1).unwrap().instruction(1).unwrap().operation(),
+        il::Operation::Nop
+    );
+    assert!(caller.block(1).unwrap().instruction(2).unwrap().is_branch());
+}
@@ -0,0 +1,628 @@
+//! Stack frame layout and local variable recovery.
+//!
+//! Every `Load` and `Store` whose address points only into the `Stack`
+//! region of `value_sets` is an access to the stack frame, at an offset from
+//! the stack pointer at function entry. Overlapping accesses are merged into the
+//! `StackSlot`s of a `StackFrame`, so the size of each slot is found from
+//! the widths of its accesses, and each access is given relative to its
+//! slot.
+//!
+//! Slots are separated into the return address and arguments, by the
+//! `CallingConvention`, the preserved registers the function saves, buffers,
+//! which are accessed at varying offsets, and locals.
+//!
+//! A slot escapes if a pointer into it is stored to memory, or passed to a
+//! call in an argument register. Locals which do not escape, and are only
+//! accessed whole, can be promoted to `il::Scalar`s with
+//! `promote_stack_slots`. Slots an access may reach through a pointer which
+//! may also point outside the stack escape. Accesses through pointers which
+//! are not known to point into the stack are assumed not to access slots
+//! which do not escape.
+
+use crate::analysis::calling_convention::ReturnAddressType;
+use crate::analysis::interprocedural::is_call;
+use crate::analysis::stack_pointer_offsets::stack_pointer_offsets;
+use crate::analysis::value_set::{value_sets, Region, ValueSets};
+use crate::architecture::Architecture;
+use crate::error::*;
+use crate::il;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The largest number of offsets an access may be at before it is ignored.
+const MAX_OFFSETS: u64 = 0x1000;
+
+/// Recover the layout of the stack frame of a function.
+pub fn stack_frame(function: &il::Function, architecture: &dyn Architecture) -> Result<StackFrame> {
+    stack_frame_with(
+        function,
+        architecture,
+        &value_sets(function, architecture, &[])?,
+    )
+}
+
+/// Recover the layout of the stack frame of a function, with the
+/// `value_sets` already computed for it.
+pub fn stack_frame_with(
+    function: &il::Function,
+    architecture: &dyn Architecture,
+    value_sets: &HashMap<il::ProgramLocation, ValueSets>,
+) -> Result<StackFrame> {
+    let calling_convention = architecture.calling_convention();
+    let stack_pointer = architecture.stack_pointer();
+    let bits = stack_pointer.bits();
+
+    // Every access, by location, as the offsets it may be at and its width
+    // in bytes.
+    let mut accesses: Vec<(il::ProgramLocation, Vec<i64>, usize)> = Vec::new();
+    // Preserved registers stored to, and loaded from, each offset.
+    let mut saved: HashMap<i64, il::Scalar> = HashMap::new();
+    let mut restored: HashSet<(i64, il::Scalar)> = HashSet::new();
+    // Offsets pointers escape to, or None if any offset may escape.
+    let mut escapes: Option<HashSet<i64>> = Some(HashSet::new());
+
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let ref_location = il::RefProgramLocation::new(
+                function,
+                il::RefFunctionLocation::Instruction(block, instruction),
+            );
+            let location: il::ProgramLocation = ref_location.clone().into();
+            let state = match value_sets.get(&location) {
+                Some(state) => state,
+                None => continue,
+            };
+            match *instruction.operation() {
+                il::Operation::Load { ref dst, ref index } => {
+                    match stack_offsets(state, index, bits) {
+                        Some(offsets) => {
+                            if let [offset] = offsets[..] {
+                                if calling_convention.is_preserved(dst) == Some(true) {
+                                    restored.insert((offset, dst.clone()));
+                                }
+                            }
+                            accesses.push((location, offsets, dst.bits().div_ceil(8)));
+                        }
+                        None => escape(&mut escapes, state, index, bits),
+                    }
+                }
+                il::Operation::Store { ref index, ref src } => {
+                    match stack_offsets(state, index, bits) {
+                        Some(offsets) => {
+                            if let ([offset], il::Expression::Scalar(scalar)) = (&offsets[..], src)
+                            {
+                                if *scalar != stack_pointer
+                                    && calling_convention.is_preserved(scalar) == Some(true)
+                                {
+                                    saved.entry(*offset).or_insert_with(|| scalar.clone());
+                                }
+                            }
+                            accesses.push((location, offsets, src.bits().div_ceil(8)));
+                        }
+                        None => escape(&mut escapes, state, index, bits),
+                    }
+                    escape(&mut escapes, state, src, bits);
+                }
+                il::Operation::Branch { ref target } if is_call(&ref_location, target)? => {
+                    for argument in calling_convention.argument_registers() {
+                        escape(
+                            &mut escapes,
+                            state,
+                            &il::Expression::scalar(argument.clone()),
+                            bits,
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Merge overlapping accesses into slots.
+    let mut ranges: Vec<(i64, i64, bool)> = accesses
+        .iter()
+        .map(|(_, offsets, width)| {
+            let lower = offsets[0];
+            let upper = offsets[offsets.len() - 1] + *width as i64;
+            (lower, upper, offsets.len() > 1)
+        })
+        .collect();
+    ranges.sort();
+    let mut merged: Vec<(i64, i64, bool)> = Vec::new();
+    for (lower, upper, varies) in ranges {
+        match merged.last_mut() {
+            Some(last) if lower < last.1 => {
+                last.1 = last.1.max(upper);
+                last.2 |= varies;
+            }
+            _ => merged.push((lower, upper, varies)),
+        }
+    }
+
+    let return_address = match *calling_convention.return_address_type() {
+        ReturnAddressType::Stack(offset) => Some((offset as i64, (offset + bits / 8) as i64)),
+        ReturnAddressType::Register(_) => None,
+    };
+    let stack_argument_offset = calling_convention.stack_argument_offset() as i64;
+
+    let mut slots = BTreeMap::new();
+    for (lower, upper, varies) in merged {
+        let kind = if return_address.is_some_and(|(start, end)| lower < end && upper > start) {
+            StackSlotKind::ReturnAddress
+        } else if lower >= stack_argument_offset {
+            StackSlotKind::Argument
+        } else if let Some(scalar) = saved
+            .get(&lower)
+            .filter(|scalar| restored.contains(&(lower, (*scalar).clone())))
+        {
+            StackSlotKind::SavedRegister(scalar.clone())
+        } else if varies {
+            StackSlotKind::Buffer
+        } else {
+            StackSlotKind::Local
+        };
+        let slot_escapes = match escapes {
+            Some(ref escapes) => escapes
+                .iter()
+                .any(|offset| *offset >= lower && *offset < upper),
+            None => true,
+        };
+        slots.insert(
+            lower,
+            StackSlot {
+                offset: lower,
+                size: (upper - lower) as usize,
+                kind,
+                escapes: slot_escapes,
+            },
+        );
+    }
+
+    let accesses = accesses
+        .into_iter()
+        .map(|(location, offsets, width)| {
+            let slot = *slots
+                .range(..=offsets[0])
+                .next_back()
+                .map(|(offset, _)| offset)
+                .unwrap();
+            let offset = if offsets.len() == 1 {
+                Some((offsets[0] - slot) as usize)
+            } else {
+                None
+            };
+            (
+                location,
+                StackAccess {
+                    slot,
+                    offset,
+                    bits: width * 8,
+                },
+            )
+        })
+        .collect();
+
+    let size = stack_pointer_offsets(function, architecture)?
+        .values()
+        .filter_map(|offset| offset.value())
+        .min()
+        .map(|offset| (-offset).max(0) as usize)
+        .unwrap_or(0);
+
+    Ok(StackFrame {
+        slots,
+        accesses,
+        size,
+    })
+}
+
+/// Promote the locals of a stack frame which do not escape, and are only
+/// accessed whole, to scalars named after their slots.
+///
+/// Loads from a promoted slot become assignments from its scalar, and stores
+/// to it become assignments to its scalar.
+pub fn promote_stack_slots(function: &il::Function, frame: &StackFrame) -> Result<il::Function> {
+    let promoted: HashMap<i64, il::Scalar> = frame
+        .slots()
+        .values()
+        .filter(|slot| frame.is_promotable(slot.offset()))
+        .map(|slot| (slot.offset(), il::scalar(slot.name(), slot.size() * 8)))
+        .collect();
+
+    let mut function = function.clone();
+    for (location, access) in frame.accesses() {
+        let scalar = match promoted.get(&access.slot()) {
+            Some(scalar) => scalar,
+            None => continue,
+        };
+        let (block_index, instruction_index) = match *location.function_location() {
+            il::FunctionLocation::Instruction(block_index, instruction_index) => {
+                (block_index, instruction_index)
+            }
+            _ => continue,
+        };
+        let operation = function
+            .block_mut(block_index)?
+            .instruction_mut(instruction_index)
+            .ok_or("Failed to find instruction")?
+            .operation_mut();
+        *operation = match *operation {
+            il::Operation::Load { ref dst, .. } => {
+                il::Operation::assign(dst.clone(), il::Expression::scalar(scalar.clone()))
+            }
+            il::Operation::Store { ref src, .. } => {
+                il::Operation::assign(scalar.clone(), src.clone())
+            }
+            _ => bail!("Stack access at {} is not a load or store", location),
+        };
+    }
+    Ok(function)
+}
+
+/// What a slot of a stack frame holds.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum StackSlotKind {
+    /// An argument passed on the stack.
+    Argument,
+    /// The return address.
+    ReturnAddress,
+    /// A preserved register saved by the function, and restored before it
+    /// returns.
+    SavedRegister(il::Scalar),
+    /// A buffer, accessed at varying offsets.
+    Buffer,
+    /// A local variable.
+    Local,
+}
+
+/// A slot of a stack frame.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StackSlot {
+    offset: i64,
+    size: usize,
+    kind: StackSlotKind,
+    escapes: bool,
+}
+
+impl StackSlot {
+    /// Get the offset of this slot from the stack pointer at function entry.
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    /// Get the size of this slot in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Get what this slot holds.
+    pub fn kind(&self) -> &StackSlotKind {
+        &self.kind
+    }
+
+    /// Returns true if a pointer into this slot escapes the function.
+    pub fn escapes(&self) -> bool {
+        self.escapes
+    }
+
+    /// Get the name of this slot, `var_` for offsets below the stack pointer
+    /// at function entry, and `arg_` for those above, with the offset in
+    /// hex.
+    pub fn name(&self) -> String {
+        if self.offset < 0 {
+            format!("var_{:x}", -self.offset)
+        } else {
+            format!("arg_{:x}", self.offset)
+        }
+    }
+}
+
+/// An access to a slot of a stack frame.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StackAccess {
+    slot: i64,
+    offset: Option<usize>,
+    bits: usize,
+}
+
+impl StackAccess {
+    /// Get the offset of the slot accessed.
+    pub fn slot(&self) -> i64 {
+        self.slot
+    }
+
+    /// Get the offset of this access into its slot in bytes, or `None` if
+    /// the offset varies.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Get the width of this access in bits.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+}
+
+/// The layout of the stack frame of a function.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    slots: BTreeMap<i64, StackSlot>,
+    accesses: HashMap<il::ProgramLocation, StackAccess>,
+    size: usize,
+}
+
+impl StackFrame {
+    /// Get the slots of this frame, by their offset.
+    pub fn slots(&self) -> &BTreeMap<i64, StackSlot> {
+        &self.slots
+    }
+
+    /// Get the slot at the given offset.
+    pub fn slot(&self, offset: i64) -> Option<&StackSlot> {
+        self.slots.get(&offset)
+    }
+
+    /// Get the access to the stack frame at a location.
+    pub fn access(&self, location: &il::ProgramLocation) -> Option<&StackAccess> {
+        self.accesses.get(location)
+    }
+
+    /// Get every access to the stack frame, by location.
+    pub fn accesses(&self) -> &HashMap<il::ProgramLocation, StackAccess> {
+        &self.accesses
+    }
+
+    /// Get the number of bytes the function allocates on the stack.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns true if the slot at the given offset is a local which does
+    /// not escape, and every access to it is to the whole slot.
+    pub fn is_promotable(&self, offset: i64) -> bool {
+        let slot = match self.slots.get(&offset) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        slot.kind == StackSlotKind::Local
+            && !slot.escapes
+            && self
+                .accesses
+                .values()
+                .filter(|access| access.slot == offset)
+                .all(|access| access.offset == Some(0) && access.bits == slot.size * 8)
+    }
+}
+
+/// The signed offsets into the stack an address may point to, in ascending
+/// order, if it only points into the stack.
+fn stack_offsets(state: &ValueSets, address: &il::Expression, bits: usize) -> Option<Vec<i64>> {
+    let value_set = state.eval(address);
+    if value_set.is_top() || value_set.regions().len() != 1 {
+        return None;
+    }
+    let offsets = value_set.region(&Region::Stack)?.values(MAX_OFFSETS)?;
+    let mut offsets: Vec<i64> = offsets
+        .into_iter()
+        .map(|offset| signed(offset, bits))
+        .collect();
+    offsets.sort_unstable();
+    Some(offsets)
+}
+
+fn signed(offset: u64, bits: usize) -> i64 {
+    il::const_(offset, bits)
+        .value_i64()
+        .unwrap_or(offset as i64)
+}
+
+/// Record the offsets into the stack a value which escapes, or an address
+/// which may point outside the stack, may point to.
+fn escape(
+    escapes: &mut Option<HashSet<i64>>,
+    state: &ValueSets,
+    value: &il::Expression,
+    bits: usize,
+) {
+    let value_set = state.eval(value);
+    let interval = match value_set.region(&Region::Stack) {
+        Some(interval) => interval,
+        None => return,
+    };
+    match (interval.values(MAX_OFFSETS), escapes.as_mut()) {
+        (Some(offsets), Some(escapes)) => {
+            escapes.extend(offsets.into_iter().map(|offset| signed(offset, bits)))
+        }
+        (None, _) => *escapes = None,
+        (Some(_), None) => {}
+    }
+}
+
+#[test]
+fn stack_frame_test() {
+    /*
+    rsp = rsp - 0x30
+    [rsp + 0x28] = rbx
+    rbx = [rsp + 0x38]
+    [rsp + 0x20] = rbx
+    [rsp + 0x18] = 0
+    [rsp + (in ? 0 : 4)] = 0
+    rdi = rsp + 0x18
+    call 0x4000
+
+    rax = [rsp + 0x20]
+    rbx = [rsp + 0x28]
+    rsp = rsp + 0x30
+    ret = [rsp]
+    rsp = rsp + 8
+    branch ret
+    */
+    use crate::architecture::Amd64;
+
+    let rsp = || il::expr_scalar("rsp", 64);
+    let rsp_plus = |offset| il::Expression::add(rsp(), il::expr_const(offset, 64)).unwrap();
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(
+            il::scalar("rsp", 64),
+            il::Expression::sub(rsp(), il::expr_const(0x30, 64)).unwrap(),
+        );
+        block.store(rsp_plus(0x28), il::expr_scalar("rbx", 64));
+        block.load(il::scalar("rbx", 64), rsp_plus(0x38));
+        block.store(rsp_plus(0x20), il::expr_scalar("rbx", 64));
+        block.store(rsp_plus(0x18), il::expr_const(0, 64));
+        let index = il::Expression::ite(
+            il::expr_scalar("in", 1),
+            il::expr_const(0, 64),
+            il::expr_const(4, 64),
+        )
+        .unwrap();
+        block.store(
+            il::Expression::add(rsp(), index).unwrap(),
+            il::expr_const(0, 32),
+        );
+        block.assign(il::scalar("rdi", 64), rsp_plus(0x18));
+        block.branch(il::expr_const(0x4000, 64));
+        block.index()
+    };
+    let return_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.load(il::scalar("rax", 64), rsp_plus(0x20));
+        block.load(il::scalar("rbx", 64), rsp_plus(0x28));
+        block.assign(il::scalar("rsp", 64), rsp_plus(0x30));
+        block.load(il::scalar("ret", 64), rsp());
+        block.assign(il::scalar("rsp", 64), rsp_plus(8));
+        block.branch(il::expr_scalar("ret", 64));
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(call_index, return_index)
+        .unwrap();
+    control_flow_graph.set_entry(call_index).unwrap();
+    let function = il::Function::new(0x1000, control_flow_graph);
+
+    let frame = stack_frame(&function, &Amd64::new()).unwrap();
+    assert_eq!(frame.size(), 0x30);
+
+    let kinds: Vec<(i64, usize, StackSlotKind)> = frame
+        .slots()
+        .values()
+        .map(|slot| (slot.offset(), slot.size(), slot.kind().clone()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (-0x30, 8, StackSlotKind::Buffer),
+            (-0x18, 8, StackSlotKind::Local),
+            (-0x10, 8, StackSlotKind::Local),
+            (-0x8, 8, StackSlotKind::SavedRegister(il::scalar("rbx", 64))),
+            (0, 8, StackSlotKind::ReturnAddress),
+            (8, 8, StackSlotKind::Argument),
+        ]
+    );
+    assert!(frame.slot(-0x18).unwrap().escapes());
+    assert!(!frame.slot(-0x10).unwrap().escapes());
+    assert!(frame.is_promotable(-0x10));
+    assert!(!frame.is_promotable(-0x18));
+    assert!(!frame.is_promotable(-0x30));
+
+    let location = |block, instruction| {
+        il::ProgramLocation::new(None, il::FunctionLocation::Instruction(block, instruction))
+    };
+    let buffer = frame.access(&location(call_index, 5)).unwrap();
+    assert_eq!(buffer.slot(), -0x30);
+    assert_eq!(buffer.offset(), None);
+    assert_eq!(buffer.bits(), 32);
+
+    let promoted = promote_stack_slots(&function, &frame).unwrap();
+    let var = il::scalar("var_10", 64);
+    assert_eq!(
+        *promoted
+            .block(call_index)
+            .unwrap()
+            .instruction(3)
+            .unwrap()
+            .operation(),
+        il::Operation::assign(var.clone(), il::expr_scalar("rbx", 64))
+    );
+    assert_eq!(
+        *promoted
+            .block(return_index)
+            .unwrap()
+            .instruction(0)
+            .unwrap()
+            .operation(),
+        il::Operation::assign(il::scalar("rax", 64), il::Expression::scalar(var))
+    );
+    assert_eq!(
+        promoted
+            .block(call_index)
+            .unwrap()
+            .instruction(4)
+            .unwrap()
+            .operation(),
+        function
+            .block(call_index)
+            .unwrap()
+            .instruction(4)
+            .unwrap()
+            .operation()
+    );
+}
+
+#[test]
+fn stack_frame_aliased_test() {
+    /*
+    rsp = rsp - 0x10
+    [rsp + 8] = 0
+    p = in ? rsp + 8 : 0x9000
+    [p] = 1
+    rax = [rsp + 8]
+    */
+    use crate::architecture::Amd64;
+
+    let rsp = || il::expr_scalar("rsp", 64);
+    let rsp_plus = |offset| il::Expression::add(rsp(), il::expr_const(offset, 64)).unwrap();
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let block_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(
+            il::scalar("rsp", 64),
+            il::Expression::sub(rsp(), il::expr_const(0x10, 64)).unwrap(),
+        );
+        block.store(rsp_plus(8), il::expr_const(0, 64));
+        block.assign(
+            il::scalar("p", 64),
+            il::Expression::ite(
+                il::expr_scalar("in", 1),
+                rsp_plus(8),
+                il::expr_const(0x9000, 64),
+            )
+            .unwrap(),
+        );
+        block.store(il::expr_scalar("p", 64), il::expr_const(1, 64));
+        block.load(il::scalar("rax", 64), rsp_plus(8));
+        block.index()
+    };
+    control_flow_graph.set_entry(block_index).unwrap();
+    let function = il::Function::new(0x1000, control_flow_graph);
+
+    let frame = stack_frame(&function, &Amd64::new()).unwrap();
+
+    // The store through p may be to the stack, or to global memory, so it is
+    // not a stack access, and the slot it may store to is not promoted.
+    let location = |instruction| {
+        il::ProgramLocation::new(
+            None,
+            il::FunctionLocation::Instruction(block_index, instruction),
+        )
+    };
+    assert!(frame.access(&location(3)).is_none());
+    assert!(frame.slot(-8).unwrap().escapes());
+    assert!(!frame.is_promotable(-8));
+
+    let promoted = promote_stack_slots(&function, &frame).unwrap();
+    assert_eq!(promoted, function);
+}
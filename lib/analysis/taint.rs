@@ -0,0 +1,1013 @@
+//! Static taint analysis.
+//!
+//! Values returned by, or written by, calls to source functions are tainted,
+//! and taint is propagated forwards through scalars, and through memory on
+//! the stack and at global addresses. A flow is reported for each call to a
+//! sink function whose argument, or the buffer its argument points to, is
+//! tainted.
+//!
+//! Sources and sinks are named by `Symbol`, ignoring leading underscores and
+//! any version or `@plt` suffix, and the arguments they taint or check are
+//! found with the `CallingConvention` of the `Architecture`.
+//! Addresses are resolved with `value_sets`, so memory is tracked in the
+//! `Region` of a `ValueSet`. Loads and stores whose addresses cannot be
+//! resolved neither read nor write taint.
+//!
+//! A call is a branch with a successor, or to a constant target. `taint`
+//! does not follow values into, or out of, the functions called, and calls
+//! clear the taint of the registers the calling convention trashes.
+//! `taint_interprocedural` follows taint through the functions of a program
+//! which are called.
+
+use crate::analysis::calling_convention::{ArgumentType, CallingConvention};
+use crate::analysis::fixed_point;
+use crate::analysis::interprocedural;
+use crate::analysis::value_set::{
+    value_sets, value_sets_interprocedural, Region, ValueSet, ValueSets,
+};
+use crate::architecture::Architecture;
+use crate::error::*;
+use crate::executor::eval;
+use crate::il;
+use crate::loader::{base_name, Symbol};
+use std::cmp::{Ordering, PartialOrd};
+use std::collections::{BTreeMap, HashMap};
+
+/// The largest number of addresses a value may point to before accesses
+/// through it are ignored.
+const MAX_ADDRESSES: u64 = 64;
+
+/// The default length, in bytes, of the buffers sources taint and sinks
+/// check.
+const BUFFER_LENGTH: usize = 0x100;
+
+/// Find the flows from sources to sinks in a function.
+pub fn taint(
+    function: &il::Function,
+    architecture: &dyn Architecture,
+    symbols: &[Symbol],
+    config: &TaintConfig,
+) -> Result<Vec<TaintFlow>> {
+    check_sinks(config)?;
+
+    let value_sets = value_sets(function, architecture, &[])?;
+    let analysis = TaintAnalysis::new(architecture, &value_sets, symbols, config);
+    let states = fixed_point::fixed_point_forward(analysis.clone(), function)?;
+
+    let mut flows = Vec::new();
+    for program_location in states.keys() {
+        let location = il::RefProgramLocation::new(
+            function,
+            program_location.function_location().apply(function)?,
+        );
+        let state = location.backward()?.into_iter().fold(
+            TaintState::default(),
+            |mut state, predecessor| {
+                if let Some(predecessor) = states.get(&predecessor.into()) {
+                    state.join(predecessor);
+                }
+                state
+            },
+        );
+        let source_name = |source: &il::ProgramLocation| {
+            source
+                .function_location()
+                .apply(function)
+                .ok()
+                .and_then(|source| analysis.call(&il::RefProgramLocation::new(function, source)))
+        };
+        flows.append(&mut analysis.flows(&location, &state, source_name));
+    }
+
+    sort_flows(&mut flows);
+    Ok(flows)
+}
+
+/// Find the flows from sources to sinks in a program, following taint into,
+/// and back out of, the functions of the program which are called.
+///
+/// Callees are given the taint of registers, and of memory outside of the
+/// caller's stack frame. Calls are analysed in contexts of the last
+/// `context_depth` call sites.
+pub fn taint_interprocedural(
+    program: &il::Program,
+    architecture: &dyn Architecture,
+    symbols: &[Symbol],
+    config: &TaintConfig,
+    context_depth: usize,
+) -> Result<Vec<TaintFlow>> {
+    check_sinks(config)?;
+
+    let value_sets = value_sets_interprocedural(program, architecture, &[], context_depth)?;
+    let analysis = TaintAnalysis::new(architecture, &value_sets, symbols, config);
+    let states = interprocedural::fixed_point_interprocedural(
+        &analysis,
+        program,
+        None,
+        context_depth,
+        &fixed_point::FixedPointOptions::new(),
+    )?
+    .merge_inputs(&analysis)?;
+
+    let mut flows = Vec::new();
+    for (program_location, state) in &states {
+        let location = program_location.apply(program)?;
+        let source_name = |source: &il::ProgramLocation| {
+            source
+                .apply(program)
+                .ok()
+                .and_then(|source| analysis.call(&source))
+        };
+        flows.append(&mut analysis.flows(&location, state, source_name));
+    }
+
+    sort_flows(&mut flows);
+    Ok(flows)
+}
+
+fn check_sinks(config: &TaintConfig) -> Result<()> {
+    if let Some((name, _)) = config
+        .sinks
+        .iter()
+        .find(|(_, target)| *target == TaintTarget::Return)
+    {
+        bail!("Taint sink {} cannot check the value it returns", name);
+    }
+    Ok(())
+}
+
+fn sort_flows(flows: &mut [TaintFlow]) {
+    flows.sort_by(|lhs, rhs| {
+        (&lhs.sink, &lhs.source, &lhs.sink_name).cmp(&(&rhs.sink, &rhs.source, &rhs.sink_name))
+    });
+}
+
+/// Find the flows from sources to sinks in every function of a program.
+pub fn taint_program(
+    program: &il::Program,
+    architecture: &dyn Architecture,
+    symbols: &[Symbol],
+    config: &TaintConfig,
+) -> Result<Vec<TaintFlow>> {
+    let mut flows = Vec::new();
+    for function in program.functions() {
+        flows.append(&mut taint(function, architecture, symbols, config)?);
+    }
+    Ok(flows)
+}
+
+/// The value a source taints, or a sink checks, at a call.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TaintTarget {
+    /// The value of the argument with the given index.
+    Argument(usize),
+    /// The buffer pointed to by the argument with the given index.
+    Buffer(usize),
+    /// The value returned by the call. This is only valid for sources.
+    Return,
+}
+
+/// The sources and sinks of a taint analysis.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TaintConfig {
+    sources: Vec<(String, TaintTarget)>,
+    sinks: Vec<(String, TaintTarget)>,
+    buffer_length: usize,
+}
+
+impl TaintConfig {
+    /// Create a new `TaintConfig` with no sources or sinks.
+    pub fn new() -> TaintConfig {
+        TaintConfig {
+            sources: Vec::new(),
+            sinks: Vec::new(),
+            buffer_length: BUFFER_LENGTH,
+        }
+    }
+
+    /// Taint the given target after each call to the named function.
+    pub fn add_source<S: Into<String>>(&mut self, name: S, target: TaintTarget) {
+        self.sources.push((name.into(), target));
+    }
+
+    /// Report a flow when the given target is tainted at a call to the named
+    /// function.
+    pub fn add_sink<S: Into<String>>(&mut self, name: S, target: TaintTarget) {
+        self.sinks.push((name.into(), target));
+    }
+
+    /// Get the sources, by the name of their function.
+    pub fn sources(&self) -> &[(String, TaintTarget)] {
+        &self.sources
+    }
+
+    /// Get the sinks, by the name of their function.
+    pub fn sinks(&self) -> &[(String, TaintTarget)] {
+        &self.sinks
+    }
+
+    /// Get the length, in bytes, of the buffers sources taint and sinks
+    /// check.
+    pub fn buffer_length(&self) -> usize {
+        self.buffer_length
+    }
+
+    /// Set the length, in bytes, of the buffers sources taint and sinks
+    /// check.
+    pub fn set_buffer_length(&mut self, buffer_length: usize) {
+        self.buffer_length = buffer_length;
+    }
+}
+
+impl Default for TaintConfig {
+    fn default() -> TaintConfig {
+        TaintConfig::new()
+    }
+}
+
+/// A flow of taint from a call to a source, to a call to a sink.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TaintFlow {
+    source: il::ProgramLocation,
+    source_name: String,
+    sink: il::ProgramLocation,
+    sink_name: String,
+    target: TaintTarget,
+    path: Vec<il::ProgramLocation>,
+}
+
+impl TaintFlow {
+    /// Get the location of the call to the source.
+    pub fn source(&self) -> &il::ProgramLocation {
+        &self.source
+    }
+
+    /// Get the name of the source function.
+    pub fn source_name(&self) -> &str {
+        &self.source_name
+    }
+
+    /// Get the location of the call to the sink.
+    pub fn sink(&self) -> &il::ProgramLocation {
+        &self.sink
+    }
+
+    /// Get the name of the sink function.
+    pub fn sink_name(&self) -> &str {
+        &self.sink_name
+    }
+
+    /// Get the target of the sink which was tainted.
+    pub fn target(&self) -> TaintTarget {
+        self.target
+    }
+
+    /// Get the locations the taint flowed through, from the source to the
+    /// sink.
+    pub fn path(&self) -> &[il::ProgramLocation] {
+        &self.path
+    }
+}
+
+/// The sources a value is tainted by, with the path from each source.
+#[derive(Clone, Debug, Default)]
+struct Taint {
+    paths: BTreeMap<il::ProgramLocation, Vec<il::ProgramLocation>>,
+}
+
+impl Taint {
+    fn source(location: il::ProgramLocation) -> Taint {
+        let mut paths = BTreeMap::new();
+        paths.insert(location.clone(), vec![location]);
+        Taint { paths }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Join two taints, keeping the first path found from each source.
+    fn join(&mut self, other: &Taint) {
+        for (source, path) in &other.paths {
+            self.paths
+                .entry(source.clone())
+                .or_insert_with(|| path.clone());
+        }
+    }
+
+    /// Extend the path from each source through a location.
+    fn through(mut self, location: &il::ProgramLocation) -> Taint {
+        for path in self.paths.values_mut() {
+            if !path.contains(location) {
+                path.push(location.clone());
+            }
+        }
+        self
+    }
+
+    fn is_subset(&self, other: &Taint) -> bool {
+        self.paths
+            .keys()
+            .all(|source| other.paths.contains_key(source))
+    }
+}
+
+/// The taint of scalars, and of each byte of memory.
+#[derive(Clone, Debug, Default)]
+struct TaintState {
+    scalars: HashMap<il::Scalar, Taint>,
+    memory: BTreeMap<(Region, u64), Taint>,
+}
+
+impl TaintState {
+    fn set_scalar(&mut self, scalar: il::Scalar, taint: Taint) {
+        if taint.is_empty() {
+            self.scalars.remove(&scalar);
+        } else {
+            self.scalars.insert(scalar, taint);
+        }
+    }
+
+    fn eval(&self, expression: &il::Expression) -> Taint {
+        expression
+            .scalars()
+            .into_iter()
+            .fold(Taint::default(), |mut taint, scalar| {
+                if let Some(scalar_taint) = self.scalars.get(scalar) {
+                    taint.join(scalar_taint);
+                }
+                taint
+            })
+    }
+
+    /// The taint of the bytes at each address, up to the given length.
+    fn load(&self, addresses: &[(Region, u64)], length: usize) -> Taint {
+        let mut taint = Taint::default();
+        for (region, offset) in addresses {
+            for byte in 0..length as u64 {
+                if let Some(byte_taint) = self.memory.get(&(*region, offset.wrapping_add(byte))) {
+                    taint.join(byte_taint);
+                }
+            }
+        }
+        taint
+    }
+
+    /// Taint the bytes at each address, up to the given length. The taint
+    /// replaces the taint of the bytes if there is only one address.
+    fn store(&mut self, addresses: &[(Region, u64)], length: usize, taint: &Taint) {
+        let strong = addresses.len() == 1;
+        for (region, offset) in addresses {
+            for byte in 0..length as u64 {
+                let key = (*region, offset.wrapping_add(byte));
+                if taint.is_empty() {
+                    if strong {
+                        self.memory.remove(&key);
+                    }
+                } else if strong {
+                    self.memory.insert(key, taint.clone());
+                } else {
+                    self.memory.entry(key).or_default().join(taint);
+                }
+            }
+        }
+    }
+
+    fn join(&mut self, other: &TaintState) {
+        for (scalar, taint) in &other.scalars {
+            self.scalars.entry(scalar.clone()).or_default().join(taint);
+        }
+        for (key, taint) in &other.memory {
+            self.memory.entry(*key).or_default().join(taint);
+        }
+    }
+
+    fn is_subset(&self, other: &TaintState) -> bool {
+        self.scalars.iter().all(|(scalar, taint)| {
+            other
+                .scalars
+                .get(scalar)
+                .is_some_and(|other| taint.is_subset(other))
+        }) && self.memory.iter().all(|(key, taint)| {
+            other
+                .memory
+                .get(key)
+                .is_some_and(|other| taint.is_subset(other))
+        })
+    }
+}
+
+impl PartialEq for TaintState {
+    fn eq(&self, other: &TaintState) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for TaintState {
+    fn partial_cmp(&self, other: &TaintState) -> Option<Ordering> {
+        match (self.is_subset(other), other.is_subset(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (false, false) => None,
+        }
+    }
+}
+
+/// The addresses a value set may point to, or none if there are too many.
+fn addresses(value_set: &ValueSet) -> Vec<(Region, u64)> {
+    let mut addresses = Vec::new();
+    for (region, interval) in value_set.regions() {
+        if let Some(offsets) = interval.values(MAX_ADDRESSES) {
+            addresses.extend(offsets.into_iter().map(|offset| (*region, offset)));
+        }
+    }
+    if addresses.len() as u64 > MAX_ADDRESSES {
+        return Vec::new();
+    }
+    addresses
+}
+
+#[derive(Clone)]
+struct TaintAnalysis<'a> {
+    calling_convention: CallingConvention,
+    stack_pointer: il::Scalar,
+    value_sets: &'a HashMap<il::ProgramLocation, ValueSets>,
+    /// The value sets of locations which have none, which hold any value.
+    unknown: ValueSets,
+    /// The names of functions, by their address.
+    names: HashMap<u64, &'a str>,
+    config: &'a TaintConfig,
+}
+
+impl<'a> TaintAnalysis<'a> {
+    fn new(
+        architecture: &dyn Architecture,
+        value_sets: &'a HashMap<il::ProgramLocation, ValueSets>,
+        symbols: &'a [Symbol],
+        config: &'a TaintConfig,
+    ) -> TaintAnalysis<'a> {
+        TaintAnalysis {
+            calling_convention: architecture.calling_convention(),
+            stack_pointer: architecture.stack_pointer(),
+            value_sets,
+            unknown: ValueSets::new(),
+            names: symbols
+                .iter()
+                .map(|symbol| (symbol.address(), base_name(symbol.name())))
+                .collect(),
+            config,
+        }
+    }
+
+    /// The value sets before a location.
+    fn value_sets(&self, location: &il::ProgramLocation) -> &ValueSets {
+        self.value_sets.get(location).unwrap_or(&self.unknown)
+    }
+
+    /// The flows into the sinks called at a location, given the taint before
+    /// the location, and the names of the sources called at each location.
+    fn flows<F>(
+        &self,
+        location: &il::RefProgramLocation,
+        state: &TaintState,
+        source_name: F,
+    ) -> Vec<TaintFlow>
+    where
+        F: Fn(&il::ProgramLocation) -> Option<&'a str>,
+    {
+        let sink_name = match self.call(location) {
+            Some(name) => name,
+            None => return Vec::new(),
+        };
+        let program_location: il::ProgramLocation = location.clone().into();
+        let value_sets = self.value_sets(&program_location);
+
+        let mut flows = Vec::new();
+        for (name, target) in &self.config.sinks {
+            if base_name(name) != sink_name {
+                continue;
+            }
+            let taint = match *target {
+                TaintTarget::Argument(argument) => self.argument_taint(state, value_sets, argument),
+                TaintTarget::Buffer(argument) => {
+                    let pointer = self.argument(value_sets, argument);
+                    state.load(&addresses(&pointer), self.config.buffer_length)
+                }
+                TaintTarget::Return => Taint::default(),
+            };
+            for (source, path) in taint.paths {
+                let source_name = source_name(&source).unwrap_or_default();
+                let mut path = path;
+                path.push(program_location.clone());
+                flows.push(TaintFlow {
+                    source,
+                    source_name: source_name.to_string(),
+                    sink: program_location.clone(),
+                    sink_name: sink_name.to_string(),
+                    target: *target,
+                    path,
+                });
+            }
+        }
+        flows
+    }
+
+    /// The name of the function called at a location, if it is a call to a
+    /// named function.
+    fn call(&self, location: &il::RefProgramLocation) -> Option<&'a str> {
+        match *location.instruction()?.operation() {
+            il::Operation::Branch { ref target } => {
+                let address = eval(target).ok()?.value_u64()?;
+                self.names.get(&address).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    /// The address of an argument passed on the stack.
+    fn stack_argument(&self, value_sets: &ValueSets, offset: usize) -> Vec<(Region, u64)> {
+        let bits = self.stack_pointer.bits();
+        il::Expression::add(
+            il::Expression::scalar(self.stack_pointer.clone()),
+            il::expr_const(offset as u64, bits),
+        )
+        .map(|address| addresses(&value_sets.eval(&address)))
+        .unwrap_or_default()
+    }
+
+    /// The value set of an argument.
+    fn argument(&self, value_sets: &ValueSets, argument: usize) -> ValueSet {
+        match self.calling_convention.argument_type(argument) {
+            ArgumentType::Register(scalar) => value_sets.scalar(&scalar),
+            ArgumentType::Stack(offset) => {
+                let bits = self.stack_pointer.bits();
+                match il::Expression::add(
+                    il::Expression::scalar(self.stack_pointer.clone()),
+                    il::expr_const(offset as u64, bits),
+                ) {
+                    Ok(address) => value_sets.load(&address, bits),
+                    Err(_) => ValueSet::top(bits),
+                }
+            }
+        }
+    }
+
+    /// The taint of an argument.
+    fn argument_taint(&self, state: &TaintState, value_sets: &ValueSets, argument: usize) -> Taint {
+        match self.calling_convention.argument_type(argument) {
+            ArgumentType::Register(scalar) => {
+                state.scalars.get(&scalar).cloned().unwrap_or_default()
+            }
+            ArgumentType::Stack(offset) => state.load(
+                &self.stack_argument(value_sets, offset),
+                self.calling_convention.stack_argument_length(),
+            ),
+        }
+    }
+
+    /// Clear the taint of the registers a call trashes, and taint the
+    /// targets of the sources called at a location.
+    fn sources(
+        &self,
+        mut state: TaintState,
+        location: &il::RefProgramLocation,
+        value_sets: &ValueSets,
+    ) -> TaintState {
+        for scalar in self.calling_convention.trashed_registers() {
+            state.scalars.remove(scalar);
+        }
+        self.taint_sources(state, location, value_sets)
+    }
+
+    /// Taint the targets of the sources called at a location.
+    fn taint_sources(
+        &self,
+        mut state: TaintState,
+        location: &il::RefProgramLocation,
+        value_sets: &ValueSets,
+    ) -> TaintState {
+        let name = match self.call(location) {
+            Some(name) => name,
+            None => return state,
+        };
+        let program_location: il::ProgramLocation = location.clone().into();
+        for (source_name, target) in &self.config.sources {
+            if base_name(source_name) != name {
+                continue;
+            }
+            let taint = Taint::source(program_location.clone());
+            match *target {
+                TaintTarget::Argument(argument) => {
+                    match self.calling_convention.argument_type(argument) {
+                        ArgumentType::Register(scalar) => state.set_scalar(scalar, taint),
+                        ArgumentType::Stack(offset) => state.store(
+                            &self.stack_argument(value_sets, offset),
+                            self.calling_convention.stack_argument_length(),
+                            &taint,
+                        ),
+                    }
+                }
+                TaintTarget::Buffer(argument) => {
+                    let pointer = self.argument(value_sets, argument);
+                    state.store(&addresses(&pointer), self.config.buffer_length, &taint);
+                }
+                TaintTarget::Return => {
+                    state.set_scalar(self.calling_convention.return_register().clone(), taint)
+                }
+            }
+        }
+        state
+    }
+}
+
+impl<'a, 'f> fixed_point::FixedPointAnalysis<'f, TaintState> for TaintAnalysis<'a> {
+    fn trans(
+        &self,
+        location: il::RefProgramLocation<'f>,
+        state: Option<TaintState>,
+    ) -> Result<TaintState> {
+        let mut state = state.unwrap_or_default();
+
+        let instruction = match *location.function_location() {
+            il::RefFunctionLocation::Instruction(_, instruction) => instruction,
+            _ => return Ok(state),
+        };
+        let program_location: il::ProgramLocation = location.clone().into();
+        let value_sets = self.value_sets(&program_location);
+
+        match *instruction.operation() {
+            il::Operation::Assign { ref dst, ref src } => {
+                let taint = state.eval(src).through(&program_location);
+                state.set_scalar(dst.clone(), taint);
+            }
+            il::Operation::Store { ref index, ref src } => {
+                let taint = state.eval(src).through(&program_location);
+                let addresses = addresses(&value_sets.eval(index));
+                state.store(&addresses, src.bits() / 8, &taint);
+            }
+            il::Operation::Load { ref dst, ref index } => {
+                let addresses = addresses(&value_sets.eval(index));
+                let taint = state
+                    .load(&addresses, dst.bits() / 8)
+                    .through(&program_location);
+                state.set_scalar(dst.clone(), taint);
+            }
+            il::Operation::Branch { ref target } => {
+                if interprocedural::is_call(&location, target)? {
+                    state = self.sources(state, &location, value_sets);
+                }
+            }
+            il::Operation::Intrinsic { ref intrinsic } => {
+                if let Some(scalars_written) = intrinsic.scalars_written() {
+                    let taint = intrinsic
+                        .scalars_read()
+                        .unwrap_or_default()
+                        .into_iter()
+                        .fold(Taint::default(), |mut taint, scalar| {
+                            if let Some(scalar_taint) = state.scalars.get(scalar) {
+                                taint.join(scalar_taint);
+                            }
+                            taint
+                        })
+                        .through(&program_location);
+                    for scalar in scalars_written {
+                        state.set_scalar(scalar.clone(), taint.clone());
+                    }
+                }
+            }
+            il::Operation::Nop => {}
+        }
+
+        Ok(state)
+    }
+
+    fn join(&self, mut state0: TaintState, state1: &TaintState) -> Result<TaintState> {
+        state0.join(state1);
+        Ok(state0)
+    }
+}
+
+impl<'a, 'p> interprocedural::InterproceduralAnalysis<'p, TaintState> for TaintAnalysis<'a> {
+    fn entry(&self, _function: &'p il::Function) -> Result<TaintState> {
+        Ok(TaintState::default())
+    }
+
+    fn trans(&self, location: il::RefProgramLocation<'p>, state: TaintState) -> Result<TaintState> {
+        fixed_point::FixedPointAnalysis::trans(self, location, Some(state))
+    }
+
+    fn join(&self, mut state0: TaintState, state1: &TaintState) -> Result<TaintState> {
+        state0.join(state1);
+        Ok(state0)
+    }
+
+    /// The callee is given the taint of registers, and of memory outside of
+    /// the caller's stack frame.
+    fn call(
+        &self,
+        _location: &il::RefProgramLocation<'p>,
+        _callee: &'p il::Function,
+        state: &TaintState,
+    ) -> Result<TaintState> {
+        let mut state = state.clone();
+        state
+            .memory
+            .retain(|(region, _), _| *region != Region::Stack);
+        Ok(state)
+    }
+
+    fn summary(&self, _function: &'p il::Function, mut exit: TaintState) -> Result<TaintState> {
+        exit.memory
+            .retain(|(region, _), _| *region != Region::Stack);
+        Ok(exit)
+    }
+
+    /// The taint of the registers the calling convention preserves, and of
+    /// the caller's stack frame, is kept. Everything else is taken from the
+    /// summary of the callee, and then the targets of a source are tainted.
+    fn return_(
+        &self,
+        location: &il::RefProgramLocation<'p>,
+        state: &TaintState,
+        summary: &TaintState,
+    ) -> Result<TaintState> {
+        let preserved = |scalar: &il::Scalar| {
+            *scalar == self.stack_pointer
+                || self.calling_convention.is_preserved(scalar) == Some(true)
+        };
+        let mut result = summary.clone();
+        result.scalars.retain(|scalar, _| !preserved(scalar));
+        for (scalar, taint) in &state.scalars {
+            if preserved(scalar) {
+                result.scalars.insert(scalar.clone(), taint.clone());
+            }
+        }
+        for (key, taint) in &state.memory {
+            if key.0 == Region::Stack {
+                result.memory.insert(*key, taint.clone());
+            }
+        }
+        let value_sets = self.value_sets(&location.clone().into());
+        Ok(self.taint_sources(result, location, value_sets))
+    }
+
+    fn unknown_call(
+        &self,
+        location: &il::RefProgramLocation<'p>,
+        state: TaintState,
+    ) -> Result<TaintState> {
+        let value_sets = self.value_sets(&location.clone().into());
+        Ok(self.sources(state, location, value_sets))
+    }
+}
+
+#[test]
+fn taint_test() {
+    /*
+    rsp = rsp - 0x200
+    rsi = rsp
+    rdi = 3
+    recv()
+    rdi = rsp
+    system()
+    atoi()
+    [0x9000] = rax
+    rdx = [0x9000]
+    memcpy()
+    [0x9000] = 0
+    rdx = [0x9000]
+    memcpy()
+    */
+    use crate::architecture::Amd64;
+
+    let rsp = || il::expr_scalar("rsp", 64);
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let recv_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(
+            il::scalar("rsp", 64),
+            il::Expression::sub(rsp(), il::expr_const(0x200, 64)).unwrap(),
+        );
+        block.assign(il::scalar("rsi", 64), rsp());
+        block.assign(il::scalar("rdi", 64), il::expr_const(3, 64));
+        block.branch(il::expr_const(0x4000, 64));
+        block.index()
+    };
+
+    let system_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rdi", 64), rsp());
+        block.branch(il::expr_const(0x5000, 64));
+        block.branch(il::expr_const(0x6000, 64));
+        block.index()
+    };
+
+    let memcpy_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.store(il::expr_const(0x9000, 64), il::expr_scalar("rax", 64));
+        block.load(il::scalar("rdx", 64), il::expr_const(0x9000, 64));
+        block.branch(il::expr_const(0x7000, 64));
+        block.index()
+    };
+
+    let clean_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.store(il::expr_const(0x9000, 64), il::expr_const(0, 64));
+        block.load(il::scalar("rdx", 64), il::expr_const(0x9000, 64));
+        block.branch(il::expr_const(0x7000, 64));
+        block.index()
+    };
+
+    control_flow_graph
+        .unconditional_edge(recv_index, system_index)
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(system_index, memcpy_index)
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(memcpy_index, clean_index)
+        .unwrap();
+    control_flow_graph.set_entry(recv_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+
+    let symbols = vec![
+        Symbol::new("recv", 0x4000),
+        Symbol::new("system", 0x5000),
+        Symbol::new("atoi", 0x6000),
+        Symbol::new("memcpy", 0x7000),
+    ];
+
+    let mut config = TaintConfig::new();
+    config.add_source("recv", TaintTarget::Buffer(1));
+    config.add_source("atoi", TaintTarget::Return);
+    config.add_sink("system", TaintTarget::Buffer(0));
+    config.add_sink("memcpy", TaintTarget::Argument(2));
+
+    let location = |block, instruction| {
+        il::ProgramLocation::new(None, il::FunctionLocation::Instruction(block, instruction))
+    };
+
+    let flows = taint(&function, &Amd64::new(), &symbols, &config).unwrap();
+    assert_eq!(flows.len(), 2);
+
+    assert_eq!(flows[0].source(), &location(recv_index, 3));
+    assert_eq!(flows[0].source_name(), "recv");
+    assert_eq!(flows[0].sink(), &location(system_index, 1));
+    assert_eq!(flows[0].sink_name(), "system");
+    assert_eq!(flows[0].target(), TaintTarget::Buffer(0));
+    assert_eq!(
+        flows[0].path(),
+        &[location(recv_index, 3), location(system_index, 1)]
+    );
+
+    assert_eq!(flows[1].source_name(), "atoi");
+    assert_eq!(flows[1].sink(), &location(memcpy_index, 2));
+    assert_eq!(
+        flows[1].path(),
+        &[
+            location(system_index, 2),
+            location(memcpy_index, 0),
+            location(memcpy_index, 1),
+            location(memcpy_index, 2)
+        ]
+    );
+
+    config.add_sink("system", TaintTarget::Return);
+    assert!(taint(&function, &Amd64::new(), &symbols, &config).is_err());
+}
+
+#[test]
+fn taint_versioned_symbols_test() {
+    /*
+    rsp = rsp - 0x200
+    rsi = rsp
+    recv@GLIBC_2.2.5()
+    rdi = rsp
+    system@plt()
+    */
+    use crate::architecture::Amd64;
+
+    let rsp = || il::expr_scalar("rsp", 64);
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let recv_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(
+            il::scalar("rsp", 64),
+            il::Expression::sub(rsp(), il::expr_const(0x200, 64)).unwrap(),
+        );
+        block.assign(il::scalar("rsi", 64), rsp());
+        block.branch(il::expr_const(0x4000, 64));
+        block.index()
+    };
+
+    let system_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rdi", 64), rsp());
+        block.branch(il::expr_const(0x5000, 64));
+        block.index()
+    };
+
+    control_flow_graph
+        .unconditional_edge(recv_index, system_index)
+        .unwrap();
+    control_flow_graph.set_entry(recv_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+
+    let symbols = vec![
+        Symbol::new("recv@GLIBC_2.2.5", 0x4000),
+        Symbol::new("system@plt", 0x5000),
+    ];
+
+    let mut config = TaintConfig::new();
+    config.add_source("recv", TaintTarget::Buffer(1));
+    config.add_sink("system", TaintTarget::Buffer(0));
+
+    let flows = taint(&function, &Amd64::new(), &symbols, &config).unwrap();
+    assert_eq!(flows.len(), 1);
+    assert_eq!(flows[0].source_name(), "recv");
+    assert_eq!(flows[0].sink_name(), "system");
+}
+
+#[test]
+fn taint_interprocedural_test() {
+    /*
+    0x1000:
+        call 0x2000
+        rdx = rax
+        memcpy()
+
+    0x2000:
+        atoi()
+        return
+    */
+    use crate::architecture::Amd64;
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.branch(il::expr_const(0x2000, 64));
+        block.index()
+    };
+    let memcpy_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rdx", 64), il::expr_scalar("rax", 64));
+        block.branch(il::expr_const(0x7000, 64));
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(call_index, memcpy_index)
+        .unwrap();
+    control_flow_graph.set_entry(call_index).unwrap();
+    let main = il::Function::new(0x1000, control_flow_graph);
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let atoi_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.branch(il::expr_const(0x6000, 64));
+        block.index()
+    };
+    let return_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.branch(il::expr_scalar("ra", 64));
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(atoi_index, return_index)
+        .unwrap();
+    control_flow_graph.set_entry(atoi_index).unwrap();
+    let callee = il::Function::new(0x2000, control_flow_graph);
+
+    let mut program = il::Program::new();
+    program.add_function(main);
+    program.add_function(callee);
+
+    let symbols = vec![Symbol::new("atoi", 0x6000), Symbol::new("memcpy", 0x7000)];
+
+    let mut config = TaintConfig::new();
+    config.add_source("atoi", TaintTarget::Return);
+    config.add_sink("memcpy", TaintTarget::Argument(2));
+
+    // The call to 0x2000 trashes rax.
+    let function = program.function(0).unwrap();
+    let flows = taint(function, &Amd64::new(), &symbols, &config).unwrap();
+    assert!(flows.is_empty());
+
+    // The taint atoi returns is returned by 0x2000.
+    let flows = taint_interprocedural(&program, &Amd64::new(), &symbols, &config, 0).unwrap();
+    assert_eq!(flows.len(), 1);
+    assert_eq!(
+        flows[0].source(),
+        &il::ProgramLocation::new(Some(1), il::FunctionLocation::Instruction(atoi_index, 0))
+    );
+    assert_eq!(flows[0].source_name(), "atoi");
+    assert_eq!(
+        flows[0].sink(),
+        &il::ProgramLocation::new(Some(0), il::FunctionLocation::Instruction(memcpy_index, 1))
+    );
+    assert_eq!(flows[0].sink_name(), "memcpy");
+}
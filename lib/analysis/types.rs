@@ -0,0 +1,1154 @@
+//! Constraint-based type recovery.
+//!
+//! Falcon IL only gives values a width in bits. `types` recovers the `Type`
+//! of the values in a program by unifying type variables:
+//!
+//! * Each definition of a scalar, each scalar a function reads at entry,
+//!   each slot of its `StackFrame`, and the value it returns, is a type
+//!   variable. A read of a scalar unifies the definitions which reach it.
+//! * The address of a `Load` or `Store` is a pointer. An address which is a
+//!   pointer plus a constant, directly or through a scalar assigned one,
+//!   points to a struct with a field at that offset, of the type of the value
+//!   loaded or stored.
+//! * Products, quotients, bitwise operations, shifts, comparisons and
+//!   extensions are integers, as are the operands of products, quotients
+//!   and shifts. The operands of a comparison have the same type.
+//! * A call to a function in the program, found with the `CallGraph`,
+//!   unifies the arguments of the call with the arguments of its
+//!   `Signature`, and the return register with the value it returns. A call
+//!   to a function named after a libc function is given its `Prototype`.
+//!
+//! Evidence a value is a pointer outweighs evidence it is an integer, as
+//! pointers are masked and compared. Values without constraints have no
+//! type.
+//!
+//! Types are found per definition, so for a function in SSA form each
+//! version of an `il::Scalar` has its own type.
+//!
+//! A function whose value sets, `Signature` or `StackFrame` cannot be
+//! recovered has no types, and calls to it give no constraints.
+
+use crate::analysis::call_graph::{CallGraph, CallGraphVertex, CallTarget};
+use crate::analysis::calling_convention::{ArgumentType, CallingConvention};
+use crate::analysis::interprocedural::is_call;
+use crate::analysis::reaching_definitions::{scalar_reaching_definitions, Definitions};
+use crate::analysis::signature::{signatures_with, Signature};
+use crate::analysis::stack_frame::{stack_frame_with, StackFrame};
+use crate::analysis::value_set::{value_sets, Region, ValueSets};
+use crate::architecture::Architecture;
+use crate::error::*;
+use crate::executor::eval;
+use crate::il;
+use crate::loader::{base_name, Symbol};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::mem;
+
+/// Recover the types of the values in every function of a program.
+pub fn types(
+    program: &il::Program,
+    architecture: &dyn Architecture,
+    symbols: &[Symbol],
+) -> Result<Types> {
+    let calling_convention = architecture.calling_convention();
+    let call_graph = CallGraph::new(program)?;
+
+    // A function whose value sets, signature or stack frame cannot be
+    // recovered gets no types.
+    let mut frames = BTreeMap::new();
+    for (index, function) in program.functions_map() {
+        let analyses = value_sets(function, architecture, &[]).and_then(|value_sets| {
+            let frame = stack_frame_with(function, architecture, &value_sets)?;
+            Ok((value_sets, frame))
+        });
+        match analyses {
+            Ok(analyses) => {
+                frames.insert(index, analyses);
+            }
+            Err(e) => warn!(
+                "Failed to recover the types of function {}: {}",
+                function.name(),
+                e
+            ),
+        }
+    }
+    let signatures = signatures_with(
+        program,
+        architecture,
+        &frames
+            .iter()
+            .map(|(index, (value_sets, _))| (*index, value_sets))
+            .collect(),
+    );
+    frames.retain(|index, _| signatures.contains_key(index));
+    let names: HashMap<u64, &str> = symbols
+        .iter()
+        .map(|symbol| (symbol.address(), symbol.name()))
+        .collect();
+
+    let mut solver = Solver::default();
+    for (index, function) in program.functions_map() {
+        let (value_sets, frame) = match frames.get(&index) {
+            Some(frame) => frame,
+            None => continue,
+        };
+        let mut callees = HashMap::new();
+        for (callee, call) in call_graph.calls_from(index)? {
+            callees.insert(call.location().clone(), call_graph.vertex(callee)?);
+        }
+        let definitions = scalar_reaching_definitions(function, Some(&calling_convention))?;
+
+        let mut constraints = Constraints {
+            solver: &mut solver,
+            index,
+            architecture,
+            calling_convention: &calling_convention,
+            signatures: &signatures,
+            names: &names,
+            callees,
+            definitions: HashMap::new(),
+            frame,
+            value_sets,
+        };
+        for location in definitions.keys() {
+            let location = il::RefProgramLocation::new(
+                function,
+                location.function_location().apply(function)?,
+            );
+            // The state at function entry holds every scalar's value at
+            // entry, so it is not the identity of join.
+            let state = location
+                .backward()?
+                .into_iter()
+                .filter_map(|predecessor| definitions.get(&predecessor.into()))
+                .fold(None, |state: Option<Definitions>, predecessor| {
+                    Some(match state {
+                        Some(mut state) => {
+                            state.join(predecessor);
+                            state
+                        }
+                        None => predecessor.clone(),
+                    })
+                })
+                .unwrap_or_default();
+            constraints
+                .definitions
+                .insert(location.clone().into(), state);
+        }
+        for location in definitions.keys() {
+            let location = il::RefProgramLocation::new(
+                function,
+                location.function_location().apply(function)?,
+            );
+            constraints.location(&location)?;
+        }
+    }
+    solver.propagate_offsets();
+
+    let mut functions: BTreeMap<usize, FunctionTypes> = frames
+        .into_keys()
+        .map(|index| (index, FunctionTypes::default()))
+        .collect();
+    let variables: Vec<(Variable, usize)> = solver
+        .variables
+        .iter()
+        .map(|(variable, node)| (variable.clone(), *node))
+        .collect();
+    let mut scalars: HashMap<(usize, il::Scalar), BTreeSet<Type>> = HashMap::new();
+    for (variable, node) in variables {
+        let type_ = match solver.type_of(node, &mut Vec::new()) {
+            Some(type_) => type_,
+            None => continue,
+        };
+        let index = match variable {
+            Variable::Definition(ref location, _) => location.function_index(),
+            Variable::Entry(index, _) | Variable::Slot(index, _) | Variable::Return(index) => {
+                Some(index)
+            }
+        };
+        let function = match index.and_then(|index| functions.get_mut(&index)) {
+            Some(function) => function,
+            None => continue,
+        };
+        match variable {
+            Variable::Definition(location, scalar) => {
+                scalars
+                    .entry((location.function_index().unwrap(), scalar.clone()))
+                    .or_default()
+                    .insert(type_.clone());
+                function.definitions.insert((location, scalar), type_);
+            }
+            Variable::Entry(index, scalar) => {
+                scalars
+                    .entry((index, scalar.clone()))
+                    .or_default()
+                    .insert(type_.clone());
+                function.entries.insert(scalar, type_);
+            }
+            Variable::Slot(_, offset) => {
+                function.slots.insert(offset, type_);
+            }
+            Variable::Return(_) => function.returns = Some(type_),
+        }
+    }
+    for ((index, scalar), types) in scalars {
+        if types.len() == 1 {
+            let function = functions.get_mut(&index).unwrap();
+            function
+                .scalars
+                .insert(scalar, types.into_iter().next().unwrap());
+        }
+    }
+    for (index, function) in functions.iter_mut() {
+        let signature = &signatures[index];
+        function.arguments = signature
+            .argument_types(&calling_convention)
+            .into_iter()
+            .map(|argument_type| match argument_type {
+                ArgumentType::Register(scalar) => function.entries.get(&scalar).cloned(),
+                ArgumentType::Stack(offset) => function.slots.get(&(offset as i64)).cloned(),
+            })
+            .collect();
+    }
+
+    Ok(Types { functions })
+}
+
+/// The type of a value.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Type {
+    /// An integer of the given width in bits.
+    Integer(usize),
+    /// A pointer to a value of the given type, or `None` if the type of the
+    /// value is unknown.
+    Pointer(Option<Box<Type>>),
+    /// A struct, with the type of each field by its offset in bytes.
+    Struct(BTreeMap<u64, Type>),
+}
+
+impl Type {
+    /// Create a pointer to a value of the given type.
+    pub fn pointer(pointee: Type) -> Type {
+        Type::Pointer(Some(Box::new(pointee)))
+    }
+
+    /// Returns true if this type is a pointer.
+    pub fn is_pointer(&self) -> bool {
+        matches!(*self, Type::Pointer(_))
+    }
+
+    /// Returns true if this type is an integer.
+    pub fn is_integer(&self) -> bool {
+        matches!(*self, Type::Integer(_))
+    }
+
+    /// Get the type of the value this type points to, if it is a pointer to
+    /// a value of known type.
+    pub fn pointee(&self) -> Option<&Type> {
+        match *self {
+            Type::Pointer(Some(ref pointee)) => Some(pointee),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Integer(1) => write!(f, "bool"),
+            Type::Integer(8) => write!(f, "char"),
+            Type::Integer(bits) => write!(f, "int{}_t", bits),
+            Type::Pointer(None) => write!(f, "void*"),
+            Type::Pointer(Some(ref pointee)) => write!(f, "{}*", pointee),
+            Type::Struct(ref fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(offset, field)| format!("0x{:x}: {}", offset, field))
+                    .collect();
+                write!(f, "struct {{ {} }}", fields.join(", "))
+            }
+        }
+    }
+}
+
+/// The types of the arguments a function takes, and the value it returns.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Prototype {
+    arguments: Vec<Type>,
+    returns: Option<Type>,
+}
+
+impl Prototype {
+    /// Create a new `Prototype`.
+    pub fn new(arguments: Vec<Type>, returns: Option<Type>) -> Prototype {
+        Prototype { arguments, returns }
+    }
+
+    /// Get the types of the arguments, in order. Variadic arguments are not
+    /// included.
+    pub fn arguments(&self) -> &[Type] {
+        &self.arguments
+    }
+
+    /// Get the type of the value returned, if a value is returned.
+    pub fn returns(&self) -> Option<&Type> {
+        self.returns.as_ref()
+    }
+}
+
+/// Get the prototype of a libc function by name, for an architecture with
+/// pointers of the given width in bits.
+///
+/// Leading underscores, and any version or `@plt` suffix, are ignored.
+pub fn libc_prototype(name: &str, bits: usize) -> Option<Prototype> {
+    let name = base_name(name);
+    let char_pointer = || Type::pointer(Type::Integer(8));
+    let void_pointer = || Type::Pointer(None);
+    let int = || Type::Integer(32);
+    let size = || Type::Integer(bits);
+
+    let (arguments, returns) = match name {
+        "atoi" => (vec![char_pointer()], Some(int())),
+        "calloc" => (vec![size(), size()], Some(void_pointer())),
+        "close" => (vec![int()], Some(int())),
+        "fgets" => (
+            vec![char_pointer(), int(), void_pointer()],
+            Some(char_pointer()),
+        ),
+        "fopen" => (vec![char_pointer(), char_pointer()], Some(void_pointer())),
+        "free" => (vec![void_pointer()], None),
+        "getenv" => (vec![char_pointer()], Some(char_pointer())),
+        "malloc" => (vec![size()], Some(void_pointer())),
+        "memcpy" | "memmove" => (
+            vec![void_pointer(), void_pointer(), size()],
+            Some(void_pointer()),
+        ),
+        "memset" => (vec![void_pointer(), int(), size()], Some(void_pointer())),
+        "open" => (vec![char_pointer(), int()], Some(int())),
+        "printf" | "puts" | "system" => (vec![char_pointer()], Some(int())),
+        "read" | "write" => (vec![int(), void_pointer(), size()], Some(size())),
+        "realloc" => (vec![void_pointer(), size()], Some(void_pointer())),
+        "recv" | "send" => (vec![int(), void_pointer(), size(), int()], Some(size())),
+        "snprintf" => (vec![char_pointer(), size(), char_pointer()], Some(int())),
+        "sprintf" | "strcmp" => (vec![char_pointer(), char_pointer()], Some(int())),
+        "strcat" | "strcpy" => (vec![char_pointer(), char_pointer()], Some(char_pointer())),
+        "strlen" => (vec![char_pointer()], Some(size())),
+        "strncat" | "strncpy" => (
+            vec![char_pointer(), char_pointer(), size()],
+            Some(char_pointer()),
+        ),
+        "strncmp" => (vec![char_pointer(), char_pointer(), size()], Some(int())),
+        _ => return None,
+    };
+    Some(Prototype::new(arguments, returns))
+}
+
+/// The types recovered for every function in a program.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Types {
+    functions: BTreeMap<usize, FunctionTypes>,
+}
+
+impl Types {
+    /// Get the types recovered for the function with the given index.
+    pub fn function(&self, index: usize) -> Option<&FunctionTypes> {
+        self.functions.get(&index)
+    }
+
+    /// Get the types recovered for every function, by index.
+    pub fn functions(&self) -> &BTreeMap<usize, FunctionTypes> {
+        &self.functions
+    }
+}
+
+/// The types recovered for a function.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FunctionTypes {
+    definitions: HashMap<(il::ProgramLocation, il::Scalar), Type>,
+    entries: HashMap<il::Scalar, Type>,
+    scalars: HashMap<il::Scalar, Type>,
+    slots: BTreeMap<i64, Type>,
+    arguments: Vec<Option<Type>>,
+    returns: Option<Type>,
+}
+
+impl FunctionTypes {
+    /// Get the type of the value written to a scalar at a location.
+    pub fn definition(&self, location: &il::ProgramLocation, scalar: &il::Scalar) -> Option<&Type> {
+        self.definitions.get(&(location.clone(), scalar.clone()))
+    }
+
+    /// Get the type of the value of a scalar at function entry.
+    pub fn entry(&self, scalar: &il::Scalar) -> Option<&Type> {
+        self.entries.get(scalar)
+    }
+
+    /// Get the type of a scalar, if every type found for its definitions,
+    /// and its value at function entry, is the same.
+    ///
+    /// For a function in SSA form, this is the type of a version of a
+    /// scalar.
+    pub fn scalar(&self, scalar: &il::Scalar) -> Option<&Type> {
+        self.scalars.get(scalar)
+    }
+
+    /// Get the type of the stack slot at the given offset from the stack
+    /// pointer at function entry.
+    pub fn slot(&self, offset: i64) -> Option<&Type> {
+        self.slots.get(&offset)
+    }
+
+    /// Get the types of the stack slots, by offset.
+    pub fn slots(&self) -> &BTreeMap<i64, Type> {
+        &self.slots
+    }
+
+    /// Get the type of an argument, starting with 0 index.
+    pub fn argument(&self, argument: usize) -> Option<&Type> {
+        self.arguments.get(argument)?.as_ref()
+    }
+
+    /// Get the types of the arguments of the function's `Signature`, in order.
+    pub fn arguments(&self) -> &[Option<Type>] {
+        &self.arguments
+    }
+
+    /// Get the type of the value the function returns.
+    pub fn returns(&self) -> Option<&Type> {
+        self.returns.as_ref()
+    }
+}
+
+/// A type variable.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Variable {
+    /// The value written to a scalar at a location.
+    Definition(il::ProgramLocation, il::Scalar),
+    /// The value of a scalar at entry to the function with the given index.
+    Entry(usize, il::Scalar),
+    /// A stack slot of the function with the given index.
+    Slot(usize, i64),
+    /// The value the function with the given index returns.
+    Return(usize),
+}
+
+/// What is known of the type of a set of unified type variables.
+#[derive(Clone, Debug)]
+enum Shape {
+    Unknown,
+    Integer(usize),
+    /// A pointer, with the type variable of each field it is known to point
+    /// to, by offset.
+    Pointer(BTreeMap<u64, usize>),
+}
+
+/// Unifies type variables, held as the nodes of a union-find.
+#[derive(Debug, Default)]
+struct Solver {
+    parents: Vec<usize>,
+    shapes: Vec<Shape>,
+    variables: HashMap<Variable, usize>,
+    /// Nodes which are a pointer plus a constant, with the node of the
+    /// pointer and the constant.
+    offsets: HashMap<usize, (usize, u64)>,
+}
+
+impl Solver {
+    fn node(&mut self) -> usize {
+        self.parents.push(self.parents.len());
+        self.shapes.push(Shape::Unknown);
+        self.parents.len() - 1
+    }
+
+    fn variable(&mut self, variable: Variable) -> usize {
+        if let Some(node) = self.variables.get(&variable) {
+            return *node;
+        }
+        let node = self.node();
+        self.variables.insert(variable, node);
+        node
+    }
+
+    fn find(&mut self, node: usize) -> usize {
+        let mut root = node;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        let mut node = node;
+        while self.parents[node] != root {
+            let parent = self.parents[node];
+            self.parents[node] = root;
+            node = parent;
+        }
+        root
+    }
+
+    fn unify(&mut self, lhs: usize, rhs: usize) {
+        let mut pending = vec![(lhs, rhs)];
+        while let Some((lhs, rhs)) = pending.pop() {
+            let (lhs, rhs) = (self.find(lhs), self.find(rhs));
+            if lhs == rhs {
+                continue;
+            }
+            self.parents[rhs] = lhs;
+            let lhs_shape = mem::replace(&mut self.shapes[lhs], Shape::Unknown);
+            let rhs_shape = mem::replace(&mut self.shapes[rhs], Shape::Unknown);
+            self.shapes[lhs] = match (lhs_shape, rhs_shape) {
+                (Shape::Unknown, shape) | (shape, Shape::Unknown) => shape,
+                (Shape::Integer(lhs), Shape::Integer(rhs)) => Shape::Integer(lhs.max(rhs)),
+                (Shape::Pointer(fields), Shape::Integer(_))
+                | (Shape::Integer(_), Shape::Pointer(fields)) => Shape::Pointer(fields),
+                (Shape::Pointer(mut fields), Shape::Pointer(rhs_fields)) => {
+                    for (offset, field) in rhs_fields {
+                        match fields.entry(offset) {
+                            Entry::Vacant(entry) => {
+                                entry.insert(field);
+                            }
+                            Entry::Occupied(entry) => pending.push((*entry.get(), field)),
+                        }
+                    }
+                    Shape::Pointer(fields)
+                }
+            };
+        }
+    }
+
+    /// Constrain a node to be at least an integer of the given width.
+    fn integer(&mut self, node: usize, bits: usize) {
+        let root = self.find(node);
+        self.shapes[root] = match self.shapes[root] {
+            Shape::Unknown => Shape::Integer(bits),
+            Shape::Integer(integer_bits) => Shape::Integer(integer_bits.max(bits)),
+            Shape::Pointer(_) => return,
+        };
+    }
+
+    /// Constrain a node to be a pointer, and get the node of the field it
+    /// points to at the given offset.
+    fn field(&mut self, node: usize, offset: u64) -> usize {
+        let root = self.find(node);
+        if let Shape::Pointer(ref fields) = self.shapes[root] {
+            if let Some(field) = fields.get(&offset) {
+                return *field;
+            }
+        } else {
+            self.shapes[root] = Shape::Pointer(BTreeMap::new());
+        }
+        let field = self.node();
+        if let Shape::Pointer(ref mut fields) = self.shapes[root] {
+            fields.insert(offset, field);
+        }
+        field
+    }
+
+    /// Create a node of the given type.
+    fn type_node(&mut self, type_: &Type) -> usize {
+        let node = self.node();
+        match *type_ {
+            Type::Integer(bits) => self.integer(node, bits),
+            Type::Pointer(None) => self.shapes[node] = Shape::Pointer(BTreeMap::new()),
+            Type::Pointer(Some(ref pointee)) => match **pointee {
+                Type::Struct(ref fields) => {
+                    for (offset, field) in fields {
+                        let field_node = self.type_node(field);
+                        let pointee = self.field(node, *offset);
+                        self.unify(pointee, field_node);
+                    }
+                }
+                ref pointee => {
+                    let pointee_node = self.type_node(pointee);
+                    let field = self.field(node, 0);
+                    self.unify(field, pointee_node);
+                }
+            },
+            Type::Struct(_) => {}
+        }
+        node
+    }
+
+    /// Follow the offsets from a node to the pointer it is derived from.
+    fn base(&self, node: usize) -> (usize, u64) {
+        let (mut node, mut offset) = (node, 0u64);
+        for _ in 0..=self.offsets.len() {
+            match self.offsets.get(&node) {
+                Some((base, base_offset)) => {
+                    node = *base;
+                    offset = offset.wrapping_add(*base_offset);
+                }
+                None => break,
+            }
+        }
+        (node, offset)
+    }
+
+    /// Nodes derived from a pointer, which are not known to be anything
+    /// else, are pointers.
+    fn propagate_offsets(&mut self) {
+        let offsets: Vec<(usize, usize)> = self
+            .offsets
+            .iter()
+            .map(|(node, (base, _))| (*node, *base))
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (node, base) in &offsets {
+                let (node, base) = (self.find(*node), self.find(*base));
+                if let (Shape::Unknown, Shape::Pointer(_)) =
+                    (&self.shapes[node], &self.shapes[base])
+                {
+                    self.shapes[node] = Shape::Pointer(BTreeMap::new());
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    /// Get the type of a node. Pointers to structs being visited, which are
+    /// recursive, point to values of unknown type.
+    fn type_of(&mut self, node: usize, visiting: &mut Vec<usize>) -> Option<Type> {
+        let root = self.find(node);
+        match self.shapes[root].clone() {
+            Shape::Unknown => None,
+            Shape::Integer(bits) => Some(Type::Integer(bits)),
+            Shape::Pointer(fields) => {
+                if visiting.contains(&root) {
+                    return Some(Type::Pointer(None));
+                }
+                visiting.push(root);
+                let mut fields: BTreeMap<u64, Type> = fields
+                    .into_iter()
+                    .filter_map(|(offset, field)| Some((offset, self.type_of(field, visiting)?)))
+                    .collect();
+                visiting.pop();
+                let pointee = match fields.len() {
+                    0 => None,
+                    1 if fields.contains_key(&0) => fields.remove(&0),
+                    _ => Some(Type::Struct(fields)),
+                };
+                Some(Type::Pointer(pointee.map(Box::new)))
+            }
+        }
+    }
+}
+
+/// Generates the constraints of the locations of a function.
+struct Constraints<'a> {
+    solver: &'a mut Solver,
+    index: usize,
+    architecture: &'a dyn Architecture,
+    calling_convention: &'a CallingConvention,
+    signatures: &'a BTreeMap<usize, Signature>,
+    names: &'a HashMap<u64, &'a str>,
+    /// What each call calls, by location.
+    callees: HashMap<il::ProgramLocation, &'a CallGraphVertex>,
+    /// The definitions which reach each location.
+    definitions: HashMap<il::ProgramLocation, Definitions>,
+    frame: &'a StackFrame,
+    value_sets: &'a HashMap<il::ProgramLocation, ValueSets>,
+}
+
+impl<'a> Constraints<'a> {
+    /// The nodes of the definitions of a scalar which reach a location.
+    fn reads(&mut self, location: &il::ProgramLocation, scalar: &il::Scalar) -> Vec<usize> {
+        let definitions = self
+            .definitions
+            .get(location)
+            .map(|definitions| definitions.get(scalar))
+            .unwrap_or_default();
+        definitions
+            .into_iter()
+            .map(|definition| {
+                self.solver.variable(match definition {
+                    Some(definition) => Variable::Definition(definition, scalar.clone()),
+                    None => Variable::Entry(self.index, scalar.clone()),
+                })
+            })
+            .collect()
+    }
+
+    /// The node of a scalar read at a location.
+    fn read(&mut self, location: &il::ProgramLocation, scalar: &il::Scalar) -> usize {
+        let reads = self.reads(location, scalar);
+        let node = reads[0];
+        for read in &reads[1..] {
+            self.solver.unify(node, *read);
+        }
+        node
+    }
+
+    /// The node of a pointer, and the constant offset from it, an expression
+    /// evaluates to.
+    fn address(
+        &mut self,
+        location: &il::ProgramLocation,
+        expression: &il::Expression,
+    ) -> Option<(usize, u64)> {
+        match *expression {
+            il::Expression::Scalar(ref scalar) => {
+                let reads = self.reads(location, scalar);
+                if let [read] = reads[..] {
+                    Some(self.solver.base(read))
+                } else {
+                    Some((self.read(location, scalar), 0))
+                }
+            }
+            il::Expression::Add(ref lhs, ref rhs) => {
+                let (pointer, constant) = match (lhs.get_constant(), rhs.get_constant()) {
+                    (None, Some(constant)) => (lhs, constant),
+                    (Some(constant), None) => (rhs, constant),
+                    _ => return None,
+                };
+                let (base, offset) = self.address(location, pointer)?;
+                Some((base, offset.wrapping_add(constant.value_u64()?)))
+            }
+            _ => None,
+        }
+    }
+
+    /// The node of the value of an expression, if it may have a type.
+    fn expression(
+        &mut self,
+        location: &il::ProgramLocation,
+        expression: &il::Expression,
+    ) -> Option<usize> {
+        match *expression {
+            il::Expression::Scalar(ref scalar) => Some(self.read(location, scalar)),
+            il::Expression::Constant(_) => None,
+            il::Expression::Add(ref lhs, ref rhs) | il::Expression::Sub(ref lhs, ref rhs) => {
+                self.expression(location, lhs);
+                self.expression(location, rhs);
+                None
+            }
+            il::Expression::Mul(ref lhs, ref rhs)
+            | il::Expression::Divu(ref lhs, ref rhs)
+            | il::Expression::Modu(ref lhs, ref rhs)
+            | il::Expression::Divs(ref lhs, ref rhs)
+            | il::Expression::Mods(ref lhs, ref rhs)
+            | il::Expression::Shl(ref lhs, ref rhs)
+            | il::Expression::Shr(ref lhs, ref rhs) => {
+                for operand in [lhs, rhs] {
+                    if let Some(node) = self.expression(location, operand) {
+                        self.solver.integer(node, operand.bits());
+                    }
+                }
+                Some(self.integer(expression.bits()))
+            }
+            il::Expression::And(ref lhs, ref rhs)
+            | il::Expression::Or(ref lhs, ref rhs)
+            | il::Expression::Xor(ref lhs, ref rhs) => {
+                self.expression(location, lhs);
+                self.expression(location, rhs);
+                Some(self.integer(expression.bits()))
+            }
+            il::Expression::Cmpeq(ref lhs, ref rhs)
+            | il::Expression::Cmpneq(ref lhs, ref rhs)
+            | il::Expression::Cmplts(ref lhs, ref rhs)
+            | il::Expression::Cmpltu(ref lhs, ref rhs) => {
+                let lhs = self.expression(location, lhs);
+                let rhs = self.expression(location, rhs);
+                if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
+                    self.solver.unify(lhs, rhs);
+                }
+                Some(self.integer(1))
+            }
+            il::Expression::Zext(bits, ref src)
+            | il::Expression::Sext(bits, ref src)
+            | il::Expression::Trun(bits, ref src) => {
+                self.expression(location, src);
+                Some(self.integer(bits))
+            }
+            il::Expression::Ite(ref condition, ref then, ref else_) => {
+                self.expression(location, condition);
+                let then = self.expression(location, then);
+                let else_ = self.expression(location, else_);
+                if let (Some(then), Some(else_)) = (then, else_) {
+                    self.solver.unify(then, else_);
+                }
+                then.or(else_)
+            }
+        }
+    }
+
+    fn integer(&mut self, bits: usize) -> usize {
+        let node = self.solver.node();
+        self.solver.integer(node, bits);
+        node
+    }
+
+    /// The node of the memory a load or store at a location accesses.
+    fn memory(
+        &mut self,
+        location: &il::ProgramLocation,
+        index: &il::Expression,
+        bits: usize,
+    ) -> Option<usize> {
+        let node = match self.frame.access(location) {
+            Some(access) => {
+                if access.offset() != Some(0) {
+                    return None;
+                }
+                self.solver
+                    .variable(Variable::Slot(self.index, access.slot()))
+            }
+            None => {
+                let (base, offset) = match self.address(location, index) {
+                    Some(address) => address,
+                    None => {
+                        self.expression(location, index);
+                        return None;
+                    }
+                };
+                self.solver.field(base, offset)
+            }
+        };
+        self.solver.integer(node, bits);
+        Some(node)
+    }
+
+    /// The node of the stack slot of an argument passed on the stack at a
+    /// call.
+    fn stack_argument(&mut self, location: &il::ProgramLocation, offset: usize) -> Option<usize> {
+        let stack_pointer = self.architecture.stack_pointer();
+        let bits = stack_pointer.bits();
+        let address = il::Expression::add(
+            il::Expression::scalar(stack_pointer),
+            il::expr_const(offset as u64, bits),
+        )
+        .ok()?;
+        let offsets = self
+            .value_sets
+            .get(location)?
+            .eval(&address)
+            .region(&Region::Stack)?
+            .values(1)?;
+        let slot = match offsets[..] {
+            [offset] => il::const_(offset, bits).value_i64()?,
+            _ => return None,
+        };
+        self.frame.slot(slot)?;
+        Some(self.solver.variable(Variable::Slot(self.index, slot)))
+    }
+
+    /// The node of an argument at a call.
+    fn argument(&mut self, location: &il::ProgramLocation, argument: usize) -> Option<usize> {
+        match self.calling_convention.argument_type(argument) {
+            ArgumentType::Register(scalar) => Some(self.read(location, &scalar)),
+            ArgumentType::Stack(offset) => self.stack_argument(location, offset),
+        }
+    }
+
+    fn call(&mut self, location: &il::ProgramLocation, target: &il::Expression) {
+        let return_node = self.solver.variable(Variable::Definition(
+            location.clone(),
+            self.calling_convention.return_register().clone(),
+        ));
+
+        let callee = self.callees.get(location).cloned();
+        if let Some(index) = callee.and_then(|callee| callee.function_index()) {
+            let signatures = self.signatures;
+            if let Some(signature) = signatures.get(&index) {
+                for (argument, argument_type) in signature
+                    .argument_types(self.calling_convention)
+                    .into_iter()
+                    .enumerate()
+                {
+                    let parameter = match argument_type {
+                        ArgumentType::Register(scalar) => {
+                            self.solver.variable(Variable::Entry(index, scalar))
+                        }
+                        ArgumentType::Stack(offset) => {
+                            self.solver.variable(Variable::Slot(index, offset as i64))
+                        }
+                    };
+                    if let Some(node) = self.argument(location, argument) {
+                        self.solver.unify(node, parameter);
+                    }
+                }
+                if signature.returns_value() {
+                    let returns = self.solver.variable(Variable::Return(index));
+                    self.solver.unify(return_node, returns);
+                }
+            }
+        }
+
+        let name = eval(target)
+            .ok()
+            .and_then(|address| address.value_u64())
+            .and_then(|address| self.names.get(&address).map(|name| name.to_string()))
+            .or_else(|| match callee.map(|callee| callee.target()) {
+                Some(CallTarget::Function { name, .. }) => Some(name.clone()),
+                _ => None,
+            });
+        let prototype = match name
+            .and_then(|name| libc_prototype(&name, self.architecture.stack_pointer().bits()))
+        {
+            Some(prototype) => prototype,
+            None => return,
+        };
+        for (argument, type_) in prototype.arguments().iter().enumerate() {
+            if let Some(node) = self.argument(location, argument) {
+                let type_node = self.solver.type_node(type_);
+                self.solver.unify(node, type_node);
+            }
+        }
+        if let Some(type_) = prototype.returns() {
+            let type_node = self.solver.type_node(type_);
+            self.solver.unify(return_node, type_node);
+        }
+    }
+
+    fn location(&mut self, location: &il::RefProgramLocation) -> Result<()> {
+        let program_location: il::ProgramLocation = location.clone().into();
+        let instruction = match *location.function_location() {
+            il::RefFunctionLocation::Instruction(_, instruction) => instruction,
+            il::RefFunctionLocation::Edge(edge) => {
+                if let Some(condition) = edge.condition() {
+                    self.expression(&program_location, condition);
+                }
+                return Ok(());
+            }
+            il::RefFunctionLocation::EmptyBlock(_) => return Ok(()),
+        };
+
+        match *instruction.operation() {
+            il::Operation::Assign { ref dst, ref src } => {
+                let definition = self
+                    .solver
+                    .variable(Variable::Definition(program_location.clone(), dst.clone()));
+                if src.get_scalar().is_none() {
+                    if let Some(offset) = self.address(&program_location, src) {
+                        self.solver.offsets.insert(definition, offset);
+                        return Ok(());
+                    }
+                }
+                if let Some(node) = self.expression(&program_location, src) {
+                    self.solver.unify(definition, node);
+                }
+            }
+            il::Operation::Load { ref dst, ref index } => {
+                let definition = self
+                    .solver
+                    .variable(Variable::Definition(program_location.clone(), dst.clone()));
+                if let Some(node) = self.memory(&program_location, index, dst.bits()) {
+                    self.solver.unify(definition, node);
+                }
+            }
+            il::Operation::Store { ref index, ref src } => {
+                let value = self.expression(&program_location, src);
+                if let (Some(node), Some(value)) =
+                    (self.memory(&program_location, index, src.bits()), value)
+                {
+                    self.solver.unify(node, value);
+                }
+            }
+            il::Operation::Branch { ref target } => {
+                if is_call(location, target)? {
+                    self.call(&program_location, target);
+                } else if location.forward()?.is_empty()
+                    && self.signatures[&self.index].returns_value()
+                {
+                    let return_register = self.calling_convention.return_register().clone();
+                    let node = self.read(&program_location, &return_register);
+                    let returns = self.solver.variable(Variable::Return(self.index));
+                    self.solver.unify(node, returns);
+                }
+            }
+            il::Operation::Intrinsic { .. } | il::Operation::Nop => {}
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn types_test() {
+    /*
+    parse:
+        rax = [rdi + 8]
+        rsp = rsp - 0x10
+        [rsp + 8] = rax
+        rbx = [rdi]
+        rdi = [rsp + 8]
+        rsi = 0x3000
+        call strcpy
+
+        rax = rbx * 4
+        rsp = rsp + 0x10
+        ret = [rsp]
+        rsp = rsp + 8
+        branch ret
+
+    main:
+        rdi = rbx
+        call parse
+
+        rbx = rax
+        ret = [rsp]
+        rsp = rsp + 8
+        branch ret
+    */
+    use crate::architecture::Amd64;
+
+    let rsp = || il::expr_scalar("rsp", 64);
+    let rsp_plus = |offset| il::Expression::add(rsp(), il::expr_const(offset, 64)).unwrap();
+    let ret = |block: &mut il::Block| {
+        block.load(il::scalar("ret", 64), rsp());
+        block.assign(il::scalar("rsp", 64), rsp_plus(8));
+        block.branch(il::expr_scalar("ret", 64));
+    };
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.load(
+            il::scalar("rax", 64),
+            il::Expression::add(il::expr_scalar("rdi", 64), il::expr_const(8, 64)).unwrap(),
+        );
+        block.assign(
+            il::scalar("rsp", 64),
+            il::Expression::sub(rsp(), il::expr_const(0x10, 64)).unwrap(),
+        );
+        block.store(rsp_plus(8), il::expr_scalar("rax", 64));
+        block.load(il::scalar("rbx", 64), il::expr_scalar("rdi", 64));
+        block.load(il::scalar("rdi", 64), rsp_plus(8));
+        block.assign(il::scalar("rsi", 64), il::expr_const(0x3000, 64));
+        block.branch(il::expr_const(0x4000, 64));
+        block.index()
+    };
+    let return_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(
+            il::scalar("rax", 64),
+            il::Expression::mul(il::expr_scalar("rbx", 64), il::expr_const(4, 64)).unwrap(),
+        );
+        block.assign(il::scalar("rsp", 64), rsp_plus(0x10));
+        ret(block);
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(call_index, return_index)
+        .unwrap();
+    control_flow_graph.set_entry(call_index).unwrap();
+    let parse = il::Function::new(0x1000, control_flow_graph);
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let main_call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rdi", 64), il::expr_scalar("rbx", 64));
+        block.branch(il::expr_const(0x1000, 64));
+        block.index()
+    };
+    let main_return_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rbx", 64), il::expr_scalar("rax", 64));
+        ret(block);
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(main_call_index, main_return_index)
+        .unwrap();
+    control_flow_graph.set_entry(main_call_index).unwrap();
+    let main = il::Function::new(0x2000, control_flow_graph);
+
+    let mut program = il::Program::new();
+    program.add_function(parse);
+    program.add_function(main);
+    let symbols = [Symbol::new("strcpy", 0x4000)];
+
+    let types = types(&program, &Amd64::new(), &symbols).unwrap();
+    let char_pointer = Type::pointer(Type::Integer(8));
+    let record = Type::pointer(Type::Struct(
+        vec![(0, Type::Integer(64)), (8, char_pointer.clone())]
+            .into_iter()
+            .collect(),
+    ));
+
+    let parse = types.function(0).unwrap();
+    assert_eq!(parse.argument(0), Some(&record));
+    assert_eq!(record.to_string(), "struct { 0x0: int64_t, 0x8: char* }*");
+    assert_eq!(parse.slot(-8), Some(&char_pointer));
+    assert_eq!(
+        parse.scalar(&il::scalar("rbx", 64)),
+        Some(&Type::Integer(64))
+    );
+    assert_eq!(parse.scalar(&il::scalar("rax", 64)), None);
+    assert_eq!(parse.returns(), Some(&Type::Integer(64)));
+
+    let location = |function, block, instruction| {
+        il::ProgramLocation::new(
+            Some(function),
+            il::FunctionLocation::Instruction(block, instruction),
+        )
+    };
+    assert_eq!(
+        parse.definition(&location(0, call_index, 0), &il::scalar("rax", 64)),
+        Some(&char_pointer)
+    );
+    assert_eq!(
+        parse.definition(&location(0, return_index, 0), &il::scalar("rax", 64)),
+        Some(&Type::Integer(64))
+    );
+
+    let main = types.function(1).unwrap();
+    assert_eq!(main.entry(&il::scalar("rbx", 64)), Some(&record));
+    assert_eq!(
+        main.definition(&location(1, main_return_index, 0), &il::scalar("rbx", 64)),
+        Some(&Type::Integer(64))
+    );
+
+    let strcpy = libc_prototype("_strcpy@plt", 64).unwrap();
+    assert_eq!(
+        strcpy.arguments(),
+        &[char_pointer.clone(), char_pointer.clone()]
+    );
+    assert_eq!(strcpy.returns(), Some(&char_pointer));
+    assert!(libc_prototype("parse", 64).is_none());
+}
+
+#[test]
+fn types_failed_function_test() {
+    /*
+    0x1000:
+        (no entry)
+
+    0x2000:
+        rax = [rdi]
+        ret = [rsp]
+        rsp = rsp + 8
+        branch ret
+    */
+    use crate::architecture::Amd64;
+
+    let rsp = || il::expr_scalar("rsp", 64);
+
+    let broken = il::Function::new(0x1000, il::ControlFlowGraph::new());
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let block_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.load(il::scalar("rax", 64), il::expr_scalar("rdi", 64));
+        block.load(il::scalar("ret", 64), rsp());
+        block.assign(
+            il::scalar("rsp", 64),
+            il::Expression::add(rsp(), il::expr_const(8, 64)).unwrap(),
+        );
+        block.branch(il::expr_scalar("ret", 64));
+        block.index()
+    };
+    control_flow_graph.set_entry(block_index).unwrap();
+    let load = il::Function::new(0x2000, control_flow_graph);
+
+    let mut program = il::Program::new();
+    program.add_function(broken);
+    program.add_function(load);
+
+    // The function without an entry has no signature, so it has no types,
+    // while the types of the other function are still recovered.
+    let types = types(&program, &Amd64::new(), &[]).unwrap();
+    assert!(types.function(0).is_none());
+    assert_eq!(
+        types.function(1).unwrap().argument(0),
+        Some(&Type::pointer(Type::Integer(64)))
+    );
+}
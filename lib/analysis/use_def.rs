@@ -18,6 +18,7 @@ pub fn use_def<'r>(
                 .operation()
                 .scalars_read()
                 .into_iter()
+                .flatten()
                 .fold(LocationSet::new(), |mut defs, scalar_read| {
                     rd[&location].locations().into_iter().for_each(|rd| {
                         rd.function_location()
@@ -28,6 +29,7 @@ pub fn use_def<'r>(
                             .operation()
                             .scalars_written()
                             .into_iter()
+                            .flatten()
                             .for_each(|scalar_written| {
                                 if scalar_written == scalar_read {
                                     defs.insert(rd.clone());
@@ -245,3 +247,63 @@ fn use_def_test() {
             == 2
     );
 }
+
+#[test]
+fn use_def_multiple_writes_test() {
+    /*
+    a, b = intrinsic
+    a = 1
+    c = a + b
+    a, b = intrinsic
+    d = a
+    */
+    let writes_a_b = || {
+        il::Intrinsic::new(
+            "ab",
+            "ab",
+            Vec::new(),
+            Some(vec![il::expr_scalar("a", 32), il::expr_scalar("b", 32)]),
+            Some(Vec::new()),
+            Vec::new(),
+        )
+    };
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let block_index = {
+        let block = control_flow_graph.new_block().unwrap();
+
+        block.intrinsic(writes_a_b());
+        block.assign(il::scalar("a", 32), il::expr_const(1, 32));
+        block.assign(
+            il::scalar("c", 32),
+            il::Expression::add(il::expr_scalar("a", 32), il::expr_scalar("b", 32)).unwrap(),
+        );
+        block.intrinsic(writes_a_b());
+        block.assign(il::scalar("d", 32), il::expr_scalar("a", 32));
+
+        block.index()
+    };
+
+    control_flow_graph.set_entry(block_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+
+    let location = |instruction_index| {
+        il::ProgramLocation::new(
+            None,
+            il::FunctionLocation::Instruction(block_index, instruction_index),
+        )
+    };
+
+    let ud = use_def(&function).unwrap();
+
+    // Writing a alone does not kill a definition which also writes b.
+    assert_eq!(ud[&location(2)].len(), 2);
+    assert!(ud[&location(2)].contains(&location(0)));
+    assert!(ud[&location(2)].contains(&location(1)));
+
+    // Writing a and b kills both earlier definitions.
+    assert_eq!(ud[&location(4)].len(), 1);
+    assert!(ud[&location(4)].contains(&location(3)));
+}
@@ -0,0 +1,1964 @@
+//! Value-set analysis over strided intervals.
+//!
+//! Each scalar, and each cell of memory, holds a `ValueSet`: a
+//! `StridedInterval` for each abstract memory `Region` the value may point
+//! into. Numbers are values in the `Global` region. The stack pointer starts
+//! as offset 0 in the `Stack` region, and values returned by calls to
+//! allocators are offsets into a `Heap` region named by the call site.
+//!
+//! Conditional edges refine the values of the scalars their conditions
+//! compare, so the bounds check before a jump table, or an index into a
+//! buffer, bounds the index. Loops are brought to a fixed point by widening
+//! at loop heads, and then narrowed.
+//!
+//! Scalars and memory which have no value set hold any value. Calling
+//! `ValueSets::eval` gives the `ValueSet` of an expression before a location
+//! executes.
+//!
+//! `value_sets` treats a call as writing every register the calling
+//! convention does not preserve, and any memory outside of the caller's
+//! stack frame. `value_sets_interprocedural` follows calls to the functions
+//! of a program instead, analysing each callee in its own stack frame.
+
+use crate::analysis::fixed_point;
+use crate::analysis::interprocedural;
+use crate::architecture::Architecture;
+use crate::error::*;
+use crate::executor::eval;
+use crate::il;
+use std::cmp::{Ordering, PartialOrd};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// The number of times the state at a loop head is joined before it is
+/// widened.
+const WIDENING_DELAY: usize = 2;
+
+/// The number of passes which narrow the states once widened.
+const NARROWING_PASSES: usize = 2;
+
+/// The largest number of addresses a store may weakly update, or a load may
+/// read, before the access is treated as unknown.
+const MAX_ACCESSES: u64 = 64;
+
+/// The deepest definitions are followed when refining a condition.
+const MAX_REFINEMENT_DEPTH: usize = 8;
+
+/// Compute value sets for the given function.
+///
+/// Calls to the addresses in `allocators` return a pointer to a new `Heap`
+/// region. Each location has the value sets of all scalars before execution
+/// of that location.
+pub fn value_sets(
+    function: &il::Function,
+    architecture: &dyn Architecture,
+    allocators: &[u64],
+) -> Result<HashMap<il::ProgramLocation, ValueSets>> {
+    let analysis = ValueSetAnalysis {
+        architecture,
+        allocators: allocators.iter().cloned().collect(),
+    };
+    let mut options = fixed_point::FixedPointOptions::new();
+    options.set_force(true);
+    options.set_widening_delay(WIDENING_DELAY);
+    options.set_narrowing_passes(NARROWING_PASSES);
+    let value_sets =
+        fixed_point::fixed_point_forward_with(analysis, function, &options)?.into_states();
+
+    // Remap value sets, so each location holds the value sets immediately
+    // preceeding its execution.
+    let mut result = HashMap::new();
+
+    for location in value_sets.keys() {
+        let rfl = location.function_location().apply(function).unwrap();
+        let rpl = il::RefProgramLocation::new(function, rfl);
+        let predecessors = rpl.backward()?;
+        let state = if predecessors.is_empty() {
+            entry(architecture)
+        } else {
+            predecessors
+                .into_iter()
+                .fold(ValueSets::bottom(), |state, location| {
+                    state.join(&value_sets[&location.into()])
+                })
+        };
+        result.insert(location.clone(), state);
+    }
+
+    Ok(result)
+}
+
+/// Compute value sets for every function in the given program, following
+/// calls to functions in the program.
+///
+/// A callee starts from the value sets of the caller which do not point into
+/// the caller's stack frame, with a stack frame of its own, so arguments
+/// passed on the stack are not followed. Calls are analysed in contexts of
+/// the last `context_depth` call sites, and the value sets before each
+/// location are joined over its contexts. Calls to addresses outside of the
+/// program, and to `allocators`, are treated as by `value_sets`.
+pub fn value_sets_interprocedural(
+    program: &il::Program,
+    architecture: &dyn Architecture,
+    allocators: &[u64],
+    context_depth: usize,
+) -> Result<HashMap<il::ProgramLocation, ValueSets>> {
+    let analysis = ValueSetAnalysis {
+        architecture,
+        allocators: allocators.iter().cloned().collect(),
+    };
+    let mut options = fixed_point::FixedPointOptions::new();
+    options.set_widening_delay(WIDENING_DELAY);
+    interprocedural::fixed_point_interprocedural(&analysis, program, None, context_depth, &options)?
+        .merge_inputs(&analysis)
+}
+
+fn gcd(lhs: u64, rhs: u64) -> u64 {
+    if rhs == 0 {
+        lhs
+    } else {
+        gcd(rhs, lhs % rhs)
+    }
+}
+
+fn mask(bits: usize) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// The smallest value of the form 2^n - 1 which is at least `value`.
+fn fill(value: u64) -> u64 {
+    if value == 0 {
+        0
+    } else {
+        u64::MAX >> value.leading_zeros()
+    }
+}
+
+/// A set of unsigned values `lower + stride * n`, no greater than `upper`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct StridedInterval {
+    bits: usize,
+    stride: u64,
+    lower: u64,
+    upper: u64,
+}
+
+impl StridedInterval {
+    /// Create a new `StridedInterval` of values with the given number of
+    /// bits, from `lower` to `upper` inclusive.
+    ///
+    /// `upper` is rounded down to a value in the interval, and the stride of
+    /// an interval with one value is 0.
+    pub fn new(bits: usize, stride: u64, lower: u64, upper: u64) -> StridedInterval {
+        let lower = lower & mask(bits);
+        let upper = (upper & mask(bits)).max(lower);
+        if lower == upper {
+            return StridedInterval {
+                bits,
+                stride: 0,
+                lower,
+                upper,
+            };
+        }
+        let stride = stride.max(1);
+        StridedInterval {
+            bits,
+            stride,
+            lower,
+            upper: lower + (upper - lower) / stride * stride,
+        }
+    }
+
+    /// Create a `StridedInterval` holding one value.
+    pub fn constant(bits: usize, value: u64) -> StridedInterval {
+        StridedInterval::new(bits, 0, value, value)
+    }
+
+    /// Create a `StridedInterval` holding every value of the given number of
+    /// bits.
+    pub fn top(bits: usize) -> StridedInterval {
+        StridedInterval::new(bits, 1, 0, mask(bits))
+    }
+
+    /// Create a `StridedInterval` holding 0 and 1.
+    fn boolean() -> StridedInterval {
+        StridedInterval::new(1, 1, 0, 1)
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    pub fn stride(&self) -> u64 {
+        self.stride
+    }
+
+    pub fn lower(&self) -> u64 {
+        self.lower
+    }
+
+    pub fn upper(&self) -> u64 {
+        self.upper
+    }
+
+    /// Returns true if this interval holds every value of its size.
+    pub fn is_top(&self) -> bool {
+        self.lower == 0 && self.upper == mask(self.bits) && self.stride <= 1
+    }
+
+    /// Get the value of this interval, if it holds one value.
+    pub fn value(&self) -> Option<u64> {
+        if self.stride == 0 {
+            Some(self.lower)
+        } else {
+            None
+        }
+    }
+
+    /// The number of values in this interval, saturating at `u64::MAX`.
+    pub fn size(&self) -> u64 {
+        match self.stride {
+            0 => 1,
+            stride => ((self.upper - self.lower) / stride).saturating_add(1),
+        }
+    }
+
+    /// Returns true if the given value is in this interval.
+    pub fn contains(&self, value: u64) -> bool {
+        value >= self.lower
+            && value <= self.upper
+            && (self.stride == 0 || (value - self.lower).is_multiple_of(self.stride))
+    }
+
+    /// Get every value in this interval, or `None` if there are more than
+    /// `limit` values.
+    pub fn values(&self, limit: u64) -> Option<Vec<u64>> {
+        let len = self.size();
+        if len > limit {
+            return None;
+        }
+        Some((0..len).map(|n| self.lower + n * self.stride).collect())
+    }
+
+    /// Returns true if every value in this interval is in `other`.
+    pub fn is_subset(&self, other: &StridedInterval) -> bool {
+        if self.bits != other.bits || !other.contains(self.lower) || !other.contains(self.upper) {
+            return false;
+        }
+        self.stride == 0 || (other.stride != 0 && self.stride.is_multiple_of(other.stride))
+    }
+
+    /// Join two intervals, giving an interval which holds the values of both.
+    pub fn join(&self, other: &StridedInterval) -> StridedInterval {
+        if self.bits != other.bits {
+            return StridedInterval::top(self.bits);
+        }
+        let lower = self.lower.min(other.lower);
+        let stride = gcd(
+            gcd(self.stride, other.stride),
+            self.lower.max(other.lower) - lower,
+        );
+        StridedInterval::new(self.bits, stride, lower, self.upper.max(other.upper))
+    }
+
+    /// Widen this interval with `other`, moving each bound which grows to the
+    /// limit of the interval's size.
+    pub fn widen(&self, other: &StridedInterval) -> StridedInterval {
+        let join = self.join(other);
+        if join == *self {
+            return join;
+        }
+        let stride = join.stride.max(1);
+        let lower = if join.lower < self.lower {
+            join.lower % stride
+        } else {
+            join.lower
+        };
+        let upper = if join.upper > self.upper {
+            mask(join.bits)
+        } else {
+            join.upper
+        };
+        StridedInterval::new(join.bits, stride, lower, upper)
+    }
+
+    /// The values of this interval between `lower` and `upper` inclusive, or
+    /// `None` if there are none.
+    pub fn clamp(&self, lower: u64, upper: u64) -> Option<StridedInterval> {
+        if lower > self.upper || upper < self.lower || lower > upper {
+            return None;
+        }
+        let new_lower = if lower <= self.lower {
+            self.lower
+        } else {
+            // There is more than one value, as lower is within the interval.
+            let steps = (lower - self.lower).div_ceil(self.stride);
+            self.lower + steps * self.stride
+        };
+        let new_upper = upper.min(self.upper);
+        if new_lower > new_upper {
+            return None;
+        }
+        Some(StridedInterval::new(
+            self.bits,
+            self.stride,
+            new_lower,
+            new_upper,
+        ))
+    }
+
+    /// This interval without the given value, or `None` if the value was the
+    /// only value. Only values at the bounds of the interval are removed.
+    fn remove(&self, value: u64) -> Option<StridedInterval> {
+        if self.stride == 0 {
+            return if self.lower == value {
+                None
+            } else {
+                Some(*self)
+            };
+        }
+        if value == self.lower {
+            self.clamp(self.lower + 1, self.upper)
+        } else if value == self.upper {
+            self.clamp(self.lower, self.upper - 1)
+        } else {
+            Some(*self)
+        }
+    }
+
+    /// Create an interval from bounds which may lie outside of the values of
+    /// the given size, wrapping the bounds if both are out of range.
+    fn from_bounds(bits: usize, stride: u64, lower: i128, upper: i128) -> StridedInterval {
+        let modulus = mask(bits) as i128 + 1;
+        if upper - lower >= modulus {
+            StridedInterval::top(bits)
+        } else if lower >= 0 && upper < modulus {
+            StridedInterval::new(bits, stride, lower as u64, upper as u64)
+        } else if lower >= modulus && upper < modulus * 2 {
+            StridedInterval::new(
+                bits,
+                stride,
+                (lower - modulus) as u64,
+                (upper - modulus) as u64,
+            )
+        } else if upper < 0 && lower >= -modulus {
+            StridedInterval::new(
+                bits,
+                stride,
+                (lower + modulus) as u64,
+                (upper + modulus) as u64,
+            )
+        } else {
+            StridedInterval::top(bits)
+        }
+    }
+
+    fn add(&self, other: &StridedInterval) -> StridedInterval {
+        StridedInterval::from_bounds(
+            self.bits,
+            gcd(self.stride, other.stride),
+            self.lower as i128 + other.lower as i128,
+            self.upper as i128 + other.upper as i128,
+        )
+    }
+
+    fn sub(&self, other: &StridedInterval) -> StridedInterval {
+        StridedInterval::from_bounds(
+            self.bits,
+            gcd(self.stride, other.stride),
+            self.lower as i128 - other.upper as i128,
+            self.upper as i128 - other.lower as i128,
+        )
+    }
+
+    fn mul(&self, other: &StridedInterval) -> StridedInterval {
+        let (stride, lower, upper) = match (self.value(), other.value()) {
+            (_, Some(value)) => (
+                self.stride.checked_mul(value),
+                (self.lower as i128).checked_mul(value as i128),
+                (self.upper as i128).checked_mul(value as i128),
+            ),
+            (Some(value), _) => return other.mul(&StridedInterval::constant(other.bits, value)),
+            (None, None) => (
+                Some(1),
+                (self.lower as i128).checked_mul(other.lower as i128),
+                (self.upper as i128).checked_mul(other.upper as i128),
+            ),
+        };
+        match (stride, lower, upper) {
+            (Some(stride), Some(lower), Some(upper)) => {
+                StridedInterval::from_bounds(self.bits, stride, lower, upper)
+            }
+            _ => StridedInterval::top(self.bits),
+        }
+    }
+
+    fn divu(&self, other: &StridedInterval) -> StridedInterval {
+        match other.value() {
+            Some(0) => StridedInterval::top(self.bits),
+            Some(value) => {
+                let stride = if self.stride.is_multiple_of(value) {
+                    self.stride / value
+                } else {
+                    1
+                };
+                StridedInterval::new(self.bits, stride, self.lower / value, self.upper / value)
+            }
+            None if other.lower > 0 => StridedInterval::new(
+                self.bits,
+                1,
+                self.lower / other.upper,
+                self.upper / other.lower,
+            ),
+            None => StridedInterval::top(self.bits),
+        }
+    }
+
+    fn modu(&self, other: &StridedInterval) -> StridedInterval {
+        if other.lower == 0 {
+            StridedInterval::top(self.bits)
+        } else if self.upper < other.lower {
+            *self
+        } else if other
+            .value()
+            .is_some_and(|value| self.stride.is_multiple_of(value))
+        {
+            StridedInterval::constant(self.bits, self.lower % other.lower)
+        } else {
+            StridedInterval::new(self.bits, 1, 0, self.upper.min(other.upper - 1))
+        }
+    }
+
+    fn and(&self, other: &StridedInterval) -> StridedInterval {
+        if let (Some(lhs), Some(rhs)) = (self.value(), other.value()) {
+            return StridedInterval::constant(self.bits, lhs & rhs);
+        }
+        if let Some(value) = other.value() {
+            if self.upper <= value && fill(value) == value {
+                return *self;
+            }
+        }
+        if let Some(value) = self.value() {
+            if other.upper <= value && fill(value) == value {
+                return *other;
+            }
+        }
+        StridedInterval::new(self.bits, 1, 0, self.upper.min(other.upper))
+    }
+
+    fn or(&self, other: &StridedInterval) -> StridedInterval {
+        if let (Some(lhs), Some(rhs)) = (self.value(), other.value()) {
+            return StridedInterval::constant(self.bits, lhs | rhs);
+        }
+        StridedInterval::new(
+            self.bits,
+            1,
+            self.lower.max(other.lower),
+            fill(self.upper.max(other.upper)),
+        )
+    }
+
+    fn xor(&self, other: &StridedInterval) -> StridedInterval {
+        if let (Some(lhs), Some(rhs)) = (self.value(), other.value()) {
+            return StridedInterval::constant(self.bits, lhs ^ rhs);
+        }
+        StridedInterval::new(self.bits, 1, 0, fill(self.upper.max(other.upper)))
+    }
+
+    fn shl(&self, other: &StridedInterval) -> StridedInterval {
+        match other.value() {
+            Some(value) if value < self.bits as u64 => {
+                self.mul(&StridedInterval::constant(self.bits, 1 << value))
+            }
+            Some(_) => StridedInterval::constant(self.bits, 0),
+            None if self.value() == Some(0) => *self,
+            None => StridedInterval::top(self.bits),
+        }
+    }
+
+    fn shr(&self, other: &StridedInterval) -> StridedInterval {
+        match other.value() {
+            Some(value) if value < self.bits as u64 => {
+                let stride = if self.stride.is_multiple_of(1 << value) {
+                    self.stride >> value
+                } else {
+                    1
+                };
+                StridedInterval::new(self.bits, stride, self.lower >> value, self.upper >> value)
+            }
+            Some(_) => StridedInterval::constant(self.bits, 0),
+            None => StridedInterval::new(self.bits, 1, 0, self.upper),
+        }
+    }
+
+    fn cmpeq(&self, other: &StridedInterval) -> StridedInterval {
+        match (self.value(), other.value()) {
+            (Some(lhs), Some(rhs)) => StridedInterval::constant(1, (lhs == rhs) as u64),
+            _ if self.upper < other.lower || other.upper < self.lower => {
+                StridedInterval::constant(1, 0)
+            }
+            _ => StridedInterval::boolean(),
+        }
+    }
+
+    fn cmpltu(&self, other: &StridedInterval) -> StridedInterval {
+        if self.upper < other.lower {
+            StridedInterval::constant(1, 1)
+        } else if self.lower >= other.upper {
+            StridedInterval::constant(1, 0)
+        } else {
+            StridedInterval::boolean()
+        }
+    }
+
+    fn cmplts(&self, other: &StridedInterval) -> StridedInterval {
+        // Both intervals must lie on one side of the sign bit for their bounds
+        // to be ordered as signed values.
+        let half = 1 << (self.bits - 1);
+        let signed = |interval: &StridedInterval| {
+            if interval.upper < half {
+                Some((interval.lower as i128, interval.upper as i128))
+            } else if interval.lower >= half {
+                let modulus = mask(interval.bits) as i128 + 1;
+                Some((
+                    interval.lower as i128 - modulus,
+                    interval.upper as i128 - modulus,
+                ))
+            } else {
+                None
+            }
+        };
+        match (signed(self), signed(other)) {
+            (Some((_, lhs_upper)), Some((rhs_lower, _))) if lhs_upper < rhs_lower => {
+                StridedInterval::constant(1, 1)
+            }
+            (Some((lhs_lower, _)), Some((_, rhs_upper))) if lhs_lower >= rhs_upper => {
+                StridedInterval::constant(1, 0)
+            }
+            _ => StridedInterval::boolean(),
+        }
+    }
+
+    fn zext(&self, bits: usize) -> StridedInterval {
+        StridedInterval::new(bits, self.stride, self.lower, self.upper)
+    }
+
+    fn sext(&self, bits: usize) -> StridedInterval {
+        let half = 1 << (self.bits - 1);
+        if self.upper < half {
+            self.zext(bits)
+        } else if self.lower >= half {
+            let extension = mask(bits) - mask(self.bits);
+            StridedInterval::new(
+                bits,
+                self.stride,
+                self.lower + extension,
+                self.upper + extension,
+            )
+        } else {
+            StridedInterval::top(bits)
+        }
+    }
+
+    fn trun(&self, bits: usize) -> StridedInterval {
+        if self.upper <= mask(bits) {
+            StridedInterval::new(bits, self.stride, self.lower, self.upper)
+        } else if self.stride == 0 || (bits < 64 && self.stride.is_multiple_of(1 << bits)) {
+            // Every value is the same once truncated.
+            StridedInterval::constant(bits, self.lower & mask(bits))
+        } else {
+            StridedInterval::top(bits)
+        }
+    }
+}
+
+impl fmt::Display for StridedInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}[0x{:x}, 0x{:x}]:{}",
+            self.stride, self.lower, self.upper, self.bits
+        )
+    }
+}
+
+/// An abstract region of memory a value may point into.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Region {
+    /// Absolute addresses, and values which are not pointers.
+    Global,
+    /// The stack frame of the function, relative to the stack pointer at
+    /// function entry.
+    Stack,
+    /// Memory allocated by the call to an allocator at the given address.
+    Heap(u64),
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Region::Global => write!(f, "global"),
+            Region::Stack => write!(f, "stack"),
+            Region::Heap(address) => write!(f, "heap@0x{:x}", address),
+        }
+    }
+}
+
+/// The values a scalar, or memory, may hold, as a `StridedInterval` of
+/// offsets into each `Region` it may point into.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValueSet {
+    bits: usize,
+    regions: BTreeMap<Region, StridedInterval>,
+    top: bool,
+}
+
+impl ValueSet {
+    /// Create a `ValueSet` holding any value.
+    pub fn top(bits: usize) -> ValueSet {
+        ValueSet {
+            bits,
+            regions: BTreeMap::new(),
+            top: true,
+        }
+    }
+
+    /// Create a `ValueSet` holding values in one region.
+    pub fn new(region: Region, interval: StridedInterval) -> ValueSet {
+        let mut regions = BTreeMap::new();
+        regions.insert(region, interval);
+        ValueSet {
+            bits: interval.bits(),
+            regions,
+            top: false,
+        }
+    }
+
+    /// Create a `ValueSet` holding one number.
+    pub fn constant(bits: usize, value: u64) -> ValueSet {
+        if bits > 64 {
+            return ValueSet::top(bits);
+        }
+        ValueSet::new(Region::Global, StridedInterval::constant(bits, value))
+    }
+
+    /// Create a `ValueSet` holding numbers in the given interval.
+    pub fn global(interval: StridedInterval) -> ValueSet {
+        ValueSet::new(Region::Global, interval)
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// Returns true if this value set holds any value.
+    pub fn is_top(&self) -> bool {
+        self.top
+    }
+
+    /// The intervals of offsets into each region of this value set. A value
+    /// set which is top has no regions.
+    pub fn regions(&self) -> &BTreeMap<Region, StridedInterval> {
+        &self.regions
+    }
+
+    /// Get the interval of offsets into the given region.
+    pub fn region(&self, region: &Region) -> Option<&StridedInterval> {
+        self.regions.get(region)
+    }
+
+    /// Get the interval of this value set, if it only holds numbers.
+    pub fn interval(&self) -> Option<&StridedInterval> {
+        if self.regions.len() == 1 {
+            self.regions.get(&Region::Global)
+        } else {
+            None
+        }
+    }
+
+    /// Get the value of this value set, if it only holds one number.
+    pub fn value(&self) -> Option<u64> {
+        self.interval().and_then(|interval| interval.value())
+    }
+
+    /// Returns true if every value in this value set is in `other`.
+    pub fn is_subset(&self, other: &ValueSet) -> bool {
+        if other.top {
+            return true;
+        }
+        if self.top || self.bits != other.bits {
+            return false;
+        }
+        self.regions.iter().all(|(region, interval)| {
+            other
+                .regions
+                .get(region)
+                .is_some_and(|other| interval.is_subset(other))
+        })
+    }
+
+    /// Join two value sets, giving a value set which holds the values of
+    /// both.
+    pub fn join(&self, other: &ValueSet) -> ValueSet {
+        self.combine(other, |lhs, rhs| lhs.join(rhs))
+    }
+
+    /// Widen this value set with `other`.
+    pub fn widen(&self, other: &ValueSet) -> ValueSet {
+        self.combine(other, |lhs, rhs| lhs.widen(rhs))
+    }
+
+    fn combine<F>(&self, other: &ValueSet, f: F) -> ValueSet
+    where
+        F: Fn(&StridedInterval, &StridedInterval) -> StridedInterval,
+    {
+        if self.top || other.top || self.bits != other.bits {
+            return ValueSet::top(self.bits);
+        }
+        let mut regions = self.regions.clone();
+        for (region, interval) in &other.regions {
+            let interval = match regions.get(region) {
+                Some(lhs) => f(lhs, interval),
+                None => *interval,
+            };
+            regions.insert(*region, interval);
+        }
+        ValueSet {
+            bits: self.bits,
+            regions,
+            top: false,
+        }
+    }
+
+    /// Apply an operation to the intervals of two value sets which hold only
+    /// numbers.
+    fn global_op<F>(&self, other: &ValueSet, bits: usize, f: F) -> ValueSet
+    where
+        F: Fn(&StridedInterval, &StridedInterval) -> StridedInterval,
+    {
+        match (self.interval(), other.interval()) {
+            (Some(lhs), Some(rhs)) => ValueSet::global(f(lhs, rhs)),
+            _ => ValueSet::top(bits),
+        }
+    }
+
+    fn add(&self, other: &ValueSet) -> ValueSet {
+        self.region_op(
+            other,
+            |lhs, rhs| match (lhs, rhs) {
+                (Region::Global, region) | (region, Region::Global) => Some(region),
+                _ => None,
+            },
+            StridedInterval::add,
+        )
+    }
+
+    fn sub(&self, other: &ValueSet) -> ValueSet {
+        self.region_op(
+            other,
+            |lhs, rhs| match (lhs, rhs) {
+                (region, Region::Global) => Some(region),
+                (lhs, rhs) if lhs == rhs => Some(Region::Global),
+                _ => None,
+            },
+            StridedInterval::sub,
+        )
+    }
+
+    /// Apply an operation to every pair of regions in two value sets, giving
+    /// the region of each result, or `None` if the result may be anything.
+    fn region_op<R, F>(&self, other: &ValueSet, region: R, f: F) -> ValueSet
+    where
+        R: Fn(Region, Region) -> Option<Region>,
+        F: Fn(&StridedInterval, &StridedInterval) -> StridedInterval,
+    {
+        if self.top || other.top {
+            return ValueSet::top(self.bits);
+        }
+        let mut result: Option<ValueSet> = None;
+        for (lhs_region, lhs) in &self.regions {
+            for (rhs_region, rhs) in &other.regions {
+                let value_set = match region(*lhs_region, *rhs_region) {
+                    Some(region) => ValueSet::new(region, f(lhs, rhs)),
+                    None => return ValueSet::top(self.bits),
+                };
+                result = Some(match result {
+                    Some(result) => result.join(&value_set),
+                    None => value_set,
+                });
+            }
+        }
+        result.unwrap_or_else(|| ValueSet::top(self.bits))
+    }
+
+    /// A comparison of values which are not both numbers may be true or
+    /// false.
+    fn or_boolean(self) -> ValueSet {
+        if self.is_top() {
+            ValueSet::global(StridedInterval::boolean())
+        } else {
+            self
+        }
+    }
+
+    /// Align a pointer by masking its low bits, which gives an offset up to
+    /// the mask below the pointer.
+    fn align(&self, value: u64) -> Option<ValueSet> {
+        let alignment = !value & mask(self.bits);
+        if self.top || fill(alignment) != alignment || alignment >= 1 << (self.bits - 1) {
+            return None;
+        }
+        let below = StridedInterval::new(self.bits, 1, 0, alignment);
+        let mut regions = BTreeMap::new();
+        for (region, interval) in &self.regions {
+            match region {
+                Region::Global => regions.insert(
+                    *region,
+                    interval.and(&StridedInterval::constant(self.bits, value)),
+                ),
+                _ => regions.insert(*region, interval.sub(&below)),
+            };
+        }
+        Some(ValueSet {
+            bits: self.bits,
+            regions,
+            top: false,
+        })
+    }
+}
+
+impl fmt::Display for ValueSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.top {
+            return write!(f, "top:{}", self.bits);
+        }
+        write!(f, "{{")?;
+        for (n, (region, interval)) in self.regions.iter().enumerate() {
+            if n > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", region, interval)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// The value sets of all scalars, and of memory, before the
+/// `RefProgramLocation` is evaluated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueSets {
+    /// True if this location cannot be reached.
+    bottom: bool,
+    scalars: HashMap<il::Scalar, ValueSet>,
+    /// Value sets stored in memory, keyed by the region and offset they are
+    /// stored at.
+    memory: BTreeMap<(Region, u64), ValueSet>,
+    /// The expressions last assigned to scalars, which no longer hold if a
+    /// scalar they read is assigned. These relate flags to the values they
+    /// were computed from, so conditions over flags refine those values.
+    definitions: HashMap<il::Scalar, il::Expression>,
+}
+
+impl PartialOrd for ValueSets {
+    fn partial_cmp(&self, other: &ValueSets) -> Option<Ordering> {
+        match (self.is_subset(other), other.is_subset(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (false, false) => None,
+        }
+    }
+}
+
+impl ValueSets {
+    /// Value sets in which every scalar, and all memory, holds any value.
+    pub(crate) fn new() -> ValueSets {
+        ValueSets {
+            bottom: false,
+            scalars: HashMap::new(),
+            memory: BTreeMap::new(),
+            definitions: HashMap::new(),
+        }
+    }
+
+    fn bottom() -> ValueSets {
+        ValueSets {
+            bottom: true,
+            ..ValueSets::new()
+        }
+    }
+
+    /// Returns true if the location of these value sets cannot be reached.
+    pub fn is_bottom(&self) -> bool {
+        self.bottom
+    }
+
+    /// Get the value set of a scalar.
+    pub fn scalar(&self, scalar: &il::Scalar) -> ValueSet {
+        self.scalars
+            .get(scalar)
+            .cloned()
+            .unwrap_or_else(|| ValueSet::top(scalar.bits()))
+    }
+
+    fn set_scalar(&mut self, scalar: il::Scalar, value_set: ValueSet) {
+        if value_set.is_top() {
+            self.scalars.remove(&scalar);
+        } else {
+            self.scalars.insert(scalar, value_set);
+        }
+    }
+
+    /// Get the value set stored in memory at an offset into a region.
+    pub fn memory(&self, region: Region, offset: u64) -> Option<&ValueSet> {
+        self.memory.get(&(region, offset))
+    }
+
+    /// Evaluate an expression to the value set of its values, using the value
+    /// sets found by this analysis.
+    pub fn eval(&self, expression: &il::Expression) -> ValueSet {
+        let bits = expression.bits();
+        if bits > 64 {
+            return ValueSet::top(bits);
+        }
+        match *expression {
+            il::Expression::Scalar(ref scalar) => self.scalar(scalar),
+            il::Expression::Constant(ref constant) => match constant.value_u64() {
+                Some(value) => ValueSet::constant(bits, value),
+                None => ValueSet::top(bits),
+            },
+            il::Expression::Add(ref lhs, ref rhs)
+            | il::Expression::Sub(ref lhs, ref rhs)
+            | il::Expression::Mul(ref lhs, ref rhs)
+            | il::Expression::Divu(ref lhs, ref rhs)
+            | il::Expression::Modu(ref lhs, ref rhs)
+            | il::Expression::Divs(ref lhs, ref rhs)
+            | il::Expression::Mods(ref lhs, ref rhs)
+            | il::Expression::And(ref lhs, ref rhs)
+            | il::Expression::Or(ref lhs, ref rhs)
+            | il::Expression::Xor(ref lhs, ref rhs)
+            | il::Expression::Shl(ref lhs, ref rhs)
+            | il::Expression::Shr(ref lhs, ref rhs)
+            | il::Expression::Cmpeq(ref lhs, ref rhs)
+            | il::Expression::Cmpneq(ref lhs, ref rhs)
+            | il::Expression::Cmplts(ref lhs, ref rhs)
+            | il::Expression::Cmpltu(ref lhs, ref rhs) => {
+                let lhs = self.eval(lhs);
+                let rhs = self.eval(rhs);
+                if let (Some(lhs_value), Some(rhs_value)) = (lhs.value(), rhs.value()) {
+                    return constant_op(expression, lhs_value, lhs.bits(), rhs_value, rhs.bits());
+                }
+                self.binary(expression, &lhs, &rhs)
+            }
+            il::Expression::Zext(bits, ref src) => match self.eval(src).interval() {
+                Some(interval) => ValueSet::global(interval.zext(bits)),
+                None => ValueSet::top(bits),
+            },
+            il::Expression::Sext(bits, ref src) => match self.eval(src).interval() {
+                Some(interval) => ValueSet::global(interval.sext(bits)),
+                None => ValueSet::top(bits),
+            },
+            il::Expression::Trun(bits, ref src) => match self.eval(src).interval() {
+                Some(interval) => ValueSet::global(interval.trun(bits)),
+                None => ValueSet::top(bits),
+            },
+            il::Expression::Ite(ref condition, ref then, ref else_) => {
+                match self.eval(condition).value() {
+                    Some(0) => self.eval(else_),
+                    Some(_) => self.eval(then),
+                    None => self.eval(then).join(&self.eval(else_)),
+                }
+            }
+        }
+    }
+
+    fn binary(&self, expression: &il::Expression, lhs: &ValueSet, rhs: &ValueSet) -> ValueSet {
+        let bits = expression.bits();
+        match *expression {
+            il::Expression::Add(..) => lhs.add(rhs),
+            il::Expression::Sub(..) => lhs.sub(rhs),
+            il::Expression::Mul(..) => lhs.global_op(rhs, bits, StridedInterval::mul),
+            il::Expression::Divu(..) => lhs.global_op(rhs, bits, StridedInterval::divu),
+            il::Expression::Modu(..) => lhs.global_op(rhs, bits, StridedInterval::modu),
+            il::Expression::And(..) => match (lhs.interval(), rhs.value(), lhs.value()) {
+                (None, Some(mask), _) => lhs.align(mask).unwrap_or_else(|| ValueSet::top(bits)),
+                (_, None, Some(mask)) if rhs.interval().is_none() => {
+                    rhs.align(mask).unwrap_or_else(|| ValueSet::top(bits))
+                }
+                _ => lhs.global_op(rhs, bits, StridedInterval::and),
+            },
+            il::Expression::Or(..) => lhs.global_op(rhs, bits, StridedInterval::or),
+            il::Expression::Xor(..) => lhs.global_op(rhs, bits, StridedInterval::xor),
+            il::Expression::Shl(..) => lhs.global_op(rhs, bits, StridedInterval::shl),
+            il::Expression::Shr(..) => lhs.global_op(rhs, bits, StridedInterval::shr),
+            il::Expression::Cmpeq(..) => lhs
+                .global_op(rhs, bits, StridedInterval::cmpeq)
+                .or_boolean(),
+            il::Expression::Cmpneq(..) => {
+                match lhs.global_op(rhs, bits, StridedInterval::cmpeq).value() {
+                    Some(value) => ValueSet::constant(1, value ^ 1),
+                    None => ValueSet::global(StridedInterval::boolean()),
+                }
+            }
+            il::Expression::Cmplts(..) => lhs
+                .global_op(rhs, bits, StridedInterval::cmplts)
+                .or_boolean(),
+            il::Expression::Cmpltu(..) => lhs
+                .global_op(rhs, bits, StridedInterval::cmpltu)
+                .or_boolean(),
+            _ => ValueSet::top(bits),
+        }
+    }
+
+    /// Get the interval of an expression, if it only evaluates to numbers.
+    pub fn interval(&self, expression: &il::Expression) -> Option<StridedInterval> {
+        self.eval(expression).interval().cloned()
+    }
+
+    /// Get the value set loaded from the given address.
+    pub fn load(&self, address: &il::Expression, bits: usize) -> ValueSet {
+        let address = self.eval(address);
+        if address.is_top() {
+            return ValueSet::top(bits);
+        }
+        let mut result: Option<ValueSet> = None;
+        for (region, interval) in address.regions() {
+            let offsets = match interval.values(MAX_ACCESSES) {
+                Some(offsets) => offsets,
+                None => return ValueSet::top(bits),
+            };
+            for offset in offsets {
+                let value_set = match self.memory.get(&(*region, offset)) {
+                    Some(value_set) if value_set.bits() == bits => value_set,
+                    _ => return ValueSet::top(bits),
+                };
+                result = Some(match result {
+                    Some(result) => result.join(value_set),
+                    None => value_set.clone(),
+                });
+            }
+        }
+        result.unwrap_or_else(|| ValueSet::top(bits))
+    }
+
+    /// Store a value set to memory at the given address.
+    ///
+    /// A store to one address replaces the value set there. A store to a few
+    /// addresses joins with the value sets at each of them, and a store to
+    /// more addresses forgets the memory it may write.
+    fn store(&mut self, address: &il::Expression, value_set: ValueSet) {
+        let address = self.eval(address);
+        if address.is_top() {
+            self.memory.clear();
+            return;
+        }
+        let bytes = (value_set.bits() as u64).div_ceil(8);
+        for (region, interval) in address.regions() {
+            match interval.values(MAX_ACCESSES) {
+                Some(offsets) => {
+                    let strong = offsets.len() == 1;
+                    for offset in offsets {
+                        let old = self.memory.get(&(*region, offset)).cloned();
+                        self.forget(*region, offset, offset.saturating_add(bytes));
+                        let new = match old {
+                            _ if strong => value_set.clone(),
+                            Some(old) => old.join(&value_set),
+                            None => continue,
+                        };
+                        if !new.is_top() {
+                            self.memory.insert((*region, offset), new);
+                        }
+                    }
+                }
+                None => self.forget(
+                    *region,
+                    interval.lower(),
+                    interval.upper().saturating_add(bytes),
+                ),
+            }
+        }
+    }
+
+    /// Forget the value sets in memory which overlap the offsets from `start`
+    /// up to `end` in the given region.
+    fn forget(&mut self, region: Region, start: u64, end: u64) {
+        let overlapping: Vec<(Region, u64)> = self
+            .memory
+            .range((region, start.saturating_sub(16))..(region, end))
+            .filter(|((_, offset), value_set)| {
+                offset.saturating_add((value_set.bits() as u64).div_ceil(8)) > start
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        for key in overlapping {
+            self.memory.remove(&key);
+        }
+    }
+
+    /// Forget the definitions which read the given scalar, and its own
+    /// definition.
+    fn forget_definitions(&mut self, scalar: &il::Scalar) {
+        self.definitions.remove(scalar);
+        self.definitions
+            .retain(|_, expression| !expression.scalars().contains(&scalar));
+    }
+
+    fn assign(&mut self, dst: &il::Scalar, src: &il::Expression) {
+        let value_set = self.eval(src);
+        self.forget_definitions(dst);
+        if !src.scalars().contains(&dst) {
+            self.definitions.insert(dst.clone(), src.clone());
+        }
+        self.set_scalar(dst.clone(), value_set);
+    }
+
+    /// Refine these value sets by assuming the given condition has the given
+    /// truth, returning bottom if it cannot.
+    fn refine(self, condition: &il::Expression, truth: bool) -> ValueSets {
+        self.refine_depth(condition, truth, 0)
+    }
+
+    fn refine_depth(mut self, condition: &il::Expression, truth: bool, depth: usize) -> ValueSets {
+        if self.bottom || depth > MAX_REFINEMENT_DEPTH {
+            return self;
+        }
+        match self.eval(condition).value() {
+            Some(value) if (value != 0) != truth => return ValueSets::bottom(),
+            Some(_) => return self,
+            None => {}
+        }
+        match *condition {
+            il::Expression::Scalar(ref scalar) => {
+                self.set_scalar(scalar.clone(), ValueSet::constant(1, truth as u64));
+                match self.definitions.get(scalar).cloned() {
+                    Some(definition) => self.refine_depth(&definition, truth, depth + 1),
+                    None => self,
+                }
+            }
+            il::Expression::And(ref lhs, ref rhs) if condition.bits() == 1 => {
+                if truth {
+                    self.refine_depth(lhs, true, depth + 1)
+                        .refine_depth(rhs, true, depth + 1)
+                } else {
+                    self.clone()
+                        .refine_depth(lhs, false, depth + 1)
+                        .join(&self.refine_depth(rhs, false, depth + 1))
+                }
+            }
+            il::Expression::Or(ref lhs, ref rhs) if condition.bits() == 1 => {
+                if truth {
+                    self.clone()
+                        .refine_depth(lhs, true, depth + 1)
+                        .join(&self.refine_depth(rhs, true, depth + 1))
+                } else {
+                    self.refine_depth(lhs, false, depth + 1)
+                        .refine_depth(rhs, false, depth + 1)
+                }
+            }
+            il::Expression::Cmpneq(ref lhs, ref rhs) => self.refine_compare(
+                &il::Expression::Cmpeq(lhs.clone(), rhs.clone()),
+                !truth,
+                depth,
+            ),
+            il::Expression::Cmpeq(..) | il::Expression::Cmpltu(..) => {
+                self.refine_compare(condition, truth, depth)
+            }
+            _ => self,
+        }
+    }
+
+    fn refine_compare(self, condition: &il::Expression, truth: bool, depth: usize) -> ValueSets {
+        let (lhs, rhs) = match *condition {
+            il::Expression::Cmpeq(ref lhs, ref rhs) | il::Expression::Cmpltu(ref lhs, ref rhs) => {
+                (lhs.as_ref(), rhs.as_ref())
+            }
+            _ => return self,
+        };
+
+        if let il::Expression::Cmpeq(..) = *condition {
+            // A comparison of a boolean with a constant is the boolean, or its
+            // negation.
+            if lhs.bits() == 1 {
+                if let Some(value) = self.eval(rhs).value() {
+                    return self.refine_depth(lhs, truth == (value == 1), depth + 1);
+                }
+            }
+            // a - b == 0 when a == b.
+            if let il::Expression::Sub(ref a, ref b) = *lhs {
+                if self.eval(rhs).value() == Some(0) {
+                    let condition = il::Expression::Cmpeq(a.clone(), b.clone());
+                    return self.refine_depth(&condition, truth, depth + 1);
+                }
+            }
+        }
+
+        if let il::Expression::Cmpltu(..) = *condition {
+            // a < a - b, the borrow of a subtraction, when a < b.
+            if let il::Expression::Sub(ref a, ref b) = *rhs {
+                if a.as_ref() == lhs {
+                    let condition = il::Expression::Cmpltu(a.clone(), b.clone());
+                    return self.refine_depth(&condition, truth, depth + 1);
+                }
+            }
+        }
+
+        let lhs_interval = self.interval(lhs);
+        let rhs_interval = self.interval(rhs);
+        let mut state = self;
+        if let Some(rhs_interval) = rhs_interval {
+            state = state.restrict(lhs, |interval| match *condition {
+                il::Expression::Cmpeq(..) if truth => {
+                    interval.clamp(rhs_interval.lower(), rhs_interval.upper())
+                }
+                il::Expression::Cmpeq(..) => match rhs_interval.value() {
+                    Some(value) => interval.remove(value),
+                    None => Some(*interval),
+                },
+                _ if truth => match rhs_interval.upper() {
+                    0 => None,
+                    upper => interval.clamp(0, upper - 1),
+                },
+                _ => interval.clamp(rhs_interval.lower(), u64::MAX),
+            });
+        }
+        if let Some(lhs_interval) = lhs_interval {
+            state = state.restrict(rhs, |interval| match *condition {
+                il::Expression::Cmpeq(..) if truth => {
+                    interval.clamp(lhs_interval.lower(), lhs_interval.upper())
+                }
+                il::Expression::Cmpeq(..) => match lhs_interval.value() {
+                    Some(value) => interval.remove(value),
+                    None => Some(*interval),
+                },
+                _ if truth => match lhs_interval.lower().checked_add(1) {
+                    Some(lower) => interval.clamp(lower, u64::MAX),
+                    None => None,
+                },
+                _ => interval.clamp(0, lhs_interval.upper()),
+            });
+        }
+        state
+    }
+
+    /// Restrict the interval of an expression which is a scalar holding only
+    /// numbers, returning bottom if no values remain.
+    fn restrict<F>(mut self, expression: &il::Expression, f: F) -> ValueSets
+    where
+        F: Fn(&StridedInterval) -> Option<StridedInterval>,
+    {
+        let scalar = match *expression {
+            il::Expression::Scalar(ref scalar) if scalar.bits() <= 64 => scalar,
+            _ => return self,
+        };
+        let value_set = self.scalar(scalar);
+        let interval = match value_set.interval() {
+            Some(interval) => *interval,
+            None if value_set.is_top() => StridedInterval::top(scalar.bits()),
+            None => return self,
+        };
+        match f(&interval) {
+            Some(interval) => {
+                self.set_scalar(scalar.clone(), ValueSet::global(interval));
+                self
+            }
+            None => ValueSets::bottom(),
+        }
+    }
+
+    /// Returns true if every value in these value sets is in `other`.
+    fn is_subset(&self, other: &ValueSets) -> bool {
+        if self.bottom {
+            return true;
+        }
+        if other.bottom {
+            return false;
+        }
+        other.scalars.iter().all(|(scalar, value_set)| {
+            self.scalars
+                .get(scalar)
+                .is_some_and(|lhs| lhs.is_subset(value_set))
+        }) && other.memory.iter().all(|(key, value_set)| {
+            self.memory
+                .get(key)
+                .is_some_and(|lhs| lhs.is_subset(value_set))
+        }) && other
+            .definitions
+            .iter()
+            .all(|(scalar, expression)| self.definitions.get(scalar) == Some(expression))
+    }
+
+    /// These value sets without the values which point into, or are stored
+    /// in, the stack frame of the function, for use in another function.
+    fn without_stack(mut self) -> ValueSets {
+        self.scalars
+            .retain(|_, value_set| value_set.region(&Region::Stack).is_none());
+        self.memory.retain(|(region, _), value_set| {
+            *region != Region::Stack && value_set.region(&Region::Stack).is_none()
+        });
+        self.definitions.clear();
+        self
+    }
+
+    fn join(self, other: &ValueSets) -> ValueSets {
+        self.combine(other, ValueSet::join)
+    }
+
+    fn widen(self, other: &ValueSets) -> ValueSets {
+        self.combine(other, ValueSet::widen)
+    }
+
+    /// Combine the value sets of scalars and memory which both `ValueSets`
+    /// hold. Those held by only one may hold any value in the other.
+    fn combine<F>(self, other: &ValueSets, f: F) -> ValueSets
+    where
+        F: Fn(&ValueSet, &ValueSet) -> ValueSet,
+    {
+        if self.bottom {
+            return other.clone();
+        }
+        if other.bottom {
+            return self;
+        }
+        let mut result = ValueSets::new();
+        for (scalar, value_set) in self.scalars {
+            if let Some(rhs) = other.scalars.get(&scalar) {
+                result.set_scalar(scalar, f(&value_set, rhs));
+            }
+        }
+        for (key, value_set) in self.memory {
+            if let Some(rhs) = other.memory.get(&key) {
+                let value_set = f(&value_set, rhs);
+                if !value_set.is_top() {
+                    result.memory.insert(key, value_set);
+                }
+            }
+        }
+        for (scalar, expression) in self.definitions {
+            if other.definitions.get(&scalar) == Some(&expression) {
+                result.definitions.insert(scalar, expression);
+            }
+        }
+        result
+    }
+}
+
+/// Evaluate an operation over two numbers exactly.
+fn constant_op(
+    expression: &il::Expression,
+    lhs: u64,
+    lhs_bits: usize,
+    rhs: u64,
+    rhs_bits: usize,
+) -> ValueSet {
+    let bits = expression.bits();
+    let lhs = il::expr_const(lhs, lhs_bits);
+    let rhs = il::expr_const(rhs, rhs_bits);
+    let expression = match *expression {
+        il::Expression::Add(..) => il::Expression::add(lhs, rhs),
+        il::Expression::Sub(..) => il::Expression::sub(lhs, rhs),
+        il::Expression::Mul(..) => il::Expression::mul(lhs, rhs),
+        il::Expression::Divu(..) => il::Expression::divu(lhs, rhs),
+        il::Expression::Modu(..) => il::Expression::modu(lhs, rhs),
+        il::Expression::Divs(..) => il::Expression::divs(lhs, rhs),
+        il::Expression::Mods(..) => il::Expression::mods(lhs, rhs),
+        il::Expression::And(..) => il::Expression::and(lhs, rhs),
+        il::Expression::Or(..) => il::Expression::or(lhs, rhs),
+        il::Expression::Xor(..) => il::Expression::xor(lhs, rhs),
+        il::Expression::Shl(..) => il::Expression::shl(lhs, rhs),
+        il::Expression::Shr(..) => il::Expression::shr(lhs, rhs),
+        il::Expression::Cmpeq(..) => il::Expression::cmpeq(lhs, rhs),
+        il::Expression::Cmpneq(..) => il::Expression::cmpneq(lhs, rhs),
+        il::Expression::Cmplts(..) => il::Expression::cmplts(lhs, rhs),
+        il::Expression::Cmpltu(..) => il::Expression::cmpltu(lhs, rhs),
+        _ => return ValueSet::top(bits),
+    };
+    expression
+        .ok()
+        .and_then(|expression| eval(&expression).ok())
+        .and_then(|constant| {
+            constant
+                .value_u64()
+                .map(|value| ValueSet::constant(constant.bits(), value))
+        })
+        .unwrap_or_else(|| ValueSet::top(bits))
+}
+
+/// The value sets at entry to a function.
+fn entry(architecture: &dyn Architecture) -> ValueSets {
+    let mut state = ValueSets::new();
+    let stack_pointer = architecture.stack_pointer();
+    let bits = stack_pointer.bits();
+    state.set_scalar(
+        stack_pointer,
+        ValueSet::new(Region::Stack, StridedInterval::constant(bits, 0)),
+    );
+    state
+}
+
+struct ValueSetAnalysis<'a> {
+    architecture: &'a dyn Architecture,
+    allocators: HashSet<u64>,
+}
+
+impl<'a> ValueSetAnalysis<'a> {
+    /// A call may write any register it does not preserve, and any memory
+    /// outside of the caller's stack frame.
+    fn call(
+        &self,
+        mut state: ValueSets,
+        instruction: &il::Instruction,
+        target: &il::Expression,
+    ) -> ValueSets {
+        let calling_convention = self.architecture.calling_convention();
+        let stack_pointer = self.architecture.stack_pointer();
+        state.scalars.retain(|scalar, _| {
+            *scalar == stack_pointer || calling_convention.is_preserved(scalar) == Some(true)
+        });
+        state
+            .memory
+            .retain(|(region, _), _| *region == Region::Stack);
+        state.definitions.clear();
+
+        let allocator = state
+            .eval(target)
+            .value()
+            .is_some_and(|target| self.allocators.contains(&target));
+        if allocator {
+            let return_register = calling_convention.return_register().clone();
+            let bits = return_register.bits();
+            let site = instruction.address().unwrap_or(0);
+            state.set_scalar(
+                return_register,
+                ValueSet::new(Region::Heap(site), StridedInterval::constant(bits, 0)),
+            );
+        }
+        state
+    }
+}
+
+impl<'a, 'f> fixed_point::FixedPointAnalysis<'f, ValueSets> for ValueSetAnalysis<'a> {
+    fn trans(
+        &self,
+        location: il::RefProgramLocation<'f>,
+        state: Option<ValueSets>,
+    ) -> Result<ValueSets> {
+        let mut state = match state {
+            Some(state) => state,
+            None => entry(self.architecture),
+        };
+
+        let state = match *location.function_location() {
+            il::RefFunctionLocation::Instruction(_, instruction) => {
+                match *instruction.operation() {
+                    il::Operation::Assign { ref dst, ref src } => {
+                        state.assign(dst, src);
+                        state
+                    }
+                    il::Operation::Store { ref index, ref src } => {
+                        let value_set = state.eval(src);
+                        state.store(index, value_set);
+                        state
+                    }
+                    il::Operation::Load { ref dst, ref index } => {
+                        let value_set = state.load(index, dst.bits());
+                        state.forget_definitions(dst);
+                        state.set_scalar(dst.clone(), value_set);
+                        state
+                    }
+                    il::Operation::Branch { ref target } => self.call(state, instruction, target),
+                    il::Operation::Intrinsic { ref intrinsic } => {
+                        match intrinsic.scalars_written() {
+                            Some(scalars_written) => {
+                                for scalar in scalars_written {
+                                    state.forget_definitions(scalar);
+                                    state.set_scalar(scalar.clone(), ValueSet::top(scalar.bits()));
+                                }
+                            }
+                            None => {
+                                state.scalars.clear();
+                                state.definitions.clear();
+                            }
+                        }
+                        state.memory.clear();
+                        state
+                    }
+                    il::Operation::Nop => state,
+                }
+            }
+            il::RefFunctionLocation::Edge(edge) => match edge.condition() {
+                Some(condition) => state.refine(condition, true),
+                None => state,
+            },
+            il::RefFunctionLocation::EmptyBlock(_) => state,
+        };
+
+        Ok(state)
+    }
+
+    fn join(&self, state0: ValueSets, state1: &ValueSets) -> Result<ValueSets> {
+        Ok(state0.join(state1))
+    }
+
+    fn widen(&self, previous: &ValueSets, state: ValueSets) -> Result<ValueSets> {
+        Ok(previous.clone().widen(&state))
+    }
+}
+
+impl<'a, 'p> interprocedural::InterproceduralAnalysis<'p, ValueSets> for ValueSetAnalysis<'a> {
+    fn entry(&self, _function: &'p il::Function) -> Result<ValueSets> {
+        Ok(entry(self.architecture))
+    }
+
+    fn trans(&self, location: il::RefProgramLocation<'p>, state: ValueSets) -> Result<ValueSets> {
+        fixed_point::FixedPointAnalysis::trans(self, location, Some(state))
+    }
+
+    fn join(&self, state0: ValueSets, state1: &ValueSets) -> Result<ValueSets> {
+        Ok(state0.join(state1))
+    }
+
+    fn widen(&self, previous: &ValueSets, state: ValueSets) -> Result<ValueSets> {
+        Ok(previous.clone().widen(&state))
+    }
+
+    /// Targets are resolved with the value set of the target. Calls to
+    /// allocators are left to `unknown_call`.
+    fn targets(&self, location: &il::RefProgramLocation<'p>, state: &ValueSets) -> Vec<u64> {
+        let target = match location
+            .instruction()
+            .map(|instruction| instruction.operation())
+        {
+            Some(il::Operation::Branch { target }) => target,
+            _ => return Vec::new(),
+        };
+        state
+            .eval(target)
+            .interval()
+            .and_then(|interval| interval.values(MAX_ACCESSES))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|target| !self.allocators.contains(target))
+            .collect()
+    }
+
+    fn call(
+        &self,
+        _location: &il::RefProgramLocation<'p>,
+        _callee: &'p il::Function,
+        state: &ValueSets,
+    ) -> Result<ValueSets> {
+        let mut state = state.clone().without_stack();
+        let stack_pointer = self.architecture.stack_pointer();
+        let bits = stack_pointer.bits();
+        state.set_scalar(
+            stack_pointer,
+            ValueSet::new(Region::Stack, StridedInterval::constant(bits, 0)),
+        );
+        Ok(state)
+    }
+
+    fn summary(&self, _function: &'p il::Function, exit: ValueSets) -> Result<ValueSets> {
+        Ok(exit.without_stack())
+    }
+
+    /// The registers the calling convention preserves, the stack pointer,
+    /// and the caller's stack frame are kept. Everything else is taken from
+    /// the summary of the callee.
+    fn return_(
+        &self,
+        _location: &il::RefProgramLocation<'p>,
+        state: &ValueSets,
+        summary: &ValueSets,
+    ) -> Result<ValueSets> {
+        if state.bottom || summary.bottom {
+            return Ok(ValueSets::bottom());
+        }
+        let calling_convention = self.architecture.calling_convention();
+        let stack_pointer = self.architecture.stack_pointer();
+        let preserved = |scalar: &il::Scalar| {
+            *scalar == stack_pointer || calling_convention.is_preserved(scalar) == Some(true)
+        };
+        let mut state = state.clone();
+        state.scalars.retain(|scalar, _| preserved(scalar));
+        for (scalar, value_set) in &summary.scalars {
+            if !preserved(scalar) {
+                state.scalars.insert(scalar.clone(), value_set.clone());
+            }
+        }
+        state
+            .memory
+            .retain(|(region, _), _| *region == Region::Stack);
+        state.memory.extend(
+            summary
+                .memory
+                .iter()
+                .map(|(key, value_set)| (*key, value_set.clone())),
+        );
+        state.definitions.clear();
+        Ok(state)
+    }
+
+    fn unknown_call(
+        &self,
+        location: &il::RefProgramLocation<'p>,
+        state: ValueSets,
+    ) -> Result<ValueSets> {
+        let instruction = location.instruction().ok_or("Call is not an instruction")?;
+        match *instruction.operation() {
+            il::Operation::Branch { ref target } => Ok(self.call(state, instruction, target)),
+            _ => bail!("Call is not a branch"),
+        }
+    }
+}
+
+#[cfg(test)]
+fn first_location(function: &il::Function, block_index: usize) -> il::ProgramLocation {
+    let block = function.control_flow_graph().block(block_index).unwrap();
+    let location = match block.instructions().first() {
+        Some(instruction) => il::RefFunctionLocation::Instruction(block, instruction),
+        None => il::RefFunctionLocation::EmptyBlock(block),
+    };
+    il::RefProgramLocation::new(function, location).into()
+}
+
+#[test]
+fn strided_interval_test() {
+    let a = StridedInterval::new(32, 4, 0x10, 0x22);
+    assert_eq!(a.upper(), 0x20);
+    assert_eq!(a.size(), 5);
+    assert!(a.contains(0x18));
+    assert!(!a.contains(0x1a));
+    assert_eq!(a.values(8), Some(vec![0x10, 0x14, 0x18, 0x1c, 0x20]));
+    assert_eq!(a.values(4), None);
+
+    let b = StridedInterval::constant(32, 0x2);
+    assert_eq!(a.join(&b), StridedInterval::new(32, 2, 0x2, 0x20));
+    assert!(a.is_subset(&a.join(&b)));
+    assert!(!a.join(&b).is_subset(&a));
+
+    assert_eq!(a.add(&b), StridedInterval::new(32, 4, 0x12, 0x22));
+    assert_eq!(
+        b.sub(&StridedInterval::constant(32, 4)),
+        StridedInterval::constant(32, 0xffff_fffe)
+    );
+    assert_eq!(
+        b.sub(&a),
+        StridedInterval::new(32, 4, 0xffff_ffe2, 0xffff_fff2)
+    );
+    assert!(StridedInterval::new(32, 1, 0, 0x10)
+        .sub(&StridedInterval::constant(32, 1))
+        .is_top());
+    assert_eq!(
+        a.mul(&StridedInterval::constant(32, 2)),
+        StridedInterval::new(32, 8, 0x20, 0x40)
+    );
+    assert_eq!(
+        a.shr(&StridedInterval::constant(32, 2)),
+        StridedInterval::new(32, 1, 0x4, 0x8)
+    );
+    assert_eq!(
+        a.and(&StridedInterval::constant(32, 0xff)),
+        StridedInterval::new(32, 4, 0x10, 0x20)
+    );
+    assert_eq!(
+        a.clamp(0x11, 0x1f),
+        Some(StridedInterval::new(32, 4, 0x14, 0x1c))
+    );
+    assert_eq!(a.clamp(0x21, 0x30), None);
+    assert_eq!(a.trun(8), StridedInterval::new(8, 4, 0x10, 0x20));
+    assert_eq!(
+        StridedInterval::constant(8, 0x80).sext(16),
+        StridedInterval::constant(16, 0xff80)
+    );
+
+    // Widening moves growing bounds to the limits of the interval.
+    let c = StridedInterval::new(32, 1, 0, 1);
+    assert_eq!(
+        c.widen(&StridedInterval::new(32, 1, 0, 2)),
+        StridedInterval::new(32, 1, 0, 0xffff_ffff)
+    );
+    assert_eq!(c.widen(&StridedInterval::constant(32, 1)), c);
+}
+
+#[test]
+fn value_sets_loop_test() {
+    /*
+    i = 0
+    while i < 10 {
+        [0x1000 + i * 4] = 0
+        i = i + 1
+    }
+    */
+    use crate::architecture::Amd64;
+
+    let i = || il::expr_scalar("i", 32);
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let entry_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("i", 32), il::expr_const(0, 32));
+        block.index()
+    };
+
+    let head_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.nop();
+        block.index()
+    };
+
+    let body_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.store(
+            il::Expression::add(
+                il::expr_const(0x1000, 32),
+                il::Expression::mul(i(), il::expr_const(4, 32)).unwrap(),
+            )
+            .unwrap(),
+            il::expr_const(0, 32),
+        );
+        block.assign(
+            il::scalar("i", 32),
+            il::Expression::add(i(), il::expr_const(1, 32)).unwrap(),
+        );
+        block.index()
+    };
+
+    let exit_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.nop();
+        block.index()
+    };
+
+    let condition = il::Expression::cmpltu(i(), il::expr_const(10, 32)).unwrap();
+    control_flow_graph
+        .unconditional_edge(entry_index, head_index)
+        .unwrap();
+    control_flow_graph
+        .conditional_edge(head_index, body_index, condition.clone())
+        .unwrap();
+    control_flow_graph
+        .conditional_edge(
+            head_index,
+            exit_index,
+            il::Expression::cmpeq(condition, il::expr_const(0, 1)).unwrap(),
+        )
+        .unwrap();
+    control_flow_graph
+        .unconditional_edge(body_index, head_index)
+        .unwrap();
+    control_flow_graph.set_entry(entry_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+    let value_sets = value_sets(&function, &Amd64::new(), &[]).unwrap();
+
+    let body = &value_sets[&first_location(&function, body_index)];
+    assert_eq!(body.interval(&i()), Some(StridedInterval::new(32, 1, 0, 9)));
+    let address = match function
+        .control_flow_graph()
+        .block(body_index)
+        .unwrap()
+        .instructions()[0]
+        .operation()
+    {
+        il::Operation::Store { index, .. } => index.clone(),
+        _ => panic!("expected a store"),
+    };
+    assert_eq!(
+        body.interval(&address),
+        Some(StridedInterval::new(32, 4, 0x1000, 0x1024))
+    );
+
+    // Widening takes i to the top of its range, and narrowing brings it back.
+    let head = &value_sets[&first_location(&function, head_index)];
+    assert_eq!(
+        head.interval(&i()),
+        Some(StridedInterval::new(32, 1, 0, 10))
+    );
+    let exit = &value_sets[&first_location(&function, exit_index)];
+    assert_eq!(exit.interval(&i()), Some(StridedInterval::constant(32, 10)));
+}
+
+#[test]
+fn value_sets_bounds_check_test() {
+    /*
+    rsp = rsp - 8
+    [rsp] = 5
+    rax = [rsp]
+    ZF = rdi - 3 == 0
+    CF = rdi <u rdi - 3
+    if CF || ZF {
+        // table
+    }
+    else {
+        // default
+    }
+    */
+    use crate::architecture::Amd64;
+
+    let rdi = || il::expr_scalar("rdi", 64);
+    let rdi_3 = || il::Expression::sub(rdi(), il::expr_const(3, 64)).unwrap();
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+
+    let head_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(
+            il::scalar("rsp", 64),
+            il::Expression::sub(il::expr_scalar("rsp", 64), il::expr_const(8, 64)).unwrap(),
+        );
+        block.store(il::expr_scalar("rsp", 64), il::expr_const(5, 64));
+        block.load(il::scalar("rax", 64), il::expr_scalar("rsp", 64));
+        block.assign(
+            il::scalar("ZF", 1),
+            il::Expression::cmpeq(rdi_3(), il::expr_const(0, 64)).unwrap(),
+        );
+        block.assign(
+            il::scalar("CF", 1),
+            il::Expression::cmpltu(rdi(), rdi_3()).unwrap(),
+        );
+        block.index()
+    };
+
+    let table_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.nop();
+        block.index()
+    };
+
+    let default_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.nop();
+        block.index()
+    };
+
+    let flag = |name: &str, value: u64| {
+        il::Expression::cmpeq(il::expr_scalar(name, 1), il::expr_const(value, 1)).unwrap()
+    };
+    control_flow_graph
+        .conditional_edge(
+            head_index,
+            table_index,
+            il::Expression::or(flag("CF", 1), flag("ZF", 1)).unwrap(),
+        )
+        .unwrap();
+    control_flow_graph
+        .conditional_edge(
+            head_index,
+            default_index,
+            il::Expression::and(flag("CF", 0), flag("ZF", 0)).unwrap(),
+        )
+        .unwrap();
+    control_flow_graph.set_entry(head_index).unwrap();
+
+    let function = il::Function::new(0, control_flow_graph);
+    let value_sets = value_sets(&function, &Amd64::new(), &[]).unwrap();
+
+    let table = &value_sets[&first_location(&function, table_index)];
+    assert_eq!(
+        table.interval(&rdi()),
+        Some(StridedInterval::new(64, 1, 0, 3))
+    );
+    assert_eq!(table.eval(&il::expr_scalar("rax", 64)).value(), Some(5));
+    assert_eq!(
+        table.scalar(&il::scalar("rsp", 64)),
+        ValueSet::new(
+            Region::Stack,
+            StridedInterval::constant(64, 0xffff_ffff_ffff_fff8)
+        )
+    );
+    assert_eq!(
+        table.memory(Region::Stack, 0xffff_ffff_ffff_fff8),
+        Some(&ValueSet::constant(64, 5))
+    );
+
+    let default = &value_sets[&first_location(&function, default_index)];
+    assert_eq!(
+        default.interval(&rdi()),
+        Some(StridedInterval::new(64, 1, 4, u64::MAX))
+    );
+}
+
+#[test]
+fn value_sets_interprocedural_test() {
+    /*
+    0x1000:
+        rdi = 5
+        rbx = 7
+        call 0x2000
+        nop
+
+    0x2000:
+        rax = rdi + 1
+        rbx = 9
+        [0x9000] = rax
+        return
+    */
+    use crate::architecture::Amd64;
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let call_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(il::scalar("rdi", 64), il::expr_const(5, 64));
+        block.assign(il::scalar("rbx", 64), il::expr_const(7, 64));
+        block.branch(il::expr_const(0x2000, 64));
+        block.index()
+    };
+    let exit_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.nop();
+        block.index()
+    };
+    control_flow_graph
+        .unconditional_edge(call_index, exit_index)
+        .unwrap();
+    control_flow_graph.set_entry(call_index).unwrap();
+    let main = il::Function::new(0x1000, control_flow_graph);
+
+    let mut control_flow_graph = il::ControlFlowGraph::new();
+    let block_index = {
+        let block = control_flow_graph.new_block().unwrap();
+        block.assign(
+            il::scalar("rax", 64),
+            il::Expression::add(il::expr_scalar("rdi", 64), il::expr_const(1, 64)).unwrap(),
+        );
+        block.assign(il::scalar("rbx", 64), il::expr_const(9, 64));
+        block.store(il::expr_const(0x9000, 64), il::expr_scalar("rax", 64));
+        block.branch(il::expr_scalar("ra", 64));
+        block.index()
+    };
+    control_flow_graph.set_entry(block_index).unwrap();
+    let callee = il::Function::new(0x2000, control_flow_graph);
+
+    let mut program = il::Program::new();
+    program.add_function(main);
+    program.add_function(callee);
+
+    let after_call =
+        il::ProgramLocation::new(Some(0), il::FunctionLocation::Instruction(exit_index, 0));
+    let rax = il::scalar("rax", 64);
+    let rbx = il::scalar("rbx", 64);
+    let rsp = il::scalar("rsp", 64);
+
+    // Calls write every register which is not preserved.
+    let function = program.function(0).unwrap();
+    let value_sets = value_sets(function, &Amd64::new(), &[]).unwrap();
+    assert!(value_sets[&after_call].scalar(&rax).is_top());
+
+    // The callee's summary gives the values it returns, and the memory it
+    // writes, while the caller keeps its preserved registers and stack.
+    let value_sets = value_sets_interprocedural(&program, &Amd64::new(), &[], 0).unwrap();
+    let after_call = &value_sets[&after_call];
+    assert_eq!(after_call.scalar(&rax).value(), Some(6));
+    assert_eq!(after_call.scalar(&rbx).value(), Some(7));
+    assert_eq!(
+        after_call.memory(Region::Global, 0x9000),
+        Some(&ValueSet::constant(64, 6))
+    );
+    assert_eq!(
+        after_call.scalar(&rsp).region(&Region::Stack),
+        Some(&StridedInterval::constant(64, 0))
+    );
+}
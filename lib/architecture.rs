@@ -1,6 +1,7 @@
 //! Information and types for Falcon's supported architectures.
 
 use crate::analysis::calling_convention::{CallingConvention, CallingConventionType};
+use crate::executor::GdbTarget;
 use crate::il;
 use crate::translator;
 use std::fmt::Debug;
@@ -27,8 +28,18 @@ pub trait Architecture: Debug + Send + Sync {
     fn stack_pointer(&self) -> il::Scalar;
     /// Get the size of a natural word for this architecture in bits.
     fn word_size(&self) -> usize;
+    /// Get the size of the largest instruction for this architecture in
+    /// bytes.
+    fn max_instruction_size(&self) -> usize {
+        16
+    }
     /// Clone into a boxed `Architecture`
     fn box_clone(&self) -> Box<dyn Architecture>;
+    /// Get the registers of this architecture as described to GDB, if GDB
+    /// registers are described for this architecture.
+    fn gdb_target(&self) -> Option<GdbTarget> {
+        None
+    }
 }
 
 /// The 64-bit X86 Architecture.
@@ -60,9 +71,15 @@ impl Architecture for Amd64 {
     fn word_size(&self) -> usize {
         64
     }
+    fn max_instruction_size(&self) -> usize {
+        15
+    }
     fn box_clone(&self) -> Box<dyn Architecture> {
         Box::new(self.clone())
     }
+    fn gdb_target(&self) -> Option<GdbTarget> {
+        Some(GdbTarget::amd64())
+    }
 }
 
 /// The 32-bit Mips Architecture.
@@ -94,9 +111,15 @@ impl Architecture for Mips {
     fn word_size(&self) -> usize {
         32
     }
+    fn max_instruction_size(&self) -> usize {
+        4
+    }
     fn box_clone(&self) -> Box<dyn Architecture> {
         Box::new(self.clone())
     }
+    fn gdb_target(&self) -> Option<GdbTarget> {
+        Some(GdbTarget::mips())
+    }
 }
 
 /// The 32-bit Mipsel Architecture.
@@ -128,9 +151,15 @@ impl Architecture for Mipsel {
     fn word_size(&self) -> usize {
         32
     }
+    fn max_instruction_size(&self) -> usize {
+        4
+    }
     fn box_clone(&self) -> Box<dyn Architecture> {
         Box::new(self.clone())
     }
+    fn gdb_target(&self) -> Option<GdbTarget> {
+        Some(GdbTarget::mips())
+    }
 }
 
 /// The 32-bit Mips Architecture.
@@ -162,9 +191,15 @@ impl Architecture for Ppc {
     fn word_size(&self) -> usize {
         32
     }
+    fn max_instruction_size(&self) -> usize {
+        4
+    }
     fn box_clone(&self) -> Box<dyn Architecture> {
         Box::new(self.clone())
     }
+    fn gdb_target(&self) -> Option<GdbTarget> {
+        Some(GdbTarget::ppc())
+    }
 }
 
 /// The 32-bit X86 Architecture.
@@ -196,7 +231,13 @@ impl Architecture for X86 {
     fn word_size(&self) -> usize {
         32
     }
+    fn max_instruction_size(&self) -> usize {
+        15
+    }
     fn box_clone(&self) -> Box<dyn Architecture> {
         Box::new(self.clone())
     }
+    fn gdb_target(&self) -> Option<GdbTarget> {
+        Some(GdbTarget::x86())
+    }
 }
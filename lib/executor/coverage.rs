@@ -0,0 +1,404 @@
+//! Code coverage of concrete execution.
+//!
+//! `Coverage` counts the instruction addresses, `il::Block`s and `il::Edge`s
+//! hit by a `Driver`. Blocks and edges are identified by the address of their
+//! `il::Function` and their indices, so coverage collected over different
+//! runs, each of which lifts its own `il::Program`, can be merged.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::executor::{Coverage, DrcovModule, Driver};
+//!
+//! # #[allow(dead_code)]
+//! fn cover(mut driver: Driver, steps: usize) -> Result<Vec<u8>> {
+//!     let mut coverage = Coverage::new();
+//!     for _ in 0..steps {
+//!         driver = coverage.step(driver)?;
+//!     }
+//!
+//!     println!("{}", coverage.json(driver.program())?);
+//!
+//!     let mut drcov = Vec::new();
+//!     let module = DrcovModule::new("/bin/parser", 0x400000, 0x500000);
+//!     coverage.drcov(&mut drcov, &[module])?;
+//!     Ok(drcov)
+//! }
+//! ```
+
+use crate::error::*;
+use crate::executor::Driver;
+use crate::il;
+use serde_json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+/// Coverage of instruction addresses, blocks and edges, with hit counts.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Coverage {
+    addresses: BTreeMap<u64, u64>,
+    /// Keyed by (function address, block index).
+    blocks: BTreeMap<(u64, usize), u64>,
+    /// Keyed by (function address, head block index, tail block index).
+    edges: BTreeMap<(u64, usize, usize), u64>,
+}
+
+impl Coverage {
+    /// Create a new, empty, `Coverage`.
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Record the location the given driver is about to execute.
+    ///
+    /// An address is counted once for each time execution enters the
+    /// instruction at that address, not once for each `il::Instruction`
+    /// lifted from it.
+    pub fn record(&mut self, driver: &Driver) -> Result<()> {
+        let location = driver.location().apply(driver.program())?;
+        let function_address = location.function().address();
+        match *location.function_location() {
+            il::RefFunctionLocation::Instruction(block, instruction) => {
+                let index = block
+                    .instructions()
+                    .iter()
+                    .position(|i| i.index() == instruction.index())
+                    .ok_or("Failed to find instruction in its block")?;
+                if index == 0 {
+                    *self
+                        .blocks
+                        .entry((function_address, block.index()))
+                        .or_insert(0) += 1;
+                }
+                if let Some(address) = instruction.address() {
+                    let previous = if index == 0 {
+                        None
+                    } else {
+                        block.instructions()[index - 1].address()
+                    };
+                    if previous != Some(address) {
+                        *self.addresses.entry(address).or_insert(0) += 1;
+                    }
+                }
+            }
+            il::RefFunctionLocation::Edge(edge) => {
+                *self
+                    .edges
+                    .entry((function_address, edge.head(), edge.tail()))
+                    .or_insert(0) += 1;
+            }
+            il::RefFunctionLocation::EmptyBlock(block) => {
+                *self
+                    .blocks
+                    .entry((function_address, block.index()))
+                    .or_insert(0) += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the location the given driver is about to execute, and then
+    /// step the driver.
+    pub fn step(&mut self, driver: Driver) -> Result<Driver> {
+        self.record(&driver)?;
+        driver.step()
+    }
+
+    /// Merge the hits of another `Coverage` into this `Coverage`.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (address, hits) in &other.addresses {
+            *self.addresses.entry(*address).or_insert(0) += hits;
+        }
+        for (block, hits) in &other.blocks {
+            *self.blocks.entry(*block).or_insert(0) += hits;
+        }
+        for (edge, hits) in &other.edges {
+            *self.edges.entry(*edge).or_insert(0) += hits;
+        }
+    }
+
+    /// The number of times the instruction at the given address was hit.
+    pub fn address_hits(&self, address: u64) -> u64 {
+        self.addresses.get(&address).cloned().unwrap_or(0)
+    }
+
+    /// The number of times the given block, of the function at the given
+    /// address, was hit.
+    pub fn block_hits(&self, function_address: u64, block_index: usize) -> u64 {
+        self.blocks
+            .get(&(function_address, block_index))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// The number of times the given edge, of the function at the given
+    /// address, was hit.
+    pub fn edge_hits(&self, function_address: u64, head: usize, tail: usize) -> u64 {
+        self.edges
+            .get(&(function_address, head, tail))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Every address hit, and the number of times it was hit.
+    pub fn addresses(&self) -> &BTreeMap<u64, u64> {
+        &self.addresses
+    }
+
+    /// Every block hit, keyed by function address and block index, and the
+    /// number of times it was hit.
+    pub fn blocks(&self) -> &BTreeMap<(u64, usize), u64> {
+        &self.blocks
+    }
+
+    /// Every edge hit, keyed by function address, head and tail, and the
+    /// number of times it was hit.
+    pub fn edges(&self) -> &BTreeMap<(u64, usize, usize), u64> {
+        &self.edges
+    }
+
+    /// Summarise the coverage of every function in the given program.
+    pub fn summary(&self, program: &il::Program) -> Vec<FunctionCoverage> {
+        program
+            .functions()
+            .into_iter()
+            .map(|function| {
+                let address = function.address();
+                let instructions = function
+                    .blocks()
+                    .into_iter()
+                    .flat_map(|block| block.instructions().iter())
+                    .filter_map(|instruction| instruction.address())
+                    .collect::<BTreeSet<u64>>();
+                let blocks = function.blocks();
+                let edges = function.edges();
+                FunctionCoverage {
+                    address,
+                    name: function.name(),
+                    instructions: instructions.len(),
+                    instructions_hit: instructions
+                        .iter()
+                        .filter(|&&address| self.address_hits(address) > 0)
+                        .count(),
+                    blocks: blocks.len(),
+                    blocks_hit: blocks
+                        .iter()
+                        .filter(|block| self.block_hits(address, block.index()) > 0)
+                        .count(),
+                    edges: edges.len(),
+                    edges_hit: edges
+                        .iter()
+                        .filter(|edge| self.edge_hits(address, edge.head(), edge.tail()) > 0)
+                        .count(),
+                }
+            })
+            .collect()
+    }
+
+    /// Summarise the coverage of every function in the given program as
+    /// JSON.
+    pub fn json(&self, program: &il::Program) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.summary(program))?)
+    }
+
+    /// Write this coverage in the drcov format, version 2, as read by
+    /// coverage visualisers such as Lighthouse.
+    ///
+    /// Falcon does not know the size of the instructions it lifts, so every
+    /// address hit is written as a basic block of one byte. Addresses outside
+    /// of the given modules are not written.
+    pub fn drcov<W: Write>(&self, writer: &mut W, modules: &[DrcovModule]) -> Result<()> {
+        let mut entries: Vec<(u32, u16)> = Vec::new();
+        for address in self.addresses.keys() {
+            let module = modules
+                .iter()
+                .position(|module| *address >= module.base && *address < module.end);
+            if let Some(id) = module {
+                let offset = address - modules[id].base;
+                if offset > u32::MAX as u64 {
+                    bail!("Address 0x{:x} is too far from its module base", address);
+                }
+                entries.push((offset as u32, id as u16));
+            }
+        }
+
+        writer.write_all(b"DRCOV VERSION: 2\nDRCOV FLAVOR: drcov\n")?;
+        writer
+            .write_all(format!("Module Table: version 2, count {}\n", modules.len()).as_bytes())?;
+        writer.write_all(b"Columns: id, base, end, entry, checksum, timestamp, path\n")?;
+        for (id, module) in modules.iter().enumerate() {
+            writer.write_all(
+                format!(
+                    "{}, 0x{:x}, 0x{:x}, 0x0, 0x0, 0x0, {}\n",
+                    id, module.base, module.end, module.path
+                )
+                .as_bytes(),
+            )?;
+        }
+        writer.write_all(format!("BB Table: {} bbs\n", entries.len()).as_bytes())?;
+        for (offset, id) in entries {
+            // struct { uint32_t start; uint16_t size; uint16_t mod_id; }
+            writer.write_all(&offset.to_le_bytes())?;
+            writer.write_all(&1u16.to_le_bytes())?;
+            writer.write_all(&id.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// A module, an executable or library mapped into memory, in a drcov file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DrcovModule {
+    path: String,
+    base: u64,
+    end: u64,
+}
+
+impl DrcovModule {
+    /// Create a new `DrcovModule` for the module at the given path, mapped
+    /// from `base` up to, but not including, `end`.
+    pub fn new<S: Into<String>>(path: S, base: u64, end: u64) -> DrcovModule {
+        DrcovModule {
+            path: path.into(),
+            base,
+            end,
+        }
+    }
+}
+
+/// A summary of the coverage of one `il::Function`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct FunctionCoverage {
+    /// The address of the function.
+    pub address: u64,
+    /// The name of the function.
+    pub name: String,
+    /// The number of distinct instruction addresses in the function.
+    pub instructions: usize,
+    /// The number of those addresses hit.
+    pub instructions_hit: usize,
+    /// The number of blocks in the function.
+    pub blocks: usize,
+    /// The number of those blocks hit.
+    pub blocks_hit: usize,
+    /// The number of edges in the function.
+    pub edges: usize,
+    /// The number of those edges hit.
+    pub edges_hit: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{Amd64, Endian};
+    use crate::executor::{Memory, State};
+    use crate::RC;
+
+    // A function at 0x1000 which branches on rax to one of two blocks, each
+    // made of one instruction lifted to two IL instructions.
+    fn driver(rax: u64) -> Driver {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+
+        let head_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.nop();
+            block.instructions_mut()[0].set_address(Some(0x1000));
+            block.index()
+        };
+        let mut tails = Vec::new();
+        for address in [0x1004, 0x1008].iter() {
+            let block = control_flow_graph.new_block().unwrap();
+            block.assign(il::scalar("r", 64), il::expr_const(*address, 64));
+            block.nop();
+            for instruction in block.instructions_mut() {
+                instruction.set_address(Some(*address));
+            }
+            tails.push(block.index());
+        }
+
+        let condition =
+            il::Expression::cmpeq(il::expr_scalar("rax", 64), il::expr_const(0, 64)).unwrap();
+        control_flow_graph
+            .conditional_edge(head_index, tails[0], condition.clone())
+            .unwrap();
+        control_flow_graph
+            .conditional_edge(
+                head_index,
+                tails[1],
+                il::Expression::cmpeq(condition, il::expr_const(0, 1)).unwrap(),
+            )
+            .unwrap();
+        control_flow_graph.set_entry(head_index).unwrap();
+
+        let mut program = il::Program::new();
+        program.add_function(il::Function::new(0x1000, control_flow_graph));
+
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+
+        let mut state = State::new(Memory::new(Endian::Little));
+        state.set_scalar("rax", il::const_(rax, 64));
+
+        Driver::new(RC::new(program), location, state, RC::new(Amd64::new()))
+    }
+
+    fn run(rax: u64) -> (Coverage, Driver) {
+        let mut coverage = Coverage::new();
+        let mut driver = driver(rax);
+        // nop, edge, assign, nop
+        for _ in 0..3 {
+            driver = coverage.step(driver).unwrap();
+        }
+        coverage.record(&driver).unwrap();
+        (coverage, driver)
+    }
+
+    #[test]
+    fn record_and_merge() {
+        let (mut coverage, driver) = run(0);
+        assert_eq!(coverage.address_hits(0x1000), 1);
+        assert_eq!(coverage.address_hits(0x1004), 1);
+        assert_eq!(coverage.address_hits(0x1008), 0);
+        assert_eq!(coverage.block_hits(0x1000, 0), 1);
+        assert_eq!(coverage.block_hits(0x1000, 1), 1);
+        assert_eq!(coverage.edge_hits(0x1000, 0, 1), 1);
+
+        let summary = coverage.summary(driver.program());
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].instructions, 3);
+        assert_eq!(summary[0].instructions_hit, 2);
+        assert_eq!(summary[0].blocks_hit, 2);
+        assert_eq!(summary[0].edges, 2);
+        assert_eq!(summary[0].edges_hit, 1);
+
+        coverage.merge(&run(1).0);
+        coverage.merge(&run(1).0);
+        assert_eq!(coverage.address_hits(0x1000), 3);
+        assert_eq!(coverage.address_hits(0x1008), 2);
+        assert_eq!(coverage.edge_hits(0x1000, 0, 2), 2);
+        let summary = coverage.summary(driver.program());
+        assert_eq!(summary[0].instructions_hit, 3);
+        assert_eq!(summary[0].edges_hit, 2);
+    }
+
+    #[test]
+    fn drcov() {
+        let (coverage, _) = run(0);
+        let mut bytes = Vec::new();
+        coverage
+            .drcov(&mut bytes, &[DrcovModule::new("test", 0x1000, 0x1006)])
+            .unwrap();
+
+        let header = "DRCOV VERSION: 2\n\
+                      DRCOV FLAVOR: drcov\n\
+                      Module Table: version 2, count 1\n\
+                      Columns: id, base, end, entry, checksum, timestamp, path\n\
+                      0, 0x1000, 0x1006, 0x0, 0x0, 0x0, test\n\
+                      BB Table: 2 bbs\n";
+        assert_eq!(&bytes[0..header.len()], header.as_bytes());
+        assert_eq!(
+            &bytes[header.len()..],
+            &[0, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0]
+        );
+    }
+}
@@ -0,0 +1,703 @@
+//! A debugger over the concrete executor.
+//!
+//! A `Debugger` drives a `Driver` one native instruction at a time, stopping
+//! at breakpoints and watchpoints, and reports why it stopped with a `Stop`.
+//!
+//! Calls are detected when a branch transfers control to the first
+//! instruction of a function, at which point the return address is taken
+//! from the architecture's calling convention and pushed to a shadow call
+//! stack. This allows calls to be stepped over, and functions to be run until
+//! they return.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::executor::{Debugger, Driver, Stop, WatchKind};
+//!
+//! # #[allow(dead_code)]
+//! fn debug(driver: Driver) -> Result<()> {
+//!     let mut debugger = Debugger::new(driver);
+//!     debugger.add_breakpoint(0x400800);
+//!     debugger.add_watchpoint(0x601040, 4, WatchKind::Write);
+//!     debugger.set_step_budget(Some(1_000_000));
+//!
+//!     loop {
+//!         match debugger.continue_()? {
+//!             Stop::Breakpoint { address } => println!("Breakpoint at 0x{:x}", address),
+//!             Stop::Watchpoint { address, .. } => println!("Write to 0x{:x}", address),
+//!             stop => {
+//!                 println!("Stopped: {:?}", stop);
+//!                 break;
+//!             }
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::*;
+use crate::executor::driver::instruction_start;
+use crate::executor::{return_address, Driver, State};
+use crate::il;
+use crate::memory::MemoryFault;
+use std::collections::BTreeMap;
+
+/// The kind of memory access a watchpoint stops on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WatchKind {
+    /// Stop on reads.
+    Read,
+    /// Stop on writes.
+    Write,
+    /// Stop on reads and writes.
+    Access,
+}
+
+impl WatchKind {
+    fn matches(self, kind: WatchKind) -> bool {
+        self == WatchKind::Access || self == kind
+    }
+}
+
+/// Why a `Debugger` stopped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Stop {
+    /// A single step completed.
+    Step,
+    /// Execution arrived at a breakpoint.
+    Breakpoint { address: u64 },
+    /// A watched memory access was executed. `kind` is either
+    /// `WatchKind::Read` or `WatchKind::Write`, and `address` and `bits` are
+    /// those of the access.
+    Watchpoint {
+        id: usize,
+        kind: WatchKind,
+        address: u64,
+        bits: usize,
+    },
+    /// The function being run returned to the given address.
+    Return { address: u64 },
+    /// The step budget was exhausted.
+    StepBudget,
+    /// Executing the instruction at `address` made an access to memory
+    /// which violated its permissions. The debugger remains at the location
+    /// which faulted.
+    Fault {
+        address: Option<u64>,
+        fault: MemoryFault,
+    },
+    /// Executing the instruction at `address` failed for any other reason.
+    /// The debugger remains at the location which failed.
+    Error {
+        address: Option<u64>,
+        message: String,
+    },
+}
+
+/// A breakpoint set in a `Debugger`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Breakpoint {
+    address: u64,
+    condition: Option<il::Expression>,
+    hits: u64,
+}
+
+impl Breakpoint {
+    /// The address of this breakpoint.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// The condition under which this breakpoint stops execution, if there
+    /// is one.
+    pub fn condition(&self) -> Option<&il::Expression> {
+        self.condition.as_ref()
+    }
+
+    /// The number of times this breakpoint has stopped execution.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+/// A watchpoint set in a `Debugger`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Watchpoint {
+    address: u64,
+    length: u64,
+    kind: WatchKind,
+}
+
+impl Watchpoint {
+    /// The address of the first byte watched.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// The number of bytes watched.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// The kind of access watched.
+    pub fn kind(&self) -> WatchKind {
+        self.kind
+    }
+}
+
+/// A call on the debugger's shadow call stack.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+    function_address: u64,
+    return_address: u64,
+    stack_pointer: u64,
+}
+
+impl Frame {
+    /// The address of the function called.
+    pub fn function_address(&self) -> u64 {
+        self.function_address
+    }
+
+    /// The address the function returns to.
+    pub fn return_address(&self) -> u64 {
+        self.return_address
+    }
+}
+
+/// What a `Debugger` runs until.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Until {
+    Step,
+    StepOver(usize),
+    Return(usize),
+    Stop,
+}
+
+/// A debugger over the concrete executor.
+#[derive(Clone, Debug)]
+pub struct Debugger {
+    driver: Driver,
+    breakpoints: BTreeMap<u64, Breakpoint>,
+    watchpoints: BTreeMap<usize, Watchpoint>,
+    next_watchpoint: usize,
+    frames: Vec<Frame>,
+    step_budget: Option<u64>,
+}
+
+impl Debugger {
+    /// Create a new `Debugger` for the given driver.
+    pub fn new(driver: Driver) -> Debugger {
+        Debugger {
+            driver,
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            next_watchpoint: 0,
+            frames: Vec::new(),
+            step_budget: None,
+        }
+    }
+
+    /// Retrieve the `Driver` being debugged.
+    pub fn driver(&self) -> &Driver {
+        &self.driver
+    }
+
+    /// Retrieve a mutable reference to the `Driver` being debugged.
+    pub fn driver_mut(&mut self) -> &mut Driver {
+        &mut self.driver
+    }
+
+    /// Consume this debugger, returning the `Driver` being debugged.
+    pub fn into_driver(self) -> Driver {
+        self.driver
+    }
+
+    /// The address of the instruction the debugger is stopped at.
+    pub fn address(&self) -> Option<u64> {
+        self.driver.address()
+    }
+
+    /// The shadow call stack, from the outermost call to the innermost call.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Set a breakpoint at the given address, replacing any breakpoint
+    /// already set at that address.
+    pub fn add_breakpoint(&mut self, address: u64) {
+        self.breakpoints.insert(
+            address,
+            Breakpoint {
+                address,
+                condition: None,
+                hits: 0,
+            },
+        );
+    }
+
+    /// Set a breakpoint at the given address which stops execution only when
+    /// the given condition, evaluated over the `State`, is 1.
+    pub fn add_conditional_breakpoint(&mut self, address: u64, condition: il::Expression) {
+        self.breakpoints.insert(
+            address,
+            Breakpoint {
+                address,
+                condition: Some(condition),
+                hits: 0,
+            },
+        );
+    }
+
+    /// Remove the breakpoint at the given address.
+    pub fn remove_breakpoint(&mut self, address: u64) -> Option<Breakpoint> {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Get the breakpoint at the given address, if one is set.
+    pub fn breakpoint(&self, address: u64) -> Option<&Breakpoint> {
+        self.breakpoints.get(&address)
+    }
+
+    /// Watch `length` bytes of memory from `address`, returning the id of the
+    /// watchpoint.
+    ///
+    /// Only accesses made by `il::Operation::Load` and `il::Operation::Store`
+    /// are watched. Memory accessed by hooks is not.
+    pub fn add_watchpoint(&mut self, address: u64, length: u64, kind: WatchKind) -> usize {
+        let id = self.next_watchpoint;
+        self.next_watchpoint += 1;
+        self.watchpoints.insert(
+            id,
+            Watchpoint {
+                address,
+                length,
+                kind,
+            },
+        );
+        id
+    }
+
+    /// Remove the watchpoint with the given id.
+    pub fn remove_watchpoint(&mut self, id: usize) -> Option<Watchpoint> {
+        self.watchpoints.remove(&id)
+    }
+
+    /// Get the watchpoint with the given id, if one is set.
+    pub fn watchpoint(&self, id: usize) -> Option<&Watchpoint> {
+        self.watchpoints.get(&id)
+    }
+
+    /// Set the maximum number of native instructions executed each time the
+    /// debugger resumes, or `None` for no limit.
+    pub fn set_step_budget(&mut self, step_budget: Option<u64>) {
+        self.step_budget = step_budget;
+    }
+
+    /// Execute one native instruction.
+    pub fn step(&mut self) -> Result<Stop> {
+        self.resume(Until::Step)
+    }
+
+    /// Execute one native instruction. If the instruction is a call, run
+    /// until the call returns.
+    pub fn step_over(&mut self) -> Result<Stop> {
+        let depth = self.frames.len();
+        self.resume(Until::StepOver(depth))
+    }
+
+    /// Run until the current function returns.
+    ///
+    /// The return address must be known, either because the call to the
+    /// current function was observed by this debugger, or because the
+    /// debugger is stopped at the first instruction of the function.
+    pub fn run_until_return(&mut self) -> Result<Stop> {
+        if self.frames.is_empty() {
+            let location = self.driver.location().apply(self.driver.program())?;
+            match self.call_frame(&location) {
+                Some(frame) => self.frames.push(frame),
+                None => bail!("The return address of the current function is not known"),
+            }
+        }
+        let depth = self.frames.len();
+        self.resume(Until::Return(depth))
+    }
+
+    /// Run until a breakpoint or watchpoint is hit, the step budget is
+    /// exhausted, or execution fails.
+    pub fn continue_(&mut self) -> Result<Stop> {
+        self.resume(Until::Stop)
+    }
+
+    fn stack_pointer(&self, state: &State) -> u64 {
+        let stack_pointer = self.driver.architecture().stack_pointer();
+        state
+            .get_scalar(stack_pointer.name())
+            .and_then(|sp| sp.value_u64())
+            .unwrap_or(0)
+    }
+
+    /// If the given location is the first instruction of a function, the
+    /// frame for a call to that function.
+    fn call_frame(&self, location: &il::RefProgramLocation) -> Option<Frame> {
+        let function_address = location.function().address();
+        if instruction_start(location) != Some(function_address) {
+            return None;
+        }
+        let mut state = self.driver.state().clone();
+        let stack_pointer = self.stack_pointer(&state);
+        let return_address = return_address(&mut state, self.driver.architecture()).ok()?;
+        Some(Frame {
+            function_address,
+            return_address,
+            stack_pointer,
+        })
+    }
+
+    /// The watchpoint hit by the given operation, if any.
+    fn watched(&self, operation: &il::Operation) -> Result<Option<Stop>> {
+        let (index, bits, kind) = match *operation {
+            il::Operation::Load { ref dst, ref index } => (index, dst.bits(), WatchKind::Read),
+            il::Operation::Store { ref index, ref src } => (index, src.bits(), WatchKind::Write),
+            _ => return Ok(None),
+        };
+        if self.watchpoints.is_empty() {
+            return Ok(None);
+        }
+        let address = self
+            .driver
+            .state()
+            .symbolize_and_eval(index)?
+            .value_u64()
+            .ok_or(ErrorKind::TooManyAddressBits)?;
+        let end = address.saturating_add(bits.div_ceil(8) as u64);
+        for (id, watchpoint) in &self.watchpoints {
+            if watchpoint.kind.matches(kind)
+                && address < watchpoint.address.saturating_add(watchpoint.length)
+                && watchpoint.address < end
+            {
+                return Ok(Some(Stop::Watchpoint {
+                    id: *id,
+                    kind,
+                    address,
+                    bits,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns true if the breakpoint at the given address, if any, stops
+    /// execution.
+    fn breakpoint_hit(&mut self, address: u64) -> Result<bool> {
+        let hit = match self.breakpoints.get(&address) {
+            Some(breakpoint) => match breakpoint.condition {
+                Some(ref condition) => self.driver.state().symbolize_and_eval(condition)?.is_one(),
+                None => true,
+            },
+            None => false,
+        };
+        if hit {
+            self.breakpoints.get_mut(&address).unwrap().hits += 1;
+        }
+        Ok(hit)
+    }
+
+    fn resume(&mut self, until: Until) -> Result<Stop> {
+        let mut instructions = 0;
+        let mut first = true;
+        loop {
+            let (start, operation) = {
+                let location = self.driver.location().apply(self.driver.program())?;
+                (
+                    instruction_start(&location),
+                    location.instruction().map(|i| i.operation().clone()),
+                )
+            };
+
+            if let Some(address) = start {
+                if !first {
+                    let stack_pointer = self.stack_pointer(self.driver.state());
+                    while let Some(frame) = self.frames.last() {
+                        if frame.return_address != address || stack_pointer < frame.stack_pointer {
+                            break;
+                        }
+                        self.frames.pop();
+                    }
+
+                    match until {
+                        Until::Step => return Ok(Stop::Step),
+                        Until::StepOver(depth) => {
+                            if self.frames.len() <= depth {
+                                return Ok(Stop::Step);
+                            }
+                        }
+                        Until::Return(depth) => {
+                            if self.frames.len() < depth {
+                                return Ok(Stop::Return { address });
+                            }
+                        }
+                        Until::Stop => {}
+                    }
+                    if self.breakpoint_hit(address)? {
+                        return Ok(Stop::Breakpoint { address });
+                    }
+                    if let Some(step_budget) = self.step_budget {
+                        if instructions >= step_budget {
+                            return Ok(Stop::StepBudget);
+                        }
+                    }
+                }
+                instructions += 1;
+            }
+            first = false;
+
+            let watched = match operation {
+                Some(ref operation) => self.watched(operation)?,
+                None => None,
+            };
+
+            // Keep the driver at the failing location when a step fails.
+            let driver = self.driver.clone();
+            self.driver = match driver.step() {
+                Ok(driver) => driver,
+                Err(error) => {
+                    let address = self.driver.address();
+                    return Ok(match *error.kind() {
+                        ErrorKind::MemoryFault(ref fault) => Stop::Fault {
+                            address,
+                            fault: fault.clone(),
+                        },
+                        _ => Stop::Error {
+                            address,
+                            message: error.to_string(),
+                        },
+                    });
+                }
+            };
+
+            if let Some(il::Operation::Branch { .. }) = operation {
+                let location = self.driver.location().apply(self.driver.program())?;
+                if let Some(frame) = self.call_frame(&location) {
+                    self.frames.push(frame);
+                }
+            }
+
+            if let Some(stop) = watched {
+                return Ok(stop);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{Amd64, Endian};
+    use crate::executor::Memory;
+    use crate::memory::{MemoryFaultKind, MemoryPermissions};
+    use crate::RC;
+
+    fn block(
+        control_flow_graph: &mut il::ControlFlowGraph,
+        instructions: Vec<(u64, il::Operation)>,
+    ) -> usize {
+        let block = control_flow_graph.new_block().unwrap();
+        for (address, operation) in instructions {
+            match operation {
+                il::Operation::Assign { dst, src } => block.assign(dst, src),
+                il::Operation::Store { index, src } => block.store(index, src),
+                il::Operation::Load { dst, index } => block.load(dst, index),
+                il::Operation::Branch { target } => block.branch(target),
+                _ => block.nop(),
+            }
+            let last = block.instructions().len() - 1;
+            block.instructions_mut()[last].set_address(Some(address));
+        }
+        block.index()
+    }
+
+    fn function(address: u64, instructions: Vec<(u64, il::Operation)>) -> il::Function {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+        let index = block(&mut control_flow_graph, instructions);
+        control_flow_graph.set_entry(index).unwrap();
+        il::Function::new(address, control_flow_graph)
+    }
+
+    fn rsp(offset: i64) -> il::Operation {
+        il::Operation::assign(
+            il::scalar("rsp", 64),
+            il::Expression::add(
+                il::expr_scalar("rsp", 64),
+                il::expr_const(offset as u64, 64),
+            )
+            .unwrap(),
+        )
+    }
+
+    // The function at 0x1000 calls the function at 0x2000, which writes rax
+    // to 0x9000 and returns, then increments rax and runs off the end of the
+    // program at 0x100c.
+    fn debugger() -> Debugger {
+        let mut program = il::Program::new();
+        program.add_function(function(
+            0x1000,
+            vec![
+                (0x1000, rsp(-8)),
+                (
+                    0x1000,
+                    il::Operation::store(il::expr_scalar("rsp", 64), il::expr_const(0x1008, 64)),
+                ),
+                (0x1000, il::Operation::branch(il::expr_const(0x2000, 64))),
+                (
+                    0x1008,
+                    il::Operation::assign(
+                        il::scalar("rax", 64),
+                        il::Expression::add(il::expr_scalar("rax", 64), il::expr_const(1, 64))
+                            .unwrap(),
+                    ),
+                ),
+                (0x100c, il::Operation::Nop),
+            ],
+        ));
+        program.add_function(function(
+            0x2000,
+            vec![
+                (
+                    0x2000,
+                    il::Operation::store(il::expr_const(0x9000, 64), il::expr_scalar("rax", 64)),
+                ),
+                (
+                    0x2004,
+                    il::Operation::load(il::scalar("t", 64), il::expr_scalar("rsp", 64)),
+                ),
+                (0x2004, rsp(8)),
+                (0x2004, il::Operation::branch(il::expr_scalar("t", 64))),
+            ],
+        ));
+
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+        let mut state = State::new(Memory::new(Endian::Little));
+        state.set_scalar("rsp", il::const_(0x8000, 64));
+        state.set_scalar("rax", il::const_(0x41, 64));
+
+        Debugger::new(Driver::new(
+            RC::new(program),
+            location,
+            state,
+            RC::new(Amd64::new()),
+        ))
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = debugger();
+        let rax = il::expr_scalar("rax", 64);
+        debugger.add_conditional_breakpoint(
+            0x2000,
+            il::Expression::cmpeq(rax.clone(), il::expr_const(0, 64)).unwrap(),
+        );
+        debugger.add_breakpoint(0x100c);
+        assert_eq!(
+            debugger.continue_().unwrap(),
+            Stop::Breakpoint { address: 0x100c }
+        );
+        assert_eq!(debugger.breakpoint(0x2000).unwrap().hits(), 0);
+
+        let mut debugger = self::debugger();
+        debugger.add_conditional_breakpoint(
+            0x2000,
+            il::Expression::cmpeq(rax, il::expr_const(0x41, 64)).unwrap(),
+        );
+        assert_eq!(
+            debugger.continue_().unwrap(),
+            Stop::Breakpoint { address: 0x2000 }
+        );
+        assert_eq!(debugger.frames().len(), 1);
+        assert_eq!(debugger.frames()[0].return_address(), 0x1008);
+        assert_eq!(
+            debugger.continue_().unwrap(),
+            Stop::Error {
+                address: Some(0x100c),
+                message: "No valid successor location found on fall through".to_string()
+            }
+        );
+        assert_eq!(debugger.address(), Some(0x100c));
+    }
+
+    #[test]
+    fn memory_fault() {
+        let mut debugger = debugger();
+        let memory = debugger.driver_mut().state_mut().memory_mut();
+        let code = MemoryPermissions::READ | MemoryPermissions::EXECUTE;
+        memory.set_permissions(0x1000, 0x2000, code);
+        memory.set_permissions(0x7000, 0x2000, MemoryPermissions::ALL);
+        memory.set_permissions(0x9000, 8, MemoryPermissions::READ);
+        memory.set_enforcing(true);
+        let fault = match debugger.continue_().unwrap() {
+            Stop::Fault {
+                address: Some(0x2000),
+                fault,
+            } => fault,
+            stop => panic!("Unexpected stop {:?}", stop),
+        };
+        assert_eq!(fault.kind(), MemoryFaultKind::WriteViolation);
+        assert_eq!(fault.address(), 0x9000);
+        assert_eq!(fault.bits(), 64);
+        assert_eq!(debugger.address(), Some(0x2000));
+    }
+
+    #[test]
+    fn stepping() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.step().unwrap(), Stop::Step);
+        assert_eq!(debugger.address(), Some(0x2000));
+        assert_eq!(debugger.step().unwrap(), Stop::Step);
+        assert_eq!(debugger.address(), Some(0x2004));
+        assert_eq!(
+            debugger.run_until_return().unwrap(),
+            Stop::Return { address: 0x1008 }
+        );
+        assert!(debugger.frames().is_empty());
+
+        let mut debugger = self::debugger();
+        assert_eq!(debugger.step_over().unwrap(), Stop::Step);
+        assert_eq!(debugger.address(), Some(0x1008));
+        assert!(debugger.run_until_return().is_err());
+
+        let mut debugger = self::debugger();
+        debugger.set_step_budget(Some(3));
+        assert_eq!(debugger.continue_().unwrap(), Stop::StepBudget);
+        assert_eq!(debugger.address(), Some(0x1008));
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut debugger = debugger();
+        debugger.add_watchpoint(0x7ff8, 8, WatchKind::Read);
+        let id = debugger.add_watchpoint(0x9003, 1, WatchKind::Write);
+        assert_eq!(
+            debugger.continue_().unwrap(),
+            Stop::Watchpoint {
+                id,
+                kind: WatchKind::Write,
+                address: 0x9000,
+                bits: 64
+            }
+        );
+        assert_eq!(
+            debugger.driver().state().memory().load(0x9000, 64).unwrap(),
+            Some(il::const_(0x41, 64))
+        );
+        assert_eq!(
+            debugger.continue_().unwrap(),
+            Stop::Watchpoint {
+                id: 0,
+                kind: WatchKind::Read,
+                address: 0x7ff8,
+                bits: 64
+            }
+        );
+    }
+}
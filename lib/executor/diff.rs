@@ -0,0 +1,282 @@
+//! Differences between two concrete states.
+
+use crate::executor::State;
+use crate::il;
+use crate::memory::paged::{MemoryCell, PAGE_SIZE};
+use crate::RC;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A scalar which differs between two states.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScalarChange {
+    name: String,
+    before: Option<il::Constant>,
+    after: Option<il::Constant>,
+}
+
+impl ScalarChange {
+    /// The name of the scalar.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value of the scalar in the first state, if it was set.
+    pub fn before(&self) -> Option<&il::Constant> {
+        self.before.as_ref()
+    }
+
+    /// The value of the scalar in the second state, if it was set.
+    pub fn after(&self) -> Option<&il::Constant> {
+        self.after.as_ref()
+    }
+}
+
+/// A range of memory in which every byte differs between two states.
+///
+/// A byte is `None` when it has no value in that state.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MemoryChange {
+    address: u64,
+    before: Vec<Option<u8>>,
+    after: Vec<Option<u8>>,
+}
+
+impl MemoryChange {
+    /// The address of the first byte in this range.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// The number of bytes in this range.
+    pub fn len(&self) -> usize {
+        self.before.len()
+    }
+
+    /// Returns true if this range holds no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty()
+    }
+
+    /// The bytes in this range in the first state.
+    pub fn before(&self) -> &[Option<u8>] {
+        &self.before
+    }
+
+    /// The bytes in this range in the second state.
+    pub fn after(&self) -> &[Option<u8>] {
+        &self.after
+    }
+}
+
+/// The scalars and memory which differ between two states.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StateDiff {
+    scalars: Vec<ScalarChange>,
+    memory: Vec<MemoryChange>,
+}
+
+impl StateDiff {
+    /// Compute the differences from the state `before` to the state `after`.
+    ///
+    /// Memory is compared over the pages held by either state. Bytes held
+    /// only by the backings of the states' memory are assumed not to differ,
+    /// and pages shared by both states, as they are after a `State` is
+    /// cloned, are not compared byte by byte.
+    pub fn new(before: &State, after: &State) -> StateDiff {
+        let names = before
+            .scalars()
+            .keys()
+            .chain(after.scalars().keys())
+            .collect::<BTreeSet<&String>>();
+        let scalars = names
+            .into_iter()
+            .filter_map(|name| {
+                let before = before.get_scalar(name);
+                let after = after.get_scalar(name);
+                if before == after {
+                    return None;
+                }
+                Some(ScalarChange {
+                    name: name.clone(),
+                    before: before.cloned(),
+                    after: after.cloned(),
+                })
+            })
+            .collect();
+
+        let page_addresses = before
+            .memory()
+            .pages()
+            .keys()
+            .chain(after.memory().pages().keys())
+            .cloned()
+            .collect::<BTreeSet<u64>>();
+
+        let mut memory: Vec<MemoryChange> = Vec::new();
+        for page_address in page_addresses {
+            let before_page = before.memory().pages().get(&page_address);
+            let after_page = after.memory().pages().get(&page_address);
+            let length = match (before_page, after_page) {
+                (Some(before_page), Some(after_page))
+                    if RC::ptr_eq(before_page, after_page) || before_page == after_page =>
+                {
+                    // Values stored across the start of this page are held by
+                    // the page before it, and may still differ.
+                    before_page
+                        .cells()
+                        .iter()
+                        .take_while(|cell| match **cell {
+                            Some(MemoryCell::Backref(address)) => address < page_address,
+                            _ => false,
+                        })
+                        .count()
+                }
+                _ => PAGE_SIZE,
+            };
+            for address in page_address..page_address + length as u64 {
+                let before_byte = byte(before, address);
+                let after_byte = byte(after, address);
+                if before_byte == after_byte {
+                    continue;
+                }
+                if let Some(change) = memory.last_mut() {
+                    if change.address + change.len() as u64 == address {
+                        change.before.push(before_byte);
+                        change.after.push(after_byte);
+                        continue;
+                    }
+                }
+                memory.push(MemoryChange {
+                    address,
+                    before: vec![before_byte],
+                    after: vec![after_byte],
+                });
+            }
+        }
+
+        StateDiff { scalars, memory }
+    }
+
+    /// The scalars which differ.
+    pub fn scalars(&self) -> &[ScalarChange] {
+        &self.scalars
+    }
+
+    /// The ranges of memory which differ, in order of address.
+    pub fn memory(&self) -> &[MemoryChange] {
+        &self.memory
+    }
+
+    /// Returns true if the states do not differ.
+    pub fn is_empty(&self) -> bool {
+        self.scalars.is_empty() && self.memory.is_empty()
+    }
+}
+
+fn byte(state: &State, address: u64) -> Option<u8> {
+    state
+        .memory()
+        .load_unchecked(address, 8)
+        .ok()
+        .and_then(|value| value)
+        .and_then(|value| value.value_u64())
+        .map(|value| value as u8)
+}
+
+fn constant(constant: Option<&il::Constant>) -> String {
+    constant
+        .map(|constant| constant.to_string())
+        .unwrap_or_else(|| "unset".to_string())
+}
+
+fn bytes(bytes: &[Option<u8>]) -> String {
+    bytes
+        .iter()
+        .map(|byte| match *byte {
+            Some(byte) => format!("{:02x}", byte),
+            None => "??".to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for scalar in &self.scalars {
+            writeln!(
+                f,
+                "{}: {} -> {}",
+                scalar.name,
+                constant(scalar.before()),
+                constant(scalar.after())
+            )?;
+        }
+        for change in &self.memory {
+            writeln!(
+                f,
+                "0x{:x}: {} -> {}",
+                change.address,
+                bytes(&change.before),
+                bytes(&change.after)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::Endian;
+    use crate::executor::Memory;
+
+    #[test]
+    fn diff() {
+        let mut before = State::new(Memory::new(Endian::Little));
+        before.set_scalar("rax", il::const_(1, 64));
+        before.set_scalar("rbx", il::const_(2, 64));
+        before
+            .memory_mut()
+            .store(0x3fe, il::const_(0x1122_3344, 32))
+            .unwrap();
+        before.memory_mut().store(0x800, il::const_(0, 8)).unwrap();
+
+        let mut after = before.clone();
+        assert!(before.diff(&after).is_empty());
+
+        after.set_scalar("rax", il::const_(3, 64));
+        after.set_scalar("rcx", il::const_(4, 64));
+        // Spans a page boundary, and only two of its bytes change.
+        after
+            .memory_mut()
+            .store(0x3fe, il::const_(0x1155_6644, 32))
+            .unwrap();
+        after.memory_mut().store(0x1000, il::const_(7, 8)).unwrap();
+
+        let diff = before.diff(&after);
+        assert_eq!(
+            diff.scalars()
+                .iter()
+                .map(|scalar| scalar.name())
+                .collect::<Vec<&str>>(),
+            vec!["rax", "rcx"]
+        );
+        assert_eq!(diff.scalars()[1].before(), None);
+
+        assert_eq!(diff.memory().len(), 2);
+        assert_eq!(diff.memory()[0].address(), 0x3ff);
+        assert_eq!(diff.memory()[0].before(), &[Some(0x33), Some(0x22)]);
+        assert_eq!(diff.memory()[0].after(), &[Some(0x66), Some(0x55)]);
+        assert_eq!(diff.memory()[1].address(), 0x1000);
+        assert_eq!(diff.memory()[1].before(), &[None]);
+
+        assert_eq!(
+            diff.to_string(),
+            "rax: 0x1:64 -> 0x3:64\n\
+             rcx: unset -> 0x4:64\n\
+             0x3ff: 33 22 -> 66 55\n\
+             0x1000: ?? -> 07\n"
+        );
+    }
+}
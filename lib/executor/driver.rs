@@ -1,11 +1,19 @@
 //! A driver concretely executes a Falcon IL programs.
 
+use crate::analysis::calling_convention::ReturnAddressType;
 use crate::architecture::Architecture;
 use crate::error::*;
+use crate::executor::hooks::*;
 use crate::executor::successor::*;
 use crate::executor::State;
 use crate::il;
+use crate::memory::{MemoryFault, MemoryPermissions};
 use crate::RC;
+use std::collections::BTreeSet;
+
+/// The largest number of times hooks may transfer control to another address
+/// before code is executed.
+const MAX_HOOK_TRANSFERS: usize = 0x100;
 
 /// A driver for a concrete executor over Falcon IL.
 #[derive(Debug, Clone)]
@@ -14,149 +22,167 @@ pub struct Driver {
     location: il::ProgramLocation,
     state: State,
     architecture: RC<dyn Architecture>,
+    hooks: Hooks,
+    /// Indices of functions whose code was written after they were lifted.
+    stale: BTreeSet<usize>,
 }
 
 impl Driver {
     /// Create a new driver for concrete execution over Falcon IL.
+    ///
+    /// Writes to executable memory in the `State` are tracked, so code which
+    /// is written can be lifted again.
     pub fn new(
         program: RC<il::Program>,
         location: il::ProgramLocation,
-        state: State,
+        mut state: State,
         architecture: RC<dyn Architecture>,
     ) -> Driver {
+        state.memory_mut().set_tracking_code_writes(true);
         Driver {
             program: program,
             location: location,
             state: state,
             architecture: architecture,
+            hooks: Hooks::new(),
+            stale: BTreeSet::new(),
         }
     }
 
     /// Step forward over Falcon IL.
-    pub fn step(self) -> Result<Driver> {
-        let location = self.location.apply(&self.program)?;
+    ///
+    /// Functions are lifted again from the memory of this driver's `State`
+    /// when their code is written. Stale code is replaced when execution
+    /// reaches the start of a native instruction, so the native instruction
+    /// which wrote the code is executed to completion first.
+    pub fn step(mut self) -> Result<Driver> {
+        let code_writes = self.state.memory_mut().take_code_writes();
+        if !code_writes.is_empty() {
+            self.invalidate(&code_writes);
+        }
+        if !self.stale.is_empty() {
+            self.refresh()?;
+        }
+
+        let Driver {
+            program,
+            location,
+            state,
+            architecture,
+            hooks,
+            stale,
+        } = self;
+        let step = Step {
+            program: program.clone(),
+            location,
+            architecture,
+            hooks,
+            stale,
+        };
+        let location = step.location.apply(&program)?;
         match *location.function_location() {
             il::RefFunctionLocation::Instruction(_, instruction) => {
-                let successor = self.state.execute(instruction.operation())?;
-
-                match successor.type_().clone() {
-                    SuccessorType::FallThrough => {
-                        let locations = location.forward()?;
-                        if locations.len() == 1 {
-                            return Ok(Driver::new(
-                                self.program.clone(),
-                                locations[0].clone().into(),
-                                successor.into(),
-                                self.architecture,
-                            ));
-                        } else {
-                            // every location should be an edge, and only one
-                            // edge should be satisfiable
-                            for location in locations {
-                                if let il::RefFunctionLocation::Edge(edge) =
-                                    *location.function_location()
-                                {
-                                    if successor
-                                        .state()
-                                        .symbolize_and_eval(
-                                            edge.condition()
-                                                .ok_or("Failed to get edge condition")?,
-                                        )?
-                                        .is_one()
-                                    {
-                                        return Ok(Driver::new(
-                                            self.program.clone(),
-                                            location.clone().into(),
-                                            successor.into(),
-                                            self.architecture,
-                                        ));
-                                    }
-                                }
-                            }
-                            bail!("No valid successor location found on fall through");
+                if let Some(address) = instruction.address() {
+                    if state.memory().enforcing() {
+                        if let Some(fault) =
+                            state.memory().fault(address, 8, MemoryPermissions::EXECUTE)
+                        {
+                            return Err(step.fault(fault));
                         }
                     }
-                    SuccessorType::Branch(address) => {
-                        match il::RefProgramLocation::from_address(&self.program, address) {
-                            Some(location) => {
-                                return Ok(Driver::new(
-                                    self.program.clone(),
-                                    location.into(),
-                                    successor.into(),
-                                    self.architecture,
-                                ))
-                            }
+                }
+
+                let successor = state.execute(instruction.operation())?;
+
+                match successor.type_().clone() {
+                    SuccessorType::FallThrough => step.fall_through(&location, successor.into()),
+                    SuccessorType::Branch(address) => step.branch(address, successor.into()),
+                    SuccessorType::Fault(fault) => Err(step.fault(fault)),
+                    SuccessorType::Intrinsic(ref intrinsic) => {
+                        let mut state: State = successor.into();
+                        let hook_successor = match step.hooks.intrinsic(intrinsic.mnemonic()) {
+                            Some(hook) => hook(&mut state, intrinsic)?,
                             None => {
-                                let state: State = successor.into();
-                                let function = self
-                                    .architecture
-                                    .translator()
-                                    .translate_function(state.memory(), address)
-                                    .expect(&format!("Failed to lift function at 0x{:x}", address));
-                                let mut program = self.program.clone();
-                                RC::make_mut(&mut program).add_function(function);
-                                let location: il::ProgramLocation =
-                                    il::RefProgramLocation::from_address(&program, address)
-                                        .ok_or("Failed to get location for newly lifted function")?
-                                        .into();
-                                return Ok(Driver::new(
-                                    program.clone(),
-                                    location,
-                                    state,
-                                    self.architecture,
-                                ));
+                                return Err(
+                                    ErrorKind::UnhandledIntrinsic(format!("{}", intrinsic)).into()
+                                )
                             }
+                        };
+                        match hook_successor {
+                            HookSuccessor::FallThrough => step.fall_through(&location, state),
+                            HookSuccessor::Branch(address) => step.branch(address, state),
+                            HookSuccessor::Return => step.return_(state),
                         }
                     }
-                    SuccessorType::Intrinsic(ref intrinsic) => {
-                        bail!(format!(
-                            "Intrinsic is unimplemented, {}",
-                            intrinsic.instruction_str()
-                        ));
-                    }
                 }
             }
             il::RefFunctionLocation::Edge(_) => {
                 let locations = location.forward()?;
-                return Ok(Driver::new(
-                    self.program.clone(),
-                    locations[0].clone().into(),
-                    self.state,
-                    self.architecture,
-                ));
+                Ok(step.successor(locations[0].clone().into(), state))
             }
-            il::RefFunctionLocation::EmptyBlock(_) => {
-                let locations = location.forward()?;
-                if locations.len() == 1 {
-                    return Ok(Driver::new(
-                        self.program.clone(),
-                        locations[0].clone().into(),
-                        self.state,
-                        self.architecture,
-                    ));
-                } else {
-                    for location in locations {
-                        if let il::RefFunctionLocation::Edge(edge) = *location.function_location() {
-                            if self
-                                .state
-                                .symbolize_and_eval(
-                                    edge.condition().ok_or("Failed to get edge condition")?,
-                                )?
-                                .is_one()
-                            {
-                                return Ok(Driver::new(
-                                    self.program.clone(),
-                                    location.clone().into(),
-                                    self.state,
-                                    self.architecture,
-                                ));
-                            }
-                        }
-                    }
-                }
-                bail!("No valid location out of empty block");
+            il::RefFunctionLocation::EmptyBlock(_) => step.fall_through(&location, state),
+        }
+    }
+
+    /// Mark every function with an instruction which may overlap one of the
+    /// given written addresses as stale.
+    fn invalidate(&mut self, code_writes: &BTreeSet<u64>) {
+        let max_instruction_size = self.architecture.max_instruction_size() as u64;
+        for (index, function) in self.program.functions_map() {
+            let written = function.blocks().into_iter().any(|block| {
+                block.instructions().iter().any(|instruction| {
+                    instruction.address().is_some_and(|address| {
+                        code_writes
+                            .range(address..address.saturating_add(max_instruction_size))
+                            .next()
+                            .is_some()
+                    })
+                })
+            });
+            if written {
+                self.stale.insert(index);
+            }
+        }
+    }
+
+    /// If this driver is at the start of a native instruction in a stale
+    /// function, lift that function again, and move to the same address in
+    /// the lifted code.
+    fn refresh(&mut self) -> Result<()> {
+        let (index, function_address, address) = {
+            let location = self.location.apply(&self.program)?;
+            let index = match location.function().index() {
+                Some(index) => index,
+                None => return Ok(()),
+            };
+            if !self.stale.contains(&index) {
+                return Ok(());
             }
+            match instruction_start(&location) {
+                Some(address) => (index, location.function().address(), address),
+                None => return Ok(()),
+            }
+        };
+
+        let translator = self.architecture.translator();
+        let function = translator
+            .translate_function(self.state.memory(), function_address)
+            .chain_err(|| format!("Failed to lift function at 0x{:x}", function_address))?;
+        RC::make_mut(&mut self.program).replace_function(index, function)?;
+        self.stale.remove(&index);
+
+        // The new code may no longer reach this address from the start of the
+        // function, in which case a function is lifted at this address.
+        if il::RefProgramLocation::from_address(&self.program, address).is_none() {
+            let function = translator
+                .translate_function(self.state.memory(), address)
+                .chain_err(|| format!("Failed to lift function at 0x{:x}", address))?;
+            RC::make_mut(&mut self.program).add_function(function);
         }
+        self.location = il::RefProgramLocation::from_address(&self.program, address)
+            .ok_or("Failed to get location for lifted code")?
+            .into();
+        Ok(())
     }
 
     /// Retrieve the Falcon IL program associated with this driver.
@@ -164,6 +190,15 @@ impl Driver {
         &self.program
     }
 
+    /// Set the Falcon IL program associated with this driver.
+    ///
+    /// The location of this driver must remain valid in the new program, for
+    /// example because the new program was lifted further from this driver's
+    /// program by another driver.
+    pub fn set_program(&mut self, program: RC<il::Program>) {
+        self.program = program;
+    }
+
     /// If this driver is sitting on an instruction with an address, return
     /// that address.
     pub fn address(&self) -> Option<u64> {
@@ -178,6 +213,11 @@ impl Driver {
         &self.location
     }
 
+    /// Set the `il::ProgramLocation` this driver executes next.
+    pub fn set_location(&mut self, location: il::ProgramLocation) {
+        self.location = location;
+    }
+
     /// Retrieve the concrete `State` associated with this driver.
     pub fn state(&self) -> &State {
         &self.state
@@ -187,4 +227,523 @@ impl Driver {
     pub fn state_mut(&mut self) -> &mut State {
         &mut self.state
     }
+
+    /// Retrieve the `Architecture` associated with this driver.
+    pub fn architecture(&self) -> &dyn Architecture {
+        self.architecture.as_ref()
+    }
+
+    /// Retrieve the `Hooks` used by this driver.
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// Retrieve a mutable reference to the `Hooks` used by this driver.
+    pub fn hooks_mut(&mut self) -> &mut Hooks {
+        &mut self.hooks
+    }
+
+    /// Set the `Hooks` used by this driver.
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.hooks = hooks;
+    }
+}
+
+/// A `Driver` taking a step, with its state moved out to be executed.
+struct Step {
+    program: RC<il::Program>,
+    location: il::ProgramLocation,
+    architecture: RC<dyn Architecture>,
+    hooks: Hooks,
+    stale: BTreeSet<usize>,
+}
+
+impl Step {
+    /// Create the driver which follows this step, at the given location with
+    /// the given state.
+    fn successor(self, location: il::ProgramLocation, state: State) -> Driver {
+        Driver {
+            program: self.program,
+            location,
+            state,
+            architecture: self.architecture,
+            hooks: self.hooks,
+            stale: self.stale,
+        }
+    }
+
+    /// The error for a memory fault raised by the instruction at this
+    /// step's location.
+    fn fault(&self, fault: MemoryFault) -> Error {
+        ErrorKind::MemoryFault(fault.with_location(self.location.clone())).into()
+    }
+
+    /// Advance to the location following the given location.
+    ///
+    /// When there are multiple successor locations, every location should be
+    /// an edge, and only one edge should be satisfiable.
+    fn fall_through(self, location: &il::RefProgramLocation, state: State) -> Result<Driver> {
+        let locations = location.forward()?;
+        if locations.len() == 1 {
+            return Ok(self.successor(locations[0].clone().into(), state));
+        }
+        for location in locations {
+            if let il::RefFunctionLocation::Edge(edge) = *location.function_location() {
+                if state
+                    .symbolize_and_eval(edge.condition().ok_or("Failed to get edge condition")?)?
+                    .is_one()
+                {
+                    return Ok(self.successor(location.clone().into(), state));
+                }
+            }
+        }
+        bail!("No valid successor location found on fall through");
+    }
+
+    /// Transfer control to the given address, invoking any hook set for that
+    /// address, and lifting the function at that address if required.
+    ///
+    /// Hooks may branch, or return, to other hooked addresses, up to
+    /// `MAX_HOOK_TRANSFERS` times before code is executed.
+    fn branch(self, address: u64, mut state: State) -> Result<Driver> {
+        let mut address = address;
+        let mut transfers = 0;
+        loop {
+            let hook_successor = match self.hooks.address(address) {
+                Some(hook) => hook(&mut state, address)?,
+                None => HookSuccessor::FallThrough,
+            };
+            let target = match hook_successor {
+                HookSuccessor::FallThrough => break,
+                HookSuccessor::Branch(target) => {
+                    if target == address {
+                        break;
+                    }
+                    target
+                }
+                HookSuccessor::Return => return_address(&mut state, self.architecture.as_ref())?,
+            };
+            transfers += 1;
+            if transfers > MAX_HOOK_TRANSFERS {
+                bail!(
+                    "Hooks transferred control more than {} times without executing code at 0x{:x}",
+                    MAX_HOOK_TRANSFERS,
+                    target
+                );
+            }
+            address = target;
+        }
+
+        if let Some(location) = il::RefProgramLocation::from_address(&self.program, address) {
+            let location = location.into();
+            return Ok(self.successor(location, state));
+        }
+
+        let function = self
+            .architecture
+            .translator()
+            .translate_function(state.memory(), address)
+            .chain_err(|| format!("Failed to lift function at 0x{:x}", address))?;
+        let mut step = self;
+        RC::make_mut(&mut step.program).add_function(function);
+        let location: il::ProgramLocation =
+            il::RefProgramLocation::from_address(&step.program, address)
+                .ok_or("Failed to get location for newly lifted function")?
+                .into();
+        Ok(step.successor(location, state))
+    }
+
+    /// Return to the caller, as if a return instruction had been executed.
+    fn return_(self, mut state: State) -> Result<Driver> {
+        let address = return_address(&mut state, self.architecture.as_ref())?;
+        self.branch(address, state)
+    }
+}
+
+/// If the given location is the first `il::Instruction` lifted from a native
+/// instruction, the address of that instruction.
+pub(crate) fn instruction_start(location: &il::RefProgramLocation) -> Option<u64> {
+    let (block, instruction) = match *location.function_location() {
+        il::RefFunctionLocation::Instruction(block, instruction) => (block, instruction),
+        _ => return None,
+    };
+    let address = instruction.address()?;
+    let instructions = block.instructions();
+    match instructions
+        .iter()
+        .position(|i| i.index() == instruction.index())?
+    {
+        0 => Some(address),
+        index => {
+            if instructions[index - 1].address() == Some(address) {
+                None
+            } else {
+                Some(address)
+            }
+        }
+    }
+}
+
+/// Pop the return address for the current function from the given state,
+/// following the calling convention of the given architecture.
+///
+/// When the return address is held on the stack, the stack pointer is adjusted
+/// past the return address, as a return instruction would.
+pub fn return_address(state: &mut State, architecture: &dyn Architecture) -> Result<u64> {
+    let calling_convention = architecture.calling_convention();
+    match *calling_convention.return_address_type() {
+        ReturnAddressType::Register(ref scalar) => state
+            .get_scalar(scalar.name())
+            .ok_or_else(|| format!("Return address register {} is not set", scalar))?
+            .value_u64()
+            .ok_or_else(|| ErrorKind::TooManyAddressBits.into()),
+        ReturnAddressType::Stack(offset) => {
+            let stack_pointer = architecture.stack_pointer();
+            let sp = state
+                .get_scalar(stack_pointer.name())
+                .ok_or_else(|| format!("Stack pointer {} is not set", stack_pointer))?
+                .value_u64()
+                .ok_or(ErrorKind::TooManyAddressBits)?;
+            let address = sp.wrapping_add(offset as u64);
+            let bits = architecture.word_size();
+            let return_address = state
+                .memory()
+                .load(address, bits)?
+                .ok_or(ErrorKind::AccessUnmappedMemory(address))?
+                .value_u64()
+                .ok_or(ErrorKind::TooManyAddressBits)?;
+            let sp = address.wrapping_add((bits / 8) as u64);
+            state.set_scalar(stack_pointer.name(), il::const_(sp, stack_pointer.bits()));
+            Ok(return_address)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{Amd64, Endian};
+    use crate::executor::HookSuccessor;
+    use crate::executor::Memory;
+    use crate::memory::MemoryFaultKind;
+
+    fn intrinsic(mnemonic: &str) -> il::Intrinsic {
+        il::Intrinsic::new(mnemonic, mnemonic, Vec::new(), None, None, Vec::new())
+    }
+
+    // A function at 0x1000 which executes the rdtsc intrinsic, calls 0x2000,
+    // increments rax, and then does nothing.
+    fn driver() -> Driver {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+        let block_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.intrinsic(intrinsic("rdtsc"));
+            block.branch(il::expr_const(0x2000, 64));
+            block.assign(
+                il::scalar("rax", 64),
+                il::Expression::add(il::expr_scalar("rax", 64), il::expr_const(1, 64)).unwrap(),
+            );
+            block.nop();
+            for (instruction, address) in block
+                .instructions_mut()
+                .iter_mut()
+                .zip([0x1000, 0x1002, 0x1007, 0x100a].iter())
+            {
+                instruction.set_address(Some(*address));
+            }
+            block.index()
+        };
+        control_flow_graph.set_entry(block_index).unwrap();
+
+        let mut program = il::Program::new();
+        program.add_function(il::Function::new(0x1000, control_flow_graph));
+
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+
+        let mut state = State::new(Memory::new(Endian::Little));
+        state.set_scalar("rsp", il::const_(0x8000, 64));
+        state.set_scalar("rax", il::const_(0, 64));
+
+        Driver::new(RC::new(program), location, state, RC::new(Amd64::new()))
+    }
+
+    #[test]
+    fn unhandled_intrinsic() {
+        match driver().step() {
+            Err(Error(ErrorKind::UnhandledIntrinsic(_), _)) => {}
+            _ => panic!("expected an unhandled intrinsic"),
+        }
+    }
+
+    #[test]
+    fn hooks() {
+        let mut driver = driver();
+        driver.hooks_mut().set_intrinsic("rdtsc", |state, _| {
+            state.set_scalar("rdx", il::const_(0x1234, 64));
+            Ok(HookSuccessor::FallThrough)
+        });
+        driver.hooks_mut().set_address(0x2000, |state, address| {
+            state.set_scalar("rax", il::const_(address, 64));
+            Ok(HookSuccessor::Return)
+        });
+
+        // The call pushes its return address.
+        driver.state_mut().set_scalar("rsp", il::const_(0x7ff8, 64));
+        driver
+            .state_mut()
+            .memory_mut()
+            .store(0x7ff8, il::const_(0x1007, 64))
+            .unwrap();
+
+        let driver = driver.step().unwrap();
+        assert_eq!(driver.address(), Some(0x1002));
+        assert_eq!(
+            driver.state().get_scalar("rdx"),
+            Some(&il::const_(0x1234, 64))
+        );
+
+        let driver = driver.step().unwrap();
+        assert_eq!(driver.address(), Some(0x1007));
+        assert_eq!(
+            driver.state().get_scalar("rsp"),
+            Some(&il::const_(0x8000, 64))
+        );
+
+        let driver = driver.step().unwrap();
+        assert_eq!(driver.address(), Some(0x100a));
+        assert_eq!(
+            driver.state().get_scalar("rax"),
+            Some(&il::const_(0x2001, 64))
+        );
+    }
+
+    #[test]
+    fn hook_loops() {
+        let mut driver = driver();
+        driver
+            .hooks_mut()
+            .set_intrinsic("rdtsc", |_, _| Ok(HookSuccessor::FallThrough));
+        driver
+            .hooks_mut()
+            .set_address(0x2000, |_, _| Ok(HookSuccessor::Branch(0x3000)));
+        driver
+            .hooks_mut()
+            .set_address(0x3000, |_, _| Ok(HookSuccessor::Branch(0x2000)));
+
+        let driver = driver.step().unwrap();
+        assert!(driver.step().is_err());
+    }
+
+    #[cfg(feature = "thread_safe")]
+    #[test]
+    fn thread_safe() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Driver>();
+    }
+
+    #[test]
+    fn memory_faults() {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+        let block_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.store(il::expr_const(0x8000, 64), il::expr_scalar("rax", 64));
+            block.load(il::scalar("rbx", 64), il::expr_const(0x9000, 64));
+            for (instruction, address) in block
+                .instructions_mut()
+                .iter_mut()
+                .zip([0x1000, 0x1004].iter())
+            {
+                instruction.set_address(Some(*address));
+            }
+            block.index()
+        };
+        control_flow_graph.set_entry(block_index).unwrap();
+
+        let mut program = il::Program::new();
+        program.add_function(il::Function::new(0x1000, control_flow_graph));
+        let location: il::ProgramLocation = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+
+        let mut state = State::new(Memory::new(Endian::Little));
+        state.set_scalar("rax", il::const_(0, 64));
+        state
+            .memory_mut()
+            .set_permissions(0x1000, 8, MemoryPermissions::READ);
+        state
+            .memory_mut()
+            .set_permissions(0x8000, 8, MemoryPermissions::READ);
+        state.memory_mut().set_enforcing(true);
+
+        let driver = Driver::new(
+            RC::new(program),
+            location.clone(),
+            state,
+            RC::new(Amd64::new()),
+        );
+
+        // Code without execute permission faults before it executes.
+        let fault = match driver.clone().step() {
+            Err(Error(ErrorKind::MemoryFault(fault), _)) => fault,
+            _ => panic!("expected a memory fault"),
+        };
+        assert_eq!(fault.kind(), MemoryFaultKind::ExecuteViolation);
+        assert_eq!(fault.address(), 0x1000);
+        assert_eq!(fault.location(), Some(&location));
+
+        let mut driver = driver;
+        driver.state_mut().memory_mut().set_permissions(
+            0x1000,
+            8,
+            MemoryPermissions::READ | MemoryPermissions::EXECUTE,
+        );
+
+        let fault = match driver.clone().step() {
+            Err(Error(ErrorKind::MemoryFault(fault), _)) => fault,
+            _ => panic!("expected a memory fault"),
+        };
+        assert_eq!(fault.kind(), MemoryFaultKind::WriteViolation);
+        assert_eq!(fault.address(), 0x8000);
+        assert_eq!(fault.bits(), 64);
+        assert_eq!(fault.location(), Some(&location));
+
+        driver
+            .state_mut()
+            .memory_mut()
+            .set_permissions(0x8000, 8, MemoryPermissions::ALL);
+        let driver = driver.step().unwrap();
+        let fault = match driver.step() {
+            Err(Error(ErrorKind::MemoryFault(fault), _)) => fault,
+            _ => panic!("expected a memory fault"),
+        };
+        assert_eq!(fault.kind(), MemoryFaultKind::Unmapped);
+        assert_eq!(fault.address(), 0x9000);
+    }
+
+    // An architecture with one byte instructions. 0x00 ends a function, 0x80
+    // stores 0x09 to 0x1004, and any other byte is assigned to rax.
+    #[derive(Clone, Debug)]
+    struct Bytes;
+
+    impl crate::translator::Translator for Bytes {
+        fn translate_block(
+            &self,
+            bytes: &[u8],
+            address: u64,
+        ) -> Result<crate::translator::BlockTranslationResult> {
+            let mut instructions = Vec::new();
+            for (offset, byte) in bytes.iter().enumerate() {
+                let mut control_flow_graph = il::ControlFlowGraph::new();
+                let block_index = {
+                    let block = control_flow_graph.new_block()?;
+                    match *byte {
+                        0x00 => block.nop(),
+                        0x80 => block.store(il::expr_const(0x1004, 64), il::expr_const(9, 8)),
+                        byte => {
+                            block.assign(il::scalar("rax", 64), il::expr_const(byte as u64, 64))
+                        }
+                    }
+                    block.index()
+                };
+                control_flow_graph.set_entry(block_index)?;
+                control_flow_graph.set_exit(block_index)?;
+                control_flow_graph.set_address(Some(address + offset as u64));
+                instructions.push((address + offset as u64, control_flow_graph));
+                if *byte == 0 {
+                    break;
+                }
+            }
+            let length = instructions.len();
+            Ok(crate::translator::BlockTranslationResult::new(
+                instructions,
+                address,
+                length,
+                Vec::new(),
+            ))
+        }
+    }
+
+    impl Architecture for Bytes {
+        fn name(&self) -> &str {
+            "bytes"
+        }
+        fn endian(&self) -> Endian {
+            Endian::Little
+        }
+        fn translator(&self) -> Box<dyn crate::translator::Translator> {
+            Box::new(Bytes)
+        }
+        fn calling_convention(&self) -> crate::analysis::calling_convention::CallingConvention {
+            Amd64::new().calling_convention()
+        }
+        fn stack_pointer(&self) -> il::Scalar {
+            Amd64::new().stack_pointer()
+        }
+        fn word_size(&self) -> usize {
+            64
+        }
+        fn max_instruction_size(&self) -> usize {
+            1
+        }
+        fn box_clone(&self) -> Box<dyn Architecture> {
+            Box::new(Bytes)
+        }
+    }
+
+    fn rax(driver: &Driver) -> u64 {
+        driver
+            .state()
+            .get_scalar("rax")
+            .unwrap()
+            .value_u64()
+            .unwrap()
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        use crate::translator::Translator;
+
+        let mut state = State::new(Memory::new(Endian::Little));
+        state
+            .memory_mut()
+            .set_permissions(0x1000, 0x10, MemoryPermissions::ALL);
+        for (offset, byte) in [0x80, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00]
+            .iter()
+            .enumerate()
+        {
+            state
+                .memory_mut()
+                .store(0x1000 + offset as u64, il::const_(*byte, 8))
+                .unwrap();
+        }
+        assert!(state.memory().code_writes().is_empty());
+
+        let mut program = il::Program::new();
+        program.add_function(Bytes.translate_function(state.memory(), 0x1000).unwrap());
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+
+        let mut driver = Driver::new(RC::new(program), location, state, RC::new(Bytes));
+
+        // The first instruction overwrites the instruction at 0x1004.
+        while driver.address() != Some(0x1004) {
+            driver = driver.step().unwrap();
+        }
+        let driver = driver.step().unwrap();
+        assert_eq!(rax(&driver), 9);
+
+        // Code written through the state is lifted again too.
+        let mut driver = driver;
+        driver
+            .state_mut()
+            .memory_mut()
+            .store(0x1005, il::const_(0x07, 8))
+            .unwrap();
+        let driver = driver.step().unwrap();
+        assert_eq!(rax(&driver), 7);
+        assert_eq!(driver.program().functions().len(), 1);
+    }
 }
@@ -0,0 +1,705 @@
+//! A GDB remote serial protocol stub for the concrete executor.
+//!
+//! A `GdbServer` allows debuggers which speak the GDB remote serial protocol,
+//! such as `gdb-multiarch`, to drive a `Debugger`. Registers are read and
+//! written through the scalars of the `State`, as described by a
+//! `GdbTarget`, and memory through the `State`'s memory.
+//!
+//! The server is synchronous: while the target runs, interrupts from the
+//! debugger are not seen. Set a step budget on the `Debugger` to regain
+//! control of long-running targets.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::executor::{Driver, GdbServer};
+//!
+//! # #[allow(dead_code)]
+//! fn serve(driver: Driver) -> Result<()> {
+//!     // Attach with `target remote localhost:1234` in gdb-multiarch.
+//!     let mut server = GdbServer::new(driver)?;
+//!     server.listen("127.0.0.1:1234")
+//! }
+//! ```
+
+use crate::architecture::Endian;
+use crate::error::*;
+use crate::executor::{Debugger, Driver, Stop, WatchKind};
+use crate::il;
+use num_bigint::BigUint;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+
+mod target;
+
+pub use self::target::*;
+
+/// The maximum size of a packet this server accepts, in bytes.
+const PACKET_SIZE: usize = 0x4000;
+
+/// A GDB remote serial protocol server backed by a `Debugger`.
+#[derive(Debug)]
+pub struct GdbServer {
+    debugger: Debugger,
+    target: GdbTarget,
+    endian: Endian,
+    /// Watchpoint ids, keyed by the Z packet type, address and length which
+    /// created them.
+    watchpoints: BTreeMap<(u8, u64, u64), usize>,
+    no_ack: bool,
+    attached: bool,
+}
+
+impl GdbServer {
+    /// Create a new `GdbServer` for the given driver.
+    ///
+    /// An error is returned if GDB registers are not described for the
+    /// driver's architecture.
+    pub fn new(driver: Driver) -> Result<GdbServer> {
+        GdbServer::from_debugger(Debugger::new(driver))
+    }
+
+    /// Create a new `GdbServer` for the given debugger.
+    ///
+    /// An error is returned if GDB registers are not described for the
+    /// debugger's architecture.
+    pub fn from_debugger(debugger: Debugger) -> Result<GdbServer> {
+        let architecture = debugger.driver().architecture();
+        let target = match architecture.gdb_target() {
+            Some(target) => target,
+            None => bail!(
+                "No GDB target description for architecture {}",
+                architecture.name()
+            ),
+        };
+        Ok(GdbServer::with_target(debugger, target))
+    }
+
+    /// Create a new `GdbServer` for the given debugger, describing registers
+    /// to GDB with the given `GdbTarget`.
+    pub fn with_target(debugger: Debugger, target: GdbTarget) -> GdbServer {
+        let endian = debugger.driver().architecture().endian();
+        GdbServer {
+            debugger,
+            target,
+            endian,
+            watchpoints: BTreeMap::new(),
+            no_ack: false,
+            attached: false,
+        }
+    }
+
+    /// Retrieve the `Debugger` driven by this server.
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Retrieve a mutable reference to the `Debugger` driven by this server.
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Consume this server, returning the `Debugger` it drove.
+    pub fn into_debugger(self) -> Debugger {
+        self.debugger
+    }
+
+    /// Accept one connection on the given address, and serve it until the
+    /// debugger detaches.
+    pub fn listen<A: ToSocketAddrs>(&mut self, address: A) -> Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let (mut stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        self.serve(&mut reader, &mut stream)
+    }
+
+    /// Serve a debugger over stdin and stdout until it detaches.
+    pub fn serve_stdio(&mut self) -> Result<()> {
+        let stdin = std::io::stdin();
+        let stdout = std::io::stdout();
+        self.serve(&mut stdin.lock(), &mut stdout.lock())
+    }
+
+    /// Serve a debugger over the given reader and writer until it detaches,
+    /// or the reader is closed.
+    pub fn serve<R: Read, W: Write>(&mut self, reader: &mut R, writer: &mut W) -> Result<()> {
+        self.attached = true;
+        while self.attached {
+            match read_byte(reader)? {
+                None => break,
+                Some(b'$') => {}
+                // Acknowledgements, and interrupts, which are only seen once
+                // the target has already stopped.
+                Some(_) => continue,
+            }
+
+            let mut packet = Vec::new();
+            loop {
+                match read_byte(reader)? {
+                    None => return Ok(()),
+                    Some(b'#') => break,
+                    Some(byte) => packet.push(byte),
+                }
+                if packet.len() > PACKET_SIZE {
+                    bail!("GDB packet exceeds the maximum packet size");
+                }
+            }
+            let mut checksum = [0u8; 2];
+            reader.read_exact(&mut checksum)?;
+
+            if !self.no_ack {
+                let expected = std::str::from_utf8(&checksum)
+                    .ok()
+                    .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+                if expected != Some(self::checksum(&packet)) {
+                    writer.write_all(b"-")?;
+                    writer.flush()?;
+                    continue;
+                }
+                writer.write_all(b"+")?;
+            }
+
+            let packet = String::from_utf8_lossy(&packet).to_string();
+            if packet == "QStartNoAckMode" {
+                write_packet(writer, "OK")?;
+                self.no_ack = true;
+                continue;
+            }
+            match self.handle(&packet) {
+                Some(reply) => write_packet(writer, &reply)?,
+                None => writer.flush()?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a single packet, without its framing, returning the reply, if
+    /// a reply should be sent.
+    pub fn handle(&mut self, packet: &str) -> Option<String> {
+        let reply = match self.handle_packet(packet) {
+            Ok(reply) => reply,
+            Err(_) => Some("E01".to_string()),
+        };
+        if packet == "k" || packet.starts_with('D') || packet.starts_with("vKill") {
+            self.attached = false;
+        }
+        reply
+    }
+
+    fn handle_packet(&mut self, packet: &str) -> Result<Option<String>> {
+        let command_length = packet.chars().next().map(char::len_utf8).unwrap_or(0);
+        let (command, arguments) = packet.split_at(command_length);
+        Ok(Some(match command {
+            "?" => "S05".to_string(),
+            "g" => self.read_registers(),
+            "G" => {
+                self.write_registers(arguments)?;
+                "OK".to_string()
+            }
+            "p" => {
+                let regnum = usize::from_str_radix(arguments, 16)?;
+                match self.target.register(regnum) {
+                    Some(register) => self.read_register(register),
+                    None => "E00".to_string(),
+                }
+            }
+            "P" => {
+                let (regnum, value) = split(arguments, '=')?;
+                let regnum = usize::from_str_radix(regnum, 16)?;
+                let register = self
+                    .target
+                    .register(regnum)
+                    .ok_or("Invalid register")?
+                    .clone();
+                self.write_register(&register, value)?;
+                "OK".to_string()
+            }
+            "m" => {
+                let (address, length) = split(arguments, ',')?;
+                let address = u64::from_str_radix(address, 16)?;
+                let length = usize::from_str_radix(length, 16)?.min(PACKET_SIZE / 2);
+                self.read_memory(address, length)?
+            }
+            "M" => {
+                let (location, data) = split(arguments, ':')?;
+                let (address, _) = split(location, ',')?;
+                let address = u64::from_str_radix(address, 16)?;
+                let memory = self.debugger.driver_mut().state_mut().memory_mut();
+                for (offset, byte) in decode_hex(data)?.into_iter().enumerate() {
+                    memory.store(address + offset as u64, il::const_(byte as u64, 8))?;
+                }
+                "OK".to_string()
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", arguments)?,
+            "s" | "c" => {
+                if !arguments.is_empty() {
+                    self.set_pc(u64::from_str_radix(arguments, 16)?)?;
+                }
+                let stop = if command == "s" {
+                    self.debugger.step()
+                } else {
+                    self.debugger.continue_()
+                };
+                match stop {
+                    Ok(stop) => self.stop_reply(&stop),
+                    Err(_) => "S0b".to_string(),
+                }
+            }
+            "H" | "T" | "D" => "OK".to_string(),
+            "k" => return Ok(None),
+            _ => self.query(packet)?,
+        }))
+    }
+
+    /// Reply to general queries, and packets this server does not support.
+    fn query(&self, packet: &str) -> Result<String> {
+        if packet.starts_with("qSupported") {
+            return Ok(format!(
+                "PacketSize={:x};qXfer:features:read+;swbreak+;QStartNoAckMode+",
+                PACKET_SIZE
+            ));
+        }
+        if let Some(annex) = packet.strip_prefix("qXfer:features:read:") {
+            let (annex, range) = split(annex, ':')?;
+            if annex != "target.xml" {
+                return Ok("E00".to_string());
+            }
+            let (offset, length) = split(range, ',')?;
+            let offset = usize::from_str_radix(offset, 16)?;
+            let length = usize::from_str_radix(length, 16)?;
+            let xml = self.target.xml();
+            if offset >= xml.len() {
+                return Ok("l".to_string());
+            }
+            let end = xml.len().min(offset.saturating_add(length));
+            let prefix = if end < xml.len() { "m" } else { "l" };
+            return Ok(format!("{}{}", prefix, escape(&xml[offset..end])));
+        }
+        Ok(match packet {
+            "qAttached" => "1",
+            "qC" => "QC1",
+            "qfThreadInfo" => "m1",
+            "qsThreadInfo" => "l",
+            "qSymbol::" => "OK",
+            _ if packet.starts_with("vKill") => "OK",
+            _ => "",
+        }
+        .to_string())
+    }
+
+    fn stop_reply(&self, stop: &Stop) -> String {
+        match *stop {
+            Stop::Breakpoint { .. } => "T05swbreak:;".to_string(),
+            Stop::Watchpoint { id, address, .. } => {
+                let kind = match self.debugger.watchpoint(id).map(|w| w.kind()) {
+                    Some(WatchKind::Read) => "rwatch",
+                    Some(WatchKind::Access) => "awatch",
+                    _ => "watch",
+                };
+                format!("T05{}:{:x};", kind, address)
+            }
+            Stop::Fault { .. } | Stop::Error { .. } => "S0b".to_string(),
+            Stop::Step | Stop::Return { .. } | Stop::StepBudget => "S05".to_string(),
+        }
+    }
+
+    fn breakpoint(&mut self, insert: bool, arguments: &str) -> Result<String> {
+        let mut fields = arguments.split(',');
+        let type_ = fields.next().ok_or("Missing breakpoint type")?;
+        let address = u64::from_str_radix(fields.next().ok_or("Missing address")?, 16)?;
+        let length = u64::from_str_radix(fields.next().ok_or("Missing length")?, 16)?;
+        let kind = match type_ {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(address);
+                } else {
+                    self.debugger.remove_breakpoint(address);
+                }
+                return Ok("OK".to_string());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return Ok(String::new()),
+        };
+        let key = (type_.as_bytes()[0], address, length);
+        if insert {
+            let id = self.debugger.add_watchpoint(address, length, kind);
+            if let Some(id) = self.watchpoints.insert(key, id) {
+                self.debugger.remove_watchpoint(id);
+            }
+        } else if let Some(id) = self.watchpoints.remove(&key) {
+            self.debugger.remove_watchpoint(id);
+        }
+        Ok("OK".to_string())
+    }
+
+    fn set_pc(&mut self, address: u64) -> Result<()> {
+        let location =
+            il::RefProgramLocation::from_address(self.debugger.driver().program(), address)
+                .ok_or_else(|| format!("No instruction at 0x{:x} has been lifted", address))?
+                .into();
+        self.debugger.driver_mut().set_location(location);
+        Ok(())
+    }
+
+    fn read_registers(&self) -> String {
+        self.target
+            .registers()
+            .iter()
+            .map(|register| self.read_register(register))
+            .collect()
+    }
+
+    /// Read a register as target-endian hex, with unavailable bytes given as
+    /// `xx`.
+    fn read_register(&self, register: &GdbRegister) -> String {
+        let bytes = register.bits().div_ceil(8);
+        let state = self.debugger.driver().state();
+        let value = match *register.value() {
+            GdbRegisterValue::Scalar(ref scalar) => state
+                .get_scalar(scalar.name())
+                .map(|constant| constant.value().clone()),
+            GdbRegisterValue::ProgramCounter => self.debugger.address().map(BigUint::from),
+            GdbRegisterValue::Flags(ref flags) => {
+                let mut value = 0u64;
+                for (bit, flag) in flags {
+                    match state.get_scalar(flag.name()) {
+                        Some(constant) if constant.is_one() => value |= 1 << bit,
+                        _ => {}
+                    }
+                }
+                Some(BigUint::from(value))
+            }
+            GdbRegisterValue::Zero => Some(BigUint::from(0u64)),
+            GdbRegisterValue::Unmodelled => None,
+        };
+        match value {
+            Some(value) => {
+                let mut le = value.to_bytes_le();
+                le.resize(bytes, 0);
+                if self.endian == Endian::Big {
+                    le.reverse();
+                }
+                le.iter().map(|byte| format!("{:02x}", byte)).collect()
+            }
+            None => "xx".repeat(bytes),
+        }
+    }
+
+    fn write_registers(&mut self, data: &str) -> Result<()> {
+        let registers = self.target.registers().to_vec();
+        let mut offset: usize = 0;
+        for register in registers {
+            let length = register.bits().div_ceil(8) * 2;
+            let value = data
+                .get(offset..offset.saturating_add(length))
+                .ok_or("Register data is too short")?;
+            if !value.contains('x') {
+                self.write_register(&register, value)?;
+            }
+            offset += length;
+        }
+        Ok(())
+    }
+
+    fn write_register(&mut self, register: &GdbRegister, value: &str) -> Result<()> {
+        let mut bytes = decode_hex(value)?;
+        if bytes.len() != register.bits().div_ceil(8) {
+            bail!("Invalid length for register {}", register.name());
+        }
+        if self.endian == Endian::Big {
+            bytes.reverse();
+        }
+        let value = BigUint::from_bytes_le(&bytes);
+        match *register.value() {
+            GdbRegisterValue::Scalar(ref scalar) => {
+                let constant = il::Constant::new_big(value, scalar.bits());
+                self.debugger
+                    .driver_mut()
+                    .state_mut()
+                    .set_scalar(scalar.name(), constant);
+            }
+            GdbRegisterValue::ProgramCounter => {
+                let address = il::Constant::new_big(value, 64)
+                    .value_u64()
+                    .ok_or(ErrorKind::TooManyAddressBits)?;
+                if self.debugger.address() != Some(address) {
+                    self.set_pc(address)?;
+                }
+            }
+            GdbRegisterValue::Flags(ref flags) => {
+                let value = il::Constant::new_big(value, register.bits())
+                    .value_u64()
+                    .ok_or("Flags register is too large")?;
+                let state = self.debugger.driver_mut().state_mut();
+                for (bit, flag) in flags {
+                    state.set_scalar(flag.name(), il::const_((value >> bit) & 1, 1));
+                }
+            }
+            GdbRegisterValue::Zero | GdbRegisterValue::Unmodelled => {}
+        }
+        Ok(())
+    }
+
+    /// Read up to `length` bytes of memory, stopping at the first unmapped
+    /// byte. The debugger may read memory the target could not.
+    fn read_memory(&self, address: u64, length: usize) -> Result<String> {
+        let memory = self.debugger.driver().state().memory();
+        let mut hex = String::new();
+        for offset in 0..length as u64 {
+            match memory.load_unchecked(address.wrapping_add(offset), 8)? {
+                Some(byte) => hex.push_str(&format!("{:02x}", byte.value_u64().unwrap_or(0))),
+                None => break,
+            }
+        }
+        if hex.is_empty() && length > 0 {
+            return Ok("E14".to_string());
+        }
+        Ok(hex)
+    }
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> Result<Option<u8>> {
+    let mut byte = [0u8];
+    loop {
+        return match reader.read(&mut byte) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e.into()),
+        };
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn write_packet<W: Write>(writer: &mut W, data: &str) -> Result<()> {
+    write!(writer, "${}#{:02x}", data, checksum(data.as_bytes()))?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Escape the characters the remote protocol reserves in binary data.
+fn escape(data: &str) -> String {
+    let mut escaped = String::new();
+    for c in data.chars() {
+        match c {
+            '#' | '$' | '}' | '*' => {
+                escaped.push('}');
+                escaped.push((c as u8 ^ 0x20) as char);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn split(s: &str, separator: char) -> Result<(&str, &str)> {
+    let index = s
+        .find(separator)
+        .ok_or_else(|| format!("Malformed GDB packet, expected {}", separator))?;
+    Ok((&s[..index], &s[index + 1..]))
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() & 1 != 0 {
+        bail!("Hex data has an odd length");
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let digit = |byte: u8| (byte as char).to_digit(16).ok_or("Invalid hex digit");
+            Ok((digit(pair[0])? << 4 | digit(pair[1])?) as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{Amd64, Architecture, Mips};
+    use crate::executor::{Memory, State};
+    use crate::memory::MemoryPermissions;
+    use crate::RC;
+
+    // A function at 0x1000 which increments rax at 0x1000, 0x1004 and
+    // 0x1008.
+    fn server() -> GdbServer {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+        let block_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            for address in [0x1000, 0x1004, 0x1008].iter() {
+                block.assign(
+                    il::scalar("rax", 64),
+                    il::Expression::add(il::expr_scalar("rax", 64), il::expr_const(1, 64)).unwrap(),
+                );
+                let last = block.instructions().len() - 1;
+                block.instructions_mut()[last].set_address(Some(*address));
+            }
+            block.index()
+        };
+        control_flow_graph.set_entry(block_index).unwrap();
+
+        let mut program = il::Program::new();
+        program.add_function(il::Function::new(0x1000, control_flow_graph));
+
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+        let mut state = State::new(Memory::new(Endian::Little));
+        state.set_scalar("rax", il::const_(0x41, 64));
+        state.set_scalar("ZF", il::const_(1, 1));
+        state
+            .memory_mut()
+            .store(0x8000, il::const_(0x1234, 16))
+            .unwrap();
+
+        GdbServer::new(Driver::new(
+            RC::new(program),
+            location,
+            state,
+            RC::new(Amd64::new()),
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut server = server();
+        // rax, eflags and rip.
+        assert_eq!(server.handle("p0").unwrap(), "4100000000000000");
+        assert_eq!(server.handle("p11").unwrap(), "40000000");
+        assert_eq!(server.handle("p10").unwrap(), "0010000000000000");
+        // rbx is not set.
+        assert_eq!(server.handle("p1").unwrap(), "xxxxxxxxxxxxxxxx");
+
+        let registers = server.handle("g").unwrap();
+        assert!(registers.starts_with("4100000000000000xxxxxxxxxxxxxxxx"));
+
+        assert_eq!(server.handle("P1=0200000000000000").unwrap(), "OK");
+        assert_eq!(server.handle("P11=01000000").unwrap(), "OK");
+        let state = server.debugger().driver().state();
+        assert_eq!(state.get_scalar("rbx"), Some(&il::const_(2, 64)));
+        assert_eq!(state.get_scalar("CF"), Some(&il::const_(1, 1)));
+        assert_eq!(state.get_scalar("ZF"), Some(&il::const_(0, 1)));
+
+        assert_eq!(server.handle("P10=0810000000000000").unwrap(), "OK");
+        assert_eq!(server.debugger().address(), Some(0x1008));
+
+        assert_eq!(server.handle("m8000,4").unwrap(), "3412");
+        assert_eq!(server.handle("m9000,4").unwrap(), "E14");
+        assert_eq!(server.handle("M8001,2:abcd").unwrap(), "OK");
+        assert_eq!(server.handle("m8000,3").unwrap(), "34abcd");
+
+        // Data which is not hex is rejected, even when it is not ASCII.
+        assert_eq!(server.handle("M8001,2:a\u{e9}b").unwrap(), "E01");
+        assert_eq!(server.handle("M8001,2:\u{fffd}\u{fffd}").unwrap(), "E01");
+        assert_eq!(server.handle("P1=\u{e9}00000000000000").unwrap(), "E01");
+        assert_eq!(server.handle("m8000,3").unwrap(), "34abcd");
+
+        // Memory the target may not read can still be read by the debugger.
+        let memory = server.debugger_mut().driver_mut().state_mut().memory_mut();
+        memory.set_permissions(0x8000, 4, MemoryPermissions::EXECUTE);
+        memory.set_enforcing(true);
+        assert_eq!(server.handle("m8000,3").unwrap(), "34abcd");
+    }
+
+    #[test]
+    fn execution() {
+        let mut server = server();
+        assert_eq!(server.handle("s").unwrap(), "S05");
+        assert_eq!(server.debugger().address(), Some(0x1004));
+        assert_eq!(server.handle("Z0,1008,1").unwrap(), "OK");
+        assert_eq!(server.handle("c").unwrap(), "T05swbreak:;");
+        assert_eq!(server.debugger().address(), Some(0x1008));
+        assert_eq!(server.handle("z0,1008,1").unwrap(), "OK");
+        // Execution faults running off the end of the program, leaving the
+        // debugger at the last instruction.
+        assert_eq!(server.handle("c").unwrap(), "S0b");
+        assert_eq!(server.debugger().address(), Some(0x1008));
+        assert_eq!(
+            server.debugger().driver().state().get_scalar("rax"),
+            Some(&il::const_(0x43, 64))
+        );
+    }
+
+    #[test]
+    fn protocol() {
+        let mut server = server();
+        let mut input = Vec::new();
+        for packet in &["qSupported:xmlRegisters=i386", "p0", "c", "D"] {
+            write_packet(&mut input, packet).unwrap();
+            input.push(b'+');
+        }
+        let mut output = Vec::new();
+        server
+            .serve(&mut std::io::Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "+$PacketSize=4000;qXfer:features:read+;swbreak+;QStartNoAckMode+#3a\
+             +$4100000000000000#05\
+             +$S0b#e5\
+             +$OK#9a"
+        );
+
+        let mut output = Vec::new();
+        server
+            .serve(&mut std::io::Cursor::new(b"$g#00".to_vec()), &mut output)
+            .unwrap();
+        assert_eq!(output, b"-");
+
+        // A command which is not ASCII is not supported.
+        let mut output = Vec::new();
+        server
+            .serve(&mut std::io::Cursor::new(b"$\xff#ff".to_vec()), &mut output)
+            .unwrap();
+        assert_eq!(output, b"+$#00");
+        assert_eq!(server.handle("\u{e9}").unwrap(), "");
+    }
+
+    #[test]
+    fn target_description() {
+        let mut server = server();
+        let xml = Amd64::new().gdb_target().unwrap().xml();
+        let mut read = String::new();
+        loop {
+            let reply = server
+                .handle(&format!(
+                    "qXfer:features:read:target.xml:{:x},100",
+                    read.len()
+                ))
+                .unwrap();
+            read.push_str(&reply[1..]);
+            if reply.starts_with('l') {
+                break;
+            }
+        }
+        assert_eq!(read, xml);
+        assert_eq!(
+            server
+                .handle(&format!(
+                    "qXfer:features:read:target.xml:1,{:x}",
+                    usize::MAX
+                ))
+                .unwrap(),
+            format!("l{}", &xml[1..])
+        );
+
+        let target = Mips::new().gdb_target().unwrap();
+        let xml = target.xml();
+        assert!(xml.contains("<architecture>mips</architecture>"));
+        assert!(xml.contains("<feature name=\"org.gnu.gdb.mips.cpu\">"));
+        assert!(xml.contains("<reg name=\"r29\" bitsize=\"32\" regnum=\"29\" type=\"int\"/>"));
+        assert!(xml.contains("<reg name=\"pc\" bitsize=\"32\" regnum=\"37\" type=\"int\"/>"));
+        assert_eq!(
+            target.register(29).unwrap().value(),
+            &GdbRegisterValue::Scalar(il::scalar("$sp", 32))
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! The registers of Falcon's supported architectures, as described to GDB.
+
+use crate::il;
+
+/// How the value of a register described to GDB is held in an executor
+/// `State`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GdbRegisterValue {
+    /// The value of the given scalar.
+    Scalar(il::Scalar),
+    /// The address of the current instruction.
+    ProgramCounter,
+    /// A register composed of one-bit flags, each given as the index of its
+    /// bit in the register, and the scalar holding it.
+    Flags(Vec<(usize, il::Scalar)>),
+    /// A register which always holds zero.
+    Zero,
+    /// A register Falcon does not model.
+    Unmodelled,
+}
+
+/// A register described to GDB.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GdbRegister {
+    name: String,
+    regnum: usize,
+    bits: usize,
+    type_: &'static str,
+    feature: &'static str,
+    value: GdbRegisterValue,
+}
+
+impl GdbRegister {
+    /// The name GDB knows this register by.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The number GDB knows this register by.
+    pub fn regnum(&self) -> usize {
+        self.regnum
+    }
+
+    /// The size of this register in bits.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// How the value of this register is held in a `State`.
+    pub fn value(&self) -> &GdbRegisterValue {
+        &self.value
+    }
+}
+
+/// The registers of an architecture, as described to GDB in a target
+/// description.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GdbTarget {
+    architecture: &'static str,
+    registers: Vec<GdbRegister>,
+}
+
+impl GdbTarget {
+    /// Create a new `GdbTarget` for the given GDB architecture, with no
+    /// registers.
+    pub fn new(architecture: &'static str) -> GdbTarget {
+        GdbTarget {
+            architecture,
+            registers: Vec::new(),
+        }
+    }
+
+    /// Add a register to the given target description feature. Registers
+    /// must be added in the order of their regnums.
+    pub fn add_register<S: Into<String>>(
+        &mut self,
+        feature: &'static str,
+        regnum: usize,
+        name: S,
+        bits: usize,
+        type_: &'static str,
+        value: GdbRegisterValue,
+    ) {
+        self.registers.push(GdbRegister {
+            name: name.into(),
+            regnum,
+            bits,
+            type_,
+            feature,
+            value,
+        });
+    }
+
+    /// Add a register with the regnum following the last register added.
+    fn push<S: Into<String>>(
+        &mut self,
+        feature: &'static str,
+        name: S,
+        bits: usize,
+        type_: &'static str,
+        value: GdbRegisterValue,
+    ) {
+        let regnum = self.registers.last().map(|r| r.regnum + 1).unwrap_or(0);
+        self.add_register(feature, regnum, name, bits, type_, value);
+    }
+
+    /// The name GDB knows this architecture by.
+    pub fn architecture(&self) -> &str {
+        self.architecture
+    }
+
+    /// The registers of this target, ordered by regnum.
+    pub fn registers(&self) -> &[GdbRegister] {
+        &self.registers
+    }
+
+    /// Get the register with the given regnum.
+    pub fn register(&self, regnum: usize) -> Option<&GdbRegister> {
+        self.registers.iter().find(|r| r.regnum == regnum)
+    }
+
+    /// The target description XML for this target.
+    pub fn xml(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\"?>\n");
+        xml.push_str("<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n");
+        xml.push_str("<target version=\"1.0\">\n");
+        xml.push_str(&format!(
+            "  <architecture>{}</architecture>\n",
+            self.architecture
+        ));
+        let mut feature: Option<&str> = None;
+        for register in &self.registers {
+            if feature != Some(register.feature) {
+                if feature.is_some() {
+                    xml.push_str("  </feature>\n");
+                }
+                xml.push_str(&format!("  <feature name=\"{}\">\n", register.feature));
+                feature = Some(register.feature);
+            }
+            xml.push_str(&format!(
+                "    <reg name=\"{}\" bitsize=\"{}\" regnum=\"{}\" type=\"{}\"/>\n",
+                register.name, register.bits, register.regnum, register.type_
+            ));
+        }
+        if feature.is_some() {
+            xml.push_str("  </feature>\n");
+        }
+        xml.push_str("</target>\n");
+        xml
+    }
+
+    /// Add the x87 registers, which Falcon does not model, to an x86
+    /// `org.gnu.gdb.i386.core` feature.
+    fn push_x87(&mut self) {
+        const CORE: &str = "org.gnu.gdb.i386.core";
+        for i in 0..8 {
+            self.push(
+                CORE,
+                format!("st{}", i),
+                80,
+                "i387_ext",
+                GdbRegisterValue::Unmodelled,
+            );
+        }
+        for name in &[
+            "fctrl", "fstat", "ftag", "fiseg", "fioff", "foseg", "fooff", "fop",
+        ] {
+            self.push(CORE, *name, 32, "int", GdbRegisterValue::Unmodelled);
+        }
+    }
+
+    fn x86_flags() -> GdbRegisterValue {
+        GdbRegisterValue::Flags(
+            [
+                (0, "CF"),
+                (2, "PF"),
+                (4, "AF"),
+                (6, "ZF"),
+                (7, "SF"),
+                (9, "IF"),
+                (10, "DF"),
+                (11, "OF"),
+            ]
+            .iter()
+            .map(|(bit, flag)| (*bit, il::scalar(*flag, 1)))
+            .collect(),
+        )
+    }
+
+    /// The registers of the 64-bit X86 architecture.
+    pub fn amd64() -> GdbTarget {
+        const CORE: &str = "org.gnu.gdb.i386.core";
+        const SSE: &str = "org.gnu.gdb.i386.sse";
+        let mut target = GdbTarget::new("i386:x86-64");
+        let registers = [
+            "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11",
+            "r12", "r13", "r14", "r15",
+        ];
+        for name in registers.iter() {
+            let type_ = match *name {
+                "rbp" | "rsp" => "data_ptr",
+                _ => "int64",
+            };
+            target.push(
+                CORE,
+                *name,
+                64,
+                type_,
+                GdbRegisterValue::Scalar(il::scalar(*name, 64)),
+            );
+        }
+        target.push(
+            CORE,
+            "rip",
+            64,
+            "code_ptr",
+            GdbRegisterValue::ProgramCounter,
+        );
+        target.push(CORE, "eflags", 32, "int", GdbTarget::x86_flags());
+        for name in &["cs", "ss", "ds", "es", "fs", "gs"] {
+            target.push(CORE, *name, 32, "int32", GdbRegisterValue::Unmodelled);
+        }
+        target.push_x87();
+        for i in 0..16 {
+            let name = format!("xmm{}", i);
+            let value = GdbRegisterValue::Scalar(il::scalar(name.clone(), 128));
+            target.push(SSE, name, 128, "uint128", value);
+        }
+        target.push(SSE, "mxcsr", 32, "int", GdbRegisterValue::Unmodelled);
+        target
+    }
+
+    /// The registers of the 32-bit X86 architecture.
+    pub fn x86() -> GdbTarget {
+        const CORE: &str = "org.gnu.gdb.i386.core";
+        const SSE: &str = "org.gnu.gdb.i386.sse";
+        let mut target = GdbTarget::new("i386");
+        let registers = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi"];
+        for name in registers.iter() {
+            let type_ = match *name {
+                "ebp" | "esp" => "data_ptr",
+                _ => "int32",
+            };
+            target.push(
+                CORE,
+                *name,
+                32,
+                type_,
+                GdbRegisterValue::Scalar(il::scalar(*name, 32)),
+            );
+        }
+        target.push(
+            CORE,
+            "eip",
+            32,
+            "code_ptr",
+            GdbRegisterValue::ProgramCounter,
+        );
+        target.push(CORE, "eflags", 32, "int", GdbTarget::x86_flags());
+        for name in &["cs", "ss", "ds", "es", "fs", "gs"] {
+            target.push(CORE, *name, 32, "int32", GdbRegisterValue::Unmodelled);
+        }
+        target.push_x87();
+        for i in 0..8 {
+            let name = format!("xmm{}", i);
+            let value = GdbRegisterValue::Scalar(il::scalar(name.clone(), 128));
+            target.push(SSE, name, 128, "uint128", value);
+        }
+        target.push(SSE, "mxcsr", 32, "int", GdbRegisterValue::Unmodelled);
+        target
+    }
+
+    /// The registers of the 32-bit Mips architectures.
+    pub fn mips() -> GdbTarget {
+        const CPU: &str = "org.gnu.gdb.mips.cpu";
+        const CP0: &str = "org.gnu.gdb.mips.cp0";
+        const FPU: &str = "org.gnu.gdb.mips.fpu";
+        let mut target = GdbTarget::new("mips");
+        let registers = [
+            "$zero", "$at", "$v0", "$v1", "$a0", "$a1", "$a2", "$a3", "$t0", "$t1", "$t2", "$t3",
+            "$t4", "$t5", "$t6", "$t7", "$s0", "$s1", "$s2", "$s3", "$s4", "$s5", "$s6", "$s7",
+            "$t8", "$t9", "$k0", "$k1", "$gp", "$sp", "$fp", "$ra",
+        ];
+        for (i, name) in registers.iter().enumerate() {
+            let value = match i {
+                0 => GdbRegisterValue::Zero,
+                _ => GdbRegisterValue::Scalar(il::scalar(*name, 32)),
+            };
+            target.push(CPU, format!("r{}", i), 32, "int", value);
+        }
+        // GDB numbers the MIPS registers in the order of the original
+        // remote protocol, which interleaves the cp0 and cpu registers.
+        let unmodelled = GdbRegisterValue::Unmodelled;
+        target.add_register(CP0, 32, "status", 32, "int", unmodelled.clone());
+        target.add_register(CPU, 33, "lo", 32, "int", scalar("$lo", 32));
+        target.add_register(CPU, 34, "hi", 32, "int", scalar("$hi", 32));
+        target.add_register(CP0, 35, "badvaddr", 32, "int", unmodelled.clone());
+        target.add_register(CP0, 36, "cause", 32, "int", unmodelled.clone());
+        let pc = GdbRegisterValue::ProgramCounter;
+        target.add_register(CPU, 37, "pc", 32, "int", pc);
+        for i in 0..32 {
+            target.push(
+                FPU,
+                format!("f{}", i),
+                32,
+                "ieee_single",
+                unmodelled.clone(),
+            );
+        }
+        target.push(FPU, "fcsr", 32, "int", unmodelled.clone());
+        target.push(FPU, "fir", 32, "int", unmodelled);
+        target
+    }
+
+    /// The registers of the 32-bit PowerPC architecture.
+    pub fn ppc() -> GdbTarget {
+        const CORE: &str = "org.gnu.gdb.power.core";
+        let mut target = GdbTarget::new("powerpc:common");
+        for i in 0..32 {
+            let name = format!("r{}", i);
+            target.push(CORE, name.clone(), 32, "uint32", scalar(&name, 32));
+        }
+        // Regnums 32 to 63 are the floating-point registers, which are not
+        // described.
+        let pc = GdbRegisterValue::ProgramCounter;
+        target.add_register(CORE, 64, "pc", 32, "code_ptr", pc);
+        target.push(CORE, "msr", 32, "uint32", GdbRegisterValue::Unmodelled);
+        let mut cr = Vec::new();
+        for field in 0..8 {
+            for (bit, flag) in ["lt", "gt", "eq", "so"].iter().enumerate() {
+                let name = format!("cr{}-{}", field, flag);
+                cr.push((31 - (field * 4 + bit), il::scalar(name, 1)));
+            }
+        }
+        target.push(CORE, "cr", 32, "uint32", GdbRegisterValue::Flags(cr));
+        target.push(CORE, "lr", 32, "code_ptr", scalar("lr", 32));
+        target.push(CORE, "ctr", 32, "uint32", scalar("ctr", 32));
+        target.push(CORE, "xer", 32, "uint32", GdbRegisterValue::Unmodelled);
+        target
+    }
+}
+
+fn scalar(name: &str, bits: usize) -> GdbRegisterValue {
+    GdbRegisterValue::Scalar(il::scalar(name, bits))
+}
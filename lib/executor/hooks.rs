@@ -0,0 +1,161 @@
+//! Hooks which intercept execution in a `Driver`.
+//!
+//! Hooks allow behaviour the executor cannot, or should not, execute to be
+//! modelled instead. Intrinsic hooks are keyed by the mnemonic of an
+//! `il::Intrinsic` (`cpuid`, `rdtsc`, `syscall`, ...), and are invoked when
+//! the `Driver` encounters that intrinsic. Address hooks are invoked when
+//! control is transferred to an address by an `il::Operation::Branch`, which
+//! makes them suitable for replacing functions such as `malloc` or `printf`
+//! with models.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::executor::{HookSuccessor, Hooks};
+//! use falcon::il;
+//!
+//! # fn example() -> Result<()> {
+//! let mut hooks = Hooks::new();
+//!
+//! // rdtsc always returns 0.
+//! hooks.set_intrinsic("rdtsc", |state, _| {
+//!     state.set_scalar("eax", il::const_(0, 32));
+//!     state.set_scalar("edx", il::const_(0, 32));
+//!     Ok(HookSuccessor::FallThrough)
+//! });
+//!
+//! // The function at 0x400800 always returns 1.
+//! hooks.set_address(0x400800, |state, _| {
+//!     state.set_scalar("rax", il::const_(1, 64));
+//!     Ok(HookSuccessor::Return)
+//! });
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::*;
+use crate::executor::State;
+use crate::il;
+use crate::RC;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Where a `Driver` should continue execution after a hook has run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HookSuccessor {
+    /// For intrinsic hooks, continue with the location following the
+    /// intrinsic. For address hooks, continue execution at the hooked address
+    /// as if no hook were present.
+    FallThrough,
+    /// Transfer control to the given address.
+    Branch(u64),
+    /// Return to the caller, using the return address given by the
+    /// architecture's calling convention.
+    Return,
+}
+
+/// A hook invoked when the `Driver` encounters an `il::Intrinsic`.
+#[cfg(not(feature = "thread_safe"))]
+pub type IntrinsicHook = dyn Fn(&mut State, &il::Intrinsic) -> Result<HookSuccessor>;
+
+/// A hook invoked when the `Driver` encounters an `il::Intrinsic`.
+#[cfg(feature = "thread_safe")]
+pub type IntrinsicHook = dyn Fn(&mut State, &il::Intrinsic) -> Result<HookSuccessor> + Send + Sync;
+
+/// A hook invoked when the `Driver` branches to an address. The hook receives
+/// the address branched to.
+#[cfg(not(feature = "thread_safe"))]
+pub type AddressHook = dyn Fn(&mut State, u64) -> Result<HookSuccessor>;
+
+/// A hook invoked when the `Driver` branches to an address. The hook receives
+/// the address branched to.
+#[cfg(feature = "thread_safe")]
+pub type AddressHook = dyn Fn(&mut State, u64) -> Result<HookSuccessor> + Send + Sync;
+
+/// Implemented by every type which may be held by a hook. With the
+/// `thread_safe` feature hooks must be `Send` and `Sync`, so a `Driver`
+/// holding them is too.
+#[cfg(not(feature = "thread_safe"))]
+pub trait HookSafe {}
+
+#[cfg(not(feature = "thread_safe"))]
+impl<T> HookSafe for T {}
+
+/// Implemented by every type which may be held by a hook. With the
+/// `thread_safe` feature hooks must be `Send` and `Sync`, so a `Driver`
+/// holding them is too.
+#[cfg(feature = "thread_safe")]
+pub trait HookSafe: Send + Sync {}
+
+#[cfg(feature = "thread_safe")]
+impl<T: Send + Sync> HookSafe for T {}
+
+/// A registry of hooks used by a `Driver`.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    intrinsics: HashMap<String, RC<IntrinsicHook>>,
+    addresses: HashMap<u64, RC<AddressHook>>,
+}
+
+impl Hooks {
+    /// Create a new, empty, set of hooks.
+    pub fn new() -> Hooks {
+        Hooks::default()
+    }
+
+    /// Set the hook for intrinsics with the given mnemonic, replacing any
+    /// hook previously set for that mnemonic.
+    pub fn set_intrinsic<S, F>(&mut self, mnemonic: S, hook: F)
+    where
+        S: Into<String>,
+        F: 'static + HookSafe + Fn(&mut State, &il::Intrinsic) -> Result<HookSuccessor>,
+    {
+        self.intrinsics.insert(mnemonic.into(), RC::new(hook));
+    }
+
+    /// Remove the hook for intrinsics with the given mnemonic.
+    pub fn remove_intrinsic(&mut self, mnemonic: &str) {
+        self.intrinsics.remove(mnemonic);
+    }
+
+    /// Get the hook for intrinsics with the given mnemonic, if one is set.
+    pub fn intrinsic(&self, mnemonic: &str) -> Option<&IntrinsicHook> {
+        self.intrinsics.get(mnemonic).map(|hook| hook.as_ref())
+    }
+
+    /// Set the hook for the given address, replacing any hook previously set
+    /// for that address.
+    pub fn set_address<F>(&mut self, address: u64, hook: F)
+    where
+        F: 'static + HookSafe + Fn(&mut State, u64) -> Result<HookSuccessor>,
+    {
+        self.addresses.insert(address, RC::new(hook));
+    }
+
+    /// Remove the hook for the given address.
+    pub fn remove_address(&mut self, address: u64) {
+        self.addresses.remove(&address);
+    }
+
+    /// Get the hook for the given address, if one is set.
+    pub fn address(&self, address: u64) -> Option<&AddressHook> {
+        self.addresses.get(&address).map(|hook| hook.as_ref())
+    }
+
+    /// Returns true if no hooks are set.
+    pub fn is_empty(&self) -> bool {
+        self.intrinsics.is_empty() && self.addresses.is_empty()
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut mnemonics = self.intrinsics.keys().collect::<Vec<&String>>();
+        mnemonics.sort();
+        let mut addresses = self.addresses.keys().collect::<Vec<&u64>>();
+        addresses.sort();
+        f.debug_struct("Hooks")
+            .field("intrinsics", &mnemonics)
+            .field("addresses", &addresses)
+            .finish()
+    }
+}
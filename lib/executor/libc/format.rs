@@ -0,0 +1,346 @@
+//! Formatting for the printf family of functions.
+
+use crate::error::*;
+use crate::executor::libc::{read_string, Arguments, MAX_LENGTH};
+use crate::executor::State;
+
+/// The conversion specification following a `%`.
+#[derive(Default)]
+struct Specification {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+    /// The size of an integer argument in bits, if a length modifier was
+    /// given.
+    bits: Option<usize>,
+}
+
+/// Format the given printf format string, taking values for its conversions
+/// from the given arguments.
+///
+/// Integer, character, string and pointer conversions are supported, with
+/// flags, widths, precisions and length modifiers. Floating point
+/// conversions, and `%n`, are not. An error is returned if a width, a
+/// precision, or the output, is longer than a model may write.
+pub(crate) fn format(state: &State, format: &[u8], arguments: &mut Arguments) -> Result<Vec<u8>> {
+    let word_size = arguments.libc().word_size();
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < format.len() {
+        if format[i] != b'%' {
+            output.push(format[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+
+        let mut specification = Specification::default();
+        while i < format.len() {
+            match format[i] {
+                b'-' => specification.left = true,
+                b'0' => specification.zero = true,
+                b'+' => specification.plus = true,
+                b' ' => specification.space = true,
+                b'#' => specification.alternate = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        if format.get(i) == Some(&b'*') {
+            let width = sign_extend(arguments.next(state)?, 32);
+            if width < 0 {
+                specification.left = true;
+            }
+            specification.width = field_length(width.unsigned_abs())?;
+            i += 1;
+        } else {
+            specification.width = decimal(format, &mut i)?;
+        }
+
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            if format.get(i) == Some(&b'*') {
+                let precision = sign_extend(arguments.next(state)?, 32);
+                specification.precision = if precision < 0 {
+                    None
+                } else {
+                    Some(field_length(precision as u64)?)
+                };
+                i += 1;
+            } else {
+                specification.precision = Some(decimal(format, &mut i)?);
+            }
+        }
+
+        loop {
+            let bits = match format.get(i) {
+                Some(b'h') => {
+                    if specification.bits == Some(16) {
+                        8
+                    } else {
+                        16
+                    }
+                }
+                Some(b'l') => {
+                    if specification.bits == Some(word_size) {
+                        64
+                    } else {
+                        word_size
+                    }
+                }
+                Some(b'q') => 64,
+                Some(b'z') | Some(b'j') | Some(b't') => word_size,
+                _ => break,
+            };
+            specification.bits = Some(bits);
+            i += 1;
+        }
+
+        let conversion = *format
+            .get(i)
+            .ok_or("Incomplete conversion at the end of format string")?;
+        i += 1;
+
+        let bits = specification.bits.unwrap_or(32);
+        let field = match conversion {
+            b'%' => b"%".to_vec(),
+            b'd' | b'i' => {
+                let value = sign_extend(arguments.integer(state, bits)?, bits);
+                let sign = if value < 0 {
+                    "-"
+                } else if specification.plus {
+                    "+"
+                } else if specification.space {
+                    " "
+                } else {
+                    ""
+                };
+                integer(&specification, sign, value.unsigned_abs(), 10, false)
+            }
+            b'u' | b'x' | b'X' | b'o' => {
+                let value = arguments.integer(state, bits)?;
+                let value = if bits < 64 {
+                    value & ((1 << bits) - 1)
+                } else {
+                    value
+                };
+                let (radix, prefix) = match conversion {
+                    b'u' => (10, ""),
+                    b'x' => (16, "0x"),
+                    b'X' => (16, "0X"),
+                    _ => (8, "0"),
+                };
+                let prefix = if specification.alternate && value != 0 {
+                    prefix
+                } else {
+                    ""
+                };
+                integer(&specification, prefix, value, radix, conversion == b'X')
+            }
+            b'p' => {
+                let value = arguments.next(state)?;
+                if value == 0 {
+                    pad(&specification, false, Vec::new(), b"(nil)".to_vec())
+                } else {
+                    integer(&specification, "0x", value, 16, false)
+                }
+            }
+            b'c' => pad(
+                &specification,
+                false,
+                Vec::new(),
+                vec![arguments.next(state)? as u8],
+            ),
+            b's' => {
+                let address = arguments.next(state)?;
+                let string = if address == 0 {
+                    b"(null)".to_vec()
+                } else {
+                    read_string(state, address, specification.precision)?
+                };
+                let length = specification
+                    .precision
+                    .unwrap_or(string.len())
+                    .min(string.len());
+                pad(
+                    &specification,
+                    false,
+                    Vec::new(),
+                    string[0..length].to_vec(),
+                )
+            }
+            conversion => bail!("Unsupported printf conversion '{}'", conversion as char),
+        };
+        output.extend(field);
+        if output.len() as u64 > MAX_LENGTH {
+            bail!("Formatted output is too large");
+        }
+    }
+    Ok(output)
+}
+
+/// Parse the decimal width or precision starting at `format[*i]`, advancing
+/// `i` past it.
+fn decimal(format: &[u8], i: &mut usize) -> Result<usize> {
+    let mut value: u64 = 0;
+    while let Some(digit) = format.get(*i).filter(|byte| byte.is_ascii_digit()) {
+        value = value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add((digit - b'0') as u64))
+            .ok_or("Width or precision is too large")?;
+        *i += 1;
+    }
+    field_length(value)
+}
+
+/// Check a width or precision is no longer than a model may write.
+fn field_length(length: u64) -> Result<usize> {
+    if length > MAX_LENGTH {
+        bail!("Width or precision 0x{:x} is too large", length);
+    }
+    Ok(length as usize)
+}
+
+fn sign_extend(value: u64, bits: usize) -> i64 {
+    if bits >= 64 {
+        value as i64
+    } else {
+        ((value << (64 - bits)) as i64) >> (64 - bits)
+    }
+}
+
+/// Format an integer with the given prefix, which is a sign or a radix
+/// prefix.
+fn integer(
+    specification: &Specification,
+    prefix: &str,
+    value: u64,
+    radix: u32,
+    upper: bool,
+) -> Vec<u8> {
+    let mut digits = if value == 0 && specification.precision == Some(0) {
+        String::new()
+    } else {
+        match radix {
+            8 => format!("{:o}", value),
+            16 if upper => format!("{:X}", value),
+            16 => format!("{:x}", value),
+            _ => format!("{}", value),
+        }
+    };
+    if let Some(precision) = specification.precision {
+        if digits.len() < precision {
+            digits = "0".repeat(precision - digits.len()) + &digits;
+        }
+    }
+    // The alternate octal form is satisfied by a leading 0 from the
+    // precision.
+    let prefix = if prefix == "0" && digits.starts_with('0') {
+        ""
+    } else {
+        prefix
+    };
+    pad(
+        specification,
+        specification.zero && specification.precision.is_none(),
+        prefix.as_bytes().to_vec(),
+        digits.into_bytes(),
+    )
+}
+
+/// Pad a field to the width of the specification, with zeros placed between
+/// the prefix and the body of the field, or with spaces.
+fn pad(specification: &Specification, zero: bool, prefix: Vec<u8>, body: Vec<u8>) -> Vec<u8> {
+    let length = prefix.len() + body.len();
+    let padding = specification.width.saturating_sub(length);
+    let mut field = Vec::with_capacity(length + padding);
+    if specification.left {
+        field.extend(prefix);
+        field.extend(body);
+        field.resize(length + padding, b' ');
+    } else if zero {
+        field.extend(prefix);
+        field.resize(field.len() + padding, b'0');
+        field.extend(body);
+    } else {
+        field.resize(padding, b' ');
+        field.extend(prefix);
+        field.extend(body);
+    }
+    field
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{Endian, Mips};
+    use crate::executor::libc::Libc;
+    use crate::executor::Memory;
+    use crate::il;
+
+    #[test]
+    fn format() {
+        // Mips passes four arguments in registers, and the rest on the stack.
+        let libc = Libc::new(&Mips::new());
+        let mut state = State::new(Memory::new(Endian::Big));
+        state.set_scalar("$sp", il::const_(0x8000, 32));
+        let arguments = [0x1000, 42, 0x1010, 0xff, 7, 0, 0xffff_fffe, 0, 9];
+        for (n, argument) in arguments.iter().enumerate() {
+            if n < 4 {
+                state.set_scalar(format!("$a{}", n), il::const_(*argument, 32));
+            } else {
+                let address = 0x8000 + 4 * n as u64;
+                state
+                    .memory_mut()
+                    .store(address, il::const_(*argument, 32))
+                    .unwrap();
+            }
+        }
+        for (offset, byte) in b"ab\0".iter().enumerate() {
+            state
+                .memory_mut()
+                .store(0x1010 + offset as u64, il::const_(*byte as u64, 8))
+                .unwrap();
+        }
+
+        let output = super::format(
+            &state,
+            b"%5d|%-4s|%#x|%08.3d|%p|%i|%llx%%",
+            &mut Arguments::new(&libc, 1),
+        )
+        .unwrap();
+        assert_eq!(output, b"   42|ab  |0xff|     007|(nil)|-2|9%".to_vec());
+
+        assert!(super::format(&state, b"%f", &mut Arguments::new(&libc, 1)).is_err());
+    }
+
+    #[test]
+    fn format_bounds() {
+        let libc = Libc::new(&Mips::new());
+        let mut state = State::new(Memory::new(Endian::Big));
+        state.set_scalar("$a1", il::const_(0x7fff_ffff, 32));
+        state.set_scalar("$a2", il::const_(1, 32));
+
+        let format = |state: &State, format: &[u8]| {
+            super::format(state, format, &mut Arguments::new(&libc, 1))
+        };
+
+        // Widths and precisions longer than a model may write are errors,
+        // including those which overflow.
+        assert!(format(&state, b"%268435457d").is_err());
+        assert!(format(&state, b"%.268435457d").is_err());
+        assert!(format(&state, b"%99999999999999999999d").is_err());
+        assert!(format(&state, b"%.99999999999999999999d").is_err());
+        assert!(format(&state, b"%*d").is_err());
+        assert!(format(&state, b"%.*d").is_err());
+
+        state.set_scalar("$a1", il::const_(-8i32 as u32 as u64, 32));
+        assert_eq!(format(&state, b"%*d|").unwrap(), b"1       |".to_vec());
+        assert_eq!(format(&state, b"%.4d").unwrap(), b"-0008".to_vec());
+    }
+}
@@ -0,0 +1,109 @@
+//! An emulated heap.
+
+use crate::error::*;
+use std::collections::BTreeMap;
+
+/// The alignment of allocations made by a `Heap`.
+pub const HEAP_ALIGNMENT: u64 = 16;
+
+/// A first-fit allocator over a fixed region of memory.
+///
+/// The heap only tracks which addresses are allocated. The memory of the
+/// region itself is held in the `State`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Heap {
+    base: u64,
+    size: u64,
+    /// Allocated chunks, mapping their addresses to their sizes in bytes.
+    chunks: BTreeMap<u64, u64>,
+}
+
+impl Heap {
+    /// Create a new `Heap` over `size` bytes from `base`.
+    pub fn new(base: u64, size: u64) -> Heap {
+        Heap {
+            base,
+            size,
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    /// The address of the heap region.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// The size of the heap region in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The allocated chunks, mapping their addresses to their sizes in bytes.
+    pub fn chunks(&self) -> &BTreeMap<u64, u64> {
+        &self.chunks
+    }
+
+    /// The size of the chunk allocated at the given address, if there is one.
+    pub fn chunk_size(&self, address: u64) -> Option<u64> {
+        self.chunks.get(&address).cloned()
+    }
+
+    /// Allocate `size` bytes, returning the address of the allocation, or
+    /// `None` if the heap is exhausted.
+    ///
+    /// Every allocation, including those of 0 bytes, has a unique address.
+    pub fn allocate(&mut self, size: u64) -> Option<u64> {
+        let size = size.max(1);
+        let end = self.base.checked_add(self.size)?;
+        let mut address = self.base;
+        for (&chunk, &chunk_size) in &self.chunks {
+            if chunk.saturating_sub(address) >= size {
+                break;
+            }
+            address = align(chunk.checked_add(chunk_size)?)?;
+        }
+        if address.checked_add(size)? > end {
+            return None;
+        }
+        self.chunks.insert(address, size);
+        Some(address)
+    }
+
+    /// Free the chunk allocated at the given address, returning its size.
+    ///
+    /// Freeing an address which is not allocated, including an address which
+    /// was already freed, is an error.
+    pub fn free(&mut self, address: u64) -> Result<u64> {
+        self.chunks
+            .remove(&address)
+            .ok_or_else(|| format!("Free of unallocated address 0x{:x}", address).into())
+    }
+}
+
+fn align(address: u64) -> Option<u64> {
+    Some(address.checked_add(HEAP_ALIGNMENT - 1)? & !(HEAP_ALIGNMENT - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap() {
+        let mut heap = Heap::new(0x10000, 0x100);
+        assert_eq!(heap.allocate(0x10), Some(0x10000));
+        assert_eq!(heap.allocate(0x11), Some(0x10010));
+        assert_eq!(heap.allocate(0), Some(0x10030));
+        assert_eq!(heap.chunk_size(0x10010), Some(0x11));
+
+        assert_eq!(heap.free(0x10010).unwrap(), 0x11);
+        assert!(heap.free(0x10010).is_err());
+        assert!(heap.free(0x10008).is_err());
+
+        // Freed chunks are reused when large enough.
+        assert_eq!(heap.allocate(0x20), Some(0x10010));
+        assert_eq!(heap.allocate(0x30), Some(0x10040));
+        assert_eq!(heap.allocate(0x91), None);
+        assert_eq!(heap.allocate(0x90), Some(0x10070));
+    }
+}
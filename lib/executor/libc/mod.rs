@@ -0,0 +1,575 @@
+//! Native models of common libc functions.
+//!
+//! Stepping through a real libc is slow, and often reaches intrinsics the
+//! executor cannot execute. `Libc` instead binds address hooks which model
+//! libc functions natively. Models are keyed by symbol name, take their
+//! arguments and give their return values following the architecture's
+//! `CallingConvention`, and return to their caller.
+//!
+//! The memory allocated by the models is tracked by a `Heap`, and files are
+//! read and written in a `Vfs`. Both are held in the `Environment` of the
+//! executor's `State`, so they are copied, snapshotted and restored along
+//! with the rest of the `State`.
+//!
+//! `Libc::install` binds every enabled model which has a symbol. A symbol in
+//! executable memory, such as a function in a loaded libc, is hooked
+//! directly. A symbol in memory which is not executable, such as the GOT
+//! entry of an import which was not linked, is pointed at a stub address
+//! which is hooked instead.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::executor::libc::{Libc, STDOUT};
+//! use falcon::executor::Driver;
+//! use falcon::loader::{ElfLinker, Loader};
+//!
+//! # #[allow(dead_code)]
+//! fn run(mut driver: Driver, elf_linker: &ElfLinker) -> Result<Vec<u8>> {
+//!     let mut libc = Libc::new(driver.architecture());
+//!     // Step through the real strlen.
+//!     libc.set_enabled("strlen", false);
+//!     libc.install(&mut driver, &elf_linker.symbols())?;
+//!
+//!     for _ in 0..10000 {
+//!         driver = driver.step()?;
+//!     }
+//!
+//!     let environment = driver.state().environment().unwrap();
+//!     Ok(environment.vfs().file(STDOUT).unwrap().to_vec())
+//! }
+//! ```
+
+use crate::analysis::calling_convention::{ArgumentType, CallingConvention};
+use crate::architecture::{Architecture, Endian};
+use crate::error::*;
+use crate::executor::{Driver, HookSuccessor, Hooks, State};
+use crate::il;
+use crate::loader::{unversioned_name, Symbol};
+use crate::memory::MemoryPermissions;
+use std::collections::{BTreeMap, BTreeSet};
+
+mod format;
+mod heap;
+mod models;
+mod vfs;
+
+pub use self::heap::*;
+pub use self::vfs::*;
+
+/// The size of the stub hooked in place of each import which was not linked.
+const STUB_SIZE: u64 = 16;
+
+/// The largest number of bytes a model reads or fills in one call.
+const MAX_LENGTH: u64 = 0x1000_0000;
+
+/// The emulated environment of a process, used by the libc models.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Environment {
+    heap: Heap,
+    vfs: Vfs,
+    /// `FILE` pointers, mapped to their file descriptors.
+    streams: BTreeMap<u64, u64>,
+}
+
+impl Environment {
+    /// Create a new `Environment` with the given heap, and a new `Vfs`.
+    pub fn new(heap: Heap) -> Environment {
+        Environment {
+            heap,
+            vfs: Vfs::new(),
+            streams: BTreeMap::new(),
+        }
+    }
+
+    /// The heap models allocate from.
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// A mutable reference to the heap models allocate from.
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    /// The file system models read and write.
+    pub fn vfs(&self) -> &Vfs {
+        &self.vfs
+    }
+
+    /// A mutable reference to the file system models read and write.
+    pub fn vfs_mut(&mut self) -> &mut Vfs {
+        &mut self.vfs
+    }
+
+    /// The file descriptor of the `FILE` at the given address.
+    pub fn stream(&self, address: u64) -> Option<u64> {
+        self.streams.get(&address).cloned()
+    }
+
+    /// Use the given address as a `FILE` for the given file descriptor.
+    pub fn set_stream(&mut self, address: u64, fd: u64) {
+        self.streams.insert(address, fd);
+    }
+
+    /// Stop using the given address as a `FILE`, returning its descriptor.
+    pub fn remove_stream(&mut self, address: u64) -> Option<u64> {
+        self.streams.remove(&address)
+    }
+}
+
+/// Models of libc functions for an architecture.
+#[derive(Clone, Debug)]
+pub struct Libc {
+    calling_convention: CallingConvention,
+    stack_pointer: il::Scalar,
+    word_size: usize,
+    endian: Endian,
+    heap_base: u64,
+    heap_size: u64,
+    disabled: BTreeSet<String>,
+}
+
+impl Libc {
+    /// Create models of libc for the given architecture, with every model
+    /// enabled.
+    pub fn new(architecture: &dyn Architecture) -> Libc {
+        let (heap_base, heap_size) = if architecture.word_size() == 64 {
+            (0x7e00_0000_0000, 0x1_0000_0000)
+        } else {
+            (0x7000_0000, 0x0800_0000)
+        };
+        Libc {
+            calling_convention: architecture.calling_convention(),
+            stack_pointer: architecture.stack_pointer(),
+            word_size: architecture.word_size(),
+            endian: architecture.endian(),
+            heap_base,
+            heap_size,
+            disabled: BTreeSet::new(),
+        }
+    }
+
+    /// The names of every function modelled.
+    pub fn models() -> Vec<&'static str> {
+        models::MODELS.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Enable or disable the model of the function with the given name.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(name);
+        } else {
+            self.disabled.insert(name.to_string());
+        }
+    }
+
+    /// Returns true if a model of the function with the given name exists,
+    /// and is enabled.
+    pub fn enabled(&self, name: &str) -> bool {
+        !self.disabled.contains(name) && models::model(name).is_some()
+    }
+
+    /// Set the region of memory the heap allocates from.
+    ///
+    /// The stubs for imports which were not linked are placed immediately
+    /// after the heap.
+    pub fn set_heap(&mut self, base: u64, size: u64) {
+        self.heap_base = base;
+        self.heap_size = size;
+    }
+
+    /// The address of the region of memory the heap allocates from.
+    pub fn heap_base(&self) -> u64 {
+        self.heap_base
+    }
+
+    /// The size of the region of memory the heap allocates from.
+    pub fn heap_size(&self) -> u64 {
+        self.heap_size
+    }
+
+    /// The calling convention models follow.
+    pub fn calling_convention(&self) -> &CallingConvention {
+        &self.calling_convention
+    }
+
+    /// The size of a natural word of the architecture in bits.
+    pub fn word_size(&self) -> usize {
+        self.word_size
+    }
+
+    /// Create a new `Environment` for these models.
+    pub fn environment(&self) -> Environment {
+        Environment::new(Heap::new(self.heap_base, self.heap_size))
+    }
+
+    /// Hook the given address with the model of the function with the given
+    /// name, returning false if there is no enabled model with that name.
+    pub fn bind(&self, hooks: &mut Hooks, address: u64, name: &str) -> bool {
+        if !self.enabled(name) {
+            return false;
+        }
+        let model = models::model(name).expect("Failed to get enabled model");
+        let libc = self.clone();
+        hooks.set_address(address, move |state, _| {
+            let value = model(&libc, state)?;
+            libc.set_return(state, value);
+            Ok(HookSuccessor::Return)
+        });
+        true
+    }
+
+    /// Bind the models of every symbol with an enabled model to the given
+    /// driver, returning the symbols bound.
+    ///
+    /// An `Environment` is given to the driver's `State` if it does not have
+    /// one. The `stdin`, `stdout` and `stderr` streams are given `FILE`
+    /// pointers from their symbols.
+    pub fn install(&self, driver: &mut Driver, symbols: &[Symbol]) -> Result<Vec<Symbol>> {
+        if driver.state().environment().is_none() {
+            driver.state_mut().set_environment(Some(self.environment()));
+        }
+
+        let mut bound = Vec::new();
+        let mut stubs: BTreeMap<&str, u64> = BTreeMap::new();
+        let mut next_stub = self.heap_base.wrapping_add(self.heap_size);
+        for symbol in symbols {
+            let name = unversioned_name(symbol.name());
+
+            let fd = match name {
+                "stdin" | "_IO_2_1_stdin_" => Some(0),
+                "stdout" | "_IO_2_1_stdout_" => Some(1),
+                "stderr" | "_IO_2_1_stderr_" => Some(2),
+                _ => None,
+            };
+            if let Some(fd) = fd {
+                self.install_stream(driver.state_mut(), symbol, fd)?;
+                continue;
+            }
+
+            if !self.enabled(name) {
+                continue;
+            }
+
+            let executable = driver
+                .state()
+                .memory()
+                .permissions(symbol.address())
+                .map(|permissions| permissions.contains(MemoryPermissions::EXECUTE))
+                .unwrap_or(false);
+            let address = if executable {
+                symbol.address()
+            } else {
+                let stub = *stubs.entry(name).or_insert_with(|| {
+                    let stub = next_stub;
+                    next_stub = next_stub.wrapping_add(STUB_SIZE);
+                    stub
+                });
+                driver
+                    .state_mut()
+                    .memory_mut()
+                    .store(symbol.address(), il::const_(stub, self.word_size))?;
+                stub
+            };
+            self.bind(driver.hooks_mut(), address, name);
+            bound.push(symbol.clone());
+        }
+        Ok(bound)
+    }
+
+    /// Give the stream with the given descriptor a `FILE` pointer from the
+    /// given symbol.
+    ///
+    /// `_IO_2_1_*` symbols are the `FILE`s themselves. `stdin`, `stdout` and
+    /// `stderr` are pointers to `FILE`s, which are allocated if the pointer
+    /// is not set.
+    fn install_stream(&self, state: &mut State, symbol: &Symbol, fd: u64) -> Result<()> {
+        let address = if symbol.name().starts_with("_IO_2_1_") {
+            symbol.address()
+        } else {
+            let pointer = state
+                .memory()
+                .load(symbol.address(), self.word_size)?
+                .and_then(|pointer| pointer.value_u64())
+                .unwrap_or(0);
+            if pointer != 0 {
+                pointer
+            } else {
+                let file = allocate(state, 0x100)?;
+                state
+                    .memory_mut()
+                    .store(symbol.address(), il::const_(file, self.word_size))?;
+                file
+            }
+        };
+        environment(state)?.set_stream(address, fd);
+        Ok(())
+    }
+
+    /// Get the value of the given argument, counting from 0, at entry to a
+    /// function.
+    pub fn argument(&self, state: &State, n: usize) -> Result<u64> {
+        match self.calling_convention.argument_type(n) {
+            ArgumentType::Register(scalar) => state
+                .get_scalar(scalar.name())
+                .ok_or_else(|| format!("Argument register {} is not set", scalar))?
+                .value_u64()
+                .ok_or_else(|| ErrorKind::TooManyAddressBits.into()),
+            ArgumentType::Stack(offset) => {
+                let stack_pointer = state
+                    .get_scalar(self.stack_pointer.name())
+                    .ok_or_else(|| format!("Stack pointer {} is not set", self.stack_pointer))?
+                    .value_u64()
+                    .ok_or(ErrorKind::TooManyAddressBits)?;
+                let address = stack_pointer.wrapping_add(offset as u64);
+                state
+                    .memory()
+                    .load(address, self.word_size)?
+                    .ok_or(ErrorKind::AccessUnmappedMemory(address))?
+                    .value_u64()
+                    .ok_or_else(|| ErrorKind::TooManyAddressBits.into())
+            }
+        }
+    }
+
+    /// Set the value returned by a function, truncated to the return
+    /// register.
+    pub fn set_return(&self, state: &mut State, value: u64) {
+        let register = self.calling_convention.return_register();
+        let value = if register.bits() < 64 {
+            value & ((1 << register.bits()) - 1)
+        } else {
+            value
+        };
+        state.set_scalar(register.name(), il::const_(value, register.bits()));
+    }
+
+    /// The value -1, as returned in the return register.
+    fn error(&self) -> u64 {
+        u64::MAX
+    }
+}
+
+/// The successive arguments of a variadic function.
+pub(crate) struct Arguments<'l> {
+    libc: &'l Libc,
+    next: usize,
+}
+
+impl<'l> Arguments<'l> {
+    /// Take arguments from the argument with the given number, counting from
+    /// 0.
+    pub(crate) fn new(libc: &'l Libc, first: usize) -> Arguments<'l> {
+        Arguments { libc, next: first }
+    }
+
+    pub(crate) fn libc(&self) -> &Libc {
+        self.libc
+    }
+
+    /// Take the next argument, which is a word.
+    pub(crate) fn next(&mut self, state: &State) -> Result<u64> {
+        let value = self.libc.argument(state, self.next)?;
+        self.next += 1;
+        Ok(value)
+    }
+
+    /// Take the next argument, which is an integer of the given size.
+    ///
+    /// Integers larger than a word are passed in two consecutive arguments,
+    /// the most significant first on big-endian architectures.
+    pub(crate) fn integer(&mut self, state: &State, bits: usize) -> Result<u64> {
+        if bits <= self.libc.word_size {
+            return self.next(state);
+        }
+        let first = self.next(state)?;
+        let second = self.next(state)?;
+        let (high, low) = match self.libc.endian {
+            Endian::Big => (first, second),
+            Endian::Little => (second, first),
+        };
+        Ok((high << self.libc.word_size) | low)
+    }
+}
+
+/// Get the `Environment` of the given state.
+fn environment(state: &mut State) -> Result<&mut Environment> {
+    state
+        .environment_mut()
+        .ok_or_else(|| "State has no libc environment".into())
+}
+
+/// Allocate `size` bytes on the heap of the given state's environment,
+/// making the memory allocated readable and writable.
+fn allocate(state: &mut State, size: u64) -> Result<u64> {
+    let address = environment(state)?
+        .heap_mut()
+        .allocate(size)
+        .ok_or("Heap exhausted")?;
+    state.memory_mut().set_permissions(
+        address,
+        size.max(1),
+        MemoryPermissions::READ | MemoryPermissions::WRITE,
+    );
+    Ok(address)
+}
+
+fn read_byte(state: &State, address: u64) -> Result<u8> {
+    Ok(state
+        .memory()
+        .load(address, 8)?
+        .ok_or(ErrorKind::AccessUnmappedMemory(address))?
+        .value_u64()
+        .ok_or(ErrorKind::TooManyAddressBits)? as u8)
+}
+
+/// Read `length` bytes from memory.
+fn read_bytes(state: &State, address: u64, length: u64) -> Result<Vec<u8>> {
+    if length > MAX_LENGTH {
+        bail!(
+            "Read of 0x{:x} bytes at 0x{:x} is too large",
+            length,
+            address
+        );
+    }
+    (0..length)
+        .map(|offset| read_byte(state, address.wrapping_add(offset)))
+        .collect()
+}
+
+/// Read a NUL-terminated string from memory, without its terminator,
+/// reading no more than `limit` bytes if a limit is given.
+pub(crate) fn read_string(state: &State, address: u64, limit: Option<usize>) -> Result<Vec<u8>> {
+    let mut string = Vec::new();
+    while limit.is_none_or(|limit| string.len() < limit) {
+        let byte = read_byte(state, address.wrapping_add(string.len() as u64))?;
+        if byte == 0 {
+            break;
+        }
+        string.push(byte);
+    }
+    Ok(string)
+}
+
+/// Fill `length` bytes of memory with the given byte, a word at a time.
+fn fill_bytes(state: &mut State, address: u64, length: u64, byte: u8) -> Result<()> {
+    if length > MAX_LENGTH {
+        bail!(
+            "Fill of 0x{:x} bytes at 0x{:x} is too large",
+            length,
+            address
+        );
+    }
+    let word = u64::from_ne_bytes([byte; 8]);
+    let mut offset = 0;
+    while offset < length {
+        let address = address.wrapping_add(offset);
+        if length - offset >= 8 {
+            state.memory_mut().store(address, il::const_(word, 64))?;
+            offset += 8;
+        } else {
+            state
+                .memory_mut()
+                .store(address, il::const_(byte as u64, 8))?;
+            offset += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Write bytes to memory.
+fn write_bytes(state: &mut State, address: u64, bytes: &[u8]) -> Result<()> {
+    for (offset, byte) in bytes.iter().enumerate() {
+        state.memory_mut().store(
+            address.wrapping_add(offset as u64),
+            il::const_(*byte as u64, 8),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::Amd64;
+    use crate::executor::Memory;
+    use crate::RC;
+
+    // A function at 0x1000 which calls puts through its GOT entry at 0x3000,
+    // and then does nothing.
+    fn driver() -> Driver {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+        let block_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.load(il::scalar("rax", 64), il::expr_const(0x3000, 64));
+            block.branch(il::expr_scalar("rax", 64));
+            block.nop();
+            for (instruction, address) in block
+                .instructions_mut()
+                .iter_mut()
+                .zip([0x1000, 0x1004, 0x1006].iter())
+            {
+                instruction.set_address(Some(*address));
+            }
+            block.index()
+        };
+        control_flow_graph.set_entry(block_index).unwrap();
+
+        let mut program = il::Program::new();
+        program.add_function(il::Function::new(0x1000, control_flow_graph));
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+
+        let mut state = State::new(Memory::new(Endian::Little));
+        state.set_scalar("rdi", il::const_(0x2000, 64));
+        // The call pushes its return address.
+        state.set_scalar("rsp", il::const_(0x7ff8, 64));
+        state
+            .memory_mut()
+            .store(0x7ff8, il::const_(0x1006, 64))
+            .unwrap();
+        write_bytes(&mut state, 0x2000, b"hi\0").unwrap();
+        state.memory_mut().store(0x3000, il::const_(0, 64)).unwrap();
+
+        Driver::new(RC::new(program), location, state, RC::new(Amd64::new()))
+    }
+
+    #[test]
+    fn install() {
+        let mut driver = driver();
+        let libc = Libc::new(driver.architecture());
+        let symbols = vec![
+            Symbol::new("puts@GLIBC_2.2.5", 0x3000),
+            Symbol::new("not_modelled", 0x3008),
+            Symbol::new("__printf_chk@GLIBC_2.3.4", 0x3010),
+            Symbol::new("_IO_2_1_stdout_@@GLIBC_2.2.5", 0x5000),
+        ];
+        let bound = libc.install(&mut driver, &symbols).unwrap();
+        assert_eq!(bound, vec![symbols[0].clone(), symbols[2].clone()]);
+        let environment = driver.state().environment().unwrap();
+        assert_eq!(environment.stream(0x5000), Some(1));
+
+        // The GOT entry points at a stub after the heap.
+        let stub = libc.heap_base() + libc.heap_size();
+        assert_eq!(
+            driver.state().memory().load(0x3000, 64).unwrap(),
+            Some(il::const_(stub, 64))
+        );
+
+        let driver = driver.step().unwrap().step().unwrap();
+        assert_eq!(driver.address(), Some(0x1006));
+        assert_eq!(driver.state().get_scalar("rax"), Some(&il::const_(3, 64)));
+        assert_eq!(
+            driver.state().get_scalar("rsp"),
+            Some(&il::const_(0x8000, 64))
+        );
+        let environment = driver.state().environment().unwrap();
+        assert_eq!(environment.vfs().file(STDOUT), Some(&b"hi\n"[..]));
+
+        let mut libc = libc;
+        libc.set_enabled("puts", false);
+        assert!(!libc.enabled("puts"));
+        assert!(!libc.bind(&mut Hooks::new(), 0x4000, "puts"));
+    }
+}
@@ -0,0 +1,556 @@
+//! The models of libc functions.
+
+use crate::analysis::calling_convention::CallingConventionType;
+use crate::executor::libc::format::format;
+use crate::executor::libc::*;
+
+/// A model of a libc function, returning the value the function returns.
+pub(crate) type Model = fn(&Libc, &mut State) -> Result<u64>;
+
+/// Every model, by the name of the function modelled.
+pub(crate) const MODELS: &[(&str, Model)] = &[
+    ("malloc", malloc),
+    ("calloc", calloc),
+    ("realloc", realloc),
+    ("free", free),
+    ("memcpy", memcpy),
+    ("memmove", memcpy),
+    ("memset", memset),
+    ("strlen", strlen),
+    ("strcmp", strcmp),
+    ("strncmp", strncmp),
+    ("strcpy", strcpy),
+    ("puts", puts),
+    ("printf", printf),
+    ("__printf_chk", printf_chk),
+    ("fprintf", fprintf),
+    ("__fprintf_chk", fprintf_chk),
+    ("sprintf", sprintf),
+    ("snprintf", snprintf),
+    ("fputs", fputs),
+    ("open", open),
+    ("read", read),
+    ("write", write),
+    ("close", close),
+    ("fopen", fopen),
+    ("fread", fread),
+    ("fwrite", fwrite),
+    ("fclose", fclose),
+];
+
+/// Get the model of the function with the given name.
+pub(crate) fn model(name: &str) -> Option<Model> {
+    MODELS
+        .iter()
+        .find(|(model, _)| *model == name)
+        .map(|(_, model)| *model)
+}
+
+fn malloc(libc: &Libc, state: &mut State) -> Result<u64> {
+    let size = libc.argument(state, 0)?;
+    Ok(allocate(state, size).unwrap_or(0))
+}
+
+fn calloc(libc: &Libc, state: &mut State) -> Result<u64> {
+    let size = match libc
+        .argument(state, 0)?
+        .checked_mul(libc.argument(state, 1)?)
+    {
+        Some(size) if size <= MAX_LENGTH => size,
+        _ => return Ok(0),
+    };
+    let address = match allocate(state, size) {
+        Ok(address) => address,
+        Err(_) => return Ok(0),
+    };
+    // Chunks may be reused, so are zeroed rather than assumed zero.
+    fill_bytes(state, address, size, 0)?;
+    Ok(address)
+}
+
+fn realloc(libc: &Libc, state: &mut State) -> Result<u64> {
+    let address = libc.argument(state, 0)?;
+    let size = libc.argument(state, 1)?;
+    if address == 0 {
+        return Ok(allocate(state, size).unwrap_or(0));
+    }
+    let old_size = environment(state)?
+        .heap()
+        .chunk_size(address)
+        .ok_or_else(|| format!("Realloc of unallocated address 0x{:x}", address))?;
+    if size == 0 {
+        environment(state)?.heap_mut().free(address)?;
+        return Ok(0);
+    }
+    let new_address = match allocate(state, size) {
+        Ok(new_address) => new_address,
+        Err(_) => return Ok(0),
+    };
+    let bytes = read_bytes(state, address, old_size.min(size))?;
+    write_bytes(state, new_address, &bytes)?;
+    environment(state)?.heap_mut().free(address)?;
+    Ok(new_address)
+}
+
+fn free(libc: &Libc, state: &mut State) -> Result<u64> {
+    let address = libc.argument(state, 0)?;
+    if address != 0 {
+        environment(state)?.heap_mut().free(address)?;
+    }
+    Ok(0)
+}
+
+/// Also models memmove, as the source is read before the destination is
+/// written.
+fn memcpy(libc: &Libc, state: &mut State) -> Result<u64> {
+    let destination = libc.argument(state, 0)?;
+    let source = libc.argument(state, 1)?;
+    let length = libc.argument(state, 2)?;
+    let bytes = read_bytes(state, source, length)?;
+    write_bytes(state, destination, &bytes)?;
+    Ok(destination)
+}
+
+fn memset(libc: &Libc, state: &mut State) -> Result<u64> {
+    let destination = libc.argument(state, 0)?;
+    let byte = libc.argument(state, 1)? as u8;
+    let length = libc.argument(state, 2)?;
+    fill_bytes(state, destination, length, byte)?;
+    Ok(destination)
+}
+
+fn strlen(libc: &Libc, state: &mut State) -> Result<u64> {
+    let string = libc.argument(state, 0)?;
+    Ok(read_string(state, string, None)?.len() as u64)
+}
+
+/// Compare strings as unsigned characters, up to the given number of
+/// characters, returning -1, 0 or 1.
+fn compare(libc: &Libc, state: &State, limit: Option<usize>) -> Result<u64> {
+    let lhs = read_string(state, libc.argument(state, 0)?, limit)?;
+    let rhs = read_string(state, libc.argument(state, 1)?, limit)?;
+    Ok(match lhs.cmp(&rhs) {
+        std::cmp::Ordering::Less => libc.error(),
+        std::cmp::Ordering::Equal => 0,
+        std::cmp::Ordering::Greater => 1,
+    })
+}
+
+fn strcmp(libc: &Libc, state: &mut State) -> Result<u64> {
+    compare(libc, state, None)
+}
+
+fn strncmp(libc: &Libc, state: &mut State) -> Result<u64> {
+    let limit = libc.argument(state, 2)? as usize;
+    compare(libc, state, Some(limit))
+}
+
+fn strcpy(libc: &Libc, state: &mut State) -> Result<u64> {
+    let destination = libc.argument(state, 0)?;
+    let mut string = read_string(state, libc.argument(state, 1)?, None)?;
+    string.push(0);
+    write_bytes(state, destination, &string)?;
+    Ok(destination)
+}
+
+/// Write bytes to the given descriptor, returning the number of bytes
+/// written, or -1.
+fn write_fd(libc: &Libc, state: &mut State, fd: u64, bytes: &[u8]) -> Result<u64> {
+    Ok(environment(state)?
+        .vfs_mut()
+        .write(fd, bytes)
+        .map(|written| written as u64)
+        .unwrap_or_else(|| libc.error()))
+}
+
+/// Write bytes to the given `FILE`, returning the number of bytes written,
+/// or -1.
+fn write_stream(libc: &Libc, state: &mut State, stream: u64, bytes: &[u8]) -> Result<u64> {
+    match environment(state)?.stream(stream) {
+        Some(fd) => write_fd(libc, state, fd, bytes),
+        None => Ok(libc.error()),
+    }
+}
+
+fn puts(libc: &Libc, state: &mut State) -> Result<u64> {
+    let mut string = read_string(state, libc.argument(state, 0)?, None)?;
+    string.push(b'\n');
+    write_fd(libc, state, 1, &string)
+}
+
+fn fputs(libc: &Libc, state: &mut State) -> Result<u64> {
+    let string = read_string(state, libc.argument(state, 0)?, None)?;
+    let stream = libc.argument(state, 1)?;
+    write_stream(libc, state, stream, &string)
+}
+
+/// Format the format string in the given argument, with the arguments
+/// following it.
+fn format_argument(libc: &Libc, state: &State, n: usize) -> Result<Vec<u8>> {
+    let string = read_string(state, libc.argument(state, n)?, None)?;
+    format(state, &string, &mut Arguments::new(libc, n + 1))
+}
+
+fn printf(libc: &Libc, state: &mut State) -> Result<u64> {
+    let output = format_argument(libc, state, 0)?;
+    write_fd(libc, state, 1, &output)
+}
+
+fn printf_chk(libc: &Libc, state: &mut State) -> Result<u64> {
+    let output = format_argument(libc, state, 1)?;
+    write_fd(libc, state, 1, &output)
+}
+
+fn fprintf(libc: &Libc, state: &mut State) -> Result<u64> {
+    let stream = libc.argument(state, 0)?;
+    let output = format_argument(libc, state, 1)?;
+    write_stream(libc, state, stream, &output)
+}
+
+fn fprintf_chk(libc: &Libc, state: &mut State) -> Result<u64> {
+    let stream = libc.argument(state, 0)?;
+    let output = format_argument(libc, state, 2)?;
+    write_stream(libc, state, stream, &output)
+}
+
+fn sprintf(libc: &Libc, state: &mut State) -> Result<u64> {
+    let destination = libc.argument(state, 0)?;
+    let mut output = format_argument(libc, state, 1)?;
+    let length = output.len() as u64;
+    output.push(0);
+    write_bytes(state, destination, &output)?;
+    Ok(length)
+}
+
+fn snprintf(libc: &Libc, state: &mut State) -> Result<u64> {
+    let destination = libc.argument(state, 0)?;
+    let size = libc.argument(state, 1)?;
+    let mut output = format_argument(libc, state, 2)?;
+    let length = output.len() as u64;
+    if size > 0 {
+        output.truncate((size - 1) as usize);
+        output.push(0);
+        write_bytes(state, destination, &output)?;
+    }
+    Ok(length)
+}
+
+/// Translate the flags of `open` to `OpenFlags`.
+fn open_flags(libc: &Libc, flags: u64) -> OpenFlags {
+    let mut open_flags = match flags & 3 {
+        0 => OpenFlags::READ,
+        1 => OpenFlags::WRITE,
+        _ => OpenFlags::READ | OpenFlags::WRITE,
+    };
+    let (create, truncate, append) = match libc.calling_convention().type_() {
+        CallingConventionType::MipsSystemV | CallingConventionType::MipselSystemV => {
+            (0x100, 0x200, 0x8)
+        }
+        _ => (0x40, 0x200, 0x400),
+    };
+    if flags & create != 0 {
+        open_flags |= OpenFlags::CREATE;
+    }
+    if flags & truncate != 0 {
+        open_flags |= OpenFlags::TRUNCATE;
+    }
+    if flags & append != 0 {
+        open_flags |= OpenFlags::APPEND;
+    }
+    open_flags
+}
+
+fn open(libc: &Libc, state: &mut State) -> Result<u64> {
+    let path = read_string(state, libc.argument(state, 0)?, None)?;
+    let flags = open_flags(libc, libc.argument(state, 1)?);
+    Ok(environment(state)?
+        .vfs_mut()
+        .open(&String::from_utf8_lossy(&path), flags)
+        .unwrap_or_else(|| libc.error()))
+}
+
+fn read(libc: &Libc, state: &mut State) -> Result<u64> {
+    let fd = libc.argument(state, 0)?;
+    let buffer = libc.argument(state, 1)?;
+    let length = libc.argument(state, 2)?;
+    match environment(state)?.vfs_mut().read(fd, length as usize) {
+        Some(bytes) => {
+            write_bytes(state, buffer, &bytes)?;
+            Ok(bytes.len() as u64)
+        }
+        None => Ok(libc.error()),
+    }
+}
+
+fn write(libc: &Libc, state: &mut State) -> Result<u64> {
+    let fd = libc.argument(state, 0)?;
+    let bytes = read_bytes(state, libc.argument(state, 1)?, libc.argument(state, 2)?)?;
+    write_fd(libc, state, fd, &bytes)
+}
+
+fn close(libc: &Libc, state: &mut State) -> Result<u64> {
+    let fd = libc.argument(state, 0)?;
+    Ok(if environment(state)?.vfs_mut().close(fd) {
+        0
+    } else {
+        libc.error()
+    })
+}
+
+fn fopen(libc: &Libc, state: &mut State) -> Result<u64> {
+    let path = read_string(state, libc.argument(state, 0)?, None)?;
+    let mode = read_string(state, libc.argument(state, 1)?, None)?;
+    let mut flags = match mode.first() {
+        Some(b'r') => OpenFlags::READ,
+        Some(b'w') => OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+        Some(b'a') => OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::APPEND,
+        _ => return Ok(0),
+    };
+    if mode.contains(&b'+') {
+        flags |= OpenFlags::READ | OpenFlags::WRITE;
+    }
+    let fd = match environment(state)?
+        .vfs_mut()
+        .open(&String::from_utf8_lossy(&path), flags)
+    {
+        Some(fd) => fd,
+        None => return Ok(0),
+    };
+    let stream = allocate(state, 0x100)?;
+    environment(state)?.set_stream(stream, fd);
+    Ok(stream)
+}
+
+fn fread(libc: &Libc, state: &mut State) -> Result<u64> {
+    let buffer = libc.argument(state, 0)?;
+    let size = libc.argument(state, 1)?;
+    let count = libc.argument(state, 2)?;
+    let stream = libc.argument(state, 3)?;
+    let length = size.checked_mul(count).ok_or("fread length overflows")?;
+    if length == 0 {
+        return Ok(0);
+    }
+    let environment = environment(state)?;
+    let bytes = match environment.stream(stream) {
+        Some(fd) => environment
+            .vfs_mut()
+            .read(fd, length as usize)
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+    write_bytes(state, buffer, &bytes)?;
+    Ok(bytes.len() as u64 / size)
+}
+
+fn fwrite(libc: &Libc, state: &mut State) -> Result<u64> {
+    let buffer = libc.argument(state, 0)?;
+    let size = libc.argument(state, 1)?;
+    let count = libc.argument(state, 2)?;
+    let stream = libc.argument(state, 3)?;
+    let length = size.checked_mul(count).ok_or("fwrite length overflows")?;
+    if length == 0 {
+        return Ok(0);
+    }
+    let bytes = read_bytes(state, buffer, length)?;
+    let written = write_stream(libc, state, stream, &bytes)?;
+    Ok(if written == libc.error() {
+        0
+    } else {
+        written / size
+    })
+}
+
+fn fclose(libc: &Libc, state: &mut State) -> Result<u64> {
+    let stream = libc.argument(state, 0)?;
+    let environment = environment(state)?;
+    let fd = match environment.remove_stream(stream) {
+        Some(fd) => fd,
+        None => return Ok(libc.error()),
+    };
+    environment.vfs_mut().close(fd);
+    if environment.heap().chunk_size(stream).is_some() {
+        environment.heap_mut().free(stream)?;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{Amd64, Endian, Mips};
+    use crate::executor::Memory;
+
+    const ARGUMENTS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
+    fn state(libc: &Libc) -> State {
+        let mut state = State::new(Memory::new(Endian::Little));
+        state.set_environment(Some(libc.environment()));
+        state
+    }
+
+    fn call(libc: &Libc, state: &mut State, name: &str, arguments: &[u64]) -> u64 {
+        for (register, argument) in ARGUMENTS.iter().zip(arguments) {
+            state.set_scalar(*register, il::const_(*argument, 64));
+        }
+        model(name).unwrap()(libc, state).unwrap()
+    }
+
+    fn string(state: &mut State, address: u64, string: &str) -> u64 {
+        write_bytes(state, address, string.as_bytes()).unwrap();
+        write_bytes(state, address + string.len() as u64, &[0]).unwrap();
+        address
+    }
+
+    #[test]
+    fn heap() {
+        let libc = Libc::new(&Amd64::new());
+        let mut state = state(&libc);
+        let base = libc.heap_base();
+
+        let a = call(&libc, &mut state, "malloc", &[4]);
+        assert_eq!(a, base);
+        write_bytes(&mut state, a, b"abcd").unwrap();
+
+        let b = call(&libc, &mut state, "calloc", &[2, 8]);
+        assert_eq!(b, base + 0x10);
+        assert_eq!(read_bytes(&state, b, 16).unwrap(), vec![0; 16]);
+        assert_eq!(call(&libc, &mut state, "calloc", &[u64::MAX, 2]), 0);
+        // Allocations too large to zero fail.
+        assert_eq!(call(&libc, &mut state, "calloc", &[0x1000, 0x10_0000]), 0);
+        assert_eq!(environment(&mut state).unwrap().heap().chunks().len(), 2);
+
+        let c = call(&libc, &mut state, "realloc", &[a, 0x20]);
+        assert_eq!(c, base + 0x20);
+        assert_eq!(read_bytes(&state, c, 4).unwrap(), b"abcd".to_vec());
+        assert_eq!(environment(&mut state).unwrap().heap().chunk_size(a), None);
+
+        assert_eq!(call(&libc, &mut state, "free", &[0]), 0);
+        call(&libc, &mut state, "free", &[b]);
+        assert!(model("free").unwrap()(&libc, &mut state).is_err());
+
+        // A chunk reused by calloc is zeroed.
+        write_bytes(&mut state, c, &[0xff; 0x20]).unwrap();
+        call(&libc, &mut state, "free", &[c]);
+        let d = call(&libc, &mut state, "calloc", &[1, 0x33]);
+        assert_eq!(d, base);
+        assert_eq!(read_bytes(&state, d, 0x33).unwrap(), vec![0; 0x33]);
+    }
+
+    #[test]
+    fn strings() {
+        let libc = Libc::new(&Amd64::new());
+        let mut state = state(&libc);
+        let hello = string(&mut state, 0x1000, "hello");
+        let help = string(&mut state, 0x1100, "help");
+
+        assert_eq!(call(&libc, &mut state, "strlen", &[hello]), 5);
+        assert_eq!(call(&libc, &mut state, "strcmp", &[hello, hello]), 0);
+        assert_eq!(call(&libc, &mut state, "strcmp", &[hello, help]), u64::MAX);
+        assert_eq!(call(&libc, &mut state, "strcmp", &[help, hello]), 1);
+        assert_eq!(call(&libc, &mut state, "strncmp", &[hello, help, 3]), 0);
+
+        assert_eq!(
+            call(&libc, &mut state, "memset", &[0x2000, 0x41, 3]),
+            0x2000
+        );
+        let memset = [0x2000, 0x41, u64::MAX];
+        for (register, argument) in ARGUMENTS.iter().zip(memset.iter()) {
+            state.set_scalar(*register, il::const_(*argument, 64));
+        }
+        assert!(model("memset").unwrap()(&libc, &mut state).is_err());
+        call(&libc, &mut state, "memcpy", &[0x2003, hello, 6]);
+        assert_eq!(read_string(&state, 0x2000, None).unwrap(), b"AAAhello");
+        call(&libc, &mut state, "strcpy", &[0x2000, help]);
+        assert_eq!(read_string(&state, 0x2000, None).unwrap(), b"help");
+    }
+
+    #[test]
+    fn formatting() {
+        let libc = Libc::new(&Amd64::new());
+        let mut state = state(&libc);
+        let format = string(&mut state, 0x1000, "%s=%d\n");
+        let name = string(&mut state, 0x1100, "x");
+
+        assert_eq!(
+            call(&libc, &mut state, "printf", &[format, name, (-3i64) as u64]),
+            5
+        );
+        assert_eq!(call(&libc, &mut state, "puts", &[name]), 2);
+        let vfs = environment(&mut state).unwrap().vfs();
+        assert_eq!(vfs.file(STDOUT), Some(&b"x=-3\nx\n"[..]));
+
+        assert_eq!(
+            call(
+                &libc,
+                &mut state,
+                "snprintf",
+                &[0x2000, 4, format, name, (-3i64) as u64]
+            ),
+            5
+        );
+        assert_eq!(read_string(&state, 0x2000, None).unwrap(), b"x=-");
+    }
+
+    #[test]
+    fn files() {
+        let libc = Libc::new(&Amd64::new());
+        let mut state = state(&libc);
+        environment(&mut state)
+            .unwrap()
+            .vfs_mut()
+            .set_file("/in", b"input".to_vec());
+        let input = string(&mut state, 0x1000, "/in");
+        let output = string(&mut state, 0x1010, "/out");
+        let read_mode = string(&mut state, 0x1020, "rb");
+        let write_mode = string(&mut state, 0x1030, "w");
+
+        let stream = call(&libc, &mut state, "fopen", &[input, read_mode]);
+        assert_ne!(stream, 0);
+        assert_eq!(call(&libc, &mut state, "fread", &[0x2000, 1, 8, stream]), 5);
+        assert_eq!(read_bytes(&state, 0x2000, 5).unwrap(), b"input".to_vec());
+        assert_eq!(call(&libc, &mut state, "fclose", &[stream]), 0);
+        assert_eq!(call(&libc, &mut state, "fclose", &[stream]), u64::MAX);
+
+        let stream = call(&libc, &mut state, "fopen", &[output, write_mode]);
+        assert_eq!(
+            call(&libc, &mut state, "fwrite", &[0x2000, 2, 2, stream]),
+            2
+        );
+        call(&libc, &mut state, "fclose", &[stream]);
+
+        // O_WRONLY | O_APPEND
+        let fd = call(&libc, &mut state, "open", &[output, 0x401]);
+        assert_eq!(fd, 3);
+        assert_eq!(call(&libc, &mut state, "write", &[fd, 0x2004, 1]), 1);
+        assert_eq!(call(&libc, &mut state, "read", &[fd, 0x2000, 1]), u64::MAX);
+        assert_eq!(call(&libc, &mut state, "close", &[fd]), 0);
+        let vfs = environment(&mut state).unwrap().vfs();
+        assert_eq!(vfs.file("/out"), Some(&b"input"[..]));
+        let missing = string(&mut state, 0x1040, "/missing");
+        assert_eq!(call(&libc, &mut state, "open", &[missing, 0]), u64::MAX);
+    }
+
+    #[test]
+    fn mips_open_flags() {
+        // O_WRONLY | O_CREAT | O_TRUNC, and O_RDWR | O_APPEND.
+        let amd64 = Libc::new(&Amd64::new());
+        assert_eq!(
+            open_flags(&amd64, 0x241),
+            OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE
+        );
+        assert_eq!(
+            open_flags(&amd64, 0x402),
+            OpenFlags::READ | OpenFlags::WRITE | OpenFlags::APPEND
+        );
+
+        let mips = Libc::new(&Mips::new());
+        assert_eq!(
+            open_flags(&mips, 0x301),
+            OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE
+        );
+        assert_eq!(
+            open_flags(&mips, 0xa),
+            OpenFlags::READ | OpenFlags::WRITE | OpenFlags::APPEND
+        );
+    }
+}
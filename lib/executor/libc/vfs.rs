@@ -0,0 +1,233 @@
+//! A virtual file system.
+
+use crate::RC;
+use std::collections::BTreeMap;
+
+/// The path of the file read through standard input.
+pub const STDIN: &str = "/dev/stdin";
+/// The path of the file written through standard output.
+pub const STDOUT: &str = "/dev/stdout";
+/// The path of the file written through standard error.
+pub const STDERR: &str = "/dev/stderr";
+
+bitflags! {
+    /// How a file is opened in a `Vfs`.
+    #[derive(Deserialize, Serialize)]
+    pub struct OpenFlags: u32 {
+        /// The file may be read.
+        const READ     = 0b00001;
+        /// The file may be written.
+        const WRITE    = 0b00010;
+        /// The file is created if it does not exist.
+        const CREATE   = 0b00100;
+        /// The file is truncated when opened.
+        const TRUNCATE = 0b01000;
+        /// Writes are made to the end of the file.
+        const APPEND   = 0b10000;
+    }
+}
+
+/// An open file in a `Vfs`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Descriptor {
+    path: String,
+    offset: u64,
+    flags: OpenFlags,
+}
+
+impl Descriptor {
+    /// The path of the open file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The offset the next read or write is made at.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The flags the file was opened with.
+    pub fn flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
+
+/// An in-memory file system, and the file descriptors open in it.
+///
+/// Descriptors 0, 1 and 2 are open on `STDIN`, `STDOUT` and `STDERR` when the
+/// `Vfs` is created, so the input of a program can be given by setting the
+/// contents of `STDIN`, and its output read from `STDOUT`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Vfs {
+    files: BTreeMap<String, RC<Vec<u8>>>,
+    descriptors: BTreeMap<u64, Descriptor>,
+}
+
+impl Vfs {
+    /// Create a new `Vfs` holding empty standard input, output and error.
+    pub fn new() -> Vfs {
+        let mut vfs = Vfs {
+            files: BTreeMap::new(),
+            descriptors: BTreeMap::new(),
+        };
+        for (fd, (path, flags)) in [
+            (STDIN, OpenFlags::READ),
+            (STDOUT, OpenFlags::WRITE | OpenFlags::APPEND),
+            (STDERR, OpenFlags::WRITE | OpenFlags::APPEND),
+        ]
+        .iter()
+        .enumerate()
+        {
+            vfs.set_file(*path, Vec::new());
+            vfs.descriptors.insert(
+                fd as u64,
+                Descriptor {
+                    path: path.to_string(),
+                    offset: 0,
+                    flags: *flags,
+                },
+            );
+        }
+        vfs
+    }
+
+    /// Set the contents of the file at the given path, creating it if it
+    /// does not exist.
+    pub fn set_file<S: Into<String>>(&mut self, path: S, data: Vec<u8>) {
+        self.files.insert(path.into(), RC::new(data));
+    }
+
+    /// Remove the file at the given path. Descriptors open on the file remain
+    /// open, but fail to read or write.
+    pub fn remove_file(&mut self, path: &str) -> Option<Vec<u8>> {
+        self.files
+            .remove(path)
+            .map(|data| RC::try_unwrap(data).unwrap_or_else(|data| data.as_ref().clone()))
+    }
+
+    /// Get the contents of the file at the given path.
+    pub fn file(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(path).map(|data| data.as_slice())
+    }
+
+    /// Get the paths of every file.
+    pub fn paths(&self) -> Vec<&str> {
+        self.files.keys().map(|path| path.as_str()).collect()
+    }
+
+    /// Get the open descriptor with the given number.
+    pub fn descriptor(&self, fd: u64) -> Option<&Descriptor> {
+        self.descriptors.get(&fd)
+    }
+
+    /// Open the file at the given path, returning the lowest free descriptor,
+    /// or `None` if the file does not exist and is not created.
+    pub fn open(&mut self, path: &str, flags: OpenFlags) -> Option<u64> {
+        if !self.files.contains_key(path) {
+            if !flags.contains(OpenFlags::CREATE) {
+                return None;
+            }
+            self.set_file(path, Vec::new());
+        }
+        if flags.contains(OpenFlags::TRUNCATE) && flags.contains(OpenFlags::WRITE) {
+            self.set_file(path, Vec::new());
+        }
+        let fd = (0..)
+            .find(|fd| !self.descriptors.contains_key(fd))
+            .expect("Failed to find a free descriptor");
+        self.descriptors.insert(
+            fd,
+            Descriptor {
+                path: path.to_string(),
+                offset: 0,
+                flags,
+            },
+        );
+        Some(fd)
+    }
+
+    /// Close the given descriptor, returning false if it was not open.
+    pub fn close(&mut self, fd: u64) -> bool {
+        self.descriptors.remove(&fd).is_some()
+    }
+
+    /// Read up to `length` bytes from the given descriptor, returning `None`
+    /// if the descriptor cannot be read.
+    pub fn read(&mut self, fd: u64, length: usize) -> Option<Vec<u8>> {
+        let descriptor = self.descriptors.get_mut(&fd)?;
+        if !descriptor.flags.contains(OpenFlags::READ) {
+            return None;
+        }
+        let data = self.files.get(&descriptor.path)?;
+        let start = (descriptor.offset as usize).min(data.len());
+        let end = start.saturating_add(length).min(data.len());
+        descriptor.offset = end as u64;
+        Some(data[start..end].to_vec())
+    }
+
+    /// Write bytes to the given descriptor, returning the number of bytes
+    /// written, or `None` if the descriptor cannot be written.
+    pub fn write(&mut self, fd: u64, bytes: &[u8]) -> Option<usize> {
+        let descriptor = self.descriptors.get_mut(&fd)?;
+        if !descriptor.flags.contains(OpenFlags::WRITE) {
+            return None;
+        }
+        let data = RC::make_mut(self.files.get_mut(&descriptor.path)?);
+        if descriptor.flags.contains(OpenFlags::APPEND) {
+            descriptor.offset = data.len() as u64;
+        }
+        let start = descriptor.offset as usize;
+        if data.len() < start + bytes.len() {
+            data.resize(start + bytes.len(), 0);
+        }
+        data[start..start + bytes.len()].copy_from_slice(bytes);
+        descriptor.offset += bytes.len() as u64;
+        Some(bytes.len())
+    }
+}
+
+impl Default for Vfs {
+    fn default() -> Vfs {
+        Vfs::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vfs() {
+        let mut vfs = Vfs::new();
+        vfs.set_file(STDIN, b"input".to_vec());
+        vfs.set_file("/etc/passwd", b"root".to_vec());
+
+        assert_eq!(vfs.read(0, 3), Some(b"inp".to_vec()));
+        assert_eq!(vfs.read(0, 10), Some(b"ut".to_vec()));
+        assert_eq!(vfs.read(0, 10), Some(Vec::new()));
+        assert_eq!(vfs.write(0, b"x"), None);
+
+        assert_eq!(vfs.open("/missing", OpenFlags::READ), None);
+        let fd = vfs.open("/etc/passwd", OpenFlags::READ).unwrap();
+        assert_eq!(fd, 3);
+        assert_eq!(vfs.read(fd, 10), Some(b"root".to_vec()));
+        assert!(vfs.close(fd));
+        assert!(!vfs.close(fd));
+
+        let fd = vfs
+            .open("/tmp/out", OpenFlags::WRITE | OpenFlags::CREATE)
+            .unwrap();
+        assert_eq!(fd, 3);
+        assert_eq!(vfs.write(fd, b"hello"), Some(5));
+        assert_eq!(vfs.write(1, b"a"), Some(1));
+        assert_eq!(vfs.write(1, b"b"), Some(1));
+        assert_eq!(vfs.file("/tmp/out"), Some(&b"hello"[..]));
+        assert_eq!(vfs.file(STDOUT), Some(&b"ab"[..]));
+
+        let fd = vfs
+            .open("/tmp/out", OpenFlags::WRITE | OpenFlags::TRUNCATE)
+            .unwrap();
+        assert_eq!(fd, 4);
+        assert_eq!(vfs.file("/tmp/out"), Some(&b""[..]));
+    }
+}
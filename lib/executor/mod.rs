@@ -1,18 +1,46 @@
 //! Concrete execution over Falcon IL.
+//!
+//! Intrinsics, and calls to functions which should not be executed, can be
+//! modelled by registering `Hooks` with a `Driver`. A `Tracer` records the
+//! steps a `Driver` takes, which can later be replayed, and `Coverage`
+//! records the addresses, blocks and edges it hits. A `Debugger` runs a
+//! `Driver` until breakpoints and watchpoints are hit, and a `GdbServer`
+//! exposes a `Debugger` to GDB.
+//!
+//! A `State` can be checkpointed with a `Snapshot`, and two states compared
+//! with a `StateDiff`.
+//!
+//! The `libc` module models common libc functions natively, with an emulated
+//! heap and file system.
 
 use crate::error::*;
 use crate::il;
 use crate::memory;
 
+mod coverage;
+mod debugger;
+mod diff;
 mod driver;
 mod eval;
+mod gdb;
+mod hooks;
+pub mod libc;
+mod snapshot;
 mod state;
 mod successor;
+mod trace;
 
+pub use self::coverage::*;
+pub use self::debugger::*;
+pub use self::diff::*;
 pub use self::driver::*;
 pub use self::eval::eval;
+pub use self::gdb::*;
+pub use self::hooks::*;
+pub use self::snapshot::*;
 pub use self::state::*;
 pub use self::successor::*;
+pub use self::trace::*;
 
 /// A `falcon::memory::paged::Memory` over `il::Constant`.
 pub type Memory = memory::paged::Memory<il::Constant>;
@@ -22,7 +50,7 @@ use crate::translator;
 
 impl translator::TranslationMemory for Memory {
     fn get_u8(&self, address: u64) -> Option<u8> {
-        match self.load(address, 8).unwrap() {
+        match self.load_unchecked(address, 8).unwrap() {
             Some(constant) => Some(constant.value_u64().unwrap() as u8),
             None => None,
         }
@@ -0,0 +1,147 @@
+//! Snapshots of a concrete `State`.
+//!
+//! A `Snapshot` holds the scalars and paged memory of a `State`. The backing
+//! of the paged memory, which is often the whole loaded binary, is not
+//! copied into the snapshot. Instead its digest is recorded, and the same
+//! backing must be given when the snapshot is restored.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::executor::{Snapshot, State};
+//!
+//! # #[allow(dead_code)]
+//! fn checkpoint(state: &State) -> Result<State> {
+//!     let mut bytes = Vec::new();
+//!     state.snapshot().write(&mut bytes)?;
+//!
+//!     let snapshot = Snapshot::read(&mut bytes.as_slice())?;
+//!     snapshot.restore(state.memory().backing())
+//! }
+//! ```
+
+use crate::error::*;
+use crate::executor::State;
+use crate::memory::backing;
+use crate::RC;
+use std::io::{Read, Write};
+
+/// A serialisable snapshot of a `State`, which references the backing of the
+/// `State`'s memory rather than copying it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    state: State,
+    backing: Option<u64>,
+}
+
+impl Snapshot {
+    /// Take a snapshot of the given `State`.
+    pub fn new(state: &State) -> Snapshot {
+        let mut state = state.clone();
+        let backing = state.memory().backing().map(|backing| backing.digest());
+        state.memory_mut().set_backing(None);
+        Snapshot { state, backing }
+    }
+
+    /// The digest of the backing of the `State`'s memory, if it had one.
+    pub fn backing(&self) -> Option<u64> {
+        self.backing
+    }
+
+    /// Restore the `State` in this snapshot, giving its memory the given
+    /// backing.
+    ///
+    /// The backing must be the one the `State` had when this snapshot was
+    /// taken.
+    pub fn restore(self, backing: Option<RC<backing::Memory>>) -> Result<State> {
+        match (
+            self.backing,
+            backing.as_ref().map(|backing| backing.digest()),
+        ) {
+            (None, None) => {}
+            (Some(expected), Some(digest)) => {
+                if expected != digest {
+                    bail!(
+                        "Snapshot backing digest 0x{:016x} does not match backing 0x{:016x}",
+                        expected,
+                        digest
+                    );
+                }
+            }
+            (Some(_), None) => bail!("Snapshot requires a memory backing"),
+            (None, Some(_)) => bail!("Snapshot was taken without a memory backing"),
+        }
+        let mut state = self.state;
+        state.memory_mut().set_backing(backing);
+        Ok(state)
+    }
+
+    /// Write this snapshot as JSON.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Read a snapshot written with `Snapshot::write`.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Snapshot> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::Endian;
+    use crate::executor::Memory;
+    use crate::il;
+    use crate::memory::MemoryPermissions;
+
+    #[test]
+    fn snapshot() {
+        let mut backing = backing::Memory::new(Endian::Little);
+        backing.set_memory(
+            0x1000,
+            vec![0x11, 0x22, 0x33, 0x44],
+            MemoryPermissions::READ,
+        );
+        let backing = RC::new(backing);
+
+        let mut state = State::new(Memory::new_with_backing(Endian::Little, backing.clone()));
+        state.set_scalar("rax", il::const_(0x1234, 64));
+        state
+            .memory_mut()
+            .store(0x1001, il::const_(0x55, 8))
+            .unwrap();
+        state
+            .memory_mut()
+            .set_permissions(0x8000, 0x10, MemoryPermissions::READ);
+        state.memory_mut().set_enforcing(true);
+
+        let mut bytes = Vec::new();
+        state.snapshot().write(&mut bytes).unwrap();
+        let snapshot = Snapshot::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(snapshot.backing(), Some(backing.digest()));
+
+        let mut other = backing::Memory::new(Endian::Little);
+        other.set_memory(
+            0x1000,
+            vec![0x11, 0x22, 0x33, 0x45],
+            MemoryPermissions::READ,
+        );
+        assert!(snapshot.clone().restore(Some(RC::new(other))).is_err());
+        assert!(snapshot.clone().restore(None).is_err());
+
+        let restored = snapshot.restore(Some(backing.clone())).unwrap();
+        assert!(RC::ptr_eq(&restored.memory().backing().unwrap(), &backing));
+        assert_eq!(restored.get_scalar("rax"), Some(&il::const_(0x1234, 64)));
+        assert_eq!(
+            restored.memory().load(0x1000, 32).unwrap(),
+            Some(il::const_(0x4433_5511, 32))
+        );
+        assert!(restored.memory().enforcing());
+        assert_eq!(
+            restored.memory().permissions(0x8000),
+            Some(MemoryPermissions::READ)
+        );
+        assert!(state.diff(&restored).is_empty());
+    }
+}
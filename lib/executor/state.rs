@@ -1,14 +1,21 @@
 //! A concrete state for execution over Falcon IL.
 
+use crate::executor::libc::Environment;
 use crate::executor::successor::*;
 use crate::executor::*;
+use crate::RC;
 use std::collections::BTreeMap;
 
 /// A concrete `State`.
-#[derive(Debug, Clone)]
+///
+/// A `State` serialises without the backing of its memory. Use `snapshot` to
+/// serialise a `State` which references its backing.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct State {
     scalars: BTreeMap<String, il::Constant>,
     memory: Memory,
+    #[serde(default)]
+    environment: Option<RC<Environment>>,
 }
 
 impl State {
@@ -17,6 +24,7 @@ impl State {
         State {
             scalars: BTreeMap::new(),
             memory: memory,
+            environment: None,
         }
     }
 
@@ -41,6 +49,38 @@ impl State {
         self.scalars.get(name)
     }
 
+    /// Get all scalars set in this state, and their concrete values.
+    pub fn scalars(&self) -> &BTreeMap<String, il::Constant> {
+        &self.scalars
+    }
+
+    /// The emulated environment used by libc models, if there is one.
+    pub fn environment(&self) -> Option<&Environment> {
+        self.environment.as_deref()
+    }
+
+    /// A mutable reference to the emulated environment used by libc models,
+    /// if there is one.
+    pub fn environment_mut(&mut self) -> Option<&mut Environment> {
+        self.environment.as_mut().map(RC::make_mut)
+    }
+
+    /// Set the emulated environment used by libc models.
+    pub fn set_environment(&mut self, environment: Option<Environment>) {
+        self.environment = environment.map(RC::new);
+    }
+
+    /// Take a `Snapshot` of this state, which can be serialised, and later
+    /// restored.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self)
+    }
+
+    /// The scalars and memory which differ between this state and `other`.
+    pub fn diff(&self, other: &State) -> StateDiff {
+        StateDiff::new(self, other)
+    }
+
     /// Symbolize an expression, replacing all scalars with the concrete values
     /// stored in this state.
     pub fn symbolize_expression(&self, expression: &il::Expression) -> Result<il::Expression> {
@@ -150,24 +190,30 @@ impl State {
             il::Operation::Store { ref index, ref src } => {
                 let src = self.symbolize_and_eval(src)?;
                 let index = self.symbolize_and_eval(index)?;
-                self.memory
-                    .store(index.value_u64().ok_or(ErrorKind::TooManyAddressBits)?, src)?;
-                Successor::new(self, SuccessorType::FallThrough)
+                let index = index.value_u64().ok_or(ErrorKind::TooManyAddressBits)?;
+                match self.memory.store(index, src) {
+                    Ok(()) => Successor::new(self, SuccessorType::FallThrough),
+                    Err(Error(ErrorKind::MemoryFault(fault), _)) => {
+                        Successor::new(self, SuccessorType::Fault(fault))
+                    }
+                    Err(e) => return Err(e),
+                }
             }
             il::Operation::Load { ref dst, ref index } => {
-                let index = self.symbolize_and_eval(index)?;
-                let value = self.memory.load(
-                    index.value_u64().ok_or(ErrorKind::TooManyAddressBits)?,
-                    dst.bits(),
-                )?;
-                match value {
-                    Some(v) => {
-                        self.set_scalar(dst.name(), v.into());
+                let index = self
+                    .symbolize_and_eval(index)?
+                    .value_u64()
+                    .ok_or(ErrorKind::TooManyAddressBits)?;
+                match self.memory.load(index, dst.bits()) {
+                    Ok(Some(v)) => {
+                        self.set_scalar(dst.name(), v);
                         Successor::new(self, SuccessorType::FallThrough)
                     }
-                    None => {
-                        bail!("Got invalid concretized address {}", index);
+                    Ok(None) => return Err(ErrorKind::AccessUnmappedMemory(index).into()),
+                    Err(Error(ErrorKind::MemoryFault(fault), _)) => {
+                        Successor::new(self, SuccessorType::Fault(fault))
                     }
+                    Err(e) => return Err(e),
                 }
             }
             il::Operation::Branch { ref target } => {
@@ -178,7 +224,7 @@ impl State {
                 )
             }
             il::Operation::Intrinsic { ref intrinsic } => {
-                return Err(ErrorKind::UnhandledIntrinsic(format!("{}", intrinsic)).into());
+                Successor::new(self, SuccessorType::Intrinsic(intrinsic.clone()))
             }
             il::Operation::Nop => Successor::new(self, SuccessorType::FallThrough),
         })
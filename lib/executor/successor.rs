@@ -2,6 +2,7 @@
 
 use crate::executor::State;
 use crate::il;
+use crate::memory::MemoryFault;
 
 /// A representation of the successor location in an `il::Program` after
 /// execution of an `il::Operation`.
@@ -10,6 +11,9 @@ pub enum SuccessorType {
     FallThrough,
     Branch(u64),
     Intrinsic(il::Intrinsic),
+    /// The operation accessed memory in violation of its permissions. The
+    /// state is left as it was before the operation.
+    Fault(MemoryFault),
 }
 
 /// The result of executing an `il::Operation` over a `State`.
@@ -0,0 +1,718 @@
+//! Recording and replay of execution traces.
+//!
+//! A `Tracer` steps a `Driver` while recording, for every step, the
+//! `il::ProgramLocation` executed, the address of the instruction, the
+//! scalars written and the memory read and written. Writes made by hooks are
+//! recorded along with the writes of the operation itself.
+//!
+//! A `Trace` is written to, and read from, a compact binary format. Given the
+//! `State` the trace began with, a `Replay` reconstructs the `State` after
+//! any number of steps.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::executor::{Driver, Replay, Tracer};
+//!
+//! # #[allow(dead_code)]
+//! fn record(driver: Driver, steps: usize) -> Result<Vec<u8>> {
+//!     let initial = driver.state().clone();
+//!     let mut tracer = Tracer::new(driver);
+//!     for _ in 0..steps {
+//!         tracer.step()?;
+//!     }
+//!
+//!     // The state after 10 steps.
+//!     let mut replay = Replay::new(tracer.trace(), initial);
+//!     let _state = replay.seek(10)?;
+//!
+//!     let mut bytes = Vec::new();
+//!     tracer.trace().write(&mut bytes)?;
+//!     Ok(bytes)
+//! }
+//! ```
+
+use crate::error::*;
+use crate::executor::{Driver, State};
+use crate::il;
+use crate::memory::paged::PAGE_SIZE;
+use crate::RC;
+use num_bigint::BigUint;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// A read from, or write to, memory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MemoryAccess {
+    address: u64,
+    value: il::Constant,
+}
+
+impl MemoryAccess {
+    /// Create a new `MemoryAccess` of the given value at the given address.
+    pub fn new(address: u64, value: il::Constant) -> MemoryAccess {
+        MemoryAccess { address, value }
+    }
+
+    /// The address accessed.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// The value read or written.
+    pub fn value(&self) -> &il::Constant {
+        &self.value
+    }
+}
+
+/// The effects of a single step of a `Driver`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraceStep {
+    location: il::ProgramLocation,
+    address: Option<u64>,
+    scalars: Vec<(String, il::Constant)>,
+    reads: Vec<MemoryAccess>,
+    writes: Vec<MemoryAccess>,
+}
+
+impl TraceStep {
+    /// The location executed in this step.
+    pub fn location(&self) -> &il::ProgramLocation {
+        &self.location
+    }
+
+    /// The address of the instruction executed in this step, if any.
+    pub fn address(&self) -> Option<u64> {
+        self.address
+    }
+
+    /// The scalars written in this step, and their new values.
+    pub fn scalars(&self) -> &[(String, il::Constant)] {
+        &self.scalars
+    }
+
+    /// The memory read in this step.
+    pub fn reads(&self) -> &[MemoryAccess] {
+        &self.reads
+    }
+
+    /// The memory written in this step.
+    pub fn writes(&self) -> &[MemoryAccess] {
+        &self.writes
+    }
+
+    /// Apply the writes of this step to a `State`.
+    pub fn apply(&self, state: &mut State) -> Result<()> {
+        for (name, value) in &self.scalars {
+            state.set_scalar(name.clone(), value.clone());
+        }
+        for write in &self.writes {
+            state
+                .memory_mut()
+                .store(write.address, write.value.clone())?;
+        }
+        Ok(())
+    }
+}
+
+/// A sequence of steps taken by a `Driver`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Trace {
+    steps: Vec<TraceStep>,
+}
+
+/// Magic bytes at the beginning of a serialised `Trace`.
+const MAGIC: &[u8; 4] = b"FTRC";
+/// The version of the serialised `Trace` format.
+const VERSION: u64 = 1;
+/// The largest width in bits of a constant in a serialised `Trace`.
+const MAX_CONSTANT_BITS: usize = 0x1000;
+/// The largest length in bytes of a scalar name in a serialised `Trace`.
+const MAX_NAME_LENGTH: usize = 0x1000;
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            writer.write_all(&[byte])?;
+            return Ok(());
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        if shift > 63 {
+            bail!("Varint in trace is too long");
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+fn read_usize<R: Read>(reader: &mut R) -> Result<usize> {
+    Ok(read_varint(reader)? as usize)
+}
+
+fn write_option<W: Write>(writer: &mut W, value: Option<u64>) -> Result<()> {
+    match value {
+        Some(value) => {
+            writer.write_all(&[1])?;
+            write_varint(writer, value)
+        }
+        None => Ok(writer.write_all(&[0])?),
+    }
+}
+
+fn read_option<R: Read>(reader: &mut R) -> Result<Option<u64>> {
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    match tag[0] {
+        0 => Ok(None),
+        1 => Ok(Some(read_varint(reader)?)),
+        tag => bail!("Invalid option tag {} in trace", tag),
+    }
+}
+
+fn write_constant<W: Write>(writer: &mut W, constant: &il::Constant) -> Result<()> {
+    write_varint(writer, constant.bits() as u64)?;
+    let mut bytes = constant.value().to_bytes_le();
+    bytes.resize(constant.bits().div_ceil(8), 0);
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+fn read_constant<R: Read>(reader: &mut R) -> Result<il::Constant> {
+    let bits = read_usize(reader)?;
+    if bits > MAX_CONSTANT_BITS {
+        bail!("Constant of {} bits in trace is too wide", bits);
+    }
+    let mut bytes = vec![0u8; bits.div_ceil(8)];
+    reader.read_exact(&mut bytes)?;
+    Ok(il::Constant::new_big(BigUint::from_bytes_le(&bytes), bits))
+}
+
+fn write_location<W: Write>(writer: &mut W, location: &il::ProgramLocation) -> Result<()> {
+    write_option(writer, location.function_index().map(|index| index as u64))?;
+    match *location.function_location() {
+        il::FunctionLocation::Instruction(block_index, instruction_index) => {
+            writer.write_all(&[0])?;
+            write_varint(writer, block_index as u64)?;
+            write_varint(writer, instruction_index as u64)
+        }
+        il::FunctionLocation::Edge(head, tail) => {
+            writer.write_all(&[1])?;
+            write_varint(writer, head as u64)?;
+            write_varint(writer, tail as u64)
+        }
+        il::FunctionLocation::EmptyBlock(block_index) => {
+            writer.write_all(&[2])?;
+            write_varint(writer, block_index as u64)
+        }
+    }
+}
+
+fn read_location<R: Read>(reader: &mut R) -> Result<il::ProgramLocation> {
+    let function_index = read_option(reader)?.map(|index| index as usize);
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    let function_location = match tag[0] {
+        0 => il::FunctionLocation::Instruction(read_usize(reader)?, read_usize(reader)?),
+        1 => il::FunctionLocation::Edge(read_usize(reader)?, read_usize(reader)?),
+        2 => il::FunctionLocation::EmptyBlock(read_usize(reader)?),
+        tag => bail!("Invalid location tag {} in trace", tag),
+    };
+    Ok(il::ProgramLocation::new(function_index, function_location))
+}
+
+fn write_accesses<W: Write>(writer: &mut W, accesses: &[MemoryAccess]) -> Result<()> {
+    write_varint(writer, accesses.len() as u64)?;
+    for access in accesses {
+        write_varint(writer, access.address)?;
+        write_constant(writer, &access.value)?;
+    }
+    Ok(())
+}
+
+fn read_accesses<R: Read>(reader: &mut R) -> Result<Vec<MemoryAccess>> {
+    let length = read_usize(reader)?;
+    let mut accesses = Vec::new();
+    for _ in 0..length {
+        let address = read_varint(reader)?;
+        accesses.push(MemoryAccess::new(address, read_constant(reader)?));
+    }
+    Ok(accesses)
+}
+
+impl Trace {
+    /// Create a new, empty, `Trace`.
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    /// The steps of this trace.
+    pub fn steps(&self) -> &[TraceStep] {
+        &self.steps
+    }
+
+    /// The number of steps in this trace.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns true if this trace has no steps.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Append a step to this trace.
+    pub fn push(&mut self, step: TraceStep) {
+        self.steps.push(step);
+    }
+
+    /// Serialise this trace.
+    ///
+    /// Integers are written as LEB128 varints, and each scalar name is
+    /// written once, and then referred to by index.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        write_varint(writer, VERSION)?;
+        write_varint(writer, self.steps.len() as u64)?;
+
+        let mut names: HashMap<&str, u64> = HashMap::new();
+        for step in &self.steps {
+            write_location(writer, &step.location)?;
+            write_option(writer, step.address)?;
+
+            write_varint(writer, step.scalars.len() as u64)?;
+            for (name, value) in &step.scalars {
+                // An index one past the end of the table introduces a name.
+                let next = names.len() as u64;
+                let index = *names.entry(name).or_insert(next);
+                write_varint(writer, index)?;
+                if index == next {
+                    write_varint(writer, name.len() as u64)?;
+                    writer.write_all(name.as_bytes())?;
+                }
+                write_constant(writer, value)?;
+            }
+
+            write_accesses(writer, &step.reads)?;
+            write_accesses(writer, &step.writes)?;
+        }
+        Ok(())
+    }
+
+    /// Deserialise a trace written by `Trace::write`.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Trace> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("Not a Falcon trace");
+        }
+        let version = read_varint(reader)?;
+        if version != VERSION {
+            bail!("Unsupported trace version {}", version);
+        }
+
+        let length = read_usize(reader)?;
+        let mut names: Vec<String> = Vec::new();
+        let mut steps = Vec::new();
+        for _ in 0..length {
+            let location = read_location(reader)?;
+            let address = read_option(reader)?;
+
+            let mut scalars = Vec::new();
+            for _ in 0..read_usize(reader)? {
+                let index = read_usize(reader)?;
+                if index == names.len() {
+                    let name_length = read_usize(reader)?;
+                    if name_length > MAX_NAME_LENGTH {
+                        bail!("Scalar name of {} bytes in trace is too long", name_length);
+                    }
+                    let mut name = vec![0u8; name_length];
+                    reader.read_exact(&mut name)?;
+                    names.push(String::from_utf8(name)?);
+                }
+                let name = names
+                    .get(index)
+                    .ok_or("Invalid scalar name index in trace")?
+                    .clone();
+                scalars.push((name, read_constant(reader)?));
+            }
+
+            let reads = read_accesses(reader)?;
+            let writes = read_accesses(reader)?;
+            steps.push(TraceStep {
+                location,
+                address,
+                scalars,
+                reads,
+                writes,
+            });
+        }
+        Ok(Trace { steps })
+    }
+}
+
+/// Steps a `Driver`, recording a `Trace`.
+#[derive(Clone, Debug)]
+pub struct Tracer {
+    driver: Driver,
+    trace: Trace,
+}
+
+impl Tracer {
+    /// Create a new `Tracer` which records the steps of the given driver.
+    pub fn new(driver: Driver) -> Tracer {
+        Tracer {
+            driver,
+            trace: Trace::new(),
+        }
+    }
+
+    /// The driver being traced.
+    pub fn driver(&self) -> &Driver {
+        &self.driver
+    }
+
+    /// The trace recorded so far.
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// Consume this `Tracer`, returning the recorded trace.
+    pub fn into_trace(self) -> Trace {
+        self.trace
+    }
+
+    /// Step the driver forward, recording the step.
+    ///
+    /// If the step fails, the driver and trace are left unchanged.
+    pub fn step(&mut self) -> Result<()> {
+        let location = self.driver.location().clone();
+        let address = self.driver.address();
+
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        let mut load_dst = None;
+        // Hooks may run on branches and intrinsics, and write to memory.
+        let mut hooked = false;
+        {
+            let ref_location = location.apply(self.driver.program())?;
+            if let Some(instruction) = ref_location.instruction() {
+                let state = self.driver.state();
+                match *instruction.operation() {
+                    il::Operation::Store { ref index, ref src } => {
+                        let address = state
+                            .symbolize_and_eval(index)?
+                            .value_u64()
+                            .ok_or(ErrorKind::TooManyAddressBits)?;
+                        writes.push(MemoryAccess::new(address, state.symbolize_and_eval(src)?));
+                    }
+                    il::Operation::Load { ref dst, ref index } => {
+                        let address = state
+                            .symbolize_and_eval(index)?
+                            .value_u64()
+                            .ok_or(ErrorKind::TooManyAddressBits)?;
+                        load_dst = Some((address, dst.name().to_string()));
+                    }
+                    il::Operation::Branch { .. } | il::Operation::Intrinsic { .. } => {
+                        hooked = true;
+                    }
+                    il::Operation::Assign { .. } | il::Operation::Nop => {}
+                }
+            }
+        }
+
+        let driver = self.driver.clone().step()?;
+
+        if let Some((address, dst)) = load_dst {
+            if let Some(value) = driver.state().get_scalar(&dst) {
+                reads.push(MemoryAccess::new(address, value.clone()));
+            }
+        }
+        if hooked {
+            writes.append(&mut memory_writes(self.driver.state(), driver.state())?);
+        }
+
+        let before = self.driver.state().scalars();
+        let scalars = driver
+            .state()
+            .scalars()
+            .iter()
+            .filter(|(name, value)| before.get(*name) != Some(value))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        self.trace.push(TraceStep {
+            location,
+            address,
+            scalars,
+            reads,
+            writes,
+        });
+        self.driver = driver;
+        Ok(())
+    }
+}
+
+/// Find the bytes of memory which differ between two states, by comparing
+/// the copy-on-write pages which are no longer shared.
+fn memory_writes(before: &State, after: &State) -> Result<Vec<MemoryAccess>> {
+    let mut page_addresses = after
+        .memory()
+        .pages()
+        .iter()
+        .filter(
+            |(page_address, page)| match before.memory().pages().get(page_address) {
+                Some(before_page) => !RC::ptr_eq(before_page, page),
+                None => true,
+            },
+        )
+        .map(|(page_address, _)| *page_address)
+        .collect::<Vec<u64>>();
+    page_addresses.sort();
+
+    let mut writes = Vec::new();
+    for page_address in page_addresses {
+        for offset in 0..PAGE_SIZE as u64 {
+            let address = page_address + offset;
+            let value = match after.memory().load_unchecked(address, 8)? {
+                Some(value) => value,
+                None => continue,
+            };
+            if before.memory().load_unchecked(address, 8)?.as_ref() != Some(&value) {
+                writes.push(MemoryAccess::new(address, value));
+            }
+        }
+    }
+    Ok(writes)
+}
+
+/// Reconstructs the `State` at any step of a `Trace`.
+///
+/// States are checkpointed at regular intervals as the trace is replayed, so
+/// seeking backwards does not replay the trace from the beginning.
+#[derive(Clone, Debug)]
+pub struct Replay<'t> {
+    trace: &'t Trace,
+    /// Checkpoints, where checkpoint `i` is the state after
+    /// `i * interval` steps.
+    checkpoints: Vec<State>,
+    interval: usize,
+}
+
+impl<'t> Replay<'t> {
+    /// Create a new `Replay` of the given trace, which began in the given
+    /// state.
+    pub fn new(trace: &'t Trace, initial: State) -> Replay<'t> {
+        Replay::with_interval(trace, initial, 1024)
+    }
+
+    /// Create a new `Replay`, checkpointing the state every `interval`
+    /// steps.
+    pub fn with_interval(trace: &'t Trace, initial: State, interval: usize) -> Replay<'t> {
+        Replay {
+            trace,
+            checkpoints: vec![initial],
+            interval: interval.max(1),
+        }
+    }
+
+    /// Reconstruct the `State` after the first `step` steps of the trace.
+    ///
+    /// `seek(0)` gives the initial state.
+    pub fn seek(&mut self, step: usize) -> Result<State> {
+        if step > self.trace.len() {
+            bail!(
+                "Step {} is beyond the end of a trace of {} steps",
+                step,
+                self.trace.len()
+            );
+        }
+
+        let checkpoint = (step / self.interval).min(self.checkpoints.len() - 1);
+        let mut state = self.checkpoints[checkpoint].clone();
+        for i in (checkpoint * self.interval)..step {
+            self.trace.steps[i].apply(&mut state)?;
+            if (i + 1) % self.interval == 0 && (i + 1) / self.interval == self.checkpoints.len() {
+                self.checkpoints.push(state.clone());
+            }
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{Amd64, Endian};
+    use crate::executor::{HookSuccessor, Memory};
+    use crate::memory::MemoryPermissions;
+
+    // A function at 0x1000 which stores rax to [rsp], calls a hooked function
+    // at 0x2000 which writes to memory, and loads the result back.
+    fn driver() -> Driver {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+        let block_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.store(il::expr_scalar("rsp", 64), il::expr_scalar("rax", 64));
+            block.branch(il::expr_const(0x2000, 64));
+            block.load(il::scalar("rbx", 32), il::expr_const(0x9000, 64));
+            block.assign(
+                il::scalar("rax", 64),
+                il::Expression::add(il::expr_scalar("rax", 64), il::expr_const(1, 64)).unwrap(),
+            );
+            block.nop();
+            for (instruction, address) in block
+                .instructions_mut()
+                .iter_mut()
+                .zip([0x1000, 0x1004, 0x1009, 0x100d, 0x1010].iter())
+            {
+                instruction.set_address(Some(*address));
+            }
+            block.index()
+        };
+        control_flow_graph.set_entry(block_index).unwrap();
+
+        let mut program = il::Program::new();
+        program.add_function(il::Function::new(0x1000, control_flow_graph));
+
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+
+        let mut state = State::new(Memory::new(Endian::Little));
+        state.set_scalar("rsp", il::const_(0x8000, 64));
+        state.set_scalar("rax", il::const_(0x41, 64));
+
+        let mut driver = Driver::new(RC::new(program), location, state, RC::new(Amd64::new()));
+        driver.hooks_mut().set_address(0x2000, |state, _| {
+            state
+                .memory_mut()
+                .store(0x9000, il::const_(0xdeadbeef, 32))?;
+            Ok(HookSuccessor::Branch(0x1009))
+        });
+        driver
+    }
+
+    #[test]
+    fn record() {
+        let mut tracer = Tracer::new(driver());
+        for _ in 0..4 {
+            tracer.step().unwrap();
+        }
+        let trace = tracer.trace();
+        assert_eq!(trace.len(), 4);
+
+        let steps = trace.steps();
+        assert_eq!(steps[0].address(), Some(0x1000));
+        assert_eq!(
+            steps[0].writes(),
+            &[MemoryAccess::new(0x8000, il::const_(0x41, 64))]
+        );
+        assert_eq!(steps[1].address(), Some(0x1004));
+        let hook_writes = steps[1]
+            .writes()
+            .iter()
+            .map(|write| (write.address(), write.value().value_u64().unwrap()))
+            .collect::<Vec<(u64, u64)>>();
+        assert_eq!(
+            hook_writes,
+            vec![
+                (0x9000, 0xef),
+                (0x9001, 0xbe),
+                (0x9002, 0xad),
+                (0x9003, 0xde)
+            ]
+        );
+        assert_eq!(
+            steps[2].reads(),
+            &[MemoryAccess::new(0x9000, il::const_(0xdeadbeef, 32))]
+        );
+        assert_eq!(
+            steps[3].scalars(),
+            &[("rax".to_string(), il::const_(0x42, 64))]
+        );
+    }
+
+    #[test]
+    fn write_only_memory() {
+        let mut driver = driver();
+        let memory = driver.state_mut().memory_mut();
+        let code = MemoryPermissions::READ | MemoryPermissions::EXECUTE;
+        memory.set_permissions(0x1000, 0x2000, code);
+        memory.set_permissions(0x7000, 0x2000, MemoryPermissions::ALL);
+        memory.set_permissions(0x9000, 4, MemoryPermissions::WRITE);
+        memory.set_enforcing(true);
+
+        // The tracer sees writes to memory the target may not read.
+        let mut tracer = Tracer::new(driver);
+        tracer.step().unwrap();
+        tracer.step().unwrap();
+        assert_eq!(tracer.trace().steps()[1].writes().len(), 4);
+    }
+
+    #[test]
+    fn serialise_and_replay() {
+        let initial = driver().state().clone();
+        let mut tracer = Tracer::new(driver());
+        for _ in 0..4 {
+            tracer.step().unwrap();
+        }
+
+        let mut bytes = Vec::new();
+        tracer.trace().write(&mut bytes).unwrap();
+        let trace = Trace::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(&trace, tracer.trace());
+
+        let mut replay = Replay::with_interval(&trace, initial, 2);
+        let state = replay.seek(4).unwrap();
+        assert_eq!(state.get_scalar("rax"), Some(&il::const_(0x42, 64)));
+        assert_eq!(state.get_scalar("rbx"), Some(&il::const_(0xdeadbeef, 32)));
+        assert_eq!(
+            state.memory().load(0x8000, 64).unwrap(),
+            Some(il::const_(0x41, 64))
+        );
+
+        let state = replay.seek(1).unwrap();
+        assert_eq!(state.get_scalar("rbx"), None);
+        assert_eq!(
+            state.memory().load(0x8000, 64).unwrap(),
+            Some(il::const_(0x41, 64))
+        );
+        assert!(replay.seek(5).is_err());
+    }
+
+    #[test]
+    fn hostile_lengths() {
+        let mut header = MAGIC.to_vec();
+        write_varint(&mut header, VERSION).unwrap();
+        // One step at an instruction, with no address, and one scalar.
+        write_varint(&mut header, 1).unwrap();
+        write_option(&mut header, None).unwrap();
+        header.extend_from_slice(&[0, 0, 0]);
+        write_option(&mut header, None).unwrap();
+        write_varint(&mut header, 1).unwrap();
+        write_varint(&mut header, 0).unwrap();
+
+        let mut bytes = header.clone();
+        write_varint(&mut bytes, u64::MAX >> 1).unwrap();
+        assert!(Trace::read(&mut bytes.as_slice()).is_err());
+
+        let mut bytes = header;
+        write_varint(&mut bytes, 3).unwrap();
+        bytes.extend_from_slice(b"rax");
+        write_varint(&mut bytes, u64::MAX >> 1).unwrap();
+        assert!(Trace::read(&mut bytes.as_slice()).is_err());
+    }
+}
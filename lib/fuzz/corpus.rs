@@ -0,0 +1,59 @@
+//! The inputs a `Fuzzer` mutates.
+
+/// An input kept in a `Corpus`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CorpusEntry {
+    input: Vec<u8>,
+    new_edges: usize,
+}
+
+impl CorpusEntry {
+    /// The bytes of this input.
+    pub fn input(&self) -> &[u8] {
+        &self.input
+    }
+
+    /// The number of edges, or edge hit counts, first seen when this input
+    /// was executed.
+    pub fn new_edges(&self) -> usize {
+        self.new_edges
+    }
+}
+
+/// The inputs which exercised new coverage during fuzzing.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Corpus {
+    entries: Vec<CorpusEntry>,
+}
+
+impl Corpus {
+    /// Create a new, empty, `Corpus`.
+    pub fn new() -> Corpus {
+        Corpus::default()
+    }
+
+    /// Add an input to this corpus.
+    pub fn add(&mut self, input: Vec<u8>, new_edges: usize) {
+        self.entries.push(CorpusEntry { input, new_edges });
+    }
+
+    /// Get the entry at the given index in this corpus.
+    pub fn get(&self, index: usize) -> Option<&CorpusEntry> {
+        self.entries.get(index)
+    }
+
+    /// Get every entry in this corpus.
+    pub fn entries(&self) -> &[CorpusEntry] {
+        &self.entries
+    }
+
+    /// The number of inputs in this corpus.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if this corpus holds no inputs.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -0,0 +1,122 @@
+//! Edge coverage feedback.
+//!
+//! Edges are hashed into a fixed-size map of hit counts, in the style of AFL.
+//! Two kinds of edges are recorded: transitions between the addresses of
+//! consecutive instructions, which capture calls, returns and branches
+//! between native instructions, and `il::Edge`s, which capture the
+//! conditional control flow Falcon IL lifts from within a single native
+//! instruction.
+
+/// The number of entries in an `EdgeMap`.
+pub const EDGE_MAP_SIZE: usize = 1 << 16;
+
+fn index(value: u64) -> usize {
+    (value.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 48) as usize
+}
+
+/// Classify a hit count into one of eight buckets, returned as a single bit.
+fn bucket(hits: u8) -> u8 {
+    match hits {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 4,
+        4..=7 => 8,
+        8..=15 => 16,
+        16..=31 => 32,
+        32..=127 => 64,
+        _ => 128,
+    }
+}
+
+/// The edges hit by a single execution, and their saturating hit counts.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EdgeMap {
+    hits: Vec<u8>,
+    /// The indices of `hits` which are not 0, so only the edges hit by an
+    /// execution are visited when merging feedback.
+    indices: Vec<usize>,
+}
+
+impl EdgeMap {
+    /// Create a new `EdgeMap` with no edges hit.
+    pub fn new() -> EdgeMap {
+        EdgeMap {
+            hits: vec![0; EDGE_MAP_SIZE],
+            indices: Vec::new(),
+        }
+    }
+
+    fn hit(&mut self, index: usize) {
+        if self.hits[index] == 0 {
+            self.indices.push(index);
+        }
+        self.hits[index] = self.hits[index].saturating_add(1);
+    }
+
+    /// Record control passing from the instruction at `from` to the
+    /// instruction at `to`.
+    pub fn transition(&mut self, from: u64, to: u64) {
+        self.hit(index(from.rotate_left(1) ^ to));
+    }
+
+    /// Record the `il::Edge` from `head` to `tail` in the function at the
+    /// given address being hit.
+    pub fn edge(&mut self, function_address: u64, head: usize, tail: usize) {
+        let edge = ((head as u64) << 40) ^ ((tail as u64) << 16) ^ 0x5555;
+        self.hit(index(function_address.rotate_left(32) ^ edge));
+    }
+
+    /// The number of entries in this map which were hit.
+    pub fn count(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+impl Default for EdgeMap {
+    fn default() -> EdgeMap {
+        EdgeMap::new()
+    }
+}
+
+/// The edges, and buckets of hit counts, seen over every execution.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Feedback {
+    seen: Vec<u8>,
+}
+
+impl Feedback {
+    /// Create a new `Feedback` which has seen no edges.
+    pub fn new() -> Feedback {
+        Feedback {
+            seen: vec![0; EDGE_MAP_SIZE],
+        }
+    }
+
+    /// Merge the edges hit by an execution into this `Feedback`, returning
+    /// the number of edges, or buckets of edge hit counts, not seen before.
+    ///
+    /// An execution is interesting when this is greater than 0.
+    pub fn update(&mut self, edges: &EdgeMap) -> usize {
+        let mut new = 0;
+        for &index in &edges.indices {
+            let bucket = bucket(edges.hits[index]);
+            if bucket & !self.seen[index] != 0 {
+                self.seen[index] |= bucket;
+                new += 1;
+            }
+        }
+        new
+    }
+
+    /// The number of edges seen.
+    pub fn edges(&self) -> usize {
+        self.seen.iter().filter(|seen| **seen > 0).count()
+    }
+}
+
+impl Default for Feedback {
+    fn default() -> Feedback {
+        Feedback::new()
+    }
+}
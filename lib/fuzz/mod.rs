@@ -0,0 +1,555 @@
+//! Coverage-guided fuzzing over the concrete executor.
+//!
+//! A `Fuzzer` repeatedly executes a snapshot of an `executor::Driver`,
+//! placing a different input in it each time. Inputs are derived from the
+//! inputs in a `Corpus` by `Mutator`s, and inputs which hit new edges, as
+//! recorded in an `EdgeMap`, are added to the corpus. Executions which end
+//! in an error, such as `ErrorKind::AccessUnmappedMemory`, are triaged into
+//! unique crashes by the kind of error and the address of the faulting
+//! instruction.
+//!
+//! As the fuzzer runs over Falcon IL, programs for any architecture Falcon
+//! can lift can be fuzzed on any host.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::executor::Driver;
+//! use falcon::fuzz::{Fuzzer, Placement, SyscallRead};
+//!
+//! # #[allow(dead_code)]
+//! fn fuzz(driver: Driver, return_address: u64) -> Result<()> {
+//!     let placement = Placement::SyscallRead(Box::new(SyscallRead::linux_mips()));
+//!     let mut fuzzer = Fuzzer::new(driver, placement, 0);
+//!     fuzzer.add_exit(return_address);
+//!     fuzzer.add_seed(b"GET / HTTP/1.0\r\n\r\n".to_vec())?;
+//!
+//!     for _ in 0..100000 {
+//!         fuzzer.fuzz_one()?;
+//!     }
+//!
+//!     for crash in fuzzer.crashes() {
+//!         println!("{} hits: {:?}", crash.hits(), crash.crash());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::*;
+use crate::executor::Driver;
+use crate::il;
+use crate::memory::{MemoryFault, MemoryFaultKind};
+use crate::RC;
+use std::collections::{BTreeMap, BTreeSet};
+
+mod corpus;
+mod feedback;
+mod mutator;
+mod placement;
+mod rng;
+
+pub use self::corpus::*;
+pub use self::feedback::*;
+pub use self::mutator::*;
+pub use self::placement::*;
+pub use self::rng::*;
+
+/// The kind of error which ended an execution.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CrashKind {
+    /// Memory was accessed at the given, unmapped, address.
+    UnmappedMemory(u64),
+    /// Memory was accessed in violation of its permissions.
+    MemoryFault(MemoryFault),
+    /// Division by zero.
+    DivideByZero,
+    /// An intrinsic without a hook was executed.
+    UnhandledIntrinsic(String),
+    /// Any other error.
+    Error,
+}
+
+impl CrashKind {
+    /// Classify the given executor error.
+    pub fn from_error(error: &Error) -> CrashKind {
+        match *error.kind() {
+            ErrorKind::AccessUnmappedMemory(address) => CrashKind::UnmappedMemory(address),
+            ErrorKind::MemoryFault(ref fault) => CrashKind::MemoryFault(fault.clone()),
+            ErrorKind::DivideByZero => CrashKind::DivideByZero,
+            ErrorKind::UnhandledIntrinsic(ref intrinsic) => {
+                CrashKind::UnhandledIntrinsic(intrinsic.clone())
+            }
+            _ => CrashKind::Error,
+        }
+    }
+
+    /// The category of this crash, used to triage crashes. Unlike the
+    /// `CrashKind`, the category does not depend on the address accessed.
+    pub fn category(&self) -> &str {
+        match *self {
+            CrashKind::UnmappedMemory(_) => "unmapped memory",
+            CrashKind::MemoryFault(ref fault) => match fault.kind() {
+                MemoryFaultKind::ReadViolation => "read violation",
+                MemoryFaultKind::WriteViolation => "write violation",
+                MemoryFaultKind::ExecuteViolation => "execute violation",
+                MemoryFaultKind::Unmapped => "unmapped memory",
+            },
+            CrashKind::DivideByZero => "divide by zero",
+            CrashKind::UnhandledIntrinsic(ref intrinsic) => intrinsic,
+            CrashKind::Error => "error",
+        }
+    }
+}
+
+/// An execution which ended in an error.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Crash {
+    kind: CrashKind,
+    address: Option<u64>,
+    message: String,
+}
+
+impl Crash {
+    /// The kind of error which ended the execution.
+    pub fn kind(&self) -> &CrashKind {
+        &self.kind
+    }
+
+    /// The address of the last instruction executed, if there was one.
+    pub fn address(&self) -> Option<u64> {
+        self.address
+    }
+
+    /// The message of the error which ended the execution.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// A unique crash found by a `Fuzzer`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CrashRecord {
+    crash: Crash,
+    input: Vec<u8>,
+    hits: u64,
+}
+
+impl CrashRecord {
+    /// The first crash found with this crash's category and address.
+    pub fn crash(&self) -> &Crash {
+        &self.crash
+    }
+
+    /// The input which caused the first crash.
+    pub fn input(&self) -> &[u8] {
+        &self.input
+    }
+
+    /// The number of inputs which caused this crash.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+/// How an execution ended.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// Execution reached the given exit address.
+    Exit(u64),
+    /// Execution did not reach an exit address within the step budget.
+    Timeout,
+    /// Execution ended in an error.
+    Crash(Crash),
+}
+
+/// A coverage-guided fuzzer over the concrete executor.
+#[derive(Debug)]
+pub struct Fuzzer {
+    driver: Driver,
+    placement: Placement,
+    mutators: Vec<Box<dyn Mutator>>,
+    corpus: Corpus,
+    feedback: Feedback,
+    crashes: BTreeMap<(String, Option<u64>), CrashRecord>,
+    exits: BTreeSet<u64>,
+    max_steps: usize,
+    max_input_length: usize,
+    rng: Rng,
+    executions: u64,
+}
+
+impl Fuzzer {
+    /// Create a new `Fuzzer` which executes from the given driver, placing
+    /// inputs with the given placement, and mutating inputs with the
+    /// `BitFlip`, `Havoc` and `Splice` mutators seeded with `seed`.
+    pub fn new(driver: Driver, placement: Placement, seed: u64) -> Fuzzer {
+        Fuzzer {
+            driver,
+            placement,
+            mutators: vec![
+                Box::new(BitFlip::new()),
+                Box::new(Havoc::new()),
+                Box::new(Splice::new()),
+            ],
+            corpus: Corpus::new(),
+            feedback: Feedback::new(),
+            crashes: BTreeMap::new(),
+            exits: BTreeSet::new(),
+            max_steps: 1_000_000,
+            max_input_length: 4096,
+            rng: Rng::new(seed),
+            executions: 0,
+        }
+    }
+
+    /// Add an address at which execution ends successfully, such as the
+    /// return address of the function under test.
+    pub fn add_exit(&mut self, address: u64) {
+        self.exits.insert(address);
+    }
+
+    /// Set the number of `Driver` steps after which an execution times out.
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+
+    /// Set the maximum length of mutated inputs.
+    pub fn set_max_input_length(&mut self, max_input_length: usize) {
+        self.max_input_length = max_input_length;
+    }
+
+    /// Set the mutators used to derive new inputs. At least one mutator must
+    /// be given.
+    pub fn set_mutators(&mut self, mutators: Vec<Box<dyn Mutator>>) {
+        self.mutators = mutators;
+    }
+
+    /// Execute the given input, adding it to the corpus unless it crashes.
+    pub fn add_seed(&mut self, input: Vec<u8>) -> Result<Outcome> {
+        self.evaluate(input, true)
+    }
+
+    /// Mutate an input from the corpus, execute it, and keep it if it hits
+    /// new edges or crashes.
+    pub fn fuzz_one(&mut self) -> Result<Outcome> {
+        if self.corpus.is_empty() {
+            bail!("The corpus is empty, add a seed input before fuzzing");
+        }
+        if self.mutators.is_empty() {
+            bail!("No mutators are set");
+        }
+        let entry = self.rng.below(self.corpus.len());
+        let mutator = self.rng.below(self.mutators.len());
+        let mut input = self.mutators[mutator].mutate(
+            self.corpus.get(entry).unwrap().input(),
+            &self.corpus,
+            &mut self.rng,
+        );
+        input.truncate(self.max_input_length);
+        self.evaluate(input, false)
+    }
+
+    fn evaluate(&mut self, input: Vec<u8>, seed: bool) -> Result<Outcome> {
+        let (outcome, edges) = self.execute(&input)?;
+        self.executions += 1;
+        let new_edges = self.feedback.update(&edges);
+        match outcome {
+            Outcome::Crash(ref crash) => {
+                let key = (crash.kind().category().to_string(), crash.address());
+                self.crashes
+                    .entry(key)
+                    .or_insert_with(|| CrashRecord {
+                        crash: crash.clone(),
+                        input,
+                        hits: 0,
+                    })
+                    .hits += 1;
+            }
+            _ => {
+                if seed || new_edges > 0 {
+                    self.corpus.add(input, new_edges);
+                }
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Execute the given input, returning how the execution ended and the
+    /// edges it hit.
+    ///
+    /// An error is returned only when the input could not be placed. Errors
+    /// during execution are returned as `Outcome::Crash`.
+    pub fn execute(&mut self, input: &[u8]) -> Result<(Outcome, EdgeMap)> {
+        let mut driver = self.driver.clone();
+        self.placement.place(&mut driver, input)?;
+
+        let mut edges = EdgeMap::new();
+        let mut previous: Option<u64> = None;
+        for _ in 0..self.max_steps {
+            {
+                let location = driver.location().apply(driver.program())?;
+                if let Some(address) = location.address() {
+                    if previous != Some(address) {
+                        edges.transition(previous.unwrap_or(0), address);
+                        previous = Some(address);
+                    }
+                    if self.exits.contains(&address) {
+                        self.adopt_program(&driver);
+                        return Ok((Outcome::Exit(address), edges));
+                    }
+                }
+                if let il::RefFunctionLocation::Edge(edge) = *location.function_location() {
+                    edges.edge(location.function().address(), edge.head(), edge.tail());
+                }
+            }
+            let program_functions = driver.program().functions().len();
+            driver = match driver.step() {
+                Ok(driver) => driver,
+                Err(error) => {
+                    let crash = Crash {
+                        kind: CrashKind::from_error(&error),
+                        address: previous,
+                        message: error.to_string(),
+                    };
+                    return Ok((Outcome::Crash(crash), edges));
+                }
+            };
+            if driver.program().functions().len() != program_functions {
+                self.adopt_program(&driver);
+            }
+        }
+        Ok((Outcome::Timeout, edges))
+    }
+
+    /// Keep functions lifted during an execution, so they are not lifted
+    /// again by every execution.
+    ///
+    /// Only functions lifted from code the execution left unmodified are
+    /// kept, as code written by one input must not be seen by the next.
+    fn adopt_program(&mut self, driver: &Driver) {
+        let base = self.driver.program();
+        let functions = driver
+            .program()
+            .functions_map()
+            .into_iter()
+            .filter(|(index, _)| base.function(*index).is_none())
+            .filter(|(_, function)| self.unmodified(driver, function))
+            .map(|(_, function)| function.clone())
+            .collect::<Vec<il::Function>>();
+        if functions.is_empty() {
+            return;
+        }
+        let mut program = base.clone();
+        for function in functions {
+            program.add_function(function);
+        }
+        self.driver.set_program(RC::new(program));
+    }
+
+    /// Whether the bytes of every instruction in `function` are the same in
+    /// `driver` as they are in the base driver.
+    fn unmodified(&self, driver: &Driver, function: &il::Function) -> bool {
+        let size = driver.architecture().max_instruction_size() as u64;
+        let base = self.driver.state().memory();
+        let memory = driver.state().memory();
+        function
+            .blocks()
+            .iter()
+            .flat_map(|block| block.instructions().iter())
+            .filter_map(|instruction| instruction.address())
+            .all(|address| {
+                (0..size).all(|offset| {
+                    let address = address.wrapping_add(offset);
+                    base.load_unchecked(address, 8).ok() == memory.load_unchecked(address, 8).ok()
+                })
+            })
+    }
+
+    /// The corpus of inputs which hit new edges.
+    pub fn corpus(&self) -> &Corpus {
+        &self.corpus
+    }
+
+    /// The edges seen over every execution.
+    pub fn feedback(&self) -> &Feedback {
+        &self.feedback
+    }
+
+    /// The unique crashes found.
+    pub fn crashes(&self) -> Vec<&CrashRecord> {
+        self.crashes.values().collect()
+    }
+
+    /// The number of inputs executed.
+    pub fn executions(&self) -> u64 {
+        self.executions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{Amd64, Endian};
+    use crate::executor::{Memory, State};
+
+    // A function at 0x1000 which reads an address from 0xdead0000, faulting,
+    // if the input at 0x8000 begins with "CE", and otherwise exits at 0x100c.
+    fn driver() -> Driver {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+
+        let mut blocks = Vec::new();
+        for (address, load) in [(0x1000, 0x8000), (0x1004, 0x8001), (0x1008, 0xdead_0000)].iter() {
+            let block = control_flow_graph.new_block().unwrap();
+            block.load(il::scalar("t", 8), il::expr_const(*load, 64));
+            block.instructions_mut()[0].set_address(Some(*address));
+            blocks.push(block.index());
+        }
+        let exit = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.nop();
+            block.instructions_mut()[0].set_address(Some(0x100c));
+            block.index()
+        };
+
+        for (head, (tail, byte)) in blocks
+            .iter()
+            .zip([(blocks[1], 'C'), (blocks[2], 'E')].iter())
+        {
+            let condition =
+                il::Expression::cmpeq(il::expr_scalar("t", 8), il::expr_const(*byte as u64, 8))
+                    .unwrap();
+            control_flow_graph
+                .conditional_edge(*head, *tail, condition.clone())
+                .unwrap();
+            control_flow_graph
+                .conditional_edge(
+                    *head,
+                    exit,
+                    il::Expression::cmpeq(condition, il::expr_const(0, 1)).unwrap(),
+                )
+                .unwrap();
+        }
+        control_flow_graph.set_entry(blocks[0]).unwrap();
+
+        let mut program = il::Program::new();
+        program.add_function(il::Function::new(0x1000, control_flow_graph));
+
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+
+        let mut state = State::new(Memory::new(Endian::Little));
+        state.memory_mut().store(0x8000, il::const_(0, 64)).unwrap();
+
+        Driver::new(RC::new(program), location, state, RC::new(Amd64::new()))
+    }
+
+    #[test]
+    fn fuzz() {
+        let placement = Placement::Memory {
+            address: 0x8000,
+            length: None,
+        };
+        let mut fuzzer = Fuzzer::new(driver(), placement, 0);
+        fuzzer.add_exit(0x100c);
+        fuzzer.set_max_input_length(8);
+
+        assert_eq!(
+            fuzzer.add_seed(b"AA".to_vec()).unwrap(),
+            Outcome::Exit(0x100c)
+        );
+        assert_eq!(fuzzer.corpus().len(), 1);
+
+        for _ in 0..100000 {
+            fuzzer.fuzz_one().unwrap();
+            if !fuzzer.crashes().is_empty() {
+                break;
+            }
+        }
+
+        let crashes = fuzzer.crashes();
+        assert_eq!(crashes.len(), 1);
+        assert_eq!(
+            crashes[0].crash().kind(),
+            &CrashKind::UnmappedMemory(0xdead_0000)
+        );
+        assert_eq!(crashes[0].crash().address(), Some(0x1008));
+        assert_eq!(&crashes[0].input()[0..2], b"CE");
+        // The seed, and an input beginning with "C".
+        assert_eq!(fuzzer.corpus().len(), 2);
+    }
+
+    #[test]
+    fn adopt_program() {
+        // A function at 0x2000, as if lifted during an execution.
+        let function = || {
+            let mut control_flow_graph = il::ControlFlowGraph::new();
+            let block = control_flow_graph.new_block().unwrap();
+            block.nop();
+            block.instructions_mut()[0].set_address(Some(0x2000));
+            let entry = block.index();
+            control_flow_graph.set_entry(entry).unwrap();
+            il::Function::new(0x2000, control_flow_graph)
+        };
+
+        let mut base = driver();
+        base.state_mut()
+            .memory_mut()
+            .store(0x2000, il::const_(0x90, 8))
+            .unwrap();
+
+        let placement = Placement::Memory {
+            address: 0x8000,
+            length: None,
+        };
+        let mut fuzzer = Fuzzer::new(base.clone(), placement, 0);
+
+        // Code written by the input is not kept.
+        let mut driver = base.clone();
+        driver
+            .state_mut()
+            .memory_mut()
+            .store(0x2001, il::const_(0xcc, 8))
+            .unwrap();
+        let mut program = driver.program().clone();
+        program.add_function(function());
+        driver.set_program(RC::new(program));
+        fuzzer.adopt_program(&driver);
+        assert_eq!(fuzzer.driver.program().functions().len(), 1);
+
+        // Unmodified code is kept.
+        let mut driver = base;
+        let mut program = driver.program().clone();
+        program.add_function(function());
+        driver.set_program(RC::new(program));
+        fuzzer.adopt_program(&driver);
+        assert_eq!(fuzzer.driver.program().functions().len(), 2);
+    }
+
+    #[test]
+    fn syscall_read() {
+        let mut driver = driver();
+        let mut state = driver.state().clone();
+        state.set_scalar("rax", il::const_(0, 64));
+        state.set_scalar("rsi", il::const_(0x9000, 64));
+        state.set_scalar("rdx", il::const_(3, 64));
+
+        Placement::SyscallRead(Box::new(SyscallRead::linux_amd64()))
+            .place(&mut driver, b"abcde")
+            .unwrap();
+        let hook = driver.hooks().intrinsic("syscall").unwrap();
+        let intrinsic =
+            il::Intrinsic::new("syscall", "syscall", Vec::new(), None, None, Vec::new());
+
+        hook(&mut state, &intrinsic).unwrap();
+        assert_eq!(state.get_scalar("rax").unwrap().value_u64(), Some(3));
+        state.set_scalar("rax", il::const_(0, 64));
+        state.set_scalar("rsi", il::const_(0x9003, 64));
+        hook(&mut state, &intrinsic).unwrap();
+        assert_eq!(state.get_scalar("rax").unwrap().value_u64(), Some(2));
+        let bytes = state.memory().load(0x9000, 40).unwrap().unwrap();
+        assert_eq!(bytes.value_u64(), Some(0x65_6463_6261));
+
+        state.set_scalar("rax", il::const_(60, 64));
+        assert!(hook(&mut state, &intrinsic).is_err());
+    }
+}
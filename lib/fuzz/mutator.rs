@@ -0,0 +1,210 @@
+//! Mutators which derive new inputs from the inputs in a `Corpus`.
+
+use crate::fuzz::{Corpus, Rng};
+use std::fmt::Debug;
+
+/// Derives a new input from an existing input.
+pub trait Mutator: Debug {
+    /// Mutate a copy of the given input. The corpus the input was taken from
+    /// is available to mutators which combine inputs.
+    fn mutate(&self, input: &[u8], corpus: &Corpus, rng: &mut Rng) -> Vec<u8>;
+}
+
+const INTERESTING_8: &[u8] = &[0x00, 0x01, 0x10, 0x20, 0x40, 0x64, 0x7f, 0x80, 0xff];
+const INTERESTING_16: &[u16] = &[
+    0x0080, 0x00ff, 0x0100, 0x0200, 0x03e8, 0x1000, 0x7fff, 0x8000,
+];
+const INTERESTING_32: &[u32] = &[
+    0x0000_8000,
+    0x0001_0000,
+    0x7fff_ffff,
+    0x8000_0000,
+    0xffff_ffff,
+];
+
+/// Flips a single, random, bit.
+#[derive(Clone, Debug, Default)]
+pub struct BitFlip;
+
+impl BitFlip {
+    /// Create a new `BitFlip` mutator.
+    pub fn new() -> BitFlip {
+        BitFlip
+    }
+}
+
+impl Mutator for BitFlip {
+    fn mutate(&self, input: &[u8], _: &Corpus, rng: &mut Rng) -> Vec<u8> {
+        let mut input = input.to_vec();
+        if input.is_empty() {
+            input.push(rng.byte());
+        } else {
+            let bit = rng.below(input.len() * 8);
+            input[bit / 8] ^= 1 << (bit & 7);
+        }
+        input
+    }
+}
+
+/// Applies a random stack of byte-level mutations: bit flips, random and
+/// interesting values, small arithmetic, and deleting, duplicating and
+/// overwriting ranges of bytes.
+#[derive(Clone, Debug)]
+pub struct Havoc {
+    max_stack: usize,
+}
+
+impl Havoc {
+    /// Create a new `Havoc` mutator, which applies up to 16 mutations at a
+    /// time.
+    pub fn new() -> Havoc {
+        Havoc { max_stack: 16 }
+    }
+
+    /// Create a new `Havoc` mutator which applies up to `max_stack` mutations
+    /// at a time.
+    pub fn with_max_stack(max_stack: usize) -> Havoc {
+        Havoc {
+            max_stack: max_stack.max(1),
+        }
+    }
+
+    fn mutate_once(input: &mut Vec<u8>, rng: &mut Rng) {
+        if input.is_empty() {
+            input.push(rng.byte());
+            return;
+        }
+        let offset = rng.below(input.len());
+        match rng.below(10) {
+            0 => input[offset] ^= 1 << rng.below(8),
+            1 => input[offset] = rng.byte(),
+            2 => input[offset] = INTERESTING_8[rng.below(INTERESTING_8.len())],
+            3 => input[offset] = input[offset].wrapping_add(rng.below(35) as u8 + 1),
+            4 => input[offset] = input[offset].wrapping_sub(rng.below(35) as u8 + 1),
+            5 => {
+                let value = INTERESTING_16[rng.below(INTERESTING_16.len())];
+                let bytes = if rng.below(2) == 0 {
+                    value.to_le_bytes()
+                } else {
+                    value.to_be_bytes()
+                };
+                overwrite(input, offset, &bytes);
+            }
+            6 => {
+                let value = INTERESTING_32[rng.below(INTERESTING_32.len())];
+                let bytes = if rng.below(2) == 0 {
+                    value.to_le_bytes()
+                } else {
+                    value.to_be_bytes()
+                };
+                overwrite(input, offset, &bytes);
+            }
+            7 => {
+                // Delete a range of bytes, keeping at least one byte.
+                if input.len() > 1 {
+                    let length = 1 + rng.below((input.len() - offset).min(input.len() - 1));
+                    input.drain(offset..offset + length);
+                }
+            }
+            8 => {
+                // Insert a copy of a range of bytes.
+                let start = rng.below(input.len());
+                let length = 1 + rng.below((input.len() - start).min(64));
+                let bytes = input[start..start + length].to_vec();
+                let at = rng.below(input.len() + 1);
+                input.splice(at..at, bytes);
+            }
+            _ => {
+                // Overwrite a range of bytes with a copy of another range.
+                let start = rng.below(input.len());
+                let length = 1 + rng.below(input.len() - start);
+                let bytes = input[start..start + length].to_vec();
+                overwrite(input, offset, &bytes);
+            }
+        }
+    }
+}
+
+impl Default for Havoc {
+    fn default() -> Havoc {
+        Havoc::new()
+    }
+}
+
+impl Mutator for Havoc {
+    fn mutate(&self, input: &[u8], _: &Corpus, rng: &mut Rng) -> Vec<u8> {
+        let mut input = input.to_vec();
+        for _ in 0..=rng.below(self.max_stack) {
+            Havoc::mutate_once(&mut input, rng);
+        }
+        input
+    }
+}
+
+/// Overwrite the bytes of `input` at `offset` with `bytes`, truncating
+/// `bytes` at the end of `input`.
+fn overwrite(input: &mut [u8], offset: usize, bytes: &[u8]) {
+    for (dst, src) in input[offset..].iter_mut().zip(bytes.iter()) {
+        *dst = *src;
+    }
+}
+
+/// Joins the head of the input to the tail of another, random, input in the
+/// corpus.
+#[derive(Clone, Debug, Default)]
+pub struct Splice;
+
+impl Splice {
+    /// Create a new `Splice` mutator.
+    pub fn new() -> Splice {
+        Splice
+    }
+}
+
+impl Mutator for Splice {
+    fn mutate(&self, input: &[u8], corpus: &Corpus, rng: &mut Rng) -> Vec<u8> {
+        let other = match corpus.len() {
+            0 => return input.to_vec(),
+            length => corpus.get(rng.below(length)).unwrap().input(),
+        };
+        let head = rng.below(input.len() + 1);
+        let tail = rng.below(other.len() + 1);
+        let mut spliced = input[..head].to_vec();
+        spliced.extend_from_slice(&other[tail..]);
+        if spliced.is_empty() {
+            return input.to_vec();
+        }
+        spliced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutators() {
+        let mut rng = Rng::new(0);
+        let mut corpus = Corpus::new();
+        corpus.add(vec![0xaa; 8], 0);
+        corpus.add(vec![0x55; 8], 0);
+
+        let input = [0u8; 8];
+        for _ in 0..256 {
+            let flipped = BitFlip::new().mutate(&input, &corpus, &mut rng);
+            let bits: u32 = flipped.iter().map(|byte| byte.count_ones()).sum();
+            assert_eq!(flipped.len(), 8);
+            assert_eq!(bits, 1);
+
+            let havoc = Havoc::new().mutate(&input, &corpus, &mut rng);
+            assert!(!havoc.is_empty());
+
+            let spliced = Splice::new().mutate(&input, &corpus, &mut rng);
+            assert!(spliced.len() <= 16);
+            let zeros = spliced.iter().take_while(|byte| **byte == 0).count();
+            assert!(spliced[zeros..]
+                .iter()
+                .all(|byte| *byte == 0xaa || *byte == 0x55));
+        }
+    }
+}
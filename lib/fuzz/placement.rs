@@ -0,0 +1,176 @@
+//! Where a `Fuzzer` places each input in the program under test.
+
+use crate::error::*;
+use crate::executor::{Driver, HookSuccessor, State};
+use crate::il;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How an input is given to the program under test.
+#[derive(Clone, Debug)]
+pub enum Placement {
+    /// Write the input to memory at the given address before execution
+    /// begins. If a scalar is given, it is set to the length of the input.
+    Memory {
+        address: u64,
+        length: Option<il::Scalar>,
+    },
+    /// Serve the input to the program through the read system call.
+    SyscallRead(Box<SyscallRead>),
+}
+
+impl Placement {
+    /// Place the given input in the given driver.
+    pub fn place(&self, driver: &mut Driver, input: &[u8]) -> Result<()> {
+        match *self {
+            Placement::Memory {
+                address,
+                ref length,
+            } => {
+                let state = driver.state_mut();
+                for (offset, byte) in input.iter().enumerate() {
+                    state
+                        .memory_mut()
+                        .store(address + offset as u64, il::const_(*byte as u64, 8))?;
+                }
+                if let Some(length) = length {
+                    state.set_scalar(length.name(), il::const_(input.len() as u64, length.bits()));
+                }
+                Ok(())
+            }
+            Placement::SyscallRead(ref syscall_read) => {
+                syscall_read.place(driver, input);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A model of the read system call which returns the bytes of an input, in
+/// order, over as many calls as the program makes.
+///
+/// Every read is served from the input, regardless of the file descriptor
+/// read from. Once the input is exhausted, reads return 0. System calls other
+/// than read are passed to the hook set for the system call intrinsic when
+/// the input is placed, if there is one.
+#[derive(Clone, Debug)]
+pub struct SyscallRead {
+    mnemonic: String,
+    number: il::Scalar,
+    read: u64,
+    buffer: il::Scalar,
+    count: il::Scalar,
+    result: il::Scalar,
+    error: Option<il::Scalar>,
+}
+
+impl SyscallRead {
+    /// Create a new model of the read system call.
+    ///
+    /// * `mnemonic` - The mnemonic of the system call `il::Intrinsic`.
+    /// * `number` - The scalar holding the system call number.
+    /// * `read` - The system call number of read.
+    /// * `buffer` - The scalar holding the buffer argument.
+    /// * `count` - The scalar holding the count argument.
+    /// * `result` - The scalar the number of bytes read is returned in.
+    pub fn new<S: Into<String>>(
+        mnemonic: S,
+        number: il::Scalar,
+        read: u64,
+        buffer: il::Scalar,
+        count: il::Scalar,
+        result: il::Scalar,
+    ) -> SyscallRead {
+        SyscallRead {
+            mnemonic: mnemonic.into(),
+            number,
+            read,
+            buffer,
+            count,
+            result,
+            error: None,
+        }
+    }
+
+    /// Set a scalar which is cleared to indicate the system call succeeded,
+    /// such as `$a3` on MIPS.
+    pub fn set_error(&mut self, error: il::Scalar) {
+        self.error = Some(error);
+    }
+
+    /// The read system call on Linux for Amd64.
+    pub fn linux_amd64() -> SyscallRead {
+        SyscallRead::new(
+            "syscall",
+            il::scalar("rax", 64),
+            0,
+            il::scalar("rsi", 64),
+            il::scalar("rdx", 64),
+            il::scalar("rax", 64),
+        )
+    }
+
+    /// The read system call on Linux for 32-bit MIPS, using the o32 ABI.
+    pub fn linux_mips() -> SyscallRead {
+        let mut syscall_read = SyscallRead::new(
+            "syscall",
+            il::scalar("$v0", 32),
+            4003,
+            il::scalar("$a1", 32),
+            il::scalar("$a2", 32),
+            il::scalar("$v0", 32),
+        );
+        syscall_read.set_error(il::scalar("$a3", 32));
+        syscall_read
+    }
+
+    fn place(&self, driver: &mut Driver, input: &[u8]) {
+        let syscall_read = self.clone();
+        let previous = driver.hooks().clone();
+        let input = input.to_vec();
+        let offset = AtomicUsize::new(0);
+        driver
+            .hooks_mut()
+            .set_intrinsic(self.mnemonic.clone(), move |state, intrinsic| {
+                if value(state, &syscall_read.number)? != syscall_read.read {
+                    return match previous.intrinsic(intrinsic.mnemonic()) {
+                        Some(hook) => hook(state, intrinsic),
+                        None => Err(ErrorKind::UnhandledIntrinsic(format!(
+                            "{} {}",
+                            intrinsic,
+                            value(state, &syscall_read.number)?
+                        ))
+                        .into()),
+                    };
+                }
+
+                let buffer = value(state, &syscall_read.buffer)?;
+                let count = value(state, &syscall_read.count)?;
+                let start = offset.load(Ordering::Relaxed);
+                let end = input.len().min(start.saturating_add(count as usize));
+                for (i, byte) in input[start..end].iter().enumerate() {
+                    state
+                        .memory_mut()
+                        .store(buffer + i as u64, il::const_(*byte as u64, 8))?;
+                }
+                offset.store(end, Ordering::Relaxed);
+
+                let result = &syscall_read.result;
+                state.set_scalar(
+                    result.name(),
+                    il::const_((end - start) as u64, result.bits()),
+                );
+                if let Some(ref error) = syscall_read.error {
+                    state.set_scalar(error.name(), il::const_(0, error.bits()));
+                }
+                Ok(HookSuccessor::FallThrough)
+            });
+    }
+}
+
+fn value(state: &State, scalar: &il::Scalar) -> Result<u64> {
+    state
+        .get_scalar(scalar.name())
+        .ok_or_else(|| format!("Scalar {} is not set", scalar))?
+        .value_u64()
+        .ok_or_else(|| ErrorKind::TooManyAddressBits.into())
+}
@@ -0,0 +1,48 @@
+//! A small, seedable, pseudo-random number generator.
+
+/// A xorshift64* pseudo-random number generator.
+///
+/// Fuzzing campaigns are reproducible, as the same seed always produces the
+/// same sequence of mutations.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a new `Rng` from the given seed.
+    pub fn new(seed: u64) -> Rng {
+        // Scramble the seed with splitmix64, so similar seeds give unrelated
+        // sequences. A state of zero would only ever produce zero.
+        let mut state = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        state ^= state >> 31;
+        Rng {
+            state: if state == 0 {
+                0x9e37_79b9_7f4a_7c15
+            } else {
+                state
+            },
+        }
+    }
+
+    /// Get the next pseudo-random 64-bit value.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Get a pseudo-random value in the range `0..bound`. `bound` must not be
+    /// 0.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// Get a pseudo-random byte.
+    pub fn byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
@@ -636,6 +636,70 @@ where
         Ok(order.into_iter().rev().collect())
     }
 
+    /// Computes the strongly connected components of the graph.
+    ///
+    /// Each vertex is in exactly one component. Components are returned in
+    /// reverse topological order: a component comes before every component
+    /// with an edge into it.
+    pub fn compute_strongly_connected_components(&self) -> Vec<BTreeSet<usize>> {
+        struct Tarjan {
+            next_index: usize,
+            indices: HashMap<usize, usize>,
+            low_links: HashMap<usize, usize>,
+            stack: Vec<usize>,
+            on_stack: HashSet<usize>,
+            components: Vec<BTreeSet<usize>>,
+        }
+
+        fn dfs_walk<V: Vertex, E: Edge>(graph: &Graph<V, E>, node: usize, tarjan: &mut Tarjan) {
+            tarjan.indices.insert(node, tarjan.next_index);
+            tarjan.low_links.insert(node, tarjan.next_index);
+            tarjan.next_index += 1;
+            tarjan.stack.push(node);
+            tarjan.on_stack.insert(node);
+
+            for successor in &graph.successors[&node] {
+                if !tarjan.indices.contains_key(successor) {
+                    dfs_walk(graph, *successor, tarjan);
+                    let low_link = tarjan.low_links[&node].min(tarjan.low_links[successor]);
+                    tarjan.low_links.insert(node, low_link);
+                } else if tarjan.on_stack.contains(successor) {
+                    let low_link = tarjan.low_links[&node].min(tarjan.indices[successor]);
+                    tarjan.low_links.insert(node, low_link);
+                }
+            }
+
+            if tarjan.low_links[&node] == tarjan.indices[&node] {
+                let mut component = BTreeSet::new();
+                while let Some(vertex) = tarjan.stack.pop() {
+                    tarjan.on_stack.remove(&vertex);
+                    component.insert(vertex);
+                    if vertex == node {
+                        break;
+                    }
+                }
+                tarjan.components.push(component);
+            }
+        }
+
+        let mut tarjan = Tarjan {
+            next_index: 0,
+            indices: HashMap::new(),
+            low_links: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashSet::new(),
+            components: Vec::new(),
+        };
+
+        for vertex in self.vertices.keys() {
+            if !tarjan.indices.contains_key(vertex) {
+                dfs_walk(self, *vertex, &mut tarjan);
+            }
+        }
+
+        tarjan.components
+    }
+
     /// Returns all vertices in the graph.
     pub fn vertices(&self) -> Vec<&V> {
         self.vertices.values().collect()
@@ -1058,6 +1122,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_strongly_connected_components() {
+        let graph = create_test_graph();
+
+        let components = graph.compute_strongly_connected_components();
+        assert_eq!(
+            components,
+            vec![
+                vec![6].into_iter().collect(),
+                vec![2, 3, 4, 5].into_iter().collect(),
+                vec![1].into_iter().collect(),
+            ]
+        );
+    }
+
     #[test]
     fn test_vertices_without_predecessors() {
         let graph = create_test_graph();
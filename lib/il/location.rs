@@ -449,6 +449,11 @@ impl ProgramLocation {
         Ok(RefProgramLocation::new(function, function_location))
     }
 
+    /// Get the index of the `Function` for this `ProgramLocation`
+    pub fn function_index(&self) -> Option<usize> {
+        self.function_index
+    }
+
     /// Get the `FunctionLocation` for this `ProgramLocation`
     pub fn function_location(&self) -> &FunctionLocation {
         &self.function_location
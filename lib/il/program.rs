@@ -72,6 +72,19 @@ impl Program {
         self.next_index += 1;
     }
 
+    /// Replace the `Function` with the given index, for example with a
+    /// `Function` lifted again after its code was modified.
+    ///
+    /// The replacement keeps the index of the `Function` it replaces.
+    pub fn replace_function(&mut self, index: usize, mut function: Function) -> Result<()> {
+        if !self.functions.contains_key(&index) {
+            bail!("No function with index {} in program", index);
+        }
+        function.set_index(Some(index));
+        self.functions.insert(index, RC::new(function));
+        Ok(())
+    }
+
     /// Get a `Function` by its name.
     pub fn function_by_name(&self, name: &str) -> Option<&Function> {
         self.functions
@@ -9,10 +9,12 @@
 //! over Falcon IL. Example, usable analyses are given.
 //! * **architecture** - Information on Falcon's supported architectures.
 //! * **executor** - A concrete execution engine over Falcon IL.
+//! * **fuzz** - A coverage-guided fuzzer over the concrete executor.
 //! * **graph** - A simple directed graph library.
 //! * **il** - Falcon's Intermediate Language.
 //! * **loader** - Loaders for binary formats, currently supporting Elf.
 //! * **memory** - A layered memory model over generic types.
+//! * **symbolic** - A symbolic execution engine over Falcon IL.
 //! * **translator** - Translators from native architectures to Falcon IL.
 //!
 //! Falcon also has bindings for the scripting language
@@ -56,10 +58,12 @@ extern crate serde_json;
 pub mod analysis;
 pub mod architecture;
 pub mod executor;
+pub mod fuzz;
 pub mod graph;
 pub mod il;
 pub mod loader;
 pub mod memory;
+pub mod symbolic;
 pub mod transformation;
 pub mod translator;
 
@@ -132,6 +136,10 @@ pub mod error {
                 description("A vertex was not found in a graph")
                 display("The vertex id {} does not exist in the graph", vertex_id)
             }
+            MemoryFault(fault: crate::memory::MemoryFault) {
+                description("A memory access violated memory permissions")
+                display("Memory fault: {}", fault)
+            }
             ProgramLocationMigration(reason: String) {
                 description("Error migrating ProgramLocation between Program")
                 display("Failed to migrate ProgramLocation between Program: {}", reason)
@@ -18,9 +18,10 @@
 //! # }
 //! ```
 
+use crate::analysis::call_graph;
+use crate::analysis::jump_tables;
 use crate::architecture::Architecture;
 use crate::error::*;
-use crate::executor::eval;
 use crate::il;
 use crate::memory;
 use std::any::Any;
@@ -36,6 +37,7 @@ pub use self::elf::*;
 pub use self::json::*;
 pub use self::pe::*;
 pub use self::symbol::Symbol;
+pub(crate) use self::symbol::{base_name, unversioned_name};
 
 /// A declared entry point for a function.
 #[derive(Clone, Debug, PartialEq)]
@@ -90,10 +92,11 @@ pub trait Loader: fmt::Debug + Send + Sync {
     fn architecture(&self) -> &dyn Architecture;
 
     /// Lift just one function from the executable
+    ///
+    /// Jump tables in read-only memory are resolved into edges.
     fn function(&self, address: u64) -> Result<il::Function> {
-        let translator = self.architecture().translator();
         let memory = self.memory()?;
-        Ok(translator.translate_function(&memory, address)?)
+        jump_tables::translate_function(self.architecture(), &memory, address)
     }
 
     /// Cast loader to `Any`
@@ -125,9 +128,6 @@ pub trait Loader: fmt::Debug + Send + Sync {
     fn program_verbose(
         &self,
     ) -> std::result::Result<(il::Program, Vec<(FunctionEntry, Error)>), Error> {
-        // Get out architecture-specific translator
-        let translator = self.architecture().translator();
-
         // Create a mapping of the file memory
         let memory = self.memory()?;
 
@@ -142,7 +142,7 @@ pub trait Loader: fmt::Debug + Send + Sync {
                 .permissions(address)
                 .map_or(false, |p| p.contains(memory::MemoryPermissions::EXECUTE))
             {
-                match translator.translate_function(&memory, address) {
+                match jump_tables::translate_function(self.architecture(), &memory, address) {
                     Ok(mut function) => {
                         function.set_name(function_entry.name().map(|n| n.to_string()));
                         program.add_function(function);
@@ -171,81 +171,44 @@ pub trait Loader: fmt::Debug + Send + Sync {
     fn program_recursive_verbose(
         &self,
     ) -> std::result::Result<(il::Program, Vec<(FunctionEntry, Error)>), Error> {
-        fn call_targets(function: &il::Function) -> Result<Vec<u64>> {
-            let call_targets =
-                function
-                    .blocks()
-                    .iter()
-                    .fold(Vec::new(), |mut call_targets, block| {
-                        block.instructions().iter().for_each(|instruction| {
-                            match *instruction.operation() {
-                                il::Operation::Branch { ref target } => {
-                                    eval(target).ok().map(|constant| {
-                                        call_targets.push(constant.value_u64().unwrap())
-                                    });
-                                }
-                                _ => {}
-                            }
-                        });
-                        call_targets
-                    });
-            Ok(call_targets)
-        }
-
         let (mut program, mut translation_errors) = self.program_verbose()?;
-        let mut processed = HashSet::new();
+        let mut attempted = HashSet::new();
 
-        loop {
-            // Get the address of every function currently in the program
-            let function_addresses = program
-                .functions()
+        // The addresses called directly by functions of the program.
+        let direct_targets = |function: &il::Function| -> Result<Vec<u64>> {
+            Ok(call_graph::call_sites(function)?
                 .into_iter()
-                .map(|function| function.address())
-                .collect::<Vec<u64>>();
-
-            let addresses = {
-                // For every function in the program which is not currentl a
-                // member of our processed set
-                let functions = program
-                    .functions()
-                    .into_iter()
-                    .filter(|function| !processed.contains(&function.address()))
-                    .collect::<Vec<&il::Function>>();
-
-                // Insert this function into the processed set
-                functions.iter().for_each(|function| {
-                    processed.insert(function.address());
-                });
-
-                // Collect the call targets in all functions that have not yet
-                // been processed, and filter them against the functions already
-                // in program.
-                let addresses = functions
-                    .into_iter()
-                    .fold(HashSet::new(), |mut targets, function| {
-                        call_targets(function)
-                            .unwrap()
-                            .into_iter()
-                            .for_each(|target| {
-                                targets.insert(target);
-                            });
-                        targets
-                    })
-                    .into_iter()
-                    .filter(|address| !function_addresses.contains(address))
-                    .collect::<Vec<u64>>();
+                .filter_map(|(_, target)| target)
+                .collect())
+        };
+
+        // Only the functions lifted in the last round have calls to scan.
+        let mut targets = Vec::new();
+        for function in program.functions_map().values() {
+            targets.append(&mut direct_targets(function)?);
+        }
 
-                if addresses.is_empty() {
-                    break;
-                }
+        loop {
+            // Every address called directly which is not yet a function in
+            // the program, and which we have not yet attempted to lift.
+            let addresses = targets
+                .drain(..)
+                .filter(|address| {
+                    program.function_by_address(*address).is_none() && attempted.insert(*address)
+                })
+                .collect::<Vec<u64>>();
 
-                addresses
-            };
+            if addresses.is_empty() {
+                break;
+            }
 
             // For each address, attempt to lift a function
             for address in addresses {
                 match self.function(address) {
-                    Ok(function) => program.add_function(function),
+                    Ok(function) => {
+                        targets.append(&mut direct_targets(&function)?);
+                        program.add_function(function);
+                    }
                     Err(e) => {
                         let function_entry = FunctionEntry::new(address, None);
                         translation_errors.push((function_entry, e));
@@ -20,3 +20,14 @@ impl Symbol {
         self.address
     }
 }
+
+/// The name of a symbol without any version or `@plt` suffix.
+pub(crate) fn unversioned_name(name: &str) -> &str {
+    name.split('@').next().unwrap_or(name)
+}
+
+/// The name of a function without leading underscores, or any version or
+/// `@plt` suffix.
+pub(crate) fn base_name(name: &str) -> &str {
+    unversioned_name(name.trim_start_matches('_'))
+}
@@ -72,6 +72,31 @@ impl Memory {
         &self.sections
     }
 
+    /// Get a digest of the contents of this memory.
+    ///
+    /// The digest is stable across runs, and identifies a backing which was
+    /// referenced, rather than copied, when a paged memory was serialised.
+    pub fn digest(&self) -> u64 {
+        // 64-bit FNV-1a
+        fn update(hash: u64, bytes: &[u8]) -> u64 {
+            bytes.iter().fold(hash, |hash, byte| {
+                (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+        }
+        let endian = match self.endian {
+            Endian::Big => 0,
+            Endian::Little => 1,
+        };
+        let mut hash = update(0xcbf2_9ce4_8422_2325, &[endian]);
+        for (address, section) in &self.sections {
+            hash = update(hash, &address.to_le_bytes());
+            hash = update(hash, &(section.len() as u64).to_le_bytes());
+            hash = update(hash, &section.permissions().bits().to_le_bytes());
+            hash = update(hash, section.data());
+        }
+        hash
+    }
+
     /// Get the permissions at the given address.
     pub fn permissions(&self, address: u64) -> Option<MemoryPermissions> {
         match self.section_address(address) {
@@ -13,6 +13,9 @@
 //! `TranslationMemory` trait in the `translator` module. Implementation of this
 //! trait will allow the translator to lift instructions from your memory model.
 
+use crate::il;
+use std::fmt;
+
 pub mod backing;
 pub mod paged;
 mod value;
@@ -30,3 +33,80 @@ bitflags! {
         const ALL     = 0b111;
     }
 }
+
+/// The kind of a `MemoryFault`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum MemoryFaultKind {
+    /// A read from memory without `MemoryPermissions::READ`.
+    ReadViolation,
+    /// A write to memory without `MemoryPermissions::WRITE`.
+    WriteViolation,
+    /// Execution of memory without `MemoryPermissions::EXECUTE`.
+    ExecuteViolation,
+    /// An access to memory with no permissions set.
+    Unmapped,
+}
+
+/// An access to memory which violated its permissions.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct MemoryFault {
+    kind: MemoryFaultKind,
+    address: u64,
+    bits: usize,
+    location: Option<il::ProgramLocation>,
+}
+
+impl MemoryFault {
+    /// Create a new `MemoryFault` for an access of `bits` at `address`.
+    pub fn new(kind: MemoryFaultKind, address: u64, bits: usize) -> MemoryFault {
+        MemoryFault {
+            kind,
+            address,
+            bits,
+            location: None,
+        }
+    }
+
+    /// Set the location of the instruction which made the faulting access.
+    pub fn with_location(mut self, location: il::ProgramLocation) -> MemoryFault {
+        self.location = Some(location);
+        self
+    }
+
+    /// The kind of this fault.
+    pub fn kind(&self) -> MemoryFaultKind {
+        self.kind
+    }
+
+    /// The address of the first byte which faulted.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// The size of the faulting access in bits.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The location of the instruction which made the faulting access, if
+    /// known.
+    pub fn location(&self) -> Option<&il::ProgramLocation> {
+        self.location.as_ref()
+    }
+}
+
+impl fmt::Display for MemoryFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            MemoryFaultKind::ReadViolation => "Read violation",
+            MemoryFaultKind::WriteViolation => "Write violation",
+            MemoryFaultKind::ExecuteViolation => "Execute violation",
+            MemoryFaultKind::Unmapped => "Unmapped access",
+        };
+        write!(f, "{} of {} bits at 0x{:x}", kind, self.bits, self.address)?;
+        if let Some(ref location) = self.location {
+            write!(f, " at {}", location)?;
+        }
+        Ok(())
+    }
+}
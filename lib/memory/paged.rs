@@ -7,11 +7,11 @@ use crate::architecture::Endian;
 use crate::error::*;
 use crate::il;
 use crate::RC;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use crate::memory::backing;
 use crate::memory::value::Value;
-use crate::memory::MemoryPermissions;
+use crate::memory::{MemoryFault, MemoryFaultKind, MemoryPermissions};
 
 /// The size of the copy-on-write pages.
 pub const PAGE_SIZE: usize = 1024;
@@ -84,7 +84,10 @@ where
 
 impl<V: Value> PartialEq for Memory<V> {
     fn eq(&self, other: &Self) -> bool {
-        if self.pages == other.pages && self.endian == other.endian {
+        if self.pages == other.pages
+            && self.endian == other.endian
+            && self.enforcing == other.enforcing
+        {
             self.backing()
                 .and_then(|self_backing| {
                     other.backing().map(|other_backing| {
@@ -109,6 +112,12 @@ pub struct Memory<V: Value> {
     backing: Option<RC<backing::Memory>>,
     endian: Endian,
     pub(crate) pages: HashMap<u64, RC<Page<V>>>,
+    #[serde(default)]
+    enforcing: bool,
+    #[serde(default)]
+    tracking_code_writes: bool,
+    #[serde(default)]
+    code_writes: BTreeSet<u64>,
 }
 
 impl<V> Memory<V>
@@ -121,6 +130,9 @@ where
             backing: None,
             endian: endian,
             pages: HashMap::new(),
+            enforcing: false,
+            tracking_code_writes: false,
+            code_writes: BTreeSet::new(),
         }
     }
 
@@ -139,6 +151,9 @@ where
             backing: Some(backing),
             endian: endian,
             pages: HashMap::new(),
+            enforcing: false,
+            tracking_code_writes: false,
+            code_writes: BTreeSet::new(),
         }
     }
 
@@ -147,8 +162,8 @@ where
         let page_address = address & PAGE_MASK;
         self.pages
             .get(&page_address)
-            .map(|page| page.permissions().cloned())
-            .unwrap_or_else(|| {
+            .and_then(|page| page.permissions().cloned())
+            .or_else(|| {
                 self.backing()
                     .and_then(|backing| backing.permissions(address))
             })
@@ -157,8 +172,8 @@ where
     /// Set memory permissions for the page at the given address
     pub fn set_permissions(&mut self, address: u64, len: u64, permissions: MemoryPermissions) {
         let mut page_address = address & PAGE_MASK;
-        let total_length = len as u64 + (address - page_address);
-        while page_address < total_length {
+        let end = address.saturating_add(len);
+        while page_address < end {
             RC::make_mut(
                 self.pages
                     .entry(page_address)
@@ -169,6 +184,89 @@ where
         }
     }
 
+    /// Returns true if loads and stores check memory permissions.
+    pub fn enforcing(&self) -> bool {
+        self.enforcing
+    }
+
+    /// Set whether loads and stores check memory permissions.
+    ///
+    /// When enforcing, `load` and `store` fail with `ErrorKind::MemoryFault`
+    /// if any byte accessed lacks the required permission, or has no
+    /// permissions at all.
+    pub fn set_enforcing(&mut self, enforcing: bool) {
+        self.enforcing = enforcing;
+    }
+
+    /// Check an access of `bits` at `address` which requires `permission`,
+    /// returning the `MemoryFault` it would raise, if any.
+    ///
+    /// This check is made regardless of whether this memory is enforcing.
+    pub fn fault(
+        &self,
+        address: u64,
+        bits: usize,
+        permission: MemoryPermissions,
+    ) -> Option<MemoryFault> {
+        let bytes = (bits as u64).div_ceil(8).max(1);
+        for offset in 0..bytes {
+            let byte_address = address.wrapping_add(offset);
+            let kind = match self.permissions(byte_address) {
+                None => MemoryFaultKind::Unmapped,
+                Some(permissions) if permissions.contains(permission) => continue,
+                Some(_) => {
+                    if permission.contains(MemoryPermissions::EXECUTE) {
+                        MemoryFaultKind::ExecuteViolation
+                    } else if permission.contains(MemoryPermissions::WRITE) {
+                        MemoryFaultKind::WriteViolation
+                    } else {
+                        MemoryFaultKind::ReadViolation
+                    }
+                }
+            };
+            return Some(MemoryFault::new(kind, byte_address, bits));
+        }
+        None
+    }
+
+    fn check(&self, address: u64, bits: usize, permission: MemoryPermissions) -> Result<()> {
+        if self.enforcing {
+            if let Some(fault) = self.fault(address, bits, permission) {
+                return Err(ErrorKind::MemoryFault(fault).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if writes to executable bytes are recorded.
+    pub fn tracking_code_writes(&self) -> bool {
+        self.tracking_code_writes
+    }
+
+    /// Set whether writes to executable bytes are recorded, to be taken with
+    /// `take_code_writes`. Recorded writes accumulate until taken, so only
+    /// track code writes when they will be taken.
+    pub fn set_tracking_code_writes(&mut self, tracking_code_writes: bool) {
+        self.tracking_code_writes = tracking_code_writes;
+        if !tracking_code_writes {
+            self.code_writes.clear();
+        }
+    }
+
+    /// The addresses of executable bytes written since the last call to
+    /// `take_code_writes`.
+    ///
+    /// Code lifted from these bytes before they were written is stale.
+    pub fn code_writes(&self) -> &BTreeSet<u64> {
+        &self.code_writes
+    }
+
+    /// Take the addresses of executable bytes written since the last call to
+    /// this method, clearing them.
+    pub fn take_code_writes(&mut self) -> BTreeSet<u64> {
+        std::mem::take(&mut self.code_writes)
+    }
+
     /// Get a reference to the memory backing, if there is one
     pub fn backing(&self) -> Option<RC<backing::Memory>> {
         self.backing.clone()
@@ -228,6 +326,8 @@ where
     ///
     /// The value must have a bit-width >= 8, and the bit-width must be evenly
     /// divisible by 8.
+    ///
+    /// When this memory is enforcing, every byte written must be writable.
     pub fn store(&mut self, address: u64, value: V) -> Result<()> {
         if value.bits() % 8 != 0 || value.bits() == 0 {
             return Err(format!(
@@ -237,6 +337,21 @@ where
             .into());
         }
 
+        self.check(address, value.bits(), MemoryPermissions::WRITE)?;
+
+        if self.tracking_code_writes {
+            for offset in 0..(value.bits() / 8) as u64 {
+                let byte_address = address.wrapping_add(offset);
+                if self
+                    .permissions(byte_address)
+                    .map(|permissions| permissions.contains(MemoryPermissions::EXECUTE))
+                    .unwrap_or(false)
+                {
+                    self.code_writes.insert(byte_address);
+                }
+            }
+        }
+
         // There are a few scenarios here we need to account for
         // E is for Expression, B is for Backref. Consider a 4-byte write, with
         // the original memory on top and our write immediately underneath that.
@@ -276,7 +391,7 @@ where
                 // how many bits are left after our write
                 let left_bits = ((backref_furthest_address - address_after_write) * 8) as usize;
                 // load that value
-                self.load(address_after_write, left_bits)?
+                self.load_unchecked(address_after_write, left_bits)?
             } else {
                 None
             }
@@ -298,7 +413,10 @@ where
                 let overwrite_bits = (backref_furthest_address - address) * 8;
                 // how many bits are left over
                 let left_bits = backref_value.bits() - overwrite_bits as usize;
-                Some((backref_address, self.load(backref_address, left_bits)?))
+                Some((
+                    backref_address,
+                    self.load_unchecked(backref_address, left_bits)?,
+                ))
             } else {
                 None
             }
@@ -322,7 +440,18 @@ where
     ///
     /// If a value cannot be retrieved for all bits of the load, `None` will
     /// be returned.
+    ///
+    /// When this memory is enforcing, every byte read must be readable.
     pub fn load(&self, address: u64, bits: usize) -> Result<Option<V>> {
+        if bits % 8 == 0 && bits != 0 {
+            self.check(address, bits, MemoryPermissions::READ)?;
+        }
+        self.load_unchecked(address, bits)
+    }
+
+    /// Loads a value from the given address without checking memory
+    /// permissions, even when this memory is enforcing.
+    pub fn load_unchecked(&self, address: u64, bits: usize) -> Result<Option<V>> {
         if bits % 8 != 0 {
             return Err(format!("Loading paged memory with non-8 bit-width {}", bits).into());
         } else if bits == 0 {
@@ -445,7 +574,7 @@ where
         let mut result: Option<V> = None;
         let bytes = (bits / 8) as u64;
         for offset in 0..bytes {
-            let value = match self.load(address + offset, 8)? {
+            let value = match self.load_unchecked(address + offset, 8)? {
                 Some(v) => v,
                 None => match self.load_backing(address) {
                     Some(v) => v,
@@ -471,10 +600,11 @@ where
 #[cfg(test)]
 mod memory_tests {
     use crate::architecture::Endian;
+    use crate::error::*;
     use crate::il;
     use crate::memory;
     use crate::memory::paged::Memory;
-    use crate::memory::MemoryPermissions;
+    use crate::memory::{MemoryFaultKind, MemoryPermissions};
     use crate::RC;
 
     #[test]
@@ -652,4 +782,74 @@ mod memory_tests {
             memory.permissions(0x100).unwrap()
         );
     }
+
+    #[test]
+    fn enforcing() {
+        let mut memory: Memory<il::Constant> = Memory::new(Endian::Little);
+        memory.set_permissions(0x1000, 0x10, MemoryPermissions::READ);
+        memory.set_permissions(0x1400, 0x10, MemoryPermissions::ALL);
+        memory.store(0x13fe, il::const_(0xaabb_ccdd, 32)).unwrap();
+
+        memory.set_enforcing(true);
+
+        // Stores to read-only memory fault, and leave memory unchanged.
+        let fault = match memory.store(0x1000, il::const_(1, 8)) {
+            Err(Error(ErrorKind::MemoryFault(fault), _)) => fault,
+            _ => panic!("expected a memory fault"),
+        };
+        assert_eq!(fault.kind(), MemoryFaultKind::WriteViolation);
+        assert_eq!(fault.address(), 0x1000);
+        assert_eq!(fault.bits(), 8);
+        assert_eq!(memory.load(0x1000, 8).unwrap(), None);
+
+        // Accesses may span pages, and the first byte which faults is
+        // reported.
+        assert_eq!(
+            memory.load(0x13fe, 32).unwrap(),
+            Some(il::const_(0xaabb_ccdd, 32))
+        );
+        let fault = memory.fault(0x13fe, 32, MemoryPermissions::WRITE).unwrap();
+        assert_eq!(fault.kind(), MemoryFaultKind::WriteViolation);
+        assert_eq!(fault.address(), 0x13fe);
+        let fault = memory.fault(0x17fe, 32, MemoryPermissions::READ).unwrap();
+        assert_eq!(fault.kind(), MemoryFaultKind::Unmapped);
+        assert_eq!(fault.address(), 0x1800);
+
+        let fault = memory.fault(0x1000, 8, MemoryPermissions::EXECUTE).unwrap();
+        assert_eq!(fault.kind(), MemoryFaultKind::ExecuteViolation);
+
+        match memory.load(0x2000, 32) {
+            Err(Error(ErrorKind::MemoryFault(fault), _)) => {
+                assert_eq!(fault.kind(), MemoryFaultKind::Unmapped)
+            }
+            _ => panic!("expected a memory fault"),
+        }
+
+        assert_eq!(
+            memory.load(0x1400, 16).unwrap(),
+            Some(il::const_(0xaabb, 16))
+        );
+        memory.store(0x1400, il::const_(0x1122, 16)).unwrap();
+        assert!(memory.fault(0x1400, 128, MemoryPermissions::ALL).is_none());
+    }
+
+    #[test]
+    fn code_writes() {
+        let mut memory: Memory<il::Constant> = Memory::new(Endian::Little);
+        memory.set_permissions(0x1000, 0x10, MemoryPermissions::ALL);
+        memory.set_permissions(0x2000, 0x10, MemoryPermissions::READ);
+
+        // Code writes are only recorded once tracked.
+        memory.store(0x1000, il::const_(1, 32)).unwrap();
+        assert!(memory.code_writes().is_empty());
+
+        memory.set_tracking_code_writes(true);
+        memory.store(0x1000, il::const_(1, 32)).unwrap();
+        memory.store(0x2000, il::const_(1, 32)).unwrap();
+        assert_eq!(
+            memory.take_code_writes().into_iter().collect::<Vec<u64>>(),
+            vec![0x1000, 0x1001, 0x1002, 0x1003]
+        );
+        assert!(memory.code_writes().is_empty());
+    }
 }
@@ -0,0 +1,613 @@
+//! A decision procedure for Falcon IL constraints by bit-blasting.
+//!
+//! Every `il::Expression` is translated to a circuit over the bits of its
+//! operands, and each gate of the circuit is encoded as clauses with the
+//! Tseitin transformation. The clauses are decided by the built-in
+//! `sat::Sat` solver.
+//!
+//! Division and remainder by zero follow SMT-LIB2: unsigned division by zero
+//! gives all ones, and the remainder is the dividend.
+
+use crate::error::*;
+use crate::il;
+use crate::symbolic::sat::{Lit, Sat, Status};
+use crate::symbolic::{Model, Solver};
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use std::collections::{BTreeMap, HashMap};
+
+/// Translates `il::Expression` constraints to clauses in a `Sat` solver.
+///
+/// Bits are ordered from least significant to most significant.
+#[derive(Clone, Debug)]
+pub struct BitBlaster {
+    sat: Sat,
+    true_: Lit,
+    scalars: BTreeMap<String, Vec<Lit>>,
+    cache: HashMap<il::Expression, Vec<Lit>>,
+}
+
+impl Default for BitBlaster {
+    fn default() -> BitBlaster {
+        BitBlaster::new()
+    }
+}
+
+impl BitBlaster {
+    /// Create a new `BitBlaster` with no constraints.
+    pub fn new() -> BitBlaster {
+        let mut sat = Sat::new();
+        let true_ = sat.new_var();
+        sat.add_clause(&[true_]);
+        BitBlaster {
+            sat,
+            true_,
+            scalars: BTreeMap::new(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Retrieve the underlying `Sat` solver.
+    pub fn sat(&self) -> &Sat {
+        &self.sat
+    }
+
+    /// Retrieve a mutable reference to the underlying `Sat` solver.
+    pub fn sat_mut(&mut self) -> &mut Sat {
+        &mut self.sat
+    }
+
+    fn false_(&self) -> Lit {
+        !self.true_
+    }
+
+    fn constant_bit(&self, value: bool) -> Lit {
+        if value {
+            self.true_
+        } else {
+            self.false_()
+        }
+    }
+
+    fn is_constant(&self, lit: Lit) -> Option<bool> {
+        if lit == self.true_ {
+            Some(true)
+        } else if lit == self.false_() {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn and(&mut self, a: Lit, b: Lit) -> Lit {
+        match (self.is_constant(a), self.is_constant(b)) {
+            (Some(false), _) | (_, Some(false)) => return self.false_(),
+            (Some(true), _) => return b,
+            (_, Some(true)) => return a,
+            _ => {}
+        }
+        if a == b {
+            return a;
+        } else if a == !b {
+            return self.false_();
+        }
+        let g = self.sat.new_var();
+        self.sat.add_clause(&[!g, a]);
+        self.sat.add_clause(&[!g, b]);
+        self.sat.add_clause(&[g, !a, !b]);
+        g
+    }
+
+    fn or(&mut self, a: Lit, b: Lit) -> Lit {
+        !self.and(!a, !b)
+    }
+
+    fn xor(&mut self, a: Lit, b: Lit) -> Lit {
+        match (self.is_constant(a), self.is_constant(b)) {
+            (Some(a), Some(b)) => return self.constant_bit(a != b),
+            (Some(false), _) => return b,
+            (Some(true), _) => return !b,
+            (_, Some(false)) => return a,
+            (_, Some(true)) => return !a,
+            _ => {}
+        }
+        if a == b {
+            return self.false_();
+        } else if a == !b {
+            return self.true_;
+        }
+        let g = self.sat.new_var();
+        self.sat.add_clause(&[!g, a, b]);
+        self.sat.add_clause(&[!g, !a, !b]);
+        self.sat.add_clause(&[g, !a, b]);
+        self.sat.add_clause(&[g, a, !b]);
+        g
+    }
+
+    /// If `c` then `t` else `e`.
+    fn mux(&mut self, c: Lit, t: Lit, e: Lit) -> Lit {
+        match self.is_constant(c) {
+            Some(true) => return t,
+            Some(false) => return e,
+            None => {}
+        }
+        if t == e {
+            return t;
+        }
+        let g = self.sat.new_var();
+        self.sat.add_clause(&[!c, !t, g]);
+        self.sat.add_clause(&[!c, t, !g]);
+        self.sat.add_clause(&[c, !e, g]);
+        self.sat.add_clause(&[c, e, !g]);
+        g
+    }
+
+    fn mux_bits(&mut self, c: Lit, t: &[Lit], e: &[Lit]) -> Vec<Lit> {
+        t.iter()
+            .zip(e.iter())
+            .map(|(&t, &e)| self.mux(c, t, e))
+            .collect()
+    }
+
+    fn constant(&self, constant: &il::Constant) -> Vec<Lit> {
+        let value = constant.value();
+        (0..constant.bits())
+            .map(|i| self.constant_bit(!((value >> i) & BigUint::one()).is_zero()))
+            .collect()
+    }
+
+    /// Returns the sum and the carry out.
+    fn add(&mut self, a: &[Lit], b: &[Lit], carry_in: Lit) -> (Vec<Lit>, Lit) {
+        let mut carry = carry_in;
+        let mut sum = Vec::with_capacity(a.len());
+        for (&a, &b) in a.iter().zip(b.iter()) {
+            let a_xor_b = self.xor(a, b);
+            sum.push(self.xor(a_xor_b, carry));
+            let a_and_b = self.and(a, b);
+            let carry_and = self.and(a_xor_b, carry);
+            carry = self.or(a_and_b, carry_and);
+        }
+        (sum, carry)
+    }
+
+    fn negate_bits(&self, a: &[Lit]) -> Vec<Lit> {
+        a.iter().map(|&lit| !lit).collect()
+    }
+
+    /// Returns the difference, and a literal which is true if `a < b`
+    /// unsigned.
+    fn sub(&mut self, a: &[Lit], b: &[Lit]) -> (Vec<Lit>, Lit) {
+        let not_b = self.negate_bits(b);
+        let true_ = self.true_;
+        let (difference, carry) = self.add(a, &not_b, true_);
+        (difference, !carry)
+    }
+
+    fn neg(&mut self, a: &[Lit]) -> Vec<Lit> {
+        let zero = vec![self.false_(); a.len()];
+        self.sub(&zero, a).0
+    }
+
+    fn mul(&mut self, a: &[Lit], b: &[Lit]) -> Vec<Lit> {
+        let false_ = self.false_();
+        let mut product = vec![false_; a.len()];
+        for (i, &b) in b.iter().enumerate() {
+            if self.is_constant(b) == Some(false) {
+                continue;
+            }
+            let mut partial = vec![false_; a.len()];
+            for j in 0..(a.len() - i) {
+                partial[i + j] = self.and(a[j], b);
+            }
+            product = self.add(&product, &partial, false_).0;
+        }
+        product
+    }
+
+    /// Restoring division, returning the quotient and remainder.
+    fn divu(&mut self, a: &[Lit], b: &[Lit]) -> (Vec<Lit>, Vec<Lit>) {
+        let false_ = self.false_();
+        let bits = a.len();
+        let mut quotient = vec![false_; bits];
+        let mut remainder = vec![false_; bits];
+        for i in (0..bits).rev() {
+            // remainder = (remainder << 1) | a[i], with the bit shifted out
+            // kept, as the remainder may need bits + 1 bits before
+            // subtraction.
+            let overflow = remainder[bits - 1];
+            remainder.pop();
+            remainder.insert(0, a[i]);
+            let (difference, less) = self.sub(&remainder, b);
+            // remainder >= b if a bit was shifted out, or no borrow occurred
+            let greater_equal = self.or(overflow, !less);
+            quotient[i] = greater_equal;
+            remainder = self.mux_bits(greater_equal, &difference, &remainder);
+        }
+        (quotient, remainder)
+    }
+
+    fn abs(&mut self, a: &[Lit]) -> Vec<Lit> {
+        let sign = a[a.len() - 1];
+        let negated = self.neg(a);
+        self.mux_bits(sign, &negated, a)
+    }
+
+    fn divs(&mut self, a: &[Lit], b: &[Lit]) -> (Vec<Lit>, Vec<Lit>) {
+        let a_sign = a[a.len() - 1];
+        let b_sign = b[b.len() - 1];
+        let a_abs = self.abs(a);
+        let b_abs = self.abs(b);
+        let (quotient, remainder) = self.divu(&a_abs, &b_abs);
+
+        let quotient_sign = self.xor(a_sign, b_sign);
+        let negated = self.neg(&quotient);
+        let quotient = self.mux_bits(quotient_sign, &negated, &quotient);
+        let negated = self.neg(&remainder);
+        let remainder = self.mux_bits(a_sign, &negated, &remainder);
+        (quotient, remainder)
+    }
+
+    fn shift(&mut self, a: &[Lit], b: &[Lit], left: bool) -> Vec<Lit> {
+        let bits = a.len();
+        let false_ = self.false_();
+        let mut result = a.to_vec();
+        let mut overflow = false_;
+        for (stage, &b) in b.iter().enumerate() {
+            let distance = 1usize.checked_shl(stage as u32).unwrap_or(usize::MAX);
+            if distance >= bits {
+                overflow = self.or(overflow, b);
+                continue;
+            }
+            let shifted = (0..bits)
+                .map(|i| {
+                    if left {
+                        if i >= distance {
+                            result[i - distance]
+                        } else {
+                            false_
+                        }
+                    } else if i + distance < bits {
+                        result[i + distance]
+                    } else {
+                        false_
+                    }
+                })
+                .collect::<Vec<Lit>>();
+            result = self.mux_bits(b, &shifted, &result);
+        }
+        let zero = vec![false_; bits];
+        self.mux_bits(overflow, &zero, &result)
+    }
+
+    fn equal(&mut self, a: &[Lit], b: &[Lit]) -> Lit {
+        let mut equal = self.true_;
+        for (&a, &b) in a.iter().zip(b.iter()) {
+            let bit_equal = !self.xor(a, b);
+            equal = self.and(equal, bit_equal);
+        }
+        equal
+    }
+
+    fn lts(&mut self, a: &[Lit], b: &[Lit]) -> Lit {
+        // Flipping the sign bits turns a signed comparison unsigned.
+        let mut a = a.to_vec();
+        let mut b = b.to_vec();
+        let last = a.len() - 1;
+        a[last] = !a[last];
+        b[last] = !b[last];
+        self.sub(&a, &b).1
+    }
+
+    /// Translate an `il::Expression` to a circuit, returning the literals for
+    /// each bit of its value.
+    pub fn blast(&mut self, expression: &il::Expression) -> Result<Vec<Lit>> {
+        if let Some(bits) = self.cache.get(expression) {
+            return Ok(bits.clone());
+        }
+
+        let bits = match *expression {
+            il::Expression::Scalar(ref scalar) => {
+                if let Some(bits) = self.scalars.get(scalar.name()) {
+                    if bits.len() != scalar.bits() {
+                        return Err(ErrorKind::Sort.into());
+                    }
+                    bits.clone()
+                } else {
+                    let bits = (0..scalar.bits())
+                        .map(|_| self.sat.new_var())
+                        .collect::<Vec<Lit>>();
+                    self.scalars.insert(scalar.name().to_string(), bits.clone());
+                    bits
+                }
+            }
+            il::Expression::Constant(ref constant) => self.constant(constant),
+            il::Expression::Add(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                let false_ = self.false_();
+                self.add(&lhs, &rhs, false_).0
+            }
+            il::Expression::Sub(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                self.sub(&lhs, &rhs).0
+            }
+            il::Expression::Mul(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                self.mul(&lhs, &rhs)
+            }
+            il::Expression::Divu(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                self.divu(&lhs, &rhs).0
+            }
+            il::Expression::Modu(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                self.divu(&lhs, &rhs).1
+            }
+            il::Expression::Divs(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                self.divs(&lhs, &rhs).0
+            }
+            il::Expression::Mods(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                self.divs(&lhs, &rhs).1
+            }
+            il::Expression::And(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                lhs.iter()
+                    .zip(rhs.iter())
+                    .map(|(&a, &b)| self.and(a, b))
+                    .collect()
+            }
+            il::Expression::Or(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                lhs.iter()
+                    .zip(rhs.iter())
+                    .map(|(&a, &b)| self.or(a, b))
+                    .collect()
+            }
+            il::Expression::Xor(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                lhs.iter()
+                    .zip(rhs.iter())
+                    .map(|(&a, &b)| self.xor(a, b))
+                    .collect()
+            }
+            il::Expression::Shl(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                self.shift(&lhs, &rhs, true)
+            }
+            il::Expression::Shr(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                self.shift(&lhs, &rhs, false)
+            }
+            il::Expression::Cmpeq(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                vec![self.equal(&lhs, &rhs)]
+            }
+            il::Expression::Cmpneq(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                vec![!self.equal(&lhs, &rhs)]
+            }
+            il::Expression::Cmplts(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                vec![self.lts(&lhs, &rhs)]
+            }
+            il::Expression::Cmpltu(ref lhs, ref rhs) => {
+                let (lhs, rhs) = (self.blast(lhs)?, self.blast(rhs)?);
+                vec![self.sub(&lhs, &rhs).1]
+            }
+            il::Expression::Zext(bits, ref src) => {
+                let mut src = self.blast(src)?;
+                src.resize(bits, self.false_());
+                src
+            }
+            il::Expression::Sext(bits, ref src) => {
+                let mut src = self.blast(src)?;
+                let sign = src[src.len() - 1];
+                src.resize(bits, sign);
+                src
+            }
+            il::Expression::Trun(bits, ref src) => {
+                let mut src = self.blast(src)?;
+                src.truncate(bits);
+                src
+            }
+            il::Expression::Ite(ref cond, ref then, ref else_) => {
+                let cond = self.blast(cond)?[0];
+                let (then, else_) = (self.blast(then)?, self.blast(else_)?);
+                self.mux_bits(cond, &then, &else_)
+            }
+        };
+
+        self.cache.insert(expression.clone(), bits.clone());
+        Ok(bits)
+    }
+
+    /// Assert that a 1-bit `il::Expression` evaluates to 1.
+    pub fn assert(&mut self, constraint: &il::Expression) -> Result<()> {
+        if constraint.bits() != 1 {
+            return Err(ErrorKind::Sort.into());
+        }
+        let lit = self.blast(constraint)?[0];
+        self.sat.add_clause(&[lit]);
+        Ok(())
+    }
+
+    /// Decide the asserted constraints, returning the status, and a `Model`
+    /// of every scalar if the constraints are satisfiable.
+    pub fn solve(&mut self) -> (Status, Option<Model>) {
+        let status = self.sat.solve();
+        if status != Status::Sat {
+            return (status, None);
+        }
+        let mut model = Model::new();
+        for (name, bits) in &self.scalars {
+            let mut value = BigUint::zero();
+            for (i, &bit) in bits.iter().enumerate() {
+                if self.sat.value(bit) == Some(true) {
+                    value |= BigUint::one() << i;
+                }
+            }
+            model.set_scalar(name.clone(), il::Constant::new_big(value, bits.len()));
+        }
+        (status, Some(model))
+    }
+}
+
+/// A `Solver` which decides constraints with a `BitBlaster`, requiring no
+/// external solver.
+///
+/// This is best suited to small constraints, such as those over the
+/// conditions of `il::Edge`s. Large multiplications and divisions create
+/// large circuits.
+#[derive(Clone, Debug, Default)]
+pub struct BitBlastSolver {
+    conflict_limit: Option<u64>,
+}
+
+impl BitBlastSolver {
+    /// Create a new `BitBlastSolver`.
+    pub fn new() -> BitBlastSolver {
+        BitBlastSolver::default()
+    }
+
+    /// Create a new `BitBlastSolver` which gives up, returning an error,
+    /// after the given number of conflicts.
+    pub fn with_conflict_limit(conflict_limit: u64) -> BitBlastSolver {
+        BitBlastSolver {
+            conflict_limit: Some(conflict_limit),
+        }
+    }
+}
+
+impl Solver for BitBlastSolver {
+    fn solve(&self, constraints: &[il::Expression]) -> Result<Option<Model>> {
+        let mut bit_blaster = BitBlaster::new();
+        bit_blaster
+            .sat_mut()
+            .set_conflict_limit(self.conflict_limit);
+        for constraint in constraints {
+            bit_blaster.assert(constraint)?;
+        }
+        match bit_blaster.solve() {
+            (Status::Sat, model) => Ok(model),
+            (Status::Unsat, _) => Ok(None),
+            (Status::Unknown, _) => bail!("Conflict limit reached while solving constraints"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::eval;
+    use crate::il::Expression;
+
+    type Constructor = fn(Expression, Expression) -> Result<Expression>;
+
+    // Checks the circuit for every binary operation against
+    // executor::eval, by constraining the operands to constants and the
+    // result to the evaluated value.
+    #[test]
+    fn operations() {
+        let operations: Vec<Constructor> = vec![
+            Expression::add,
+            Expression::sub,
+            Expression::mul,
+            Expression::divu,
+            Expression::modu,
+            Expression::divs,
+            Expression::mods,
+            Expression::and,
+            Expression::or,
+            Expression::xor,
+            Expression::shl,
+            Expression::shr,
+            Expression::cmpeq,
+            Expression::cmpneq,
+            Expression::cmplts,
+            Expression::cmpltu,
+        ];
+        let values: [u64; 7] = [0, 1, 3, 0x7f, 0x80, 0xa5, 0xff];
+        let x = il::expr_scalar("x", 8);
+        let y = il::expr_scalar("y", 8);
+        let solver = BitBlastSolver::new();
+
+        for operation in &operations {
+            for &a in values.iter() {
+                for &b in values.iter() {
+                    let concrete = operation(il::expr_const(a, 8), il::expr_const(b, 8)).unwrap();
+                    let expected = match eval(&concrete) {
+                        Ok(expected) => expected,
+                        // Division by zero
+                        Err(_) => continue,
+                    };
+                    let symbolic = operation(x.clone(), y.clone()).unwrap();
+                    let operands = vec![
+                        Expression::cmpeq(x.clone(), il::expr_const(a, 8)).unwrap(),
+                        Expression::cmpeq(y.clone(), il::expr_const(b, 8)).unwrap(),
+                    ];
+
+                    let mut constraints = operands.clone();
+                    constraints.push(
+                        Expression::cmpeq(symbolic.clone(), expected.clone().into()).unwrap(),
+                    );
+                    assert!(solver.is_sat(&constraints).unwrap(), "{}", concrete);
+
+                    let mut constraints = operands;
+                    constraints.push(Expression::cmpneq(symbolic, expected.into()).unwrap());
+                    assert!(!solver.is_sat(&constraints).unwrap(), "{}", concrete);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn model() {
+        // x * 3 + 1 == 0x40, with the extension and truncation of x and y
+        let x = il::expr_scalar("x", 16);
+        let y = il::expr_scalar("y", 8);
+        let constraints = vec![
+            Expression::cmpeq(
+                Expression::add(
+                    Expression::mul(x.clone(), il::expr_const(3, 16)).unwrap(),
+                    il::expr_const(1, 16),
+                )
+                .unwrap(),
+                il::expr_const(0x40, 16),
+            )
+            .unwrap(),
+            Expression::cmpeq(
+                Expression::sext(16, y.clone()).unwrap(),
+                il::expr_const(0xfff0, 16),
+            )
+            .unwrap(),
+            Expression::cmpeq(
+                Expression::trun(8, x).unwrap(),
+                Expression::ite(
+                    Expression::cmplts(y, il::expr_const(0, 8)).unwrap(),
+                    il::expr_const(0x15, 8),
+                    il::expr_const(0, 8),
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        ];
+
+        let model = BitBlastSolver::new().solve(&constraints).unwrap().unwrap();
+        for constraint in &constraints {
+            assert!(model.eval(constraint).unwrap().is_one());
+        }
+        assert_eq!(model.get_scalar("x"), Some(&il::const_(0x15, 16)));
+        assert_eq!(model.get_scalar("y"), Some(&il::const_(0xf0, 8)));
+    }
+
+    #[test]
+    fn unsat() {
+        let x = il::expr_scalar("x", 32);
+        let constraints = vec![
+            Expression::cmpltu(x.clone(), il::expr_const(10, 32)).unwrap(),
+            Expression::cmpltu(il::expr_const(20, 32), x).unwrap(),
+        ];
+        assert_eq!(BitBlastSolver::new().solve(&constraints).unwrap(), None);
+    }
+}
@@ -0,0 +1,486 @@
+//! Concolic execution over Falcon IL.
+//!
+//! A `ConcolicDriver` runs an `executor::Driver` concretely, and shadows every
+//! scalar and memory byte derived from marked input bytes with a symbolic
+//! `il::Expression` over those bytes. Input bytes are named `input_0`,
+//! `input_1`, ... in the order they are marked.
+//!
+//! Each time execution follows a conditional `il::Edge` whose condition
+//! depends on input, the condition is recorded as a `Branch`. After a run,
+//! `queries` gives, for every recorded branch, the constraints for an input
+//! which follows the same path up to that branch and then takes the other
+//! direction. Solving a query with `generate_input` gives that input.
+//!
+//! Addresses of loads, stores and branches are always concretized, and
+//! writes to memory made by hooks are not shadowed.
+
+use crate::architecture::Endian;
+use crate::error::*;
+use crate::executor;
+use crate::il;
+use crate::symbolic::{simplify, Solver};
+use std::collections::BTreeMap;
+
+/// A conditional edge, whose condition depends on input, followed during
+/// concolic execution.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Branch {
+    location: il::ProgramLocation,
+    address: Option<u64>,
+    condition: il::Expression,
+}
+
+impl Branch {
+    /// The location of the edge followed.
+    pub fn location(&self) -> &il::ProgramLocation {
+        &self.location
+    }
+
+    /// The address of the instruction preceding the edge, if known.
+    pub fn address(&self) -> Option<u64> {
+        self.address
+    }
+
+    /// The condition of the edge followed, over the input bytes. This
+    /// condition held for the concrete input.
+    pub fn condition(&self) -> &il::Expression {
+        &self.condition
+    }
+}
+
+/// Constraints for an input which diverges from a concolic run at a
+/// `Branch`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Query {
+    branch: usize,
+    constraints: Vec<il::Expression>,
+}
+
+impl Query {
+    /// The index of the `Branch` at which the input should diverge.
+    pub fn branch(&self) -> usize {
+        self.branch
+    }
+
+    /// The conditions of every preceding `Branch`, followed by the negated
+    /// condition of the diverging `Branch`.
+    pub fn constraints(&self) -> &[il::Expression] {
+        &self.constraints
+    }
+}
+
+/// A driver for concolic execution over Falcon IL.
+#[derive(Clone, Debug)]
+pub struct ConcolicDriver {
+    driver: executor::Driver,
+    scalars: BTreeMap<String, il::Expression>,
+    memory: BTreeMap<u64, il::Expression>,
+    input: Vec<u8>,
+    branches: Vec<Branch>,
+}
+
+impl ConcolicDriver {
+    /// Create a new `ConcolicDriver` around the given concrete driver. No
+    /// input is marked.
+    pub fn new(driver: executor::Driver) -> ConcolicDriver {
+        ConcolicDriver {
+            driver,
+            scalars: BTreeMap::new(),
+            memory: BTreeMap::new(),
+            input: Vec::new(),
+            branches: Vec::new(),
+        }
+    }
+
+    /// Mark `length` bytes of memory, starting at `address`, as input.
+    ///
+    /// The bytes must already hold concrete values in the driver's state,
+    /// which become the initial input.
+    pub fn mark_input(&mut self, address: u64, length: usize) -> Result<()> {
+        for i in 0..length {
+            let address = address + i as u64;
+            let value = self
+                .driver
+                .state()
+                .memory()
+                .load(address, 8)?
+                .ok_or(ErrorKind::AccessUnmappedMemory(address))?;
+            let name = format!("input_{}", self.input.len());
+            self.input.push(value.value_u64().unwrap() as u8);
+            self.memory.insert(address, il::expr_scalar(name, 8));
+        }
+        Ok(())
+    }
+
+    /// The concrete value of each input byte when it was marked.
+    pub fn input(&self) -> &[u8] {
+        &self.input
+    }
+
+    /// The branches depending on input followed so far.
+    pub fn branches(&self) -> &[Branch] {
+        &self.branches
+    }
+
+    /// Retrieve the concrete `executor::Driver`.
+    pub fn driver(&self) -> &executor::Driver {
+        &self.driver
+    }
+
+    /// Retrieve a mutable reference to the concrete `executor::Driver`.
+    pub fn driver_mut(&mut self) -> &mut executor::Driver {
+        &mut self.driver
+    }
+
+    /// The symbolic value of a scalar, if it depends on input.
+    pub fn scalar(&self, name: &str) -> Option<&il::Expression> {
+        self.scalars.get(name)
+    }
+
+    /// The symbolic value of a byte of memory, if it depends on input.
+    pub fn memory(&self, address: u64) -> Option<&il::Expression> {
+        self.memory.get(&address)
+    }
+
+    /// Returns the symbolic value of an expression over the current state, or
+    /// `None` if it does not depend on input.
+    fn symbolize(&self, expression: &il::Expression) -> Result<Option<il::Expression>> {
+        if !expression
+            .scalars()
+            .iter()
+            .any(|scalar| self.scalars.contains_key(scalar.name()))
+        {
+            return Ok(None);
+        }
+
+        let mut symbolic = expression.clone();
+        for scalar in expression.scalars() {
+            let value = match self.scalars.get(scalar.name()) {
+                Some(value) => value.clone(),
+                None => self
+                    .driver
+                    .state()
+                    .get_scalar(scalar.name())
+                    .ok_or_else(|| ErrorKind::ExecutorScalar(scalar.name().to_string()))?
+                    .clone()
+                    .into(),
+            };
+            symbolic = symbolic.replace_scalar(scalar, &value)?;
+        }
+        let symbolic = simplify(&symbolic)?;
+        Ok(if symbolic.all_constants() {
+            None
+        } else {
+            Some(symbolic)
+        })
+    }
+
+    fn set_shadow_scalar(&mut self, name: &str, value: Option<il::Expression>) {
+        match value {
+            Some(value) => self.scalars.insert(name.to_string(), value),
+            None => self.scalars.remove(name),
+        };
+    }
+
+    /// The shift, in bits, of byte `i` of a value of `bytes` bytes.
+    fn byte_shift(&self, i: usize, bytes: usize) -> usize {
+        match self.driver.state().memory().endian() {
+            Endian::Little => i * 8,
+            Endian::Big => (bytes - i - 1) * 8,
+        }
+    }
+
+    fn load(&self, address: u64, bits: usize) -> Result<Option<il::Expression>> {
+        let bytes = bits / 8;
+        if !(0..bytes).any(|i| self.memory.contains_key(&(address + i as u64))) {
+            return Ok(None);
+        }
+
+        let mut value: Option<il::Expression> = None;
+        for i in 0..bytes {
+            let address = address + i as u64;
+            let byte = match self.memory.get(&address) {
+                Some(byte) => byte.clone(),
+                None => self
+                    .driver
+                    .state()
+                    .memory()
+                    .load(address, 8)?
+                    .ok_or(ErrorKind::AccessUnmappedMemory(address))?
+                    .into(),
+            };
+            if bytes == 1 {
+                return Ok(Some(byte));
+            }
+            let byte = il::Expression::shl(
+                il::Expression::zext(bits, byte)?,
+                il::expr_const(self.byte_shift(i, bytes) as u64, bits),
+            )?;
+            value = Some(match value {
+                Some(value) => il::Expression::or(value, byte)?,
+                None => byte,
+            });
+        }
+        Ok(Some(simplify(&value.unwrap())?))
+    }
+
+    fn store(&mut self, address: u64, value: Option<il::Expression>, bits: usize) -> Result<()> {
+        let bytes = bits / 8;
+        for i in 0..bytes {
+            let byte = match value {
+                Some(ref value) if bytes == 1 => Some(value.clone()),
+                Some(ref value) => {
+                    let shift = il::expr_const(self.byte_shift(i, bytes) as u64, bits);
+                    let byte = il::Expression::trun(8, il::Expression::shr(value.clone(), shift)?)?;
+                    Some(simplify(&byte)?).filter(|byte| !byte.all_constants())
+                }
+                None => None,
+            };
+            match byte {
+                Some(byte) => self.memory.insert(address + i as u64, byte),
+                None => self.memory.remove(&(address + i as u64)),
+            };
+        }
+        Ok(())
+    }
+
+    /// Update the shadow state for the operation about to be executed.
+    fn shadow_operation(&mut self, operation: &il::Operation) -> Result<()> {
+        match *operation {
+            il::Operation::Assign { ref dst, ref src } => {
+                let value = self.symbolize(src)?;
+                self.set_shadow_scalar(dst.name(), value);
+            }
+            il::Operation::Store { ref index, ref src } => {
+                let address = self.concrete_address(index)?;
+                let value = self.symbolize(src)?;
+                self.store(address, value, src.bits())?;
+            }
+            il::Operation::Load { ref dst, ref index } => {
+                let address = self.concrete_address(index)?;
+                let value = self.load(address, dst.bits())?;
+                self.set_shadow_scalar(dst.name(), value);
+            }
+            il::Operation::Branch { .. } | il::Operation::Intrinsic { .. } | il::Operation::Nop => {
+            }
+        }
+        Ok(())
+    }
+
+    fn concrete_address(&self, index: &il::Expression) -> Result<u64> {
+        self.driver
+            .state()
+            .symbolize_and_eval(index)?
+            .value_u64()
+            .ok_or_else(|| ErrorKind::TooManyAddressBits.into())
+    }
+
+    /// Step forward over Falcon IL, concretely, while updating the symbolic
+    /// shadow state and recording branches which depend on input.
+    pub fn step(mut self) -> Result<ConcolicDriver> {
+        // Branches and intrinsics may run hooks, which can change scalars we
+        // shadow.
+        let mut hooked = false;
+
+        {
+            let location = self.driver.location().apply(self.driver.program())?;
+            match *location.function_location() {
+                il::RefFunctionLocation::Instruction(_, instruction) => {
+                    let operation = instruction.operation().clone();
+                    self.shadow_operation(&operation)?;
+                    hooked = operation.is_branch() || operation.is_intrinsic();
+                }
+                il::RefFunctionLocation::Edge(edge) => {
+                    if let Some(condition) = edge.condition() {
+                        let address = location
+                            .backward()?
+                            .first()
+                            .and_then(|location| location.address());
+                        if let Some(condition) = self.symbolize(condition)? {
+                            self.branches.push(Branch {
+                                location: self.driver.location().clone(),
+                                address,
+                                condition,
+                            });
+                        }
+                    }
+                }
+                il::RefFunctionLocation::EmptyBlock(_) => {}
+            }
+        }
+
+        let before = if hooked {
+            self.scalars
+                .keys()
+                .map(|name| (name.clone(), self.driver.state().get_scalar(name).cloned()))
+                .collect::<Vec<(String, Option<il::Constant>)>>()
+        } else {
+            Vec::new()
+        };
+
+        let driver = self.driver.step()?;
+
+        for (name, value) in before {
+            if driver.state().get_scalar(&name).cloned() != value {
+                self.scalars.remove(&name);
+            }
+        }
+
+        self.driver = driver;
+        Ok(self)
+    }
+
+    /// For every recorded `Branch`, create a `Query` for inputs which take
+    /// the other direction at that branch.
+    pub fn queries(&self) -> Result<Vec<Query>> {
+        let mut queries = Vec::new();
+        for (i, branch) in self.branches.iter().enumerate() {
+            let mut constraints = self.branches[0..i]
+                .iter()
+                .map(|branch| branch.condition.clone())
+                .collect::<Vec<il::Expression>>();
+            constraints.push(il::Expression::cmpeq(
+                branch.condition.clone(),
+                il::expr_const(0, 1),
+            )?);
+            queries.push(Query {
+                branch: i,
+                constraints,
+            });
+        }
+        Ok(queries)
+    }
+
+    /// Solve a `Query`, returning the new input, or `None` if no input
+    /// satisfies the query.
+    ///
+    /// Input bytes which the query does not constrain keep their current
+    /// values.
+    pub fn generate_input(&self, query: &Query, solver: &dyn Solver) -> Result<Option<Vec<u8>>> {
+        let model = match solver.solve(&query.constraints)? {
+            Some(model) => model,
+            None => return Ok(None),
+        };
+        Ok(Some(
+            self.input
+                .iter()
+                .enumerate()
+                .map(|(i, &byte)| {
+                    model
+                        .get_scalar(&format!("input_{}", i))
+                        .and_then(|value| value.value_u64())
+                        .map(|value| value as u8)
+                        .unwrap_or(byte)
+                })
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::Amd64;
+    use crate::executor::{Driver, Memory, State};
+    use crate::symbolic::BitBlastSolver;
+    use crate::RC;
+
+    // A function at 0x1000 which loads two bytes of input from 0x8000, adds
+    // them, and then sets r to 1 if the sum is 0x80, or 2 otherwise.
+    fn driver(input: &[u8]) -> ConcolicDriver {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+
+        let head_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.load(il::scalar("a", 16), il::expr_const(0x8000, 64));
+            block.assign(
+                il::scalar("b", 8),
+                il::Expression::add(
+                    il::Expression::trun(8, il::expr_scalar("a", 16)).unwrap(),
+                    il::Expression::trun(
+                        8,
+                        il::Expression::shr(il::expr_scalar("a", 16), il::expr_const(8, 16))
+                            .unwrap(),
+                    )
+                    .unwrap(),
+                )
+                .unwrap(),
+            );
+            block.instructions_mut()[0].set_address(Some(0x1000));
+            block.instructions_mut()[1].set_address(Some(0x1002));
+            block.index()
+        };
+        let one_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.assign(il::scalar("r", 64), il::expr_const(1, 64));
+            block.instructions_mut()[0].set_address(Some(0x1004));
+            block.index()
+        };
+        let two_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.assign(il::scalar("r", 64), il::expr_const(2, 64));
+            block.instructions_mut()[0].set_address(Some(0x1008));
+            block.index()
+        };
+
+        let condition =
+            il::Expression::cmpeq(il::expr_scalar("b", 8), il::expr_const(0x80, 8)).unwrap();
+        control_flow_graph
+            .conditional_edge(head_index, one_index, condition.clone())
+            .unwrap();
+        control_flow_graph
+            .conditional_edge(
+                head_index,
+                two_index,
+                il::Expression::cmpeq(condition, il::expr_const(0, 1)).unwrap(),
+            )
+            .unwrap();
+        control_flow_graph.set_entry(head_index).unwrap();
+
+        let mut program = il::Program::new();
+        program.add_function(il::Function::new(0x1000, control_flow_graph));
+
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+
+        let mut state = State::new(Memory::new(Endian::Little));
+        for (i, byte) in input.iter().enumerate() {
+            state
+                .memory_mut()
+                .store(0x8000 + i as u64, il::const_(*byte as u64, 8))
+                .unwrap();
+        }
+
+        let driver = Driver::new(RC::new(program), location, state, RC::new(Amd64::new()));
+        let mut driver = ConcolicDriver::new(driver);
+        driver.mark_input(0x8000, input.len()).unwrap();
+        driver
+    }
+
+    fn run(mut driver: ConcolicDriver) -> ConcolicDriver {
+        for _ in 0..3 {
+            driver = driver.step().unwrap();
+        }
+        driver
+    }
+
+    #[test]
+    fn generate() {
+        let driver = run(driver(&[1, 2]));
+        assert_eq!(driver.driver().address(), Some(0x1008));
+        assert_eq!(driver.branches().len(), 1);
+        assert_eq!(driver.branches()[0].address(), Some(0x1002));
+
+        let queries = driver.queries().unwrap();
+        assert_eq!(queries.len(), 1);
+        let input = driver
+            .generate_input(&queries[0], &BitBlastSolver::new())
+            .unwrap()
+            .unwrap();
+        assert_eq!(input[0].wrapping_add(input[1]), 0x80);
+
+        let driver = run(self::driver(&input));
+        assert_eq!(driver.driver().address(), Some(0x1004));
+    }
+}
@@ -0,0 +1,343 @@
+//! A driver symbolically executes Falcon IL programs.
+
+use crate::architecture::Architecture;
+use crate::error::*;
+use crate::executor::SuccessorType;
+use crate::il;
+use crate::symbolic::{Solver, State};
+use crate::RC;
+
+/// A driver for symbolic execution over Falcon IL.
+///
+/// Each step produces the drivers which follow this one. There is one
+/// driver for every feasible path, and no drivers once a path is complete.
+#[derive(Debug, Clone)]
+pub struct Driver {
+    program: RC<il::Program>,
+    location: il::ProgramLocation,
+    state: State,
+    architecture: RC<dyn Architecture>,
+    solver: RC<dyn Solver>,
+}
+
+impl Driver {
+    /// Create a new driver for symbolic execution over Falcon IL.
+    pub fn new(
+        program: RC<il::Program>,
+        location: il::ProgramLocation,
+        state: State,
+        architecture: RC<dyn Architecture>,
+        solver: RC<dyn Solver>,
+    ) -> Driver {
+        Driver {
+            program,
+            location,
+            state,
+            architecture,
+            solver,
+        }
+    }
+
+    /// Create a driver which follows this driver, at the given location with
+    /// the given state.
+    fn successor(&self, location: il::ProgramLocation, state: State) -> Driver {
+        Driver {
+            program: self.program.clone(),
+            location,
+            state,
+            architecture: self.architecture.clone(),
+            solver: self.solver.clone(),
+        }
+    }
+
+    /// Step forward over Falcon IL, forking at conditional edges.
+    ///
+    /// Successors whose path constraints are unsatisfiable are discarded.
+    pub fn step(self) -> Result<Vec<Driver>> {
+        let program = self.program.clone();
+        let location = self.location.apply(&program)?;
+        match *location.function_location() {
+            il::RefFunctionLocation::Instruction(_, instruction) => {
+                let successor = self
+                    .state
+                    .clone()
+                    .execute(instruction.operation(), self.solver.as_ref())?;
+                match successor.type_().clone() {
+                    SuccessorType::FallThrough => self.fall_through(&location, successor.into()),
+                    SuccessorType::Branch(address) => {
+                        Ok(vec![self.branch(address, successor.into())?])
+                    }
+                    SuccessorType::Intrinsic(intrinsic) => {
+                        Err(ErrorKind::UnhandledIntrinsic(format!("{}", intrinsic)).into())
+                    }
+                    SuccessorType::Fault(fault) => Err(ErrorKind::MemoryFault(
+                        fault.with_location(self.location.clone()),
+                    )
+                    .into()),
+                }
+            }
+            il::RefFunctionLocation::Edge(_) => {
+                let locations = location.forward()?;
+                Ok(vec![self.successor(
+                    locations[0].clone().into(),
+                    self.state.clone(),
+                )])
+            }
+            il::RefFunctionLocation::EmptyBlock(_) => {
+                self.fall_through(&location, self.state.clone())
+            }
+        }
+    }
+
+    /// Fork to every feasible location following the given location.
+    fn fall_through(&self, location: &il::RefProgramLocation, state: State) -> Result<Vec<Driver>> {
+        let locations = location.forward()?;
+        if locations.len() == 1 {
+            return Ok(vec![self.successor(locations[0].clone().into(), state)]);
+        }
+
+        let mut drivers = Vec::new();
+        for location in locations {
+            let mut state = state.clone();
+            if let il::RefFunctionLocation::Edge(edge) = *location.function_location() {
+                if let Some(condition) = edge.condition() {
+                    let condition = state.symbolize_expression(condition)?;
+                    match condition.get_constant() {
+                        Some(constant) => {
+                            if !constant.is_one() {
+                                continue;
+                            }
+                        }
+                        None => {
+                            state.push_path_constraint(condition);
+                            if !state.is_sat(self.solver.as_ref())? {
+                                continue;
+                            }
+                        }
+                    }
+                }
+            }
+            drivers.push(self.successor(location.into(), state));
+        }
+        Ok(drivers)
+    }
+
+    /// Transfer control to the given address, lifting the function at that
+    /// address from the state's memory if required.
+    fn branch(self, address: u64, state: State) -> Result<Driver> {
+        if let Some(location) = il::RefProgramLocation::from_address(&self.program, address) {
+            let location = location.into();
+            return Ok(self.successor(location, state));
+        }
+
+        let function = self
+            .architecture
+            .translator()
+            .translate_function(state.memory(), address)
+            .chain_err(|| format!("Failed to lift function at 0x{:x}", address))?;
+        let mut driver = self;
+        RC::make_mut(&mut driver.program).add_function(function);
+        let location: il::ProgramLocation =
+            il::RefProgramLocation::from_address(&driver.program, address)
+                .ok_or("Failed to get location for newly lifted function")?
+                .into();
+        Ok(driver.successor(location, state))
+    }
+
+    /// Retrieve the Falcon IL program associated with this driver.
+    pub fn program(&self) -> &il::Program {
+        &self.program
+    }
+
+    /// If this driver is sitting on an instruction with an address, return
+    /// that address.
+    pub fn address(&self) -> Option<u64> {
+        self.location
+            .apply(&self.program)
+            .expect("Failed to apply program location")
+            .address()
+    }
+
+    /// Retrieve the `il::ProgramLocation` associated with this driver.
+    pub fn location(&self) -> &il::ProgramLocation {
+        &self.location
+    }
+
+    /// Retrieve the symbolic `State` associated with this driver.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Retrieve a mutable reference to the `State` associated with this driver.
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    /// Retrieve the `Architecture` associated with this driver.
+    pub fn architecture(&self) -> &dyn Architecture {
+        self.architecture.as_ref()
+    }
+
+    /// Retrieve the `Solver` used by this driver.
+    pub fn solver(&self) -> &dyn Solver {
+        self.solver.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{Amd64, Endian};
+    use crate::symbolic::{Memory, Model};
+
+    // Solves constraints over the single 8-bit scalar x by enumeration.
+    #[derive(Debug)]
+    struct EnumerationSolver;
+
+    impl Solver for EnumerationSolver {
+        fn solve(&self, constraints: &[il::Expression]) -> Result<Option<Model>> {
+            for x in 0..256 {
+                let mut model = Model::new();
+                model.set_scalar("x", il::const_(x, 8));
+                let mut sat = true;
+                for constraint in constraints {
+                    if !model.eval(constraint)?.is_one() {
+                        sat = false;
+                        break;
+                    }
+                }
+                if sat {
+                    return Ok(Some(model));
+                }
+            }
+            Ok(None)
+        }
+    }
+
+    // A function at 0x1000 which loads x from 0x8000, and then sets r to 1 if
+    // x is 0x41, or 2 otherwise.
+    fn driver() -> Driver {
+        driver_with_increment(false)
+    }
+
+    // As driver, or if increment is set, a function at 0x1000 which
+    // increments the free scalar x in place, and then sets r to 1 if x is
+    // 0x42, or 2 otherwise.
+    fn driver_with_increment(increment: bool) -> Driver {
+        let mut control_flow_graph = il::ControlFlowGraph::new();
+
+        let head_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            if increment {
+                block.assign(
+                    il::scalar("x", 8),
+                    il::Expression::add(il::expr_scalar("x", 8), il::expr_const(1, 8)).unwrap(),
+                );
+            } else {
+                block.load(il::scalar("y", 8), il::expr_const(0x8000, 64));
+            }
+            block.instructions_mut()[0].set_address(Some(0x1000));
+            block.index()
+        };
+        let one_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.assign(il::scalar("r", 64), il::expr_const(1, 64));
+            block.instructions_mut()[0].set_address(Some(0x1004));
+            block.index()
+        };
+        let two_index = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.assign(il::scalar("r", 64), il::expr_const(2, 64));
+            block.instructions_mut()[0].set_address(Some(0x1008));
+            block.index()
+        };
+
+        let condition = if increment {
+            il::Expression::cmpeq(il::expr_scalar("x", 8), il::expr_const(0x42, 8)).unwrap()
+        } else {
+            il::Expression::cmpeq(il::expr_scalar("y", 8), il::expr_const(0x41, 8)).unwrap()
+        };
+        control_flow_graph
+            .conditional_edge(head_index, one_index, condition.clone())
+            .unwrap();
+        control_flow_graph
+            .conditional_edge(
+                head_index,
+                two_index,
+                il::Expression::cmpeq(condition, il::expr_const(0, 1)).unwrap(),
+            )
+            .unwrap();
+        control_flow_graph.set_entry(head_index).unwrap();
+
+        let mut program = il::Program::new();
+        program.add_function(il::Function::new(0x1000, control_flow_graph));
+
+        let location = il::RefProgramLocation::from_address(&program, 0x1000)
+            .unwrap()
+            .into();
+
+        let mut state = State::new(Memory::new(Endian::Little));
+        state
+            .memory_mut()
+            .store(0x8000, il::expr_scalar("x", 8))
+            .unwrap();
+
+        Driver::new(
+            RC::new(program),
+            location,
+            state,
+            RC::new(Amd64::new()),
+            RC::new(EnumerationSolver),
+        )
+    }
+
+    fn run(driver: Driver) -> Vec<Driver> {
+        let mut drivers = vec![driver];
+        for _ in 0..2 {
+            drivers = drivers
+                .into_iter()
+                .flat_map(|driver| driver.step().unwrap())
+                .collect();
+        }
+        drivers
+    }
+
+    #[test]
+    fn fork() {
+        check_fork(run(driver()));
+    }
+
+    #[test]
+    fn fork_self_referential() {
+        check_fork(run(driver_with_increment(true)));
+    }
+
+    fn check_fork(drivers: Vec<Driver>) {
+        assert_eq!(drivers.len(), 2);
+
+        for driver in drivers {
+            let model = driver.state().model(driver.solver()).unwrap().unwrap();
+            let x = model.get_scalar("x").unwrap().value_u64().unwrap();
+            match driver.address() {
+                Some(0x1004) => assert_eq!(x, 0x41),
+                Some(0x1008) => assert!(x != 0x41),
+                _ => panic!("unexpected address"),
+            }
+        }
+    }
+
+    #[test]
+    fn prune() {
+        let mut driver = driver();
+        driver
+            .state_mut()
+            .add_path_constraint(
+                &il::Expression::cmpltu(il::expr_scalar("x", 8), il::expr_const(0x10, 8)).unwrap(),
+            )
+            .unwrap();
+
+        let drivers = run(driver);
+        assert_eq!(drivers.len(), 1);
+        assert_eq!(drivers[0].address(), Some(0x1008));
+    }
+}
@@ -0,0 +1,79 @@
+//! Symbolic execution over Falcon IL.
+//!
+//! Where the `executor` holds an `il::Constant` for every scalar and memory
+//! cell, the symbolic `State` holds an `il::Expression`. Scalars which have
+//! never been assigned are free, and stand for themselves. A `Driver` forks
+//! at conditional `il::Edge`s, adding the condition of each edge taken to the
+//! path constraints of the forked `State`.
+//!
+//! Questions about path constraints are answered by a `Solver`. Falcon does
+//! not depend on any one solver, and anything implementing `Solver` can be
+//! given to a `Driver`. `BitBlastSolver` decides constraints with Falcon's
+//! own SAT solver, and `smtlib2::Smtlib2Solver` runs any SMT-LIB2 solver as a
+//! subprocess.
+//!
+//! The `concolic` module runs the concrete `executor`, while tracking
+//! symbolic values derived from input, to generate inputs which follow new
+//! paths.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::symbolic::Driver;
+//!
+//! // Explore every path reachable from the given driver, breadth-first,
+//! // for at most the given number of steps.
+//! # #[allow(dead_code)]
+//! fn explore(driver: Driver, steps: usize) -> Result<Vec<Driver>> {
+//!     let mut drivers = vec![driver];
+//!     for _ in 0..steps {
+//!         let mut next = Vec::new();
+//!         for driver in drivers {
+//!             next.append(&mut driver.step()?);
+//!         }
+//!         drivers = next;
+//!     }
+//!     Ok(drivers)
+//! }
+//! ```
+
+use crate::il;
+use crate::memory;
+
+mod bitblast;
+pub mod concolic;
+mod driver;
+pub mod sat;
+mod simplify;
+pub mod smtlib2;
+mod solver;
+mod state;
+mod successor;
+
+pub use self::bitblast::*;
+pub use self::driver::*;
+pub use self::simplify::simplify;
+pub use self::solver::*;
+pub use self::state::*;
+pub use self::successor::*;
+
+/// A `falcon::memory::paged::Memory` over `il::Expression`.
+pub type Memory = memory::paged::Memory<il::Expression>;
+
+use crate::memory::MemoryPermissions;
+use crate::translator;
+
+impl translator::TranslationMemory for Memory {
+    fn get_u8(&self, address: u64) -> Option<u8> {
+        match self.load_unchecked(address, 8).unwrap() {
+            Some(expression) => match simplify(&expression).unwrap() {
+                il::Expression::Constant(constant) => Some(constant.value_u64().unwrap() as u8),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    fn permissions(&self, address: u64) -> Option<MemoryPermissions> {
+        self.permissions(address)
+    }
+}
@@ -0,0 +1,509 @@
+//! A small CDCL SAT solver.
+//!
+//! This solver is intended for the modestly sized formulas created by
+//! bit-blasting path constraints, and follows the design of MiniSat: two
+//! watched literals for unit propagation, first-UIP conflict analysis with
+//! clause learning, VSIDS-style variable activities, phase saving, and Luby
+//! restarts.
+//!
+//! ```
+//! use falcon::symbolic::sat::{Sat, Status};
+//!
+//! let mut sat = Sat::new();
+//! let a = sat.new_var();
+//! let b = sat.new_var();
+//! // (a | b) & (!a | b) & (a | !b)
+//! sat.add_clause(&[a, b]);
+//! sat.add_clause(&[!a, b]);
+//! sat.add_clause(&[a, !b]);
+//! assert_eq!(sat.solve(), Status::Sat);
+//! assert_eq!(sat.value(a), Some(true));
+//! assert_eq!(sat.value(b), Some(true));
+//! ```
+
+use std::ops::Not;
+
+/// A literal, a variable or its negation.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Lit(u32);
+
+impl Lit {
+    /// Create a literal for the given variable, which is negated if
+    /// `positive` is false.
+    pub fn new(var: usize, positive: bool) -> Lit {
+        Lit(((var as u32) << 1) | if positive { 0 } else { 1 })
+    }
+
+    /// The variable of this literal.
+    pub fn var(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+
+    /// Returns true if this literal is not negated.
+    pub fn is_positive(self) -> bool {
+        self.0 & 1 == 0
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+/// The result of solving.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// The clauses are satisfiable, and a model is available.
+    Sat,
+    /// The clauses are unsatisfiable.
+    Unsat,
+    /// The conflict limit was reached before the clauses were decided.
+    Unknown,
+}
+
+/// The Luby sequence, 1 1 2 1 1 2 4 1 1 2 ..., used for restart intervals.
+fn luby(mut i: u64) -> u64 {
+    let mut size = 1;
+    let mut sequence = 0;
+    while size < i + 1 {
+        sequence += 1;
+        size = 2 * size + 1;
+    }
+    while size - 1 != i {
+        size = (size - 1) >> 1;
+        sequence -= 1;
+        i %= size;
+    }
+    1 << sequence
+}
+
+/// A CDCL SAT solver.
+#[derive(Clone, Debug, Default)]
+pub struct Sat {
+    clauses: Vec<Vec<Lit>>,
+    /// For each literal, the clauses watching that literal.
+    watches: Vec<Vec<usize>>,
+    /// The value of each variable: 1 for true, -1 for false, 0 unassigned.
+    assigns: Vec<i8>,
+    levels: Vec<usize>,
+    reasons: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    /// The index into the trail at which each decision level begins.
+    trail_limits: Vec<usize>,
+    propagated: usize,
+    activity: Vec<f64>,
+    activity_increment: f64,
+    polarity: Vec<bool>,
+    seen: Vec<bool>,
+    model: Vec<bool>,
+    unsat: bool,
+    conflict_limit: Option<u64>,
+}
+
+impl Sat {
+    /// Create a new `Sat` with no variables or clauses.
+    pub fn new() -> Sat {
+        Sat {
+            activity_increment: 1.0,
+            ..Sat::default()
+        }
+    }
+
+    /// Create a new variable, returning its positive literal.
+    pub fn new_var(&mut self) -> Lit {
+        let var = self.assigns.len();
+        self.assigns.push(0);
+        self.levels.push(0);
+        self.reasons.push(None);
+        self.activity.push(0.0);
+        self.polarity.push(false);
+        self.seen.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        Lit::new(var, true)
+    }
+
+    /// The number of variables in this solver.
+    pub fn num_vars(&self) -> usize {
+        self.assigns.len()
+    }
+
+    /// The number of clauses in this solver, including learnt clauses.
+    pub fn num_clauses(&self) -> usize {
+        self.clauses.len()
+    }
+
+    /// Give up solving after the given number of conflicts, returning
+    /// `Status::Unknown`.
+    pub fn set_conflict_limit(&mut self, conflict_limit: Option<u64>) {
+        self.conflict_limit = conflict_limit;
+    }
+
+    fn lit_value(&self, lit: Lit) -> i8 {
+        let value = self.assigns[lit.var()];
+        if lit.is_positive() {
+            value
+        } else {
+            -value
+        }
+    }
+
+    fn level(&self) -> usize {
+        self.trail_limits.len()
+    }
+
+    fn enqueue(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.var();
+        self.assigns[var] = if lit.is_positive() { 1 } else { -1 };
+        self.levels[var] = self.level();
+        self.reasons[var] = reason;
+        self.trail.push(lit);
+    }
+
+    fn attach(&mut self, clause: Vec<Lit>) -> usize {
+        let index = self.clauses.len();
+        self.watches[clause[0].index()].push(index);
+        self.watches[clause[1].index()].push(index);
+        self.clauses.push(clause);
+        index
+    }
+
+    /// Add a clause, the disjunction of the given literals.
+    ///
+    /// Clauses may be added between calls to `solve`.
+    pub fn add_clause(&mut self, lits: &[Lit]) {
+        if self.unsat {
+            return;
+        }
+        self.backtrack(0);
+
+        let mut clause: Vec<Lit> = Vec::new();
+        for &lit in lits {
+            match self.lit_value(lit) {
+                // Satisfied at level 0
+                1 => return,
+                // Falsified at level 0
+                -1 => continue,
+                _ => {}
+            }
+            if clause.contains(&!lit) {
+                return;
+            }
+            if !clause.contains(&lit) {
+                clause.push(lit);
+            }
+        }
+
+        match clause.len() {
+            0 => self.unsat = true,
+            1 => {
+                self.enqueue(clause[0], None);
+                if self.propagate().is_some() {
+                    self.unsat = true;
+                }
+            }
+            _ => {
+                self.attach(clause);
+            }
+        }
+    }
+
+    /// Propagate all enqueued assignments, returning the index of a
+    /// conflicting clause if one is found.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let false_lit = !self.trail[self.propagated];
+            self.propagated += 1;
+
+            let watches = std::mem::take(&mut self.watches[false_lit.index()]);
+            let mut keep = Vec::with_capacity(watches.len());
+            let mut conflict = None;
+
+            for (i, &index) in watches.iter().enumerate() {
+                if conflict.is_some() {
+                    keep.extend_from_slice(&watches[i..]);
+                    break;
+                }
+
+                // Make sure the false literal is the second literal.
+                if self.clauses[index][0] == false_lit {
+                    self.clauses[index].swap(0, 1);
+                }
+
+                let first = self.clauses[index][0];
+                if self.lit_value(first) == 1 {
+                    keep.push(index);
+                    continue;
+                }
+
+                // Look for a new literal to watch.
+                let mut found = false;
+                for k in 2..self.clauses[index].len() {
+                    let lit = self.clauses[index][k];
+                    if self.lit_value(lit) != -1 {
+                        self.clauses[index].swap(1, k);
+                        self.watches[lit.index()].push(index);
+                        found = true;
+                        break;
+                    }
+                }
+                if found {
+                    continue;
+                }
+
+                keep.push(index);
+                if self.lit_value(first) == -1 {
+                    conflict = Some(index);
+                } else {
+                    self.enqueue(first, Some(index));
+                }
+            }
+
+            self.watches[false_lit.index()] = keep;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.activity_increment;
+        if self.activity[var] > 1e100 {
+            for activity in &mut self.activity {
+                *activity *= 1e-100;
+            }
+            self.activity_increment *= 1e-100;
+        }
+    }
+
+    /// Analyze a conflict, returning the learnt clause, with the asserting
+    /// literal first, and the level to backtrack to.
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let mut learnt = vec![Lit(0)];
+        let mut path_count = 0;
+        let mut lit: Option<Lit> = None;
+        let mut clause = conflict;
+        let mut index = self.trail.len();
+
+        loop {
+            let start = if lit.is_some() { 1 } else { 0 };
+            for k in start..self.clauses[clause].len() {
+                let q = self.clauses[clause][k];
+                let var = q.var();
+                if !self.seen[var] && self.levels[var] > 0 {
+                    self.seen[var] = true;
+                    self.bump(var);
+                    if self.levels[var] >= self.level() {
+                        path_count += 1;
+                    } else {
+                        learnt.push(q);
+                    }
+                }
+            }
+
+            // Find the next literal on the trail to expand.
+            loop {
+                index -= 1;
+                if self.seen[self.trail[index].var()] {
+                    break;
+                }
+            }
+            let p = self.trail[index];
+            self.seen[p.var()] = false;
+            path_count -= 1;
+            lit = Some(p);
+            if path_count == 0 {
+                break;
+            }
+            clause = self.reasons[p.var()].expect("Implied literal without a reason");
+            // The implied literal is always first in its reason clause.
+            if self.clauses[clause][0] != p {
+                let position = self.clauses[clause].iter().position(|&l| l == p).unwrap();
+                self.clauses[clause].swap(0, position);
+            }
+        }
+        learnt[0] = !lit.unwrap();
+
+        for lit in &learnt[1..] {
+            self.seen[lit.var()] = false;
+        }
+
+        // Backtrack to the highest level among the other literals, and keep
+        // the literal from that level second so it is watched.
+        let mut backtrack_level = 0;
+        if learnt.len() > 1 {
+            let mut max = 1;
+            for k in 2..learnt.len() {
+                if self.levels[learnt[k].var()] > self.levels[learnt[max].var()] {
+                    max = k;
+                }
+            }
+            learnt.swap(1, max);
+            backtrack_level = self.levels[learnt[1].var()];
+        }
+
+        (learnt, backtrack_level)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.level() <= level {
+            return;
+        }
+        let limit = self.trail_limits[level];
+        for lit in self.trail.drain(limit..) {
+            let var = lit.var();
+            self.polarity[var] = lit.is_positive();
+            self.assigns[var] = 0;
+            self.reasons[var] = None;
+        }
+        self.trail_limits.truncate(level);
+        self.propagated = limit;
+    }
+
+    fn decide(&mut self) -> Option<Lit> {
+        let mut best: Option<usize> = None;
+        for var in 0..self.assigns.len() {
+            if self.assigns[var] == 0
+                && best
+                    .map(|b| self.activity[var] > self.activity[b])
+                    .unwrap_or(true)
+            {
+                best = Some(var);
+            }
+        }
+        best.map(|var| Lit::new(var, self.polarity[var]))
+    }
+
+    /// Determine whether the clauses added to this solver are satisfiable.
+    pub fn solve(&mut self) -> Status {
+        if self.unsat {
+            return Status::Unsat;
+        }
+
+        let mut conflicts: u64 = 0;
+        let mut restarts: u64 = 0;
+        let mut restart_conflicts = 0;
+
+        loop {
+            if let Some(conflict) = self.propagate() {
+                conflicts += 1;
+                restart_conflicts += 1;
+                if self.level() == 0 {
+                    self.unsat = true;
+                    return Status::Unsat;
+                }
+
+                let (learnt, backtrack_level) = self.analyze(conflict);
+                self.backtrack(backtrack_level);
+                if learnt.len() == 1 {
+                    self.enqueue(learnt[0], None);
+                } else {
+                    let asserting = learnt[0];
+                    let index = self.attach(learnt);
+                    self.enqueue(asserting, Some(index));
+                }
+                self.activity_increment *= 1.0 / 0.95;
+
+                if let Some(limit) = self.conflict_limit {
+                    if conflicts >= limit {
+                        self.backtrack(0);
+                        return Status::Unknown;
+                    }
+                }
+            } else if restart_conflicts >= 100 * luby(restarts) {
+                restarts += 1;
+                restart_conflicts = 0;
+                self.backtrack(0);
+            } else {
+                match self.decide() {
+                    Some(lit) => {
+                        self.trail_limits.push(self.trail.len());
+                        self.enqueue(lit, None);
+                    }
+                    None => {
+                        self.model = self.assigns.iter().map(|&value| value == 1).collect();
+                        self.backtrack(0);
+                        return Status::Sat;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The value of a literal in the model found by the last call to `solve`
+    /// which returned `Status::Sat`.
+    pub fn value(&self, lit: Lit) -> Option<bool> {
+        self.model
+            .get(lit.var())
+            .map(|&value| value == lit.is_positive())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luby_sequence() {
+        let sequence = (0..15).map(luby).collect::<Vec<u64>>();
+        assert_eq!(sequence, vec![1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8]);
+    }
+
+    #[test]
+    fn pigeonhole() {
+        // Four pigeons do not fit in three holes.
+        let mut sat = Sat::new();
+        let p = (0..4)
+            .map(|_| (0..3).map(|_| sat.new_var()).collect::<Vec<Lit>>())
+            .collect::<Vec<Vec<Lit>>>();
+        for pigeon in &p {
+            sat.add_clause(pigeon);
+        }
+        for hole in 0..3 {
+            for (i, a) in p.iter().enumerate() {
+                for b in &p[(i + 1)..] {
+                    sat.add_clause(&[!a[hole], !b[hole]]);
+                }
+            }
+        }
+        assert_eq!(sat.solve(), Status::Unsat);
+    }
+
+    #[test]
+    fn satisfiable() {
+        // Exactly one of each row of three is true, and the diagonal is
+        // false.
+        let mut sat = Sat::new();
+        let x = (0..3)
+            .map(|_| (0..3).map(|_| sat.new_var()).collect::<Vec<Lit>>())
+            .collect::<Vec<Vec<Lit>>>();
+        for (i, row) in x.iter().enumerate() {
+            sat.add_clause(row);
+            for (j, &a) in row.iter().enumerate() {
+                for &b in &row[(j + 1)..] {
+                    sat.add_clause(&[!a, !b]);
+                }
+            }
+            sat.add_clause(&[!row[i]]);
+        }
+        assert_eq!(sat.solve(), Status::Sat);
+        for (i, row) in x.iter().enumerate() {
+            let count = row.iter().filter(|&&x| sat.value(x) == Some(true)).count();
+            assert_eq!(count, 1);
+            assert_eq!(sat.value(row[i]), Some(false));
+        }
+
+        // Clauses can be added after solving.
+        sat.add_clause(&[x[0][1]]);
+        sat.add_clause(&[x[0][2]]);
+        assert_eq!(sat.solve(), Status::Unsat);
+    }
+}
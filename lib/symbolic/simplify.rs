@@ -0,0 +1,191 @@
+//! Simplification of symbolic expressions.
+
+use crate::error::*;
+use crate::executor::eval;
+use crate::il;
+use crate::il::Expression;
+
+fn is_zero(expression: &Expression) -> bool {
+    expression
+        .get_constant()
+        .map(|constant| constant.is_zero())
+        .unwrap_or(false)
+}
+
+fn binop<F>(lhs: &Expression, rhs: &Expression, f: F) -> Result<Expression>
+where
+    F: Fn(Expression, Expression) -> Result<Expression>,
+{
+    f(simplify(lhs)?, simplify(rhs)?)
+}
+
+/// Simplify an `il::Expression`.
+///
+/// Sub-expressions over only constants are evaluated, and a small number of
+/// identities, such as `x + 0 = x`, are applied. Simplification keeps
+/// symbolic expressions built up over long paths small, but makes no attempt
+/// to find a canonical form.
+pub fn simplify(expression: &Expression) -> Result<Expression> {
+    let expression = match *expression {
+        Expression::Scalar(_) | Expression::Constant(_) => return Ok(expression.clone()),
+        Expression::Add(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if is_zero(&rhs) {
+                Ok(lhs)
+            } else if is_zero(&lhs) {
+                Ok(rhs)
+            } else {
+                Expression::add(lhs, rhs)
+            }
+        })?,
+        Expression::Sub(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if is_zero(&rhs) {
+                Ok(lhs)
+            } else if lhs == rhs {
+                Ok(il::expr_const(0, lhs.bits()))
+            } else {
+                Expression::sub(lhs, rhs)
+            }
+        })?,
+        Expression::Mul(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if is_zero(&lhs) {
+                Ok(lhs)
+            } else if is_zero(&rhs) {
+                Ok(rhs)
+            } else {
+                Expression::mul(lhs, rhs)
+            }
+        })?,
+        Expression::Divu(ref lhs, ref rhs) => binop(lhs, rhs, Expression::divu)?,
+        Expression::Modu(ref lhs, ref rhs) => binop(lhs, rhs, Expression::modu)?,
+        Expression::Divs(ref lhs, ref rhs) => binop(lhs, rhs, Expression::divs)?,
+        Expression::Mods(ref lhs, ref rhs) => binop(lhs, rhs, Expression::mods)?,
+        Expression::And(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if is_zero(&lhs) {
+                Ok(lhs)
+            } else if is_zero(&rhs) || lhs == rhs {
+                Ok(rhs)
+            } else {
+                Expression::and(lhs, rhs)
+            }
+        })?,
+        Expression::Or(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if is_zero(&rhs) || lhs == rhs {
+                Ok(lhs)
+            } else if is_zero(&lhs) {
+                Ok(rhs)
+            } else {
+                Expression::or(lhs, rhs)
+            }
+        })?,
+        Expression::Xor(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if is_zero(&rhs) {
+                Ok(lhs)
+            } else if is_zero(&lhs) {
+                Ok(rhs)
+            } else if lhs == rhs {
+                Ok(il::expr_const(0, lhs.bits()))
+            } else {
+                Expression::xor(lhs, rhs)
+            }
+        })?,
+        Expression::Shl(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if is_zero(&rhs) {
+                Ok(lhs)
+            } else {
+                Expression::shl(lhs, rhs)
+            }
+        })?,
+        Expression::Shr(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if is_zero(&rhs) {
+                Ok(lhs)
+            } else {
+                Expression::shr(lhs, rhs)
+            }
+        })?,
+        Expression::Cmpeq(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if lhs == rhs {
+                Ok(il::expr_const(1, 1))
+            } else {
+                Expression::cmpeq(lhs, rhs)
+            }
+        })?,
+        Expression::Cmpneq(ref lhs, ref rhs) => binop(lhs, rhs, |lhs, rhs| {
+            if lhs == rhs {
+                Ok(il::expr_const(0, 1))
+            } else {
+                Expression::cmpneq(lhs, rhs)
+            }
+        })?,
+        Expression::Cmplts(ref lhs, ref rhs) => binop(lhs, rhs, Expression::cmplts)?,
+        Expression::Cmpltu(ref lhs, ref rhs) => binop(lhs, rhs, Expression::cmpltu)?,
+        Expression::Zext(bits, ref src) => Expression::zext(bits, simplify(src)?)?,
+        Expression::Sext(bits, ref src) => Expression::sext(bits, simplify(src)?)?,
+        Expression::Trun(bits, ref src) => match simplify(src)? {
+            // Truncating an extension back to the size of its source
+            Expression::Zext(_, ref src) | Expression::Sext(_, ref src) if src.bits() == bits => {
+                src.as_ref().clone()
+            }
+            src => Expression::trun(bits, src)?,
+        },
+        Expression::Ite(ref cond, ref then, ref else_) => {
+            let cond = simplify(cond)?;
+            if let Some(constant) = cond.get_constant() {
+                return if constant.is_one() {
+                    simplify(then)
+                } else {
+                    simplify(else_)
+                };
+            }
+            let then = simplify(then)?;
+            let else_ = simplify(else_)?;
+            if then == else_ {
+                then
+            } else {
+                Expression::ite(cond, then, else_)?
+            }
+        }
+    };
+
+    if expression.all_constants() {
+        Ok(eval(&expression)?.into())
+    } else {
+        Ok(expression)
+    }
+}
+
+#[test]
+fn simplify_test() {
+    let x = il::expr_scalar("x", 32);
+
+    // (x + (4 - 4)) ^ 0 = x
+    let expression = Expression::xor(
+        Expression::add(
+            x.clone(),
+            Expression::sub(il::expr_const(4, 32), il::expr_const(4, 32)).unwrap(),
+        )
+        .unwrap(),
+        il::expr_const(0, 32),
+    )
+    .unwrap();
+    assert_eq!(simplify(&expression).unwrap(), x);
+
+    // trun.8(zext.32(trun.8(x))) = trun.8(x)
+    let expression = Expression::trun(
+        8,
+        Expression::zext(32, Expression::trun(8, x.clone()).unwrap()).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        simplify(&expression).unwrap(),
+        Expression::trun(8, x.clone()).unwrap()
+    );
+
+    // ite(1 == 2, x, 7) = 7
+    let expression = Expression::ite(
+        Expression::cmpeq(il::expr_const(1, 32), il::expr_const(2, 32)).unwrap(),
+        x,
+        il::expr_const(7, 32),
+    )
+    .unwrap();
+    assert_eq!(simplify(&expression).unwrap(), il::expr_const(7, 32));
+}
@@ -0,0 +1,575 @@
+//! Serialisation of Falcon IL to, and models from, SMT-LIB2.
+//!
+//! Falcon IL has no boolean sort. Every `il::Expression`, including
+//! comparisons, is serialised as a bitvector term, and comparisons become
+//! `#b1` or `#b0`. A constraint is a 1-bit expression, and is asserted as
+//! being equal to `#b1`.
+//!
+//! Scalars are serialised as quoted symbols, so `rax` becomes `|rax|`.
+//!
+//! ```
+//! # use falcon::error::*;
+//! use falcon::il;
+//! use falcon::symbolic::smtlib2;
+//!
+//! # fn example() -> Result<()> {
+//! let constraint = il::Expression::cmpeq(
+//!     il::Expression::add(il::expr_scalar("x", 8), il::expr_const(1, 8))?,
+//!     il::expr_const(0x42, 8)
+//! )?;
+//!
+//! // Produces the complete script to hand to a solver
+//! let script = smtlib2::query(&[constraint])?;
+//! assert!(script.contains("(declare-fun |x| () (_ BitVec 8))"));
+//!
+//! // Parses the solver's output
+//! let model = smtlib2::parse_model("((define-fun |x| () (_ BitVec 8) #x41))")?;
+//! assert_eq!(model.get_scalar("x"), Some(&il::const_(0x41, 8)));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::architecture::Endian;
+use crate::error::*;
+use crate::il;
+use crate::symbolic::{Model, Solver};
+use num_bigint::BigUint;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Quote the given name as an SMT-LIB2 symbol.
+pub fn symbol(name: &str) -> Result<String> {
+    if name.contains('|') || name.contains('\\') {
+        bail!("Cannot quote \"{}\" as an SMT-LIB2 symbol", name);
+    }
+    Ok(format!("|{}|", name))
+}
+
+/// The SMT-LIB2 sort for a bitvector of the given number of bits.
+pub fn sort(bits: usize) -> String {
+    format!("(_ BitVec {})", bits)
+}
+
+/// Serialise an `il::Constant` as an SMT-LIB2 bitvector literal.
+pub fn constant(constant: &il::Constant) -> String {
+    format!("(_ bv{} {})", constant.value(), constant.bits())
+}
+
+/// Serialise a 1-bit term as an SMT-LIB2 boolean.
+fn boolean(term: String) -> String {
+    format!("(= {} #b1)", term)
+}
+
+/// Serialise an SMT-LIB2 boolean as a 1-bit term.
+fn bitvector(boolean: String) -> String {
+    format!("(ite {} #b1 #b0)", boolean)
+}
+
+fn binop(op: &str, lhs: &il::Expression, rhs: &il::Expression) -> Result<String> {
+    Ok(format!(
+        "({} {} {})",
+        op,
+        expression(lhs)?,
+        expression(rhs)?
+    ))
+}
+
+fn cmp(op: &str, lhs: &il::Expression, rhs: &il::Expression) -> Result<String> {
+    Ok(bitvector(binop(op, lhs, rhs)?))
+}
+
+/// Serialise an `il::Expression` as an SMT-LIB2 bitvector term.
+pub fn expression(expr: &il::Expression) -> Result<String> {
+    Ok(match *expr {
+        il::Expression::Scalar(ref scalar) => symbol(scalar.name())?,
+        il::Expression::Constant(ref c) => constant(c),
+        il::Expression::Add(ref lhs, ref rhs) => binop("bvadd", lhs, rhs)?,
+        il::Expression::Sub(ref lhs, ref rhs) => binop("bvsub", lhs, rhs)?,
+        il::Expression::Mul(ref lhs, ref rhs) => binop("bvmul", lhs, rhs)?,
+        il::Expression::Divu(ref lhs, ref rhs) => binop("bvudiv", lhs, rhs)?,
+        il::Expression::Modu(ref lhs, ref rhs) => binop("bvurem", lhs, rhs)?,
+        il::Expression::Divs(ref lhs, ref rhs) => binop("bvsdiv", lhs, rhs)?,
+        il::Expression::Mods(ref lhs, ref rhs) => binop("bvsrem", lhs, rhs)?,
+        il::Expression::And(ref lhs, ref rhs) => binop("bvand", lhs, rhs)?,
+        il::Expression::Or(ref lhs, ref rhs) => binop("bvor", lhs, rhs)?,
+        il::Expression::Xor(ref lhs, ref rhs) => binop("bvxor", lhs, rhs)?,
+        il::Expression::Shl(ref lhs, ref rhs) => binop("bvshl", lhs, rhs)?,
+        il::Expression::Shr(ref lhs, ref rhs) => binop("bvlshr", lhs, rhs)?,
+        il::Expression::Cmpeq(ref lhs, ref rhs) => cmp("=", lhs, rhs)?,
+        il::Expression::Cmpneq(ref lhs, ref rhs) => cmp("distinct", lhs, rhs)?,
+        il::Expression::Cmplts(ref lhs, ref rhs) => cmp("bvslt", lhs, rhs)?,
+        il::Expression::Cmpltu(ref lhs, ref rhs) => cmp("bvult", lhs, rhs)?,
+        il::Expression::Zext(bits, ref src) => format!(
+            "((_ zero_extend {}) {})",
+            bits - src.bits(),
+            expression(src)?
+        ),
+        il::Expression::Sext(bits, ref src) => format!(
+            "((_ sign_extend {}) {})",
+            bits - src.bits(),
+            expression(src)?
+        ),
+        il::Expression::Trun(bits, ref src) => {
+            format!("((_ extract {} 0) {})", bits - 1, expression(src)?)
+        }
+        il::Expression::Ite(ref cond, ref then, ref else_) => format!(
+            "(ite {} {} {})",
+            boolean(expression(cond)?),
+            expression(then)?,
+            expression(else_)?
+        ),
+    })
+}
+
+/// Serialise a 1-bit `il::Expression` as an SMT-LIB2 `assert` command.
+pub fn assert(constraint: &il::Expression) -> Result<String> {
+    if constraint.bits() != 1 {
+        return Err(ErrorKind::Sort.into());
+    }
+    Ok(format!("(assert {})", boolean(expression(constraint)?)))
+}
+
+/// Serialise `declare-fun` commands for every scalar in the given
+/// expressions.
+///
+/// Returns an error if the same scalar appears with different bitness.
+pub fn declarations(expressions: &[il::Expression]) -> Result<String> {
+    let mut scalars: BTreeMap<&str, usize> = BTreeMap::new();
+    for expression in expressions {
+        for scalar in expression.scalars() {
+            if let Some(bits) = scalars.insert(scalar.name(), scalar.bits()) {
+                if bits != scalar.bits() {
+                    bail!(
+                        "Scalar {} appears with both {} and {} bits",
+                        scalar.name(),
+                        bits,
+                        scalar.bits()
+                    );
+                }
+            }
+        }
+    }
+    let mut declarations = String::new();
+    for (name, bits) in scalars {
+        declarations.push_str(&format!(
+            "(declare-fun {} () {})\n",
+            symbol(name)?,
+            sort(bits)
+        ));
+    }
+    Ok(declarations)
+}
+
+/// Serialise a set of constraints as a complete SMT-LIB2 script, which
+/// declares every scalar, asserts every constraint, checks satisfiability,
+/// and requests a model.
+pub fn query(constraints: &[il::Expression]) -> Result<String> {
+    let mut script = String::from("(set-option :produce-models true)\n(set-logic QF_ABV)\n");
+    script.push_str(&declarations(constraints)?);
+    for constraint in constraints {
+        script.push_str(&assert(constraint)?);
+        script.push('\n');
+    }
+    script.push_str("(check-sat)\n(get-model)\n");
+    Ok(script)
+}
+
+/// Memory modelled as an SMT-LIB2 array from addresses to bytes.
+///
+/// Loads and stores of more than one byte are split into byte accesses,
+/// following the endianness of the memory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Array {
+    name: String,
+    address_bits: usize,
+    endian: Endian,
+}
+
+impl Array {
+    /// Create a new `Array` with the given name, and addresses of the given
+    /// number of bits.
+    pub fn new<S: Into<String>>(name: S, address_bits: usize, endian: Endian) -> Array {
+        Array {
+            name: name.into(),
+            address_bits,
+            endian,
+        }
+    }
+
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The SMT-LIB2 sort of this array.
+    pub fn sort(&self) -> String {
+        format!("(Array {} {})", sort(self.address_bits), sort(8))
+    }
+
+    /// Serialise the `declare-fun` command for this array.
+    pub fn declaration(&self) -> Result<String> {
+        Ok(format!(
+            "(declare-fun {} () {})",
+            symbol(&self.name)?,
+            self.sort()
+        ))
+    }
+
+    fn check_index(&self, index: &il::Expression) -> Result<()> {
+        if index.bits() != self.address_bits {
+            return Err(ErrorKind::Sort.into());
+        }
+        Ok(())
+    }
+
+    /// The address of each byte of an access of the given size, ordered from
+    /// most significant to least significant byte of the value.
+    fn byte_addresses(&self, index: &il::Expression, bits: usize) -> Result<Vec<String>> {
+        if bits == 0 || bits & 7 != 0 {
+            bail!(
+                "Array access of {} bits is not a whole number of bytes",
+                bits
+            );
+        }
+        let index = expression(index)?;
+        let mut addresses = (0..(bits / 8))
+            .map(|offset| {
+                if offset == 0 {
+                    index.clone()
+                } else {
+                    format!(
+                        "(bvadd {} {})",
+                        index,
+                        constant(&il::const_(offset as u64, self.address_bits))
+                    )
+                }
+            })
+            .collect::<Vec<String>>();
+        if self.endian == Endian::Little {
+            addresses.reverse();
+        }
+        Ok(addresses)
+    }
+
+    /// Serialise a load of `bits` from `array`, a term of this array's sort,
+    /// at the given index.
+    pub fn load(&self, array: &str, index: &il::Expression, bits: usize) -> Result<String> {
+        self.check_index(index)?;
+        let bytes = self
+            .byte_addresses(index, bits)?
+            .into_iter()
+            .map(|address| format!("(select {} {})", array, address))
+            .collect::<Vec<String>>();
+        Ok(if bytes.len() == 1 {
+            bytes[0].clone()
+        } else {
+            format!("(concat {})", bytes.join(" "))
+        })
+    }
+
+    /// Serialise a store of `value` to `array`, a term of this array's sort,
+    /// at the given index. The result is a term of this array's sort.
+    pub fn store(
+        &self,
+        array: &str,
+        index: &il::Expression,
+        value: &il::Expression,
+    ) -> Result<String> {
+        self.check_index(index)?;
+        let bits = value.bits();
+        let value = expression(value)?;
+        let mut array = array.to_string();
+        for (i, address) in self.byte_addresses(index, bits)?.into_iter().enumerate() {
+            let high = bits - (i * 8) - 1;
+            array = format!(
+                "(store {} {} ((_ extract {} {}) {}))",
+                array,
+                address,
+                high,
+                high - 7,
+                value
+            );
+        }
+        Ok(array)
+    }
+}
+
+/// A parsed SMT-LIB2 s-expression.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    fn atom(&self) -> Option<&str> {
+        match *self {
+            Sexp::Atom(ref atom) => Some(atom),
+            Sexp::List(_) => None,
+        }
+    }
+}
+
+/// Parse a sequence of s-expressions.
+fn parse_sexps(text: &str) -> Result<Vec<Sexp>> {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut stack: Vec<Vec<Sexp>> = vec![Vec::new()];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '(' => stack.push(Vec::new()),
+            ')' => {
+                let list = stack.pop().ok_or("Unbalanced ) in SMT-LIB2 output")?;
+                stack
+                    .last_mut()
+                    .ok_or("Unbalanced ) in SMT-LIB2 output")?
+                    .push(Sexp::List(list));
+            }
+            ';' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '|' | '"' => {
+                let delimiter = chars[i];
+                let start = i + 1;
+                i += 1;
+                while i < chars.len() && chars[i] != delimiter {
+                    i += 1;
+                }
+                if i == chars.len() {
+                    bail!("Unterminated {} in SMT-LIB2 output", delimiter);
+                }
+                let atom = chars[start..i].iter().collect::<String>();
+                stack.last_mut().unwrap().push(Sexp::Atom(atom));
+            }
+            c if c.is_whitespace() => {}
+            _ => {
+                let start = i;
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && chars[i] != '('
+                    && chars[i] != ')'
+                {
+                    i += 1;
+                }
+                let atom = chars[start..i].iter().collect::<String>();
+                stack.last_mut().unwrap().push(Sexp::Atom(atom));
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if stack.len() != 1 {
+        bail!("Unbalanced ( in SMT-LIB2 output");
+    }
+    Ok(stack.pop().unwrap())
+}
+
+/// Parse a bitvector or boolean value from a model.
+fn parse_value(value: &Sexp) -> Result<il::Constant> {
+    match *value {
+        Sexp::Atom(ref atom) => {
+            if let Some(digits) = atom.strip_prefix("#b") {
+                let value = BigUint::parse_bytes(digits.as_bytes(), 2)
+                    .ok_or_else(|| format!("Invalid binary value {}", atom))?;
+                Ok(il::Constant::new_big(value, digits.len()))
+            } else if let Some(digits) = atom.strip_prefix("#x") {
+                let value = BigUint::parse_bytes(digits.as_bytes(), 16)
+                    .ok_or_else(|| format!("Invalid hexadecimal value {}", atom))?;
+                Ok(il::Constant::new_big(value, digits.len() * 4))
+            } else if atom == "true" {
+                Ok(il::const_(1, 1))
+            } else if atom == "false" {
+                Ok(il::const_(0, 1))
+            } else {
+                bail!("Unsupported value {} in SMT-LIB2 model", atom)
+            }
+        }
+        Sexp::List(ref list) => match list.as_slice() {
+            [Sexp::Atom(ref underscore), Sexp::Atom(ref value), Sexp::Atom(ref bits)]
+                if underscore == "_" && value.starts_with("bv") =>
+            {
+                let value = BigUint::parse_bytes(&value.as_bytes()[2..], 10)
+                    .ok_or_else(|| format!("Invalid decimal value {}", value))?;
+                let bits = bits
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid bitvector size {}", bits))?;
+                Ok(il::Constant::new_big(value, bits))
+            }
+            _ => bail!("Unsupported value {:?} in SMT-LIB2 model", value),
+        },
+    }
+}
+
+/// Collect the `define-fun` entries of a model into the given `Model`.
+fn collect_definitions(sexp: &Sexp, model: &mut Model) -> Result<()> {
+    let list = match *sexp {
+        Sexp::List(ref list) => list,
+        Sexp::Atom(_) => return Ok(()),
+    };
+    if list.first().and_then(|sexp| sexp.atom()) == Some("define-fun") {
+        // (define-fun name () sort value)
+        if list.len() != 5 {
+            bail!("Malformed define-fun in SMT-LIB2 model");
+        }
+        let name = list[1].atom().ok_or("Malformed define-fun name")?;
+        match list[2] {
+            Sexp::List(ref arguments) if arguments.is_empty() => {}
+            // Functions with arguments, such as arrays, are not scalars.
+            _ => return Ok(()),
+        }
+        // Values of other sorts, such as arrays, are not scalars either.
+        if let Ok(value) = parse_value(&list[4]) {
+            model.set_scalar(name, value);
+        }
+        return Ok(());
+    }
+    for sexp in list {
+        collect_definitions(sexp, model)?;
+    }
+    Ok(())
+}
+
+/// Parse the output of `get-model` into a `Model` of scalars.
+///
+/// Definitions which are not of constant bitvector or boolean values, such as
+/// arrays, are ignored. Booleans are given as 1-bit constants.
+pub fn parse_model(output: &str) -> Result<Model> {
+    let mut model = Model::new();
+    for sexp in parse_sexps(output)? {
+        collect_definitions(&sexp, &mut model)?;
+    }
+    Ok(model)
+}
+
+/// Parse the output of a script created by `query`.
+///
+/// Returns a `Model` if the solver responded `sat`, `None` if it responded
+/// `unsat`, and an error otherwise.
+pub fn parse_response(output: &str) -> Result<Option<Model>> {
+    let sexps = parse_sexps(output)?;
+    match sexps.first().and_then(|sexp| sexp.atom()) {
+        Some("sat") => {
+            let mut model = Model::new();
+            for sexp in &sexps[1..] {
+                collect_definitions(sexp, &mut model)?;
+            }
+            Ok(Some(model))
+        }
+        Some("unsat") => Ok(None),
+        _ => bail!("Solver did not decide constraints: {}", output.trim()),
+    }
+}
+
+/// A `Solver` which runs an SMT-LIB2 solver as a subprocess.
+///
+/// The solver is given the script from `query` on standard input, and must
+/// write its responses to standard output. For example, z3 is run with
+/// `Smtlib2Solver::new("z3", &["-in"])`.
+#[derive(Clone, Debug)]
+pub struct Smtlib2Solver {
+    command: String,
+    arguments: Vec<String>,
+}
+
+impl Smtlib2Solver {
+    /// Create a new `Smtlib2Solver` which runs the given command, with the
+    /// given arguments.
+    pub fn new<S: Into<String>>(command: S, arguments: &[&str]) -> Smtlib2Solver {
+        Smtlib2Solver {
+            command: command.into(),
+            arguments: arguments
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl Solver for Smtlib2Solver {
+    fn solve(&self, constraints: &[il::Expression]) -> Result<Option<Model>> {
+        let script = query(constraints)?;
+
+        let mut child = Command::new(&self.command)
+            .args(&self.arguments)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .chain_err(|| format!("Failed to run solver {}", self.command))?;
+        child
+            .stdin
+            .take()
+            .ok_or("Failed to open solver stdin")?
+            .write_all(script.as_bytes())?;
+        let output = child.wait_with_output()?;
+
+        // Solvers respond to get-model with an error when unsat, so the
+        // response is parsed regardless of exit status.
+        let output = String::from_utf8_lossy(&output.stdout);
+        parse_response(&output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialise() {
+        let x = il::expr_scalar("x", 32);
+        let expression = il::Expression::ite(
+            il::Expression::cmpltu(x.clone(), il::expr_const(10, 32)).unwrap(),
+            il::Expression::zext(32, il::Expression::trun(8, x.clone()).unwrap()).unwrap(),
+            il::Expression::sext(32, il::Expression::trun(16, x).unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            super::expression(&expression).unwrap(),
+            "(ite (= (ite (bvult |x| (_ bv10 32)) #b1 #b0) #b1) \
+             ((_ zero_extend 24) ((_ extract 7 0) |x|)) \
+             ((_ sign_extend 16) ((_ extract 15 0) |x|)))"
+        );
+    }
+
+    #[test]
+    fn array() {
+        let array = Array::new("mem", 32, Endian::Little);
+        let index = il::expr_scalar("p", 32);
+        assert_eq!(
+            array.load("|mem|", &index, 16).unwrap(),
+            "(concat (select |mem| (bvadd |p| (_ bv1 32))) (select |mem| |p|))"
+        );
+
+        let array = Array::new("mem", 32, Endian::Big);
+        assert_eq!(
+            array
+                .store("|mem|", &index, &il::expr_scalar("v", 16))
+                .unwrap(),
+            "(store (store |mem| |p| ((_ extract 15 8) |v|)) \
+             (bvadd |p| (_ bv1 32)) ((_ extract 7 0) |v|))"
+        );
+    }
+
+    #[test]
+    fn models() {
+        let output = "sat\n\
+                      (\n  \
+                        (define-fun |x| () (_ BitVec 8)\n    #x41)\n  \
+                        (define-fun y () (_ BitVec 3) #b101)\n  \
+                        (define-fun |z| () (_ BitVec 64) (_ bv1234 64))\n  \
+                        (define-fun mem () (Array (_ BitVec 32) (_ BitVec 8))\n    \
+                          ((as const (Array (_ BitVec 32) (_ BitVec 8))) #x00))\n\
+                      )\n";
+        let model = parse_response(output).unwrap().unwrap();
+        assert_eq!(model.get_scalar("x"), Some(&il::const_(0x41, 8)));
+        assert_eq!(model.get_scalar("y"), Some(&il::const_(5, 3)));
+        assert_eq!(model.get_scalar("z"), Some(&il::const_(1234, 64)));
+        assert_eq!(model.get_scalar("mem"), None);
+
+        assert_eq!(parse_response("unsat\n(error \"no model\")").unwrap(), None);
+        assert!(parse_response("unknown").is_err());
+    }
+}
@@ -0,0 +1,77 @@
+//! The interface between symbolic execution and a solver.
+
+use crate::error::*;
+use crate::executor::eval;
+use crate::il;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+/// An assignment of concrete values to scalars which satisfies a set of
+/// constraints.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Model {
+    values: BTreeMap<String, il::Constant>,
+}
+
+impl Model {
+    /// Create a new, empty, `Model`.
+    pub fn new() -> Model {
+        Model::default()
+    }
+
+    /// Set the value of the given scalar in this model.
+    pub fn set_scalar<S: Into<String>>(&mut self, name: S, value: il::Constant) {
+        self.values.insert(name.into(), value);
+    }
+
+    /// Get the value of the given scalar in this model.
+    pub fn get_scalar(&self, name: &str) -> Option<&il::Constant> {
+        self.values.get(name)
+    }
+
+    /// Iterate over the scalars assigned in this model.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &il::Constant)> {
+        self.values.iter()
+    }
+
+    /// Evaluate an expression under this model.
+    ///
+    /// Solvers need not assign values to scalars which do not affect the
+    /// satisfiability of the constraints, and any scalar this model does not
+    /// assign is evaluated as zero.
+    pub fn eval(&self, expression: &il::Expression) -> Result<il::Constant> {
+        let mut expression = expression.clone();
+        let scalars = expression
+            .scalars()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<il::Scalar>>();
+        for scalar in scalars {
+            let value = match self.values.get(scalar.name()) {
+                Some(value) => value.clone(),
+                None => il::Constant::new_zero(scalar.bits()),
+            };
+            expression = expression.replace_scalar(&scalar, &value.into())?;
+        }
+        eval(&expression)
+    }
+}
+
+/// A decision procedure for path constraints.
+///
+/// Constraints are 1-bit `il::Expression`s, and are satisfied when they
+/// evaluate to 1.
+pub trait Solver: Debug + Send + Sync {
+    /// Determine whether the conjunction of the given constraints is
+    /// satisfiable.
+    ///
+    /// Returns a `Model` if the constraints are satisfiable, `None` if they
+    /// are unsatisfiable, and an error if the solver could not decide.
+    fn solve(&self, constraints: &[il::Expression]) -> Result<Option<Model>>;
+
+    /// Returns true if the conjunction of the given constraints is
+    /// satisfiable.
+    fn is_sat(&self, constraints: &[il::Expression]) -> Result<bool> {
+        Ok(self.solve(constraints)?.is_some())
+    }
+}
@@ -0,0 +1,257 @@
+//! A symbolic state for execution over Falcon IL.
+
+use crate::error::*;
+use crate::executor::SuccessorType;
+use crate::il;
+use crate::symbolic::*;
+use std::collections::BTreeMap;
+
+/// A symbolic `State`.
+///
+/// Scalars which have not been set in this `State` are free, and stand for
+/// their own value.
+#[derive(Debug, Clone)]
+pub struct State {
+    scalars: BTreeMap<String, il::Expression>,
+    memory: Memory,
+    path_constraints: Vec<il::Expression>,
+}
+
+impl State {
+    /// Create a new `State` from the given memory model.
+    pub fn new(memory: Memory) -> State {
+        State {
+            scalars: BTreeMap::new(),
+            memory,
+            path_constraints: Vec::new(),
+        }
+    }
+
+    /// Retrieve the `Memory` associated with this `State`.
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Retrieve a mutable reference to the `Memory` associated with this
+    /// `State`.
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// Set the value of the given scalar to a symbolic value.
+    pub fn set_scalar<S: Into<String>>(&mut self, name: S, value: il::Expression) {
+        self.scalars.insert(name.into(), value);
+    }
+
+    /// Get the symbolic value of the given scalar, if it has been set.
+    pub fn get_scalar(&self, name: &str) -> Option<&il::Expression> {
+        self.scalars.get(name)
+    }
+
+    /// Fill `length` bytes of memory, starting at `address`, with free 8-bit
+    /// scalars named `<name>_0`, `<name>_1`, ...
+    ///
+    /// This is how input buffers are made symbolic.
+    pub fn make_symbolic_buffer(&mut self, address: u64, length: usize, name: &str) -> Result<()> {
+        for i in 0..length {
+            self.memory.store(
+                address + i as u64,
+                il::expr_scalar(format!("{}_{}", name, i), 8),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The constraints which must hold for execution to have reached this
+    /// `State`.
+    pub fn path_constraints(&self) -> &[il::Expression] {
+        &self.path_constraints
+    }
+
+    /// Add a 1-bit constraint to the path constraints of this `State`.
+    ///
+    /// The constraint is symbolized over this `State` first. No check is made
+    /// that the path constraints remain satisfiable.
+    pub fn add_path_constraint(&mut self, constraint: &il::Expression) -> Result<()> {
+        if constraint.bits() != 1 {
+            return Err(ErrorKind::Sort.into());
+        }
+        let constraint = self.symbolize_expression(constraint)?;
+        self.push_path_constraint(constraint);
+        Ok(())
+    }
+
+    /// Add a 1-bit constraint, which is already symbolized over this
+    /// `State`, to the path constraints of this `State`.
+    pub(crate) fn push_path_constraint(&mut self, constraint: il::Expression) {
+        if let Some(constant) = constraint.get_constant() {
+            if constant.is_one() {
+                return;
+            }
+        }
+        self.path_constraints.push(constraint);
+    }
+
+    /// Solve the path constraints of this `State`, returning a `Model` of the
+    /// free scalars if the path is feasible.
+    pub fn model(&self, solver: &dyn Solver) -> Result<Option<Model>> {
+        solver.solve(&self.path_constraints)
+    }
+
+    /// Returns true if the path constraints of this `State` are satisfiable.
+    pub fn is_sat(&self, solver: &dyn Solver) -> Result<bool> {
+        solver.is_sat(&self.path_constraints)
+    }
+
+    /// Symbolize an expression, replacing all scalars set in this state with
+    /// their symbolic values, and simplify the result.
+    pub fn symbolize_expression(&self, expression: &il::Expression) -> Result<il::Expression> {
+        simplify(&self.substitute(expression)?)
+    }
+
+    fn substitute(&self, expression: &il::Expression) -> Result<il::Expression> {
+        Ok(match *expression {
+            il::Expression::Scalar(ref scalar) => match self.scalars.get(scalar.name()) {
+                Some(expr) => expr.clone(),
+                None => il::Expression::Scalar(scalar.clone()),
+            },
+            il::Expression::Constant(_) => expression.clone(),
+            il::Expression::Add(ref lhs, ref rhs) => {
+                il::Expression::add(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Sub(ref lhs, ref rhs) => {
+                il::Expression::sub(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Mul(ref lhs, ref rhs) => {
+                il::Expression::mul(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Divu(ref lhs, ref rhs) => {
+                il::Expression::divu(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Modu(ref lhs, ref rhs) => {
+                il::Expression::modu(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Divs(ref lhs, ref rhs) => {
+                il::Expression::divs(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Mods(ref lhs, ref rhs) => {
+                il::Expression::mods(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::And(ref lhs, ref rhs) => {
+                il::Expression::and(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Or(ref lhs, ref rhs) => {
+                il::Expression::or(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Xor(ref lhs, ref rhs) => {
+                il::Expression::xor(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Shl(ref lhs, ref rhs) => {
+                il::Expression::shl(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Shr(ref lhs, ref rhs) => {
+                il::Expression::shr(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Cmpeq(ref lhs, ref rhs) => {
+                il::Expression::cmpeq(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Cmpneq(ref lhs, ref rhs) => {
+                il::Expression::cmpneq(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Cmplts(ref lhs, ref rhs) => {
+                il::Expression::cmplts(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Cmpltu(ref lhs, ref rhs) => {
+                il::Expression::cmpltu(self.substitute(lhs)?, self.substitute(rhs)?)?
+            }
+            il::Expression::Zext(bits, ref src) => {
+                il::Expression::zext(bits, self.substitute(src)?)?
+            }
+            il::Expression::Sext(bits, ref src) => {
+                il::Expression::sext(bits, self.substitute(src)?)?
+            }
+            il::Expression::Trun(bits, ref src) => {
+                il::Expression::trun(bits, self.substitute(src)?)?
+            }
+            il::Expression::Ite(ref cond, ref then, ref else_) => il::Expression::ite(
+                self.substitute(cond)?,
+                self.substitute(then)?,
+                self.substitute(else_)?,
+            )?,
+        })
+    }
+
+    /// Find a concrete value for an expression over this `State`.
+    ///
+    /// If the expression is not constant, the solver chooses a value
+    /// consistent with the path constraints, and the expression is then
+    /// constrained to that value. Returns `None` if the path constraints are
+    /// unsatisfiable.
+    pub fn concretize(
+        &mut self,
+        expression: &il::Expression,
+        solver: &dyn Solver,
+    ) -> Result<Option<il::Constant>> {
+        let expression = self.symbolize_expression(expression)?;
+        if let il::Expression::Constant(constant) = expression {
+            return Ok(Some(constant));
+        }
+        let model = match self.model(solver)? {
+            Some(model) => model,
+            None => return Ok(None),
+        };
+        let value = model.eval(&expression)?;
+        self.path_constraints
+            .push(il::Expression::cmpeq(expression, value.clone().into())?);
+        Ok(Some(value))
+    }
+
+    fn concretize_address(
+        &mut self,
+        expression: &il::Expression,
+        solver: &dyn Solver,
+    ) -> Result<u64> {
+        self.concretize(expression, solver)?
+            .ok_or("Path constraints are unsatisfiable")?
+            .value_u64()
+            .ok_or_else(|| ErrorKind::TooManyAddressBits.into())
+    }
+
+    /// Execute an `il::Operation`, returning the post-execution `State`.
+    ///
+    /// Symbolic addresses for `Load`, `Store` and `Branch` operations are
+    /// concretized to a single value with the given solver.
+    pub fn execute(mut self, operation: &il::Operation, solver: &dyn Solver) -> Result<Successor> {
+        Ok(match *operation {
+            il::Operation::Assign { ref dst, ref src } => {
+                let src = self.symbolize_expression(src)?;
+                self.set_scalar(dst.name(), src);
+                Successor::new(self, SuccessorType::FallThrough)
+            }
+            il::Operation::Store { ref index, ref src } => {
+                let src = self.symbolize_expression(src)?;
+                let index = self.concretize_address(index, solver)?;
+                self.memory.store(index, src)?;
+                Successor::new(self, SuccessorType::FallThrough)
+            }
+            il::Operation::Load { ref dst, ref index } => {
+                let index = self.concretize_address(index, solver)?;
+                let value = self
+                    .memory
+                    .load(index, dst.bits())?
+                    .ok_or(ErrorKind::AccessUnmappedMemory(index))?;
+                let value = simplify(&value)?;
+                self.set_scalar(dst.name(), value);
+                Successor::new(self, SuccessorType::FallThrough)
+            }
+            il::Operation::Branch { ref target } => {
+                let target = self.concretize_address(target, solver)?;
+                Successor::new(self, SuccessorType::Branch(target))
+            }
+            il::Operation::Intrinsic { ref intrinsic } => {
+                Successor::new(self, SuccessorType::Intrinsic(intrinsic.clone()))
+            }
+            il::Operation::Nop => Successor::new(self, SuccessorType::FallThrough),
+        })
+    }
+}
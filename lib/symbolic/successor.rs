@@ -0,0 +1,34 @@
+//! A successor after symbolic evaluation of a Falcon IL Instruction.
+
+use crate::executor::SuccessorType;
+use crate::symbolic::State;
+
+/// The result of symbolically executing an `il::Operation` over a `State`.
+#[derive(Clone, Debug)]
+pub struct Successor {
+    state: State,
+    type_: SuccessorType,
+}
+
+impl Successor {
+    pub(crate) fn new(state: State, type_: SuccessorType) -> Successor {
+        Successor { state, type_ }
+    }
+
+    /// Get the `SuccessorType` of this `Successor`.
+    pub fn type_(&self) -> &SuccessorType {
+        &self.type_
+    }
+
+    /// Get the `State` of this `Successor`.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+}
+
+/// Turn this `Successor` into its `State`, discarding the `SuccessorType`.
+impl From<Successor> for State {
+    fn from(successor: Successor) -> State {
+        successor.state
+    }
+}